@@ -1,6 +1,7 @@
 use std::{
     collections::{HashMap, HashSet},
     marker::PhantomData,
+    time::{Duration, Instant},
 };
 
 use bevy::{
@@ -27,6 +28,13 @@ impl<T: Component> Plugin for AsyncComponentPlugin<T> {
             tasks: HashMap::new(),
             added_since_last_update: HashSet::new(),
         })
+        .insert_resource(ComputeTaskStats::<T> {
+            in_flight: 0,
+            completed: 0,
+            receive_time: Duration::ZERO,
+            total_task_time: Duration::ZERO,
+            _phantom: PhantomData,
+        })
         .add_systems(
             PostUpdate,
             (
@@ -41,10 +49,26 @@ impl<T: Component> Plugin for AsyncComponentPlugin<T> {
 
 #[derive(Resource)]
 pub struct ComputeTasks<T> {
-    tasks: HashMap<Entity, Task<T>>,
+    /// Each task also returns how long it ran for
+    tasks: HashMap<Entity, Task<(T, Duration)>>,
     added_since_last_update: HashSet<Entity>,
 }
 
+/// Progress of the tasks producing `T`, updated once per frame
+#[derive(Resource)]
+pub struct ComputeTaskStats<T> {
+    /// Tasks spawned and not yet received
+    pub in_flight: usize,
+    /// Results received since the app started
+    pub completed: u64,
+    /// Time spent in the last frame polling tasks and queuing their results
+    /// for insertion
+    pub receive_time: Duration,
+    /// Time spent running the received tasks, summed since the app started
+    pub total_task_time: Duration,
+    _phantom: PhantomData<T>,
+}
+
 #[derive(Component)]
 pub struct ComputeInProgress<T> {
     _phantom: PhantomData<T>,
@@ -57,10 +81,22 @@ impl<T: Send + 'static> ComputeTasks<T> {
         future: Future,
     ) {
         let pool = AsyncComputeTaskPool::get();
-        let task = pool.spawn(future);
+        let task = pool.spawn(async move {
+            let start = Instant::now();
+            let result = future.await;
+            (result, start.elapsed())
+        });
         self.tasks.insert(entity, task);
         self.added_since_last_update.insert(entity);
     }
+
+    /// Waits for the task spawned for `entity` and returns its result rather
+    /// than inserting it, for results that can't wait for a later frame
+    pub fn finish(&mut self, entity: Entity) -> Option<T> {
+        let task = self.tasks.remove(&entity)?;
+        self.added_since_last_update.remove(&entity);
+        Some(block_on(task).0)
+    }
 }
 
 fn update_compute_in_progress_flags<T: Component>(
@@ -74,17 +110,32 @@ fn update_compute_in_progress_flags<T: Component>(
     }
 }
 
-fn recieve_compute_tasks<T: Component>(mut commands: Commands, mut tasks: ResMut<ComputeTasks<T>>) {
+fn recieve_compute_tasks<T: Component>(
+    mut commands: Commands,
+    mut tasks: ResMut<ComputeTasks<T>>,
+    mut stats: ResMut<ComputeTaskStats<T>>,
+) {
+    #[cfg(feature = "trace")]
+    let _span = info_span!(
+        "receive_compute_tasks",
+        component = std::any::type_name::<T>()
+    )
+    .entered();
+    let start = Instant::now();
     tasks.tasks.retain(|entity, task| {
-        let Some(result) = block_on(future::poll_once(task)) else {
+        let Some((result, task_time)) = block_on(future::poll_once(task)) else {
             return true;
         };
+        stats.completed += 1;
+        stats.total_task_time += task_time;
         commands
             .entity(*entity)
             .try_insert(result)
             .try_remove::<ComputeInProgress<T>>();
         return false;
     });
+    stats.in_flight = tasks.tasks.len();
+    stats.receive_time = start.elapsed();
 }
 
 fn kill_compute_task<T: Component>(
@@ -79,6 +79,11 @@ fn remove_from_index(
     index.position_by_entity.remove(&e);
 }
 
+/// Systems of every `NeighborhoodPlugin`, which keep neighbourhoods up to
+/// date with their chunks
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct NeighborhoodSystems;
+
 pub struct NeighborhoodPlugin<T: Component> {
     _phantom: PhantomData<T>,
 }
@@ -95,6 +100,7 @@ impl<T: Component + Clone> Plugin for NeighborhoodPlugin<T> {
     fn build(&self, app: &mut App) {
         app.add_event::<NeighborUpdateEvent<T>>()
             .add_event::<NewNeighborhood<T>>()
+            .add_event::<RefreshNeighborCopies<T>>()
             .add_systems(
                 Update,
                 (
@@ -107,11 +113,13 @@ impl<T: Component + Clone> Plugin for NeighborhoodPlugin<T> {
                     (
                         emit_update_event_with_changed_neighbor::<T>,
                         consume_neighbor_update_events::<T>,
+                        refresh_neighbor_copies::<T>,
                     )
                         .chain(),
                     assign_full_neighborhood::<T>,
                     revoke_full_neighborhood::<T>,
-                ),
+                )
+                    .in_set(NeighborhoodSystems),
             )
             .add_observer(notify_neighbors_on_delete::<T>);
     }
@@ -224,6 +232,8 @@ fn populate_neighborhood<T: Component + Clone>(
         entity, position, ..
     } in er.read()
     {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("populate_neighborhood", chunk = %position).entered();
         let mut neighborhood = Neighborhood::<T> {
             chunks: [const { None }; 27],
         };
@@ -283,6 +293,8 @@ fn consume_neighbor_update_events<T: Component + Clone>(
     mut q_neighborhood: Query<&mut Neighborhood<T>>,
 ) {
     for event in er.read() {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("update_neighborhoods", chunk = %event.pos.0).entered();
         let center = event.pos;
         let value = event.value.as_ref().map(|x| x.value.clone());
         for (x, y, z) in cube_iter(-1..=1) {
@@ -301,6 +313,63 @@ fn consume_neighbor_update_events<T: Component + Clone>(
     }
 }
 
+/// Copies the component of the chunk into its neighbours' neighbourhoods
+/// again without marking anything changed, for when the component was
+/// changed in a way that doesn't matter to them, like being stored
+/// differently. Otherwise neighbourhoods keep the old copy alive.
+#[derive(Event)]
+pub struct RefreshNeighborCopies<T: Component> {
+    pub entity: Entity,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Component> RefreshNeighborCopies<T> {
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+fn refresh_neighbor_copies<T: Component + Clone>(
+    mut er: EventReader<RefreshNeighborCopies<T>>,
+    chunk_index: Res<ChunkIndex>,
+    mut q_chunk: Query<(&T, &ChunkPosition, &mut ComponentCopy<T>)>,
+    mut q_neighborhood: Query<(
+        Option<&mut Neighborhood<T>>,
+        Option<&mut FullNeighborhood<T>>,
+    )>,
+) {
+    for event in er.read() {
+        let Ok((component, center, mut copy)) = q_chunk.get_mut(event.entity) else {
+            continue;
+        };
+        let value = Arc::new(component.clone());
+        copy.bypass_change_detection().value = value.clone();
+        for (x, y, z) in cube_iter(-1..=1) {
+            let offset = IVec3::new(x, y, z);
+            let Some(entity) = chunk_index.get_entity(&(center.0 + offset)) else {
+                continue;
+            };
+            let Ok((neighborhood, full_neighborhood)) = q_neighborhood.get_mut(*entity) else {
+                continue;
+            };
+            let neighborhood_chunk_pos = (offset * -1).to_array();
+            if let Some(mut neighborhood) = neighborhood {
+                neighborhood
+                    .bypass_change_detection()
+                    .put_chunk(&neighborhood_chunk_pos, Some(value.clone()));
+            }
+            if let Some(mut full_neighborhood) = full_neighborhood {
+                full_neighborhood
+                    .bypass_change_detection()
+                    .put_chunk(&neighborhood_chunk_pos, value.clone());
+            }
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct FullNeighborhood<T> {
     pub chunks: [Arc<T>; 27],
@@ -318,6 +387,14 @@ impl<T> FullNeighborhood<T> {
     pub fn get_middle(&self) -> &Arc<T> {
         return &self.get_chunk(&[1, 1, 1]);
     }
+
+    /// pos ∈ {-1, 0, 1}^3
+    pub fn put_chunk(&mut self, pos: &[i32; 3], value: Arc<T>) {
+        let [x, y, z] = pos;
+        // Coords of chunk within neighborhood
+        let index = (x + 1) + 3 * (y + 1) + 9 * (z + 1);
+        self.chunks[index as usize] = value;
+    }
 }
 
 impl<T> FullNeighborhood<T>
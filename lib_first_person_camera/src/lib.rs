@@ -4,7 +4,8 @@ use std::{
     marker::PhantomData,
 };
 
-/// Given a marker component, this plugin will make a marked entity move with the mouse like an FPS camera.
+/// Given a marker component, this plugin will make a marked entity look around with the mouse like an FPS camera.
+/// Moving it is left to whatever it's attached to.
 pub struct FirstPersonCameraPlugin<CameraMarker: Component> {
     _phantom: PhantomData<CameraMarker>,
 }
@@ -21,7 +22,6 @@ impl<CameraMarker: Component> Plugin for FirstPersonCameraPlugin<CameraMarker> {
     fn build(&self, app: &mut App) {
         app.init_resource::<CameraControls>()
             .init_resource::<CameraMouseSensitivity>()
-            .init_resource::<CameraSpeed>()
             .add_systems(
                 PreUpdate,
                 (
@@ -29,41 +29,22 @@ impl<CameraMarker: Component> Plugin for FirstPersonCameraPlugin<CameraMarker> {
                     (
                         update_pitch_yaw::<CameraMarker>,
                         align_camera_with_pitch_yaw,
-                        move_camera_from_keyboard_input::<CameraMarker>,
                     )
                         .chain(),
-                ),
+                )
+                    .in_set(FirstPersonCameraSystems),
             );
     }
 }
 
-#[derive(Resource)]
+/// Systems turning the camera with the mouse, which can be gated to stop it looking around
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FirstPersonCameraSystems;
+
+#[derive(Resource, Default)]
 pub struct CameraControls {
-    pub forward: KeyCode,
-    pub backward: KeyCode,
-    pub left: KeyCode,
-    pub right: KeyCode,
-    pub up: KeyCode,
-    pub down: KeyCode,
     pub mouse_x_inverted: bool,
     pub mouse_y_inverted: bool,
-    pub speed_up: KeyCode,
-}
-
-impl Default for CameraControls {
-    fn default() -> Self {
-        Self {
-            forward: KeyCode::KeyW,
-            backward: KeyCode::KeyS,
-            left: KeyCode::KeyA,
-            right: KeyCode::KeyD,
-            up: KeyCode::Space,
-            down: KeyCode::ShiftLeft,
-            mouse_x_inverted: false,
-            mouse_y_inverted: false,
-            speed_up: KeyCode::ControlLeft,
-        }
-    }
 }
 
 #[derive(Resource)]
@@ -78,15 +59,6 @@ impl Default for CameraMouseSensitivity {
     }
 }
 
-#[derive(Resource)]
-pub struct CameraSpeed(pub f32);
-
-impl Default for CameraSpeed {
-    fn default() -> Self {
-        Self(7.5)
-    }
-}
-
 #[derive(Component, Default)]
 struct CameraPitchYaw {
     pitch: f32,
@@ -147,42 +119,3 @@ fn align_camera_with_pitch_yaw(mut q_camera: Query<(&mut Transform, &CameraPitch
         };
     }
 }
-
-fn move_camera_from_keyboard_input<CameraMarker: Component>(
-    mut q_camera: Query<&mut Transform, With<CameraMarker>>,
-    keys: Res<ButtonInput<KeyCode>>,
-    controls: Res<CameraControls>,
-    speed: Res<CameraSpeed>,
-    time: Res<Time>,
-) {
-    for mut transform in q_camera.iter_mut() {
-        let mut d = Vec3::ZERO;
-        if keys.pressed(controls.left) {
-            d += transform.left().as_vec3();
-        }
-        if keys.pressed(controls.right) {
-            d += transform.right().as_vec3();
-        }
-        if keys.pressed(controls.forward) {
-            d += transform.forward().as_vec3().with_y(0.).normalize();
-        }
-        if keys.pressed(controls.backward) {
-            d += transform.back().as_vec3().with_y(0.).normalize();
-        }
-        if keys.pressed(controls.up) {
-            d += Vec3::Y;
-        }
-        if keys.pressed(controls.down) {
-            d += Vec3::NEG_Y;
-        }
-        if d != Vec3::ZERO {
-            d = d.normalize();
-        }
-        let factor = if keys.pressed(controls.speed_up) {
-            10.0
-        } else {
-            1.0
-        };
-        transform.translation += d * factor * speed.0 * time.delta_secs();
-    }
-}
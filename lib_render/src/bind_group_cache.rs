@@ -0,0 +1,115 @@
+use bevy::{
+    platform::collections::HashMap,
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindGroupLayoutId,
+            Buffer, BufferId, Sampler, SamplerId, TextureView, TextureViewId,
+        },
+        renderer::RenderDevice,
+    },
+};
+
+/// Bind groups not requested for this many frames are dropped by the cache,
+/// so it doesn't keep the resources of replaced textures and buffers alive
+const STALE_AFTER_FRAMES: u32 = 3;
+
+/// Resource bound at the binding of the same index as it
+#[derive(Clone, Copy)]
+pub(crate) enum Binding<'a> {
+    Buffer(&'a Buffer),
+    TextureView(&'a TextureView),
+    Sampler(&'a Sampler),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum BindingKey {
+    Buffer(BufferId),
+    TextureView(TextureViewId),
+    Sampler(SamplerId),
+}
+
+impl From<Binding<'_>> for BindingKey {
+    fn from(binding: Binding) -> Self {
+        match binding {
+            Binding::Buffer(buffer) => Self::Buffer(buffer.id()),
+            Binding::TextureView(view) => Self::TextureView(view.id()),
+            Binding::Sampler(sampler) => Self::Sampler(sampler.id()),
+        }
+    }
+}
+
+struct CachedBindGroup {
+    bind_group: BindGroup,
+    last_requested: u32,
+}
+
+/// Hands out the existing layout or bind group when an identical one is
+/// requested again, so rebuilding pipelines doesn't duplicate them
+#[derive(Resource, Default)]
+pub(crate) struct BindGroupCache {
+    layouts: HashMap<Vec<BindGroupLayoutEntry>, BindGroupLayout>,
+    bind_groups: HashMap<(BindGroupLayoutId, Vec<BindingKey>), CachedBindGroup>,
+    frame: u32,
+}
+
+impl BindGroupCache {
+    /// Layouts are never evicted, since only a handful of distinct ones exist
+    pub fn layout(
+        &mut self,
+        render_device: &RenderDevice,
+        label: &'static str,
+        entries: &[BindGroupLayoutEntry],
+    ) -> BindGroupLayout {
+        self.layouts
+            .entry(entries.to_vec())
+            .or_insert_with(|| render_device.create_bind_group_layout(Some(label), entries))
+            .clone()
+    }
+
+    pub fn bind_group(
+        &mut self,
+        render_device: &RenderDevice,
+        label: &'static str,
+        layout: &BindGroupLayout,
+        bindings: &[Binding],
+    ) -> BindGroup {
+        let key = (
+            layout.id(),
+            bindings.iter().copied().map(BindingKey::from).collect(),
+        );
+        let frame = self.frame;
+        let cached = self.bind_groups.entry(key).or_insert_with(|| {
+            let entries: Vec<_> = bindings
+                .iter()
+                .enumerate()
+                .map(|(i, binding)| BindGroupEntry {
+                    binding: i as u32,
+                    resource: match binding {
+                        Binding::Buffer(buffer) => buffer.as_entire_binding(),
+                        Binding::TextureView(view) => {
+                            bevy::render::render_resource::BindingResource::TextureView(view)
+                        }
+                        Binding::Sampler(sampler) => {
+                            bevy::render::render_resource::BindingResource::Sampler(sampler)
+                        }
+                    },
+                })
+                .collect();
+            CachedBindGroup {
+                bind_group: render_device.create_bind_group(Some(label), layout, &entries),
+                last_requested: frame,
+            }
+        });
+        cached.last_requested = frame;
+        cached.bind_group.clone()
+    }
+}
+
+pub(crate) fn evict_stale_bind_groups(mut cache: ResMut<BindGroupCache>) {
+    let frame = cache.frame;
+    cache
+        .bind_groups
+        .retain(|_, cached| frame - cached.last_requested < STALE_AFTER_FRAMES);
+    cache.frame += 1;
+}
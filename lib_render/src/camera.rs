@@ -1,4 +1,154 @@
-use bevy::prelude::*;
+use bevy::{
+    core_pipeline::core_3d::Camera3dDepthLoadOp,
+    math::Vec3A,
+    prelude::*,
+    render::{
+        camera::{CameraProjection, SubCameraView},
+        render_resource::TextureUsages,
+    },
+};
+
+use crate::globals::RenderFeatures;
 
 #[derive(Component)]
+#[require(ProjectionSettings)]
 pub struct RenderCamera;
+
+/// Projection of a `RenderCamera`. Changes are applied to the camera's
+/// `Projection` before Bevy updates its cameras.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ProjectionSettings {
+    /// Vertical field of view in radians
+    pub fov: f32,
+    pub near: f32,
+    /// Distance in blocks past which chunks are culled
+    pub far: f32,
+    /// Maps the far plane to infinity instead of `far`, which spreads depth
+    /// precision better at large render distances. Chunks are still culled
+    /// past `far`.
+    pub infinite_far: bool,
+}
+
+impl Default for ProjectionSettings {
+    fn default() -> Self {
+        Self {
+            fov: std::f32::consts::FRAC_PI_4,
+            near: 0.1,
+            far: 1000.0,
+            infinite_far: true,
+        }
+    }
+}
+
+/// Perspective projection with reversed Z, to match the `Greater` depth
+/// comparison of the terrain pipelines
+#[derive(Clone, Copy, Debug)]
+struct TerrainProjection {
+    settings: ProjectionSettings,
+    aspect_ratio: f32,
+}
+
+impl TerrainProjection {
+    fn clip_from_view(&self, aspect_ratio: f32) -> Mat4 {
+        let ProjectionSettings {
+            fov,
+            near,
+            far,
+            infinite_far,
+        } = self.settings;
+        if infinite_far {
+            Mat4::perspective_infinite_reverse_rh(fov, aspect_ratio, near)
+        } else {
+            // Swapping the planes maps `near` to depth 1 and `far` to depth 0
+            Mat4::perspective_rh(fov, aspect_ratio, far, near)
+        }
+    }
+}
+
+impl CameraProjection for TerrainProjection {
+    fn get_clip_from_view(&self) -> Mat4 {
+        self.clip_from_view(self.aspect_ratio)
+    }
+
+    fn get_clip_from_view_for_sub(&self, sub_view: &SubCameraView) -> Mat4 {
+        let full_size = sub_view.full_size.as_vec2();
+        let clip_from_view = self.clip_from_view(full_size.x / full_size.y);
+        // Scale and move the sub view's part of the clip space to fill it
+        let min = sub_view.offset / full_size * 2.0 - 1.0;
+        let max = (sub_view.offset + sub_view.size.as_vec2()) / full_size * 2.0 - 1.0;
+        let scale = 2.0 / (max - min);
+        // Pixel rows go down while clip space goes up
+        let centre = Vec2::new(min.x + max.x, -(min.y + max.y)) / 2.0;
+        let sub_from_full = Mat4::from_cols(
+            Vec4::new(scale.x, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, scale.y, 0.0, 0.0),
+            Vec4::Z,
+            (-centre * scale).extend(0.0).extend(1.0),
+        );
+        sub_from_full * clip_from_view
+    }
+
+    fn update(&mut self, width: f32, height: f32) {
+        self.aspect_ratio = width / height;
+    }
+
+    fn far(&self) -> f32 {
+        self.settings.far
+    }
+
+    fn get_frustum_corners(&self, z_near: f32, z_far: f32) -> [Vec3A; 8] {
+        let tan_half_fov = (self.settings.fov / 2.0).tan();
+        let near = z_near.abs() * tan_half_fov;
+        let far = z_far.abs() * tan_half_fov;
+        let aspect_ratio = self.aspect_ratio;
+        [
+            Vec3A::new(near * aspect_ratio, -near, z_near),
+            Vec3A::new(near * aspect_ratio, near, z_near),
+            Vec3A::new(-near * aspect_ratio, near, z_near),
+            Vec3A::new(-near * aspect_ratio, -near, z_near),
+            Vec3A::new(far * aspect_ratio, -far, z_far),
+            Vec3A::new(far * aspect_ratio, far, z_far),
+            Vec3A::new(-far * aspect_ratio, far, z_far),
+            Vec3A::new(-far * aspect_ratio, -far, z_far),
+        ]
+    }
+}
+
+pub(crate) fn apply_projection_settings(
+    mut q_camera: Query<(&ProjectionSettings, &mut Projection), Changed<ProjectionSettings>>,
+) {
+    for (settings, mut projection) in q_camera.iter_mut() {
+        // The aspect ratio is set by Bevy when it sees the projection changed
+        *projection = Projection::custom(TerrainProjection {
+            settings: *settings,
+            aspect_ratio: 1.0,
+        });
+    }
+}
+
+/// Lets Bevy's own 3D passes draw meshes over the terrain. They run after the
+/// terrain, loading its colour and the depth it copies into the view's depth
+/// texture instead of clearing them, so they need to multisample like the
+/// terrain passes.
+pub(crate) fn share_targets_with_bevy_passes(
+    mut q_camera: Query<(&mut Camera, &mut Camera3d, &mut Msaa), With<RenderCamera>>,
+    render_features: Res<RenderFeatures>,
+) {
+    let msaa = match render_features.msaa_samples {
+        samples @ (2 | 4 | 8) => Msaa::from_samples(samples),
+        _ => Msaa::Off,
+    };
+    for (mut camera, mut camera_3d, mut camera_msaa) in q_camera.iter_mut() {
+        if !matches!(camera.clear_color, ClearColorConfig::None) {
+            camera.clear_color = ClearColorConfig::None;
+        }
+        if !matches!(camera_3d.depth_load_op, Camera3dDepthLoadOp::Load) {
+            camera_3d.depth_load_op = Camera3dDepthLoadOp::Load;
+        }
+        let usages = TextureUsages::from_bits_truncate(camera_3d.depth_texture_usages.0);
+        if !usages.contains(TextureUsages::COPY_DST) {
+            camera_3d.depth_texture_usages = (usages | TextureUsages::COPY_DST).into();
+        }
+        camera_msaa.set_if_neq(msaa);
+    }
+}
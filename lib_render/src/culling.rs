@@ -0,0 +1,369 @@
+use bevy::{
+    math::{Affine3A, Vec3A},
+    platform::collections::HashSet,
+    prelude::*,
+    render::{
+        Extract,
+        primitives::{Aabb, Frustum},
+        render_resource::{
+            BindGroup, BindGroupLayout, BindGroupLayoutEntry, BindingType, Buffer,
+            BufferBindingType, BufferDescriptor, BufferUsages, ComputePipeline, DrawIndirectArgs,
+            ShaderStages,
+        },
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+
+use crate::{
+    InstanceBuffers, QuadBucket,
+    bind_group_cache::{BindGroupCache, Binding},
+    instance::RawInstance,
+    pipeline::QUAD_VERTEX_COUNT,
+    retry::PipelineRetry,
+    shader::ShaderSources,
+    stats::RenderStatsCollector,
+};
+
+pub(crate) const CHUNK_SIZE: i32 = 32;
+const WORKGROUP_SIZE: u32 = 64;
+/// Lowest limit on workgroups per dispatch dimension that WebGPU allows
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+const MIN_INSTANCE_CAPACITY: u32 = 1 << 12;
+
+/// Bounding box of every quad that can belong to the chunk at `chunk_pos`.
+///
+/// Quads are centred on their block position, so the box starts half a block
+/// before the chunk's first block.
+pub(crate) fn chunk_aabb(chunk_pos: IVec3) -> Aabb {
+    let min = (chunk_pos * CHUNK_SIZE).as_vec3() - 0.5;
+    let max = min + CHUNK_SIZE as f32;
+    Aabb::from_min_max(min, max)
+}
+
+/// The far plane is never tested: an infinite reversed-Z projection gives a
+/// degenerate far half-space, and Bevy's visibility already culls chunks past
+/// `ProjectionSettings::far`.
+pub(crate) fn is_chunk_visible(frustum: &Frustum, chunk_pos: IVec3) -> bool {
+    frustum.intersects_obb(&chunk_aabb(chunk_pos), &Affine3A::IDENTITY, true, false)
+}
+
+/// Chunks a view sees, according to Bevy's visibility system
+#[derive(Component, Default)]
+pub(crate) struct VisibleChunks(pub HashSet<IVec3>);
+
+/// Frusta of a single view, updated along with its globals
+#[derive(Component, Default)]
+pub(crate) struct ViewFrusta {
+    pub view: Frustum,
+    /// Orthographic frustum of the shadow map
+    pub shadow: Frustum,
+    /// Direction and length of the longest shadow that can be cast, so
+    /// casters behind or beside the view still shadow what it sees
+    pub shadow_extent: Vec3,
+}
+
+impl ViewFrusta {
+    /// Whether the chunk is inside the shadow map and its shadow can reach
+    /// `view`
+    pub fn is_shadow_caster_visible(&self, chunk_pos: IVec3) -> bool {
+        if !is_chunk_visible(&self.shadow, chunk_pos) {
+            return false;
+        }
+        let aabb = chunk_aabb(chunk_pos);
+        let (min, max) = (aabb.min(), aabb.max());
+        let shadow_extent = Vec3A::from(self.shadow_extent);
+        let swept = Aabb::from_min_max(
+            min.min(min + shadow_extent).into(),
+            max.max(max + shadow_extent).into(),
+        );
+        self.view
+            .intersects_obb(&swept, &Affine3A::IDENTITY, true, false)
+    }
+}
+
+// Keep in sync with `ChunkRange` in cull.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ChunkRange {
+    position: [i32; 3],
+    first_instance: u32,
+    num_instances: u32,
+    first_thread: u32,
+    _pad: [u32; 2],
+}
+
+// Keep in sync with `CullingParams` in cull.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullingParams {
+    planes: [[f32; 4]; 5],
+    camera_position: [f32; 3],
+    num_chunks: u32,
+    num_instances: u32,
+    _pad: [u32; 3],
+}
+
+#[derive(Resource)]
+pub(crate) struct GpuCullingPipeline {
+    pub pipeline: ComputePipeline,
+    layout: BindGroupLayout,
+}
+
+/// GPU-side inputs and outputs of a view's culling pass, which packs the
+/// opaque instances that survive culling into `instances` and
+/// `chunk_slots`, to be drawn with the indirect draw in `draw`
+#[derive(Component)]
+pub(crate) struct GpuCullingBuffers {
+    params: Buffer,
+    chunks: Buffer,
+    chunk_capacity: usize,
+    pub instances: Buffer,
+    pub chunk_slots: Buffer,
+    instance_capacity: u32,
+    pub draw: Buffer,
+    /// `None` until there are instances to cull
+    pub bind_group: Option<BindGroup>,
+    num_instances: u32,
+}
+
+impl GpuCullingBuffers {
+    /// Workgroups to dispatch along x and y, with one thread per instance
+    pub fn num_workgroups(&self) -> UVec2 {
+        let num_workgroups = self.num_instances.div_ceil(WORKGROUP_SIZE);
+        let x = num_workgroups.min(MAX_WORKGROUPS_PER_DIMENSION);
+        UVec2::new(x, num_workgroups.div_ceil(x.max(1)))
+    }
+}
+
+pub(crate) fn init_culling_pipeline(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    shader_sources: Extract<Res<ShaderSources>>,
+    // Separate from the other pipelines' retries, as this is built on its own
+    mut retry: Local<PipelineRetry>,
+) {
+    if !shader_sources.is_loaded() || !retry.is_ready() {
+        return;
+    }
+    let storage_entry = |binding, read_only| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let layout = render_device.create_bind_group_layout(
+        Some("culling bind group layout"),
+        &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage_entry(1, true),
+            storage_entry(2, true),
+            storage_entry(3, true),
+            storage_entry(4, false),
+            storage_entry(5, false),
+            storage_entry(6, false),
+        ],
+    );
+
+    let shader = match shader_sources.create_module(&render_device, "cull.wgsl", &[]) {
+        Ok(shader) => shader,
+        Err(e) => return retry.failed("culling pipeline", &e),
+    };
+
+    let pipeline_layout = render_device.create_pipeline_layout(
+        &bevy::render::render_resource::PipelineLayoutDescriptor {
+            label: Some("culling pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        },
+    );
+
+    let pipeline = render_device.create_compute_pipeline(
+        &bevy::render::render_resource::RawComputePipelineDescriptor {
+            label: Some("culling pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cull_instances"),
+            compilation_options: default(),
+            cache: None,
+        },
+    );
+
+    retry.succeeded();
+    commands.insert_resource(GpuCullingPipeline { pipeline, layout });
+}
+
+/// Uploads the opaque instance ranges of the chunks `view` sees and its
+/// frustum for this frame's culling pass, growing the buffers if they don't
+/// fit.
+pub(crate) fn prepare_gpu_culling(
+    world: &mut World,
+    view: Entity,
+    frustum: &Frustum,
+    camera_position: Vec3,
+) {
+    let Some(layout) = world
+        .get_resource::<GpuCullingPipeline>()
+        .map(|culling_pipeline| culling_pipeline.layout.clone())
+    else {
+        return;
+    };
+    let Some(VisibleChunks(visible_chunks)) = world.get::<VisibleChunks>(view) else {
+        return;
+    };
+    // The culling pass still tests the frustum, but only for the chunks Bevy
+    // found visible
+    let mut num_instances = 0;
+    let chunks: Vec<_> = world
+        .resource::<InstanceBuffers>()
+        .iter()
+        .filter(|(pos, _)| visible_chunks.contains(*pos))
+        .filter_map(|(pos, instances)| {
+            let opaque = instances.bucket(QuadBucket::Opaque);
+            if opaque.is_empty() {
+                return None;
+            }
+            let chunk = ChunkRange {
+                position: pos.to_array(),
+                first_instance: opaque.start,
+                num_instances: opaque.end - opaque.start,
+                first_thread: num_instances,
+                _pad: [0; 2],
+            };
+            num_instances += chunk.num_instances;
+            Some(chunk)
+        })
+        .collect();
+
+    let needs_resize = world.get::<GpuCullingBuffers>(view).is_none_or(|buffers| {
+        buffers.chunk_capacity < chunks.len() || buffers.instance_capacity < num_instances
+    });
+    if needs_resize {
+        let buffers = create_culling_buffers(
+            world.resource::<RenderDevice>(),
+            chunks.len().next_power_of_two().max(64),
+            num_instances.next_power_of_two().max(MIN_INSTANCE_CAPACITY),
+        );
+        world.entity_mut(view).insert(buffers);
+    }
+
+    let mut params = CullingParams {
+        camera_position: camera_position.to_array(),
+        num_chunks: chunks.len() as _,
+        num_instances,
+        ..default()
+    };
+    for (plane, half_space) in params.planes.iter_mut().zip(frustum.half_spaces.iter()) {
+        *plane = half_space.normal_d().to_array();
+    }
+
+    // The instance buffers are replaced when they grow, so the bind group is
+    // looked up again every frame
+    let bind_group = world.resource_scope(|world, mut cache: Mut<BindGroupCache>| {
+        let instance_buffers = world.resource::<InstanceBuffers>();
+        let (Some(instances), Some(chunk_slots)) = (
+            instance_buffers.buffer(),
+            instance_buffers.chunk_slot_buffer(),
+        ) else {
+            return None;
+        };
+        let buffers = world.get::<GpuCullingBuffers>(view)?;
+        Some(cache.bind_group(
+            world.resource::<RenderDevice>(),
+            "culling bind group",
+            &layout,
+            &[
+                Binding::Buffer(&buffers.params),
+                Binding::Buffer(&buffers.chunks),
+                Binding::Buffer(instances),
+                Binding::Buffer(chunk_slots),
+                Binding::Buffer(&buffers.instances),
+                Binding::Buffer(&buffers.chunk_slots),
+                Binding::Buffer(&buffers.draw),
+            ],
+        ))
+    });
+
+    let render_queue = world.resource::<RenderQueue>().clone();
+    let Some(mut buffers) = world.get_mut::<GpuCullingBuffers>(view) else {
+        return;
+    };
+    let params_bytes = bytemuck::bytes_of(&params);
+    let chunks_bytes = bytemuck::cast_slice(&chunks);
+    let draw = DrawIndirectArgs {
+        vertex_count: QUAD_VERTEX_COUNT,
+        instance_count: 0,
+        first_vertex: 0,
+        first_instance: 0,
+    };
+    render_queue.write_buffer(&buffers.params, 0, params_bytes);
+    if !chunks.is_empty() {
+        render_queue.write_buffer(&buffers.chunks, 0, chunks_bytes);
+    }
+    render_queue.write_buffer(&buffers.draw, 0, draw.as_bytes());
+    buffers.bind_group = bind_group;
+    buffers.num_instances = num_instances;
+    world
+        .resource::<RenderStatsCollector>()
+        .record_upload(params_bytes.len() + chunks_bytes.len() + draw.as_bytes().len());
+}
+
+fn create_culling_buffers(
+    render_device: &RenderDevice,
+    chunk_capacity: usize,
+    instance_capacity: u32,
+) -> GpuCullingBuffers {
+    let params = render_device.create_buffer(&BufferDescriptor {
+        label: Some("culling params buffer"),
+        size: std::mem::size_of::<CullingParams>() as u64,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let chunks = render_device.create_buffer(&BufferDescriptor {
+        label: Some("culling chunks buffer"),
+        size: (chunk_capacity * std::mem::size_of::<ChunkRange>()) as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let instances = render_device.create_buffer(&BufferDescriptor {
+        label: Some("compacted instance buffer"),
+        size: instance_capacity as u64 * std::mem::size_of::<RawInstance>() as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::VERTEX,
+        mapped_at_creation: false,
+    });
+    let chunk_slots = render_device.create_buffer(&BufferDescriptor {
+        label: Some("compacted chunk slot buffer"),
+        size: instance_capacity as u64 * std::mem::size_of::<u32>() as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::VERTEX,
+        mapped_at_creation: false,
+    });
+    let draw = render_device.create_buffer(&BufferDescriptor {
+        label: Some("culling indirect draw buffer"),
+        size: std::mem::size_of::<DrawIndirectArgs>() as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    GpuCullingBuffers {
+        params,
+        chunks,
+        chunk_capacity,
+        instances,
+        chunk_slots,
+        instance_capacity,
+        draw,
+        bind_group: None,
+        num_instances: 0,
+    }
+}
@@ -0,0 +1,227 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroupLayout, Buffer, BufferInitDescriptor, BufferUsages, RenderPipeline,
+            TextureFormat, VertexAttribute, VertexFormat,
+        },
+        renderer::RenderDevice,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{culling::chunk_aabb, post_process, shader::ShaderSources};
+
+/// Lines drawn over the world for debugging. Cleared at the start of every
+/// frame, so systems add the lines they want shown each frame.
+#[derive(Resource, Clone, Default)]
+pub struct DebugLines {
+    vertices: Vec<DebugLineVertex>,
+}
+
+impl DebugLines {
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Color) {
+        let color = color.to_linear().to_f32_array();
+        self.vertices.push(DebugLineVertex {
+            position: start.to_array(),
+            color,
+        });
+        self.vertices.push(DebugLineVertex {
+            position: end.to_array(),
+            color,
+        });
+    }
+
+    /// Edges of the box with corners `corners`, ordered by the bits of their
+    /// index: bit 0 picks x, bit 1 picks y and bit 2 picks z
+    pub fn cuboid(&mut self, corners: [Vec3; 8], color: Color) {
+        for (a, b) in CUBOID_EDGES {
+            self.line(corners[a], corners[b], color);
+        }
+    }
+
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: Color) {
+        self.cuboid(
+            std::array::from_fn(|i| {
+                Vec3::new(
+                    if i & 1 == 0 { min.x } else { max.x },
+                    if i & 2 == 0 { min.y } else { max.y },
+                    if i & 4 == 0 { min.z } else { max.z },
+                )
+            }),
+            color,
+        );
+    }
+
+    /// Bounds of the chunk at `chunk_pos`, matching the bounds used for
+    /// culling
+    pub fn chunk(&mut self, chunk_pos: IVec3, color: Color) {
+        let aabb = chunk_aabb(chunk_pos);
+        self.aabb(aabb.min().into(), aabb.max().into(), color);
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub(crate) fn vertices(&self) -> &[DebugLineVertex] {
+        &self.vertices
+    }
+}
+
+const CUBOID_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// Debug overlays drawn by the renderer itself
+#[derive(Resource, Clone, Copy, Default)]
+pub struct DebugOverlaySettings {
+    /// Outline the volume covered by the shadow map
+    pub shadow_frustum: bool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct DebugLineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+#[derive(Resource)]
+pub(crate) struct DebugLinePipeline {
+    pub pipeline: RenderPipeline,
+}
+
+/// Vertices of this frame's debug lines
+#[derive(Resource)]
+pub(crate) struct DebugLineBuffer {
+    pub buffer: Buffer,
+    pub vertex_count: u32,
+}
+
+pub(crate) fn clear_debug_lines(mut debug_lines: ResMut<DebugLines>) {
+    debug_lines.clear();
+}
+
+pub(crate) fn create_line_buffer(
+    render_device: &RenderDevice,
+    label: &'static str,
+    vertices: &[DebugLineVertex],
+) -> DebugLineBuffer {
+    let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(vertices),
+        usage: BufferUsages::VERTEX,
+    });
+    DebugLineBuffer {
+        buffer,
+        vertex_count: vertices.len() as u32,
+    }
+}
+
+pub(crate) fn create_debug_line_pipeline(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+    globals_layout: &BindGroupLayout,
+    depth_format: TextureFormat,
+    sample_count: u32,
+) -> Result<DebugLinePipeline, String> {
+    Ok(DebugLinePipeline {
+        pipeline: create_line_pipeline(
+            render_device,
+            shader_sources,
+            globals_layout,
+            depth_format,
+            sample_count,
+            "debug line pipeline",
+            "vs_line",
+        )?,
+    })
+}
+
+/// Pipeline drawing a list of `DebugLineVertex` lines with the vertex stage
+/// `vertex_entry_point` of debug_lines.wgsl
+pub(crate) fn create_line_pipeline(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+    globals_layout: &BindGroupLayout,
+    depth_format: TextureFormat,
+    sample_count: u32,
+    label: &'static str,
+    vertex_entry_point: &'static str,
+) -> Result<RenderPipeline, String> {
+    let shader = shader_sources.create_module(render_device, "debug_lines.wgsl", &[])?;
+    let layout = render_device.create_pipeline_layout(
+        &bevy::render::render_resource::PipelineLayoutDescriptor {
+            label: Some("debug line pipeline layout"),
+            bind_group_layouts: &[globals_layout],
+            push_constant_ranges: &[],
+        },
+    );
+    Ok(render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &shader,
+                entry_point: Some(vertex_entry_point),
+                buffers: &[bevy::render::render_resource::RawVertexBufferLayout {
+                    array_stride: std::mem::size_of::<DebugLineVertex>() as _,
+                    step_mode: bevy::render::render_resource::VertexStepMode::Vertex,
+                    attributes: &[
+                        VertexAttribute {
+                            format: VertexFormat::Float32x3,
+                            offset: 0,
+                            shader_location: 0,
+                        },
+                        VertexAttribute {
+                            format: VertexFormat::Float32x4,
+                            offset: std::mem::size_of::<[f32; 3]>() as _,
+                            shader_location: 1,
+                        },
+                    ],
+                }],
+                compilation_options: default(),
+            },
+            fragment: Some(bevy::render::render_resource::RawFragmentState {
+                module: &shader,
+                entry_point: Some("fs_line"),
+                targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                    format: post_process::HDR_TEXTURE_FORMAT,
+                    blend: Some(bevy::render::render_resource::BlendState::ALPHA_BLENDING),
+                    write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                })],
+                compilation_options: default(),
+            }),
+            primitive: bevy::render::render_resource::PrimitiveState {
+                topology: bevy::render::mesh::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            // Hidden by terrain, but never hides anything itself
+            depth_stencil: Some(bevy::render::render_resource::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: bevy::render::render_resource::CompareFunction::GreaterEqual,
+                stencil: bevy::render::render_resource::StencilState::default(),
+                bias: bevy::render::render_resource::DepthBiasState::default(),
+            }),
+            multisample: bevy::render::render_resource::MultisampleState {
+                count: sample_count,
+                ..default()
+            },
+            multiview: None,
+            cache: None,
+        },
+    ))
+}
@@ -21,6 +21,73 @@ pub struct GlobalsData {
     pub fog_b: f32,
     // _pad_6: [f32; 3],
     pub shadow_map_projection: [[f32; 4]; 4],
+    pub shadow_depth_bias: f32,
+    pub shadow_normal_offset: f32,
+    pub shadow_pcf_radius: u32,
+    /// 0 when SSAO is disabled
+    pub ssao_sample_count: u32,
+    pub inverse_projection_matrix: [[f32; 4]; 4],
+    pub ssao_radius: f32,
+    pub ssao_intensity: f32,
+    pub ssao_bias: f32,
+    _pad_7: [f32; 1],
+    pub sky_color: [f32; 3],
+    pub star_visibility: f32,
+    pub moon_direction: [f32; 3],
+    pub cloud_altitude: f32,
+    pub cloud_wind: [f32; 2],
+    pub cloud_coverage: f32,
+    pub cloud_density: f32,
+    pub cloud_scale: f32,
+    pub fog_height_falloff: f32,
+    pub fog_base_height: f32,
+    pub camera_in_fluid: u32,
+    pub underwater_fog_color: [f32; 3],
+    pub underwater_fog_b: f32,
+}
+
+/// Most point lights that can light a view at once. The ones closest to the
+/// camera are used.
+pub(crate) const MAX_POINT_LIGHTS: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Default)]
+pub(crate) struct PointLightData {
+    pub position: [f32; 3],
+    /// Distance at which the light has faded out completely, in blocks
+    pub radius: f32,
+    pub color: [f32; 3],
+    _pad: f32,
+}
+
+impl PointLightData {
+    pub fn new(position: Vec3, radius: f32, color: LinearRgba) -> Self {
+        Self {
+            position: position.to_array(),
+            radius,
+            color: color.to_f32_array_no_alpha(),
+            _pad: 0.0,
+        }
+    }
+}
+
+// Keep in sync with `PointLights` in lighting.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct PointLightsData {
+    pub count: u32,
+    _pad: [u32; 3],
+    pub lights: [PointLightData; MAX_POINT_LIGHTS],
+}
+
+impl PointLightsData {
+    pub fn new(lights: &[PointLightData]) -> Self {
+        let mut data: Self = bytemuck::Zeroable::zeroed();
+        let count = lights.len().min(MAX_POINT_LIGHTS);
+        data.count = count as u32;
+        data.lights[..count].copy_from_slice(&lights[..count]);
+        data
+    }
 }
 
 #[derive(Resource)]
@@ -32,12 +99,6 @@ impl Default for StartupTime {
     }
 }
 
-#[derive(Resource, Default)]
-pub struct CameraData {
-    pub position: Vec3,
-    pub projection_matrix: Mat4,
-}
-
 #[derive(Resource, Clone, Copy)]
 pub struct AmbientLight(pub Color);
 
@@ -53,8 +114,257 @@ pub struct DirectionalLight {
     pub direction: Dir3,
 }
 
+/// Colour the main pass is cleared to
+#[derive(Resource, Clone, Copy)]
+pub struct SkyColor(pub Color);
+
+impl Default for SkyColor {
+    fn default() -> Self {
+        Self(crate::SKY_COLOR)
+    }
+}
+
+/// Stars and moon drawn over the sky colour
+#[derive(Resource, Clone, Copy)]
+pub struct NightSky {
+    /// Direction from the world towards the moon
+    pub moon_direction: Dir3,
+    /// 0.0 hides the stars and moon, 1.0 shows them fully
+    pub star_visibility: f32,
+}
+
+impl Default for NightSky {
+    fn default() -> Self {
+        Self {
+            moon_direction: Dir3::Y,
+            star_visibility: 0.0,
+        }
+    }
+}
+
 #[derive(Resource, Clone, Copy)]
 pub struct FogSettings {
     pub color: Color,
+    /// Fog density at `base_height`
     pub b: f32,
+    /// How quickly fog thins out above `base_height`. 0 gives uniform fog.
+    pub height_falloff: f32,
+    pub base_height: f32,
+    /// Replaces the regular fog while the camera is inside a fluid
+    pub underwater: UnderwaterFog,
+}
+
+#[derive(Clone, Copy)]
+pub struct UnderwaterFog {
+    pub color: Color,
+    pub b: f32,
+}
+
+impl Default for UnderwaterFog {
+    fn default() -> Self {
+        Self {
+            color: Color::srgb(0.05, 0.2, 0.35),
+            b: 0.1,
+        }
+    }
+}
+
+/// Whether the camera is inside a fluid block, which switches to the
+/// underwater fog
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CameraInFluid(pub bool);
+
+/// Optional parts of the renderer. Each combination gets its own pipelines,
+/// built the first time it is used, so disabled features cost nothing.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderFeatures {
+    pub shadows: bool,
+    pub fog: bool,
+    /// Screen space ambient occlusion. Still needs `DepthPrepass` and
+    /// `SsaoSettings::enabled`.
+    pub ambient_occlusion: bool,
+    /// Samples per pixel of the terrain passes. 1 disables MSAA. The depth
+    /// prepass and SSAO are skipped while MSAA is enabled, since they need a
+    /// single sampled depth buffer.
+    pub msaa_samples: u32,
+    /// Blends transparent quads with weighted blended order independent
+    /// transparency instead of sorting chunks back to front, so overlapping
+    /// water doesn't pop as chunks swap order. Sorted blending is still used
+    /// while MSAA is enabled.
+    pub order_independent_transparency: bool,
+}
+
+impl Default for RenderFeatures {
+    fn default() -> Self {
+        Self {
+            shadows: true,
+            fog: true,
+            ambient_occlusion: true,
+            msaa_samples: 1,
+            order_independent_transparency: false,
+        }
+    }
+}
+
+impl RenderFeatures {
+    /// Defs of the terrain shader enabling each feature
+    pub(crate) fn shader_defs(&self) -> Vec<&'static str> {
+        [
+            (self.shadows, "SHADOWS"),
+            (self.fog, "FOG"),
+            (self.ambient_occlusion, "SSAO"),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, def)| def)
+        .collect()
+    }
+}
+
+/// Limits how much changed chunk data is uploaded to the GPU each frame.
+/// Chunks over the limit are uploaded on later frames, keeping their old
+/// quads until then.
+#[derive(Resource, Clone, Copy)]
+pub struct InstanceUploadSettings {
+    /// At least one chunk is uploaded each frame, however big it is
+    pub max_bytes_per_frame: usize,
+}
+
+impl Default for InstanceUploadSettings {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_frame: 8 << 20,
+        }
+    }
+}
+
+/// Renders the depth of opaque quads before the main pass, so that the main
+/// pass only shades the closest fragment of each pixel
+#[derive(Resource, Clone, Copy, Default)]
+pub struct DepthPrepass(pub bool);
+
+#[derive(Resource, Clone, Copy)]
+pub struct ShadowSettings {
+    /// Shadows are filtered over a square of `2 * pcf_radius + 1` texels per
+    /// side. 0 gives hard shadows.
+    pub pcf_radius: u32,
+    /// Constant depth bias applied to the receiver when sampling the shadow map
+    pub depth_bias: f32,
+    /// Distance in blocks the receiver is moved along its normal before
+    /// sampling the shadow map
+    pub normal_offset: f32,
+    /// Slope-scaled depth bias used when rendering the shadow map. Only read
+    /// when the pipeline is created.
+    pub slope_scale_bias: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            pcf_radius: 1,
+            depth_bias: 1e-3,
+            normal_offset: 0.05,
+            slope_scale_bias: 2.0,
+        }
+    }
+}
+
+/// Screen space ambient occlusion, which darkens ambient light in caves and
+/// under overhangs. Only applied while the depth prepass is enabled.
+#[derive(Resource, Clone, Copy)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    /// Samples taken per pixel. More samples give less noisy occlusion.
+    pub sample_count: u32,
+    /// Radius in blocks of the hemisphere sampled around each pixel
+    pub radius: f32,
+    /// How much fully occluded pixels darken the ambient light, from 0 to 1
+    pub intensity: f32,
+    /// Distance in blocks a sample must be behind the depth buffer to count
+    /// as occluded
+    pub bias: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sample_count: 16,
+            radius: 1.5,
+            intensity: 1.0,
+            bias: 0.05,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Copy)]
+pub struct BloomSettings {
+    /// Only colour brighter than this in any channel blooms
+    pub threshold: f32,
+    /// Strength of the bloom added onto the image. 0 disables bloom.
+    pub intensity: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TonemappingOperator {
+    /// Clamps each channel to 1
+    None,
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    #[default]
+    Aces,
+}
+
+/// Maps the HDR image to the view target after bloom
+#[derive(Resource, Clone, Copy)]
+pub struct TonemappingSettings {
+    pub operator: TonemappingOperator,
+    /// Exposure adjustment in stops, applied before tonemapping
+    pub exposure: f32,
+}
+
+impl Default for TonemappingSettings {
+    fn default() -> Self {
+        Self {
+            operator: TonemappingOperator::default(),
+            exposure: 0.0,
+        }
+    }
+}
+
+/// A flat layer of clouds drifting across the sky, which also shades the
+/// terrain below it from the directional light
+#[derive(Resource, Clone, Copy)]
+pub struct CloudSettings {
+    /// Height of the cloud layer in blocks
+    pub altitude: f32,
+    /// Fraction of the sky covered by clouds, from 0 to 1
+    pub coverage: f32,
+    /// Opacity of the thickest clouds, from 0 to 1. 0 disables clouds.
+    pub density: f32,
+    /// Cloud movement in blocks per second
+    pub wind: Vec2,
+    /// Size of the cloud pattern. Smaller values give bigger clouds.
+    pub scale: f32,
+}
+
+impl Default for CloudSettings {
+    fn default() -> Self {
+        Self {
+            altitude: 160.0,
+            coverage: 0.4,
+            density: 0.8,
+            wind: Vec2::new(2.0, 1.0),
+            scale: 0.005,
+        }
+    }
 }
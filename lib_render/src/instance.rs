@@ -4,10 +4,21 @@ pub struct Instance {
     pub texture_index: u32,
     pub normal: crate::Normal,
     pub local_pos: [u8; 3],
+    /// Width and height of the quad in blocks (1-32)
+    pub size: [u8; 2],
     /// Column-wise, starting with top right
     pub ambient_occlusion: [u8; 4],
+    pub shape: crate::QuadShape,
+    /// Brightness of light given off by the quad (0-15)
+    pub emission: u8,
+    /// Roughness of the surface (0-15), where 15 has no specular highlight
+    pub roughness: u8,
+    pub ripples: bool,
+    /// Multiplied by the chunk's tint
+    pub tinted: bool,
 }
 
+#[cfg(not(feature = "float_instances"))]
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct RawInstance {
@@ -17,10 +28,45 @@ pub struct RawInstance {
     /// - 10-14: Local z (5 bits, 0-31)
     /// - 15-26: Ambient occlusion factors (3 bits each, 4 values, 0-4)
     /// - 27-29: Normal
+    /// - 30-31: Shape
     data: u32,
     material_index: u32,
 }
 
+/// Unpacked instance, enabled by the `float_instances` feature to rule out
+/// the packing while debugging. Keep in sync with `load_quad` in cull.wgsl.
+#[cfg(feature = "float_instances")]
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RawInstance {
+    local_pos: [f32; 3],
+    normal: u32,
+    size: [f32; 2],
+    ambient_occlusion: [f32; 4],
+    material_index: u32,
+    shape: u32,
+}
+
+/// Bits:
+/// - 0-11: Texture index
+/// - 12-15: Roughness (4 bits, 0-15)
+/// - 16-20: Width - 1 (5 bits, 0-31)
+/// - 21-25: Height - 1 (5 bits, 0-31)
+/// - 26-29: Emission (4 bits, 0-15)
+/// - 30: Ripples
+/// - 31: Tinted
+fn pack_material_index(instance: &Instance) -> u32 {
+    let [width, height] = instance.size.map(|x| x as u32 - 1);
+    (instance.texture_index & 0xFFF)
+        | ((instance.roughness as u32 & 0xF) << 12)
+        | (width << 16)
+        | (height << 21)
+        | ((instance.emission as u32 & 0xF) << 26)
+        | ((instance.ripples as u32) << 30)
+        | ((instance.tinted as u32) << 31)
+}
+
+#[cfg(not(feature = "float_instances"))]
 impl From<Instance> for RawInstance {
     fn from(value: Instance) -> Self {
         let [a0, a1, a2, a3] = value.ambient_occlusion.map(|x| x as u32);
@@ -30,25 +76,79 @@ impl From<Instance> for RawInstance {
                 | ((value.local_pos[1] as u32) << 5)
                 | ((value.local_pos[2] as u32) << 10)
                 | (ambient_occlusions << 15)
-                | ((value.normal as u32) << 27),
-            material_index: value.texture_index,
+                | ((value.normal as u32) << 27)
+                | ((value.shape as u32) << 30),
+            material_index: pack_material_index(&value),
+        }
+    }
+}
+
+#[cfg(feature = "float_instances")]
+impl From<Instance> for RawInstance {
+    fn from(value: Instance) -> Self {
+        Self {
+            local_pos: value.local_pos.map(|x| x as f32),
+            normal: value.normal as u32,
+            size: value.size.map(|x| x as f32),
+            ambient_occlusion: value.ambient_occlusion.map(|x| x as f32),
+            material_index: pack_material_index(&value),
+            shape: value.shape as u32,
         }
     }
 }
 
 impl RawInstance {
-    pub fn desc() -> [VertexAttribute; 2] {
-        [
+    #[cfg(not(feature = "float_instances"))]
+    pub fn desc() -> Vec<VertexAttribute> {
+        vec![
             VertexAttribute {
                 format: VertexFormat::Uint32,
                 offset: 0,
-                shader_location: 4,
+                shader_location: 0,
             },
             VertexAttribute {
                 format: VertexFormat::Uint32,
-                offset: std::mem::size_of::<u32>() as _,
-                shader_location: 5,
+                offset: std::mem::offset_of!(Self, material_index) as _,
+                shader_location: 1,
             },
         ]
     }
+
+    #[cfg(feature = "float_instances")]
+    pub fn desc() -> Vec<VertexAttribute> {
+        let attribute = |format, offset: usize, shader_location| VertexAttribute {
+            format,
+            offset: offset as _,
+            shader_location,
+        };
+        vec![
+            attribute(
+                VertexFormat::Float32x3,
+                std::mem::offset_of!(Self, local_pos),
+                0,
+            ),
+            attribute(
+                VertexFormat::Uint32,
+                std::mem::offset_of!(Self, material_index),
+                1,
+            ),
+            attribute(VertexFormat::Uint32, std::mem::offset_of!(Self, normal), 3),
+            attribute(VertexFormat::Float32x2, std::mem::offset_of!(Self, size), 4),
+            attribute(
+                VertexFormat::Float32x4,
+                std::mem::offset_of!(Self, ambient_occlusion),
+                5,
+            ),
+            attribute(VertexFormat::Uint32, std::mem::offset_of!(Self, shape), 6),
+        ]
+    }
+
+    /// Chunk slot of the instance, stored in a separate instance-rate buffer
+    pub fn chunk_slot_desc() -> [VertexAttribute; 1] {
+        [VertexAttribute {
+            format: VertexFormat::Uint32,
+            offset: 0,
+            shader_location: 2,
+        }]
+    }
 }
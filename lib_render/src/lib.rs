@@ -1,11 +1,22 @@
-use std::{marker::PhantomData, num::NonZero, ops::Deref};
+use std::{
+    any::TypeId,
+    collections::VecDeque,
+    marker::PhantomData,
+    num::NonZero,
+    ops::{Deref, Range},
+    time::Instant,
+};
 
 use bevy::{
     platform::collections::HashMap,
     prelude::*,
     render::{
-        Extract, camera::CameraProjection, render_graph::RenderGraphApp,
-        render_resource::BufferUsages,
+        Extract,
+        render_graph::RenderGraphApp,
+        render_resource::{Buffer, BufferDescriptor, BufferUsages},
+        renderer::{RenderDevice, RenderQueue},
+        sync_world::RenderEntity,
+        view::{self, VisibilityClass, VisibleEntities},
     },
 };
 use strum::IntoEnumIterator;
@@ -15,13 +26,25 @@ use crate::{
     render_node::{MyRenderNode, MyRenderNodeLabel},
 };
 
+mod bind_group_cache;
 pub mod camera;
+mod culling;
+pub mod debug_lines;
 pub mod globals;
 mod instance;
+pub mod offscreen;
+mod oit;
+pub mod outline;
+pub mod particles;
 pub mod pipeline;
+mod post_process;
+mod range_allocator;
 mod render_node;
+mod retry;
+mod shader;
+mod ssao;
+pub mod stats;
 pub mod texture;
-mod vertex;
 
 const SKY_COLOR: Color = Color::linear_rgba(0.1, 0.2, 0.4, 1.0);
 
@@ -42,74 +65,143 @@ impl<TerrainType: 'static + Send + Sync + texture::TextureIndex + IntoEnumIterat
 {
     fn build(&self, app: &mut App) {
         let render_app = app
+            .init_resource::<globals::ShadowSettings>()
+            .init_resource::<globals::DepthPrepass>()
+            .init_resource::<globals::SsaoSettings>()
+            .init_resource::<globals::BloomSettings>()
+            .init_resource::<globals::TonemappingSettings>()
+            .init_resource::<globals::CloudSettings>()
+            .init_resource::<globals::CameraInFluid>()
+            .init_resource::<globals::RenderFeatures>()
+            .init_resource::<globals::InstanceUploadSettings>()
+            .init_resource::<debug_lines::DebugLines>()
+            .init_resource::<debug_lines::DebugOverlaySettings>()
+            .init_resource::<outline::BlockOutline>()
+            .init_resource::<particles::Particles>()
+            .add_systems(
+                First,
+                (debug_lines::clear_debug_lines, particles::clear_particles),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    camera::apply_projection_settings
+                        .before(bevy::render::camera::CameraUpdateSystem),
+                    camera::share_targets_with_bevy_passes,
+                ),
+            )
+            .add_observer(insert_terrain_aabb)
             .add_observer(emit_quads_despawn_event)
             .add_event::<TerrainDespawnEvent>()
             .add_plugins((
-                vertex::VertexPlugin,
                 texture::TexturePlugin::<TerrainType>::new(),
+                shader::ShaderPlugin,
+                stats::RenderStatsPlugin,
             ))
             .sub_app_mut(bevy::render::RenderApp)
             .init_resource::<globals::StartupTime>()
-            .init_resource::<globals::CameraData>()
             .init_resource::<InstanceBuffers>()
+            .init_resource::<PendingInstanceUploads>()
+            .init_resource::<retry::PipelineRetry>()
+            .init_resource::<bind_group_cache::BindGroupCache>()
+            .add_systems(
+                bevy::render::Render,
+                (
+                    prepare_instance_buffers.in_set(bevy::render::RenderSet::PrepareResources),
+                    bind_group_cache::evict_stale_bind_groups
+                        .in_set(bevy::render::RenderSet::Cleanup),
+                ),
+            )
             .add_systems(
                 ExtractSchedule,
                 (
                     // prepare_texture_bind_group,
-                    pipeline::init_pipeline
-                        .run_if(not(resource_exists::<pipeline::MyRenderPipeline>)),
                     (
+                        pipeline::init_pipeline
+                            .run_if(not(resource_exists::<pipeline::SpecializedPipelines>)),
+                        pipeline::specialize_pipelines
+                            .run_if(resource_exists::<pipeline::SpecializedPipelines>),
+                    )
+                        .chain(),
+                    culling::init_culling_pipeline
+                        .run_if(not(resource_exists::<culling::GpuCullingPipeline>)),
+                    (
+                        pipeline::init_chunk_offsets_buffer
+                            .run_if(not(resource_exists::<pipeline::ChunkOffsetsBuffer>)),
                         remove_buffer_for_despawned_terrain,
-                        update_instance_buffer::<TerrainType>,
+                        extract_changed_quads::<TerrainType>,
                     )
                         .chain(),
                     pipeline::resize_depth_texture,
-                    update_camera_data,
-                    extract_resource_to_render_world::<globals::AmbientLight>,
-                    extract_resource_to_render_world::<globals::DirectionalLight>,
-                    extract_resource_to_render_world::<globals::FogSettings>,
+                    extract_visible_chunks,
+                    (
+                        extract_resource_to_render_world::<globals::AmbientLight>,
+                        extract_resource_to_render_world::<globals::DirectionalLight>,
+                        extract_resource_to_render_world::<globals::FogSettings>,
+                        extract_resource_to_render_world::<globals::CameraInFluid>,
+                        extract_resource_to_render_world::<globals::SkyColor>,
+                        extract_resource_to_render_world::<globals::NightSky>,
+                        extract_resource_to_render_world::<globals::CloudSettings>,
+                        extract_resource_to_render_world::<globals::ShadowSettings>,
+                        extract_resource_to_render_world::<globals::DepthPrepass>,
+                        extract_resource_to_render_world::<globals::SsaoSettings>,
+                        extract_resource_to_render_world::<globals::BloomSettings>,
+                        extract_resource_to_render_world::<globals::TonemappingSettings>,
+                        extract_resource_to_render_world::<globals::InstanceUploadSettings>,
+                    ),
+                    extract_resource_to_render_world::<debug_lines::DebugLines>,
+                    extract_resource_to_render_world::<debug_lines::DebugOverlaySettings>,
+                    extract_resource_to_render_world::<outline::BlockOutline>,
+                    extract_resource_to_render_world::<particles::Particles>,
                 ),
             );
 
-        // add our node (use ViewNodeRunner to run a ViewNode) to the Core2d graph,
-        // and insert an ordering edge so our node runs before the UI subgraph.
+        // The terrain is drawn before Bevy's main passes, which draw any
+        // meshes over it using the depth it leaves in the view's depth
+        // texture
         render_app
             .add_render_graph_node::<bevy::render::render_graph::ViewNodeRunner<MyRenderNode>>(
                 bevy::core_pipeline::core_3d::graph::Core3d,
                 MyRenderNodeLabel,
             )
-            // (Node2d::EndMainPassPostProcessing, MyCustomPassLabel, SubGraphUi)
-            // means: EndMainPassPostProcessing -> MyCustomPass -> SubGraphUi
             .add_render_graph_edges(
                 bevy::core_pipeline::core_3d::graph::Core3d,
                 (
-                    bevy::core_pipeline::core_3d::graph::Node3d::EndMainPassPostProcessing,
+                    bevy::core_pipeline::core_3d::graph::Node3d::EndPrepasses,
                     MyRenderNodeLabel,
-                    bevy::ui::graph::NodeUi::UiPass,
+                    bevy::core_pipeline::core_3d::graph::Node3d::StartMainPass,
                 ),
+            )
+            // With MSAA, Bevy copies the terrain into its multisampled
+            // texture before its passes load it
+            .add_render_graph_edge(
+                bevy::core_pipeline::core_3d::graph::Core3d,
+                MyRenderNodeLabel,
+                bevy::core_pipeline::core_3d::graph::Node3d::MsaaWriteback,
             );
     }
 }
 
-fn update_camera_data(
-    mut camera_data: ResMut<globals::CameraData>,
-    camera_query: bevy::render::Extract<Query<(&GlobalTransform, &Projection), With<RenderCamera>>>,
+/// Every active `RenderCamera` is its own view, drawing the chunks that
+/// Bevy's visibility system found in its frustum
+fn extract_visible_chunks(
+    mut commands: Commands,
+    camera_query: Extract<Query<(RenderEntity, &Camera, &VisibleEntities), With<RenderCamera>>>,
+    q_chunk_position: Extract<Query<&TerrainPosition>>,
 ) {
-    let (camera_transform, projection) = match camera_query.single() {
-        Ok(items) => items,
-        Err(bevy::ecs::query::QuerySingleError::NoEntities(_)) => {
-            warn!("Couldn't find a rendering camera :(");
-            return;
-        }
-        Err(bevy::ecs::query::QuerySingleError::MultipleEntities(_)) => {
-            warn!("Multiple render cameras????");
-            return;
+    for (render_entity, camera, visible_entities) in camera_query.iter() {
+        let mut entity = commands.entity(render_entity);
+        if !camera.is_active {
+            entity.remove::<culling::VisibleChunks>();
+            continue;
         }
-    };
-    let projection_matrix =
-        projection.get_clip_from_view() * camera_transform.compute_matrix().inverse();
-    camera_data.projection_matrix = projection_matrix;
-    camera_data.position = camera_transform.translation();
+        let visible_chunks = visible_entities
+            .iter(TypeId::of::<TerrainPosition>())
+            .filter_map(|chunk| q_chunk_position.get(*chunk).ok())
+            .map(|TerrainPosition(pos)| *pos)
+            .collect();
+        entity.insert(culling::VisibleChunks(visible_chunks));
+    }
 }
 
 fn extract_resource_to_render_world<T: Resource + Clone>(
@@ -126,9 +218,31 @@ fn extract_resource_to_render_world<T: Resource + Clone>(
     }
 }
 
+/// Chunk whose quads are drawn by the terrain renderer. Its bounds take part
+/// in Bevy's visibility checks like any other renderable entity.
 #[derive(Component, Clone, Copy, Debug)]
+#[require(Transform, Visibility, VisibilityClass)]
+#[component(on_add = view::add_visibility_class::<TerrainPosition>)]
 pub struct TerrainPosition(pub IVec3);
 
+/// Colour the tinted terrain of a chunk is multiplied by, e.g. to vary grass
+/// across biomes. Chunks without one are left untinted. Only read when the
+/// chunk's quads change.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TerrainTint(pub Color);
+
+fn insert_terrain_aabb(
+    trigger: Trigger<OnAdd, TerrainPosition>,
+    mut commands: Commands,
+    q_chunk_position: Query<&TerrainPosition>,
+) {
+    let entity = trigger.target();
+    let Ok(TerrainPosition(pos)) = q_chunk_position.get(entity) else {
+        return;
+    };
+    commands.entity(entity).insert(culling::chunk_aabb(*pos));
+}
+
 #[derive(Event)]
 pub(crate) struct TerrainDespawnEvent(TerrainPosition);
 
@@ -144,60 +258,336 @@ fn emit_quads_despawn_event(
     ew.write(TerrainDespawnEvent(*pos));
 }
 
-pub(crate) struct InstanceBuffer {
-    buffer: bevy::render::render_resource::Buffer,
-    num_instances: u32,
+pub(crate) struct ChunkInstances {
+    pub range: Range<u32>,
+    /// Index of the chunk's offset in `ChunkOffsetsBuffer`
+    pub slot: u32,
+    /// Sub-ranges of `range` holding each bucket's instances, indexed by
+    /// `QuadBucket`
+    pub bucket_ranges: [Range<u32>; QuadBucket::ALL.len()],
+    /// One for each emissive block, at the block's centre
+    pub lights: Vec<globals::PointLightData>,
 }
 
+impl ChunkInstances {
+    pub fn bucket(&self, bucket: QuadBucket) -> Range<u32> {
+        self.bucket_ranges[bucket as usize].clone()
+    }
+}
+
+/// All chunk instances live in one vertex buffer, with each chunk owning a
+/// contiguous range of it. This lets every chunk be drawn from the same
+/// binding.
 #[derive(Resource, Default)]
 pub(crate) struct InstanceBuffers {
-    chunk_pos_to_buffer: HashMap<IVec3, InstanceBuffer>,
+    buffer: Option<Buffer>,
+    /// Chunk slot of every instance, parallel to `buffer`. This lets a single
+    /// draw cover instances from many chunks.
+    chunk_slot_buffer: Option<Buffer>,
+    allocator: range_allocator::RangeAllocator,
+    slot_allocator: range_allocator::RangeAllocator,
+    chunk_pos_to_instances: HashMap<IVec3, ChunkInstances>,
+}
+
+impl InstanceBuffers {
+    pub fn buffer(&self) -> Option<&Buffer> {
+        self.buffer.as_ref()
+    }
+
+    pub fn chunk_slot_buffer(&self) -> Option<&Buffer> {
+        self.chunk_slot_buffer.as_ref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&IVec3, &ChunkInstances)> {
+        self.chunk_pos_to_instances.iter()
+    }
+
+    /// Bytes allocated for both buffers
+    fn size_bytes(&self) -> u64 {
+        self.allocator.size() as u64
+            * (std::mem::size_of::<instance::RawInstance>() + std::mem::size_of::<u32>()) as u64
+    }
+
+    fn release(&mut self, pos: &IVec3) {
+        if let Some(ChunkInstances { range, slot, .. }) = self.chunk_pos_to_instances.remove(pos) {
+            self.allocator.free(range);
+            self.slot_allocator.free(slot..slot + 1);
+        }
+    }
+
+    /// `None` once `pipeline::MAX_CHUNKS` chunks have instances, as the chunk
+    /// offsets buffer has no slot left for another
+    fn allocate(
+        &mut self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        num_instances: u32,
+    ) -> Option<ChunkInstances> {
+        if self.slot_allocator.size() == 0 {
+            self.slot_allocator.grow(pipeline::MAX_CHUNKS);
+        }
+        let slot = self.slot_allocator.allocate(1)?.start;
+        if let Some(range) = self.allocator.allocate(num_instances) {
+            return Some(ChunkInstances {
+                range,
+                slot,
+                bucket_ranges: default(),
+                lights: vec![],
+            });
+        }
+        let size = (self.allocator.size() + num_instances)
+            .next_power_of_two()
+            .max(MIN_INSTANCE_BUFFER_SIZE);
+        self.grow(render_device, render_queue, size);
+        let range = self
+            .allocator
+            .allocate(num_instances)
+            .expect("Instance buffer was grown to fit");
+        Some(ChunkInstances {
+            range,
+            slot,
+            bucket_ranges: default(),
+            lights: vec![],
+        })
+    }
+
+    /// Replaces the buffers with bigger ones, copying over the existing
+    /// instances so that already allocated ranges stay valid.
+    fn grow(&mut self, render_device: &RenderDevice, render_queue: &RenderQueue, size: u32) {
+        let old_size = self.allocator.size();
+        self.buffer = Some(grow_buffer(
+            render_device,
+            render_queue,
+            "Instance buffer",
+            self.buffer.as_ref(),
+            std::mem::size_of::<instance::RawInstance>() as u64,
+            old_size,
+            size,
+        ));
+        self.chunk_slot_buffer = Some(grow_buffer(
+            render_device,
+            render_queue,
+            "Instance chunk slot buffer",
+            self.chunk_slot_buffer.as_ref(),
+            std::mem::size_of::<u32>() as u64,
+            old_size,
+            size,
+        ));
+        self.allocator.grow(size);
+    }
+}
+
+const MIN_INSTANCE_BUFFER_SIZE: u32 = 1 << 16;
+
+fn grow_buffer(
+    render_device: &RenderDevice,
+    render_queue: &RenderQueue,
+    label: &'static str,
+    old_buffer: Option<&Buffer>,
+    item_size: u64,
+    old_len: u32,
+    new_len: u32,
+) -> Buffer {
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size: new_len as u64 * item_size,
+        // Also read by the culling pass
+        usage: BufferUsages::VERTEX
+            | BufferUsages::STORAGE
+            | BufferUsages::COPY_DST
+            | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    if let Some(old_buffer) = old_buffer {
+        let mut encoder = render_device.create_command_encoder(
+            &bevy::render::render_resource::CommandEncoderDescriptor {
+                label: Some("Instance buffer resize"),
+            },
+        );
+        encoder.copy_buffer_to_buffer(old_buffer, 0, &buffer, 0, old_len as u64 * item_size);
+        render_queue.submit([encoder.finish()]);
+    }
+    buffer
+}
+
+/// Quads of a chunk copied out of the main world, waiting to be packed and
+/// uploaded by `prepare_instance_buffers`
+struct ExtractedChunkQuads {
+    pos: IVec3,
+    /// `TerrainTint` packed as RGBA8
+    tint: u32,
+    instances: Vec<(QuadBucket, instance::Instance)>,
+    /// One for each emissive block, at the block's centre
+    lights: Vec<globals::PointLightData>,
+}
+
+impl ExtractedChunkQuads {
+    fn upload_size(&self) -> usize {
+        self.instances.len()
+            * (std::mem::size_of::<instance::RawInstance>() + std::mem::size_of::<u32>())
+    }
 }
 
+/// Chunks whose quads changed, oldest first. A chunk keeps drawing its old
+/// instances until its new ones are uploaded.
+#[derive(Resource, Default)]
+pub(crate) struct PendingInstanceUploads(VecDeque<ExtractedChunkQuads>);
+
 fn remove_buffer_for_despawned_terrain(
     mut er: bevy::render::Extract<EventReader<TerrainDespawnEvent>>,
     mut instance_buffers: ResMut<InstanceBuffers>,
+    mut pending: ResMut<PendingInstanceUploads>,
 ) {
     for TerrainDespawnEvent(TerrainPosition(pos)) in er.read() {
-        instance_buffers.chunk_pos_to_buffer.remove(pos);
+        instance_buffers.release(pos);
+        pending.0.retain(|chunk| chunk.pos != *pos);
     }
 }
 
-#[derive(Component)]
-struct Buffered;
+/// Only copies out what changed, leaving the packing and uploading to
+/// `prepare_instance_buffers` so the extract stays short
+fn extract_changed_quads<TerrainType: Send + Sync + texture::TextureIndex>(
+    mut pending: ResMut<PendingInstanceUploads>,
+    q_quads: Extract<
+        Query<
+            (&Quads<TerrainType>, &TerrainPosition, Option<&TerrainTint>),
+            Changed<Quads<TerrainType>>,
+        >,
+    >,
+    indices: Extract<Res<texture::TerrainColorTextureIndices>>,
+    stats: Res<stats::RenderStatsCollector>,
+) {
+    let start = Instant::now();
+    for (quads, TerrainPosition(pos), tint) in q_quads.iter() {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("extract_chunk_quads", chunk = %pos).entered();
+        let instances = quads
+            .0
+            .iter()
+            .map(|quad| (quad.ty.bucket(), create_instance(quad, indices.as_ref())))
+            .collect();
+        let chunk_origin = pos * culling::CHUNK_SIZE;
+        let mut lights_by_block = HashMap::new();
+        for quad in quads.0.iter().filter(|quad| quad.ty.emission() > 0) {
+            lights_by_block.entry(quad.pos).or_insert_with(|| {
+                globals::PointLightData::new(
+                    (chunk_origin + quad.pos).as_vec3(),
+                    quad.ty.emission() as f32,
+                    quad.ty.light_color().to_linear(),
+                )
+            });
+        }
+        // Only the latest quads of a chunk are worth uploading
+        pending.0.retain(|chunk| chunk.pos != *pos);
+        pending.0.push_back(ExtractedChunkQuads {
+            pos: *pos,
+            tint: tint
+                .map_or(LinearRgba::WHITE, |tint| tint.0.to_linear())
+                .as_u32(),
+            instances,
+            lights: lights_by_block.into_values().collect(),
+        });
+    }
+    stats.record_extract_prepare_time(start.elapsed());
+}
 
-fn update_instance_buffer<TerrainType: Send + Sync + texture::TextureIndex>(
-    render_device: Res<bevy::render::renderer::RenderDevice>,
+fn prepare_instance_buffers(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
     mut instance_buffers: ResMut<InstanceBuffers>,
-    q_quads: Extract<Query<(&Quads<TerrainType>, &TerrainPosition), Changed<Quads<TerrainType>>>>,
-    indices: Extract<Res<texture::TerrainColorTextureIndices>>,
+    mut pending: ResMut<PendingInstanceUploads>,
+    chunk_offsets: Option<Res<pipeline::ChunkOffsetsBuffer>>,
+    upload_settings: Option<Res<globals::InstanceUploadSettings>>,
+    stats: Res<stats::RenderStatsCollector>,
 ) {
-    for (quads, chunk_position) in q_quads.iter() {
-        if quads.0.is_empty() {
+    let Some(chunk_offsets) = chunk_offsets else {
+        return;
+    };
+    let max_bytes_per_frame = upload_settings
+        .as_deref()
+        .copied()
+        .unwrap_or_default()
+        .max_bytes_per_frame;
+    let start = Instant::now();
+    let mut uploaded_bytes = 0;
+    while let Some(mut chunk) = pending.0.pop_front() {
+        // Always make progress, even on a chunk bigger than the budget
+        if uploaded_bytes > 0 && uploaded_bytes + chunk.upload_size() > max_bytes_per_frame {
+            pending.0.push_front(chunk);
+            break;
+        }
+        #[cfg(feature = "trace")]
+        let _span = info_span!("upload_chunk_instances", chunk = %chunk.pos).entered();
+        uploaded_bytes += chunk.upload_size();
+        instance_buffers.release(&chunk.pos);
+        if chunk.instances.is_empty() {
             continue;
         }
-        let instances_raw = quads
-            .0
-            .iter()
-            .map(|quad| create_instance(quad, indices.as_ref()))
-            .map(instance::RawInstance::from)
+        // Group the instances by bucket so each bucket can be drawn as one
+        // contiguous range
+        chunk.instances.sort_by_key(|(bucket, _)| *bucket);
+        let Some(mut instances) =
+            instance_buffers.allocate(&render_device, &render_queue, chunk.instances.len() as u32)
+        else {
+            warn_once!(
+                "More than {} chunks have quads. Only that many are drawn.",
+                pipeline::MAX_CHUNKS
+            );
+            continue;
+        };
+        let mut bucket_start = instances.range.start;
+        for bucket in QuadBucket::ALL {
+            let count = chunk
+                .instances
+                .iter()
+                .filter(|(quad_bucket, _)| *quad_bucket == bucket)
+                .count() as u32;
+            instances.bucket_ranges[bucket as usize] = bucket_start..bucket_start + count;
+            bucket_start += count;
+        }
+        instances.lights = chunk.lights;
+        let instances_raw = chunk
+            .instances
+            .into_iter()
+            .map(|(_, instance)| instance::RawInstance::from(instance))
             .collect::<Vec<_>>();
-        let num_instances = instances_raw.len() as u32;
-        let buffer = render_device.create_buffer_with_data(
-            &bevy::render::render_resource::BufferInitDescriptor {
-                label: Some("Instance buffer"),
-                contents: bytemuck::cast_slice(instances_raw.as_slice()),
-                usage: BufferUsages::VERTEX,
-            },
-        );
-        let item = InstanceBuffer {
-            buffer,
-            num_instances,
+        let (Some(buffer), Some(chunk_slot_buffer)) = (
+            instance_buffers.buffer(),
+            instance_buffers.chunk_slot_buffer(),
+        ) else {
+            continue;
         };
+        let start = instances.range.start as u64;
+        let instance_bytes = bytemuck::cast_slice(instances_raw.as_slice());
+        render_queue.write_buffer(
+            buffer,
+            start * std::mem::size_of::<instance::RawInstance>() as u64,
+            instance_bytes,
+        );
+        let chunk_slots = vec![instances.slot; instances_raw.len()];
+        let chunk_slot_bytes = bytemuck::cast_slice(&chunk_slots);
+        render_queue.write_buffer(
+            chunk_slot_buffer,
+            start * std::mem::size_of::<u32>() as u64,
+            chunk_slot_bytes,
+        );
+        // The tint rides along in the otherwise unused w
+        let chunk_offset = chunk.pos.extend(chunk.tint as i32).to_array();
+        let chunk_offset_bytes = bytemuck::bytes_of(&chunk_offset);
+        render_queue.write_buffer(
+            &chunk_offsets.buffer,
+            instances.slot as u64 * std::mem::size_of::<[i32; 4]>() as u64,
+            chunk_offset_bytes,
+        );
+        stats.record_upload(
+            instance_bytes.len() + chunk_slot_bytes.len() + chunk_offset_bytes.len(),
+        );
         instance_buffers
-            .chunk_pos_to_buffer
-            .insert(chunk_position.0, item);
+            .chunk_pos_to_instances
+            .insert(chunk.pos, instances);
     }
+    stats.record_instance_buffer_bytes(instance_buffers.size_bytes());
+    stats.record_extract_prepare_time(start.elapsed());
 }
 
 fn create_instance<TerrainType: texture::TextureIndex>(
@@ -207,25 +597,82 @@ fn create_instance<TerrainType: texture::TextureIndex>(
     instance::Instance {
         normal: quad.normal,
         local_pos: quad.pos.to_array().map(|x| x as _),
-        texture_index: *indices.get_index(&quad.ty).expect("Terrain texture index") as _,
+        size: [quad.width, quad.height].map(|x| x.get() as _),
+        // Missing textures are reported when the texture folder is scanned
+        texture_index: indices.get_index(&quad.ty).copied().unwrap_or_default() as _,
         ambient_occlusion: quad.ambient_occlusion,
+        shape: quad.shape,
+        emission: quad.ty.emission(),
+        roughness: (quad.ty.roughness().clamp(0.0, 1.0) * 15.0).round() as _,
+        ripples: quad.ty.ripples(),
+        tinted: quad.ty.tinted(),
     }
 }
 
+/// Which pass a quad is drawn in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QuadBucket {
+    #[default]
+    Opaque,
+    /// Alpha tested. Drawn with depth writes like opaque quads, but fragments
+    /// below the alpha cutoff are discarded.
+    Cutout,
+    /// Alpha blended, drawn after the opaque pass without writing depth and
+    /// sorted back to front per chunk
+    Transparent,
+}
+
+impl QuadBucket {
+    pub const ALL: [Self; 3] = [Self::Opaque, Self::Cutout, Self::Transparent];
+}
+
 #[derive(Component)]
 pub struct Quads<TerrainType>(pub Vec<Quad<TerrainType>>);
 
 pub struct Quad<TerrainType> {
     pub ty: TerrainType,
     pub normal: Normal,
+    /// Extent along the first perpendicular axis of the face, in blocks
     pub width: NonZero<u32>,
+    /// Extent along the second perpendicular axis of the face, in blocks
     pub height: NonZero<u32>,
+    /// Position of the block the quad starts from. It covers `width` blocks
+    /// from there along the first of the face's axes and `height` along the
+    /// second, which are, by normal:
+    /// - `PosX`: -z, -y
+    /// - `NegX`: +z, -y
+    /// - `PosY`: -z, +x
+    /// - `NegY`: -z, -x
+    /// - `PosZ`: +x, -y
+    /// - `NegZ`: -x, -y
     pub pos: IVec3,
     /// Column-wise, starting with top right
     pub ambient_occlusion: [u8; 4],
+    pub shape: QuadShape,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Where a quad lies within its block, for blocks that aren't full cubes.
+/// Quads of any shape but `Full` cover a single block, so they should have a
+/// width and height of 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub enum QuadShape {
+    /// On the face of the block its normal points out of
+    #[default]
+    Full,
+    /// Squashed into the lower half of the block, like a face of a slab. The
+    /// top face ends up in the middle of the block, and side faces show the
+    /// lower half of their texture.
+    BottomHalf,
+    /// Squashed into the upper half of the block, like `BottomHalf`
+    TopHalf,
+    /// Turned onto one of the block's vertical diagonals, like half of a
+    /// flower. ±X quads lie from -x +z to +x -z, and ±Z quads from -x -z to
+    /// +x +z, so one of each makes a cross. Not for ±Y quads.
+    Diagonal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub enum Normal {
     PosX,
@@ -247,4 +694,17 @@ impl Normal {
             Self::NegZ => IVec3::NEG_Z,
         }
     }
+
+    /// Normal pointing along `direction`, if it's a unit axis
+    pub fn from_unit_direction(direction: IVec3) -> Option<Self> {
+        match direction.to_array() {
+            [1, 0, 0] => Some(Self::PosX),
+            [-1, 0, 0] => Some(Self::NegX),
+            [0, 1, 0] => Some(Self::PosY),
+            [0, -1, 0] => Some(Self::NegY),
+            [0, 0, 1] => Some(Self::PosZ),
+            [0, 0, -1] => Some(Self::NegZ),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,181 @@
+//! Rendering without a window, for comparing the terrain renderer's output
+//! against reference images
+
+use std::path::Path;
+
+use bevy::{
+    app::PluginGroupBuilder,
+    asset::RenderAssetUsages,
+    image::{CompressedImageFormats, ImageSampler, ImageType},
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        gpu_readback::{Readback, ReadbackComplete},
+        render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
+    },
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+
+/// Bytes per row of a texture copy are padded to a multiple of this
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// Default plugins without a window or event loop. Drive the app with
+/// `App::update`.
+pub fn headless_plugins() -> PluginGroupBuilder {
+    DefaultPlugins
+        .set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            ..default()
+        })
+        .disable::<WinitPlugin>()
+}
+
+/// Creates an image for a `RenderCamera` to render into instead of a window
+pub fn create_render_target(images: &mut Assets<Image>, size: UVec2) -> Handle<Image> {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT;
+    images.add(image)
+}
+
+/// A camera rendering into `image`, to be spawned along with a
+/// `RenderCamera`
+pub fn offscreen_camera(image: Handle<Image>) -> Camera {
+    Camera {
+        target: RenderTarget::Image(image.into()),
+        ..default()
+    }
+}
+
+/// Latest pixels of an offscreen target, read back from the GPU every frame
+/// while this component exists
+#[derive(Component)]
+pub struct CapturedFrame {
+    pub size: UVec2,
+    /// Tightly packed RGBA8 rows, or `None` until the first readback lands
+    pub pixels: Option<Vec<u8>>,
+}
+
+/// Starts reading back `image` every frame into a `CapturedFrame` on the
+/// returned entity
+pub fn capture_frames(commands: &mut Commands, image: Handle<Image>, size: UVec2) -> Entity {
+    commands
+        .spawn((
+            Readback::texture(image),
+            CapturedFrame { size, pixels: None },
+        ))
+        .observe(store_captured_frame)
+        .id()
+}
+
+fn store_captured_frame(
+    trigger: Trigger<ReadbackComplete>,
+    mut q_frame: Query<&mut CapturedFrame>,
+) {
+    let Ok(mut frame) = q_frame.get_mut(trigger.target()) else {
+        return;
+    };
+    let pixels = remove_row_padding(&trigger.event().0, frame.size);
+    frame.pixels = Some(pixels);
+}
+
+fn remove_row_padding(data: &[u8], size: UVec2) -> Vec<u8> {
+    let row_bytes = size.x as usize * 4;
+    let padded_row_bytes = (size.x * 4).next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT) as usize;
+    if data.len() == row_bytes * size.y as usize {
+        return data.to_vec();
+    }
+    data.chunks(padded_row_bytes)
+        .take(size.y as usize)
+        .flat_map(|row| &row[..row_bytes])
+        .copied()
+        .collect()
+}
+
+/// Compares `frame` with the PNG at `reference`, returning the fraction of
+/// pixels where any channel differs by more than `tolerance`.
+///
+/// A missing reference is created from `frame`. When they differ, `frame` is
+/// saved next to the reference with an `actual.png` extension for
+/// inspection.
+pub fn compare_with_reference(
+    frame: &CapturedFrame,
+    reference: &Path,
+    tolerance: u8,
+) -> Result<f32, String> {
+    let pixels = frame
+        .pixels
+        .as_ref()
+        .ok_or("No frame has been captured yet")?;
+    if !reference.exists() {
+        save_png(pixels, frame.size, reference)?;
+        warn!("Created missing reference image {reference:?}");
+        return Ok(0.0);
+    }
+    let bytes = std::fs::read(reference).map_err(|e| format!("{reference:?}: {e}"))?;
+    let expected = Image::from_buffer(
+        &bytes,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::default(),
+    )
+    .map_err(|e| format!("{reference:?}: {e}"))?
+    .convert(TextureFormat::Rgba8UnormSrgb)
+    .ok_or_else(|| format!("{reference:?}: can't be converted to RGBA8"))?;
+    if expected.size() != frame.size {
+        return Err(format!(
+            "{reference:?} is {}, but the frame is {}",
+            expected.size(),
+            frame.size
+        ));
+    }
+    let expected_pixels = expected.data.unwrap_or_default();
+    let num_different = pixels
+        .chunks(4)
+        .zip(expected_pixels.chunks(4))
+        .filter(|(actual, expected)| {
+            actual
+                .iter()
+                .zip(expected.iter())
+                .any(|(a, b)| a.abs_diff(*b) > tolerance)
+        })
+        .count();
+    if num_different > 0 {
+        save_png(pixels, frame.size, &reference.with_extension("actual.png"))?;
+    }
+    Ok(num_different as f32 / (frame.size.x * frame.size.y) as f32)
+}
+
+fn save_png(pixels: &[u8], size: UVec2, path: &Path) -> Result<(), String> {
+    let image = Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        pixels.to_vec(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image
+        .try_into_dynamic()
+        .map_err(|e| format!("{path:?}: {e}"))?
+        .to_rgba8()
+        .save(path)
+        .map_err(|e| format!("{path:?}: {e}"))
+}
@@ -0,0 +1,223 @@
+//! Weighted blended order independent transparency (McGuire and Bavoil,
+//! 2013). Transparent quads are accumulated in any order into two targets,
+//! then composited over the HDR texture in a single fullscreen pass.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource,
+            BindingType, BlendComponent, BlendFactor, BlendOperation, BlendState, LoadOp,
+            Operations, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+            ShaderStages, StoreOp, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+            TextureViewDimension,
+        },
+        renderer::{RenderContext, RenderDevice},
+    },
+};
+
+use crate::{post_process, shader::ShaderSources};
+
+/// Sum of the weighted, premultiplied colours (rgb) and weighted alphas (a)
+pub(crate) const ACCUMULATION_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Product of `1 - alpha` over every fragment, i.e. how much of the opaque
+/// scene shows through
+pub(crate) const REVEALAGE_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+/// Blend states of the accumulation pipeline's two targets
+pub(crate) const ACCUMULATION_BLEND: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Add,
+    },
+};
+pub(crate) const REVEALAGE_BLEND: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::Zero,
+        dst_factor: BlendFactor::OneMinusSrc,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::Zero,
+        dst_factor: BlendFactor::OneMinusSrc,
+        operation: BlendOperation::Add,
+    },
+};
+
+/// Composites the accumulated transparency over the HDR texture
+#[derive(Resource)]
+pub(crate) struct OitPipeline {
+    pub composite: RenderPipeline,
+    layout: BindGroupLayout,
+}
+
+/// Screen sized targets of the accumulation pass, recreated whenever the
+/// main pass depth texture is
+#[derive(Resource)]
+pub(crate) struct OitTextures {
+    pub accumulation: TextureView,
+    pub revealage: TextureView,
+    /// Binds both targets for the composite pass
+    pub bind_group: BindGroup,
+}
+
+pub(crate) fn create_oit_pipeline(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+) -> Result<OitPipeline, String> {
+    let texture_entry = |binding| BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: false },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    let layout = render_device.create_bind_group_layout(
+        Some("oit composite bind group layout"),
+        &[texture_entry(0), texture_entry(1)],
+    );
+
+    let shader = shader_sources.create_module(render_device, "oit.wgsl", &[])?;
+    let pipeline_layout = render_device.create_pipeline_layout(
+        &bevy::render::render_resource::PipelineLayoutDescriptor {
+            label: Some("oit composite pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        },
+    );
+    let composite = render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some("oit composite pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &shader,
+                entry_point: Some("vs_fullscreen"),
+                buffers: &[],
+                compilation_options: default(),
+            },
+            fragment: Some(bevy::render::render_resource::RawFragmentState {
+                module: &shader,
+                entry_point: Some("fs_oit_composite"),
+                targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                    format: post_process::HDR_TEXTURE_FORMAT,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                })],
+                compilation_options: default(),
+            }),
+            primitive: default(),
+            depth_stencil: None,
+            multisample: default(),
+            multiview: None,
+            cache: None,
+        },
+    );
+
+    Ok(OitPipeline { composite, layout })
+}
+
+pub(crate) fn create_oit_textures(
+    render_device: &RenderDevice,
+    oit_pipeline: &OitPipeline,
+    size: UVec2,
+) -> OitTextures {
+    let create_target = |label, format| {
+        render_device
+            .create_texture(&bevy::render::render_resource::TextureDescriptor {
+                label: Some(label),
+                size: bevy::render::render_resource::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: bevy::render::render_resource::TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&bevy::render::render_resource::TextureViewDescriptor::default())
+    };
+    let accumulation = create_target("oit accumulation texture", ACCUMULATION_FORMAT);
+    let revealage = create_target("oit revealage texture", REVEALAGE_FORMAT);
+
+    let bind_group = render_device.create_bind_group(
+        Some("oit composite bind group"),
+        &oit_pipeline.layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&accumulation),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(&revealage),
+            },
+        ],
+    );
+
+    OitTextures {
+        accumulation,
+        revealage,
+        bind_group,
+    }
+}
+
+/// Attachments of the accumulation pass, cleared to nothing accumulated and
+/// everything revealed
+pub(crate) fn accumulation_attachments(
+    textures: &OitTextures,
+) -> [Option<RenderPassColorAttachment<'_>>; 2] {
+    let attachment = |view, clear: LinearRgba| {
+        Some(RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(clear.into()),
+                store: StoreOp::Store,
+            },
+        })
+    };
+    [
+        attachment(&textures.accumulation, LinearRgba::NONE),
+        attachment(&textures.revealage, LinearRgba::WHITE),
+    ]
+}
+
+/// Blends the accumulated transparency over `hdr`
+pub(crate) fn run_composite(
+    render_context: &mut RenderContext<'_>,
+    oit_pipeline: &OitPipeline,
+    textures: &OitTextures,
+    hdr: &TextureView,
+) {
+    let desc = RenderPassDescriptor {
+        label: Some("oit_composite_pass"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: hdr,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Load,
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    };
+    let mut pass = render_context.command_encoder().begin_render_pass(&desc);
+    pass.set_pipeline(&oit_pipeline.composite);
+    pass.set_bind_group(0, &textures.bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
@@ -0,0 +1,84 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{BindGroupLayout, RenderPipeline, TextureFormat},
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{
+    debug_lines::{DebugLineBuffer, DebugLines, create_line_buffer, create_line_pipeline},
+    shader::ShaderSources,
+    stats::RenderStatsCollector,
+};
+
+/// Outlines a single block, such as the one the player is looking at
+#[derive(Resource, Clone, Copy)]
+pub struct BlockOutline {
+    pub block: Option<IVec3>,
+    pub color: Color,
+}
+
+impl Default for BlockOutline {
+    fn default() -> Self {
+        Self {
+            block: None,
+            color: Color::srgba(0.0, 0.0, 0.0, 0.6),
+        }
+    }
+}
+
+/// How far the outline sits outside the block's faces, in blocks
+const OUTLINE_INFLATION: f32 = 0.002;
+
+#[derive(Resource)]
+pub(crate) struct BlockOutlinePipeline {
+    pub pipeline: RenderPipeline,
+}
+
+#[derive(Resource)]
+pub(crate) struct BlockOutlineBuffer(pub DebugLineBuffer);
+
+pub(crate) fn create_block_outline_pipeline(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+    globals_layout: &BindGroupLayout,
+    depth_format: TextureFormat,
+    sample_count: u32,
+) -> Result<BlockOutlinePipeline, String> {
+    Ok(BlockOutlinePipeline {
+        pipeline: create_line_pipeline(
+            render_device,
+            shader_sources,
+            globals_layout,
+            depth_format,
+            sample_count,
+            "block outline pipeline",
+            "vs_outline",
+        )?,
+    })
+}
+
+/// Uploads the edges of the outlined block, if there is one
+pub(crate) fn prepare_block_outline(world: &mut World) {
+    let outline = world
+        .get_resource::<BlockOutline>()
+        .copied()
+        .unwrap_or_default();
+    let Some(block) = outline.block else {
+        world.remove_resource::<BlockOutlineBuffer>();
+        return;
+    };
+    // Blocks are centred on their position
+    let half_size = Vec3::splat(0.5 + OUTLINE_INFLATION);
+    let center = block.as_vec3();
+    let mut lines = DebugLines::default();
+    lines.aabb(center - half_size, center + half_size, outline.color);
+
+    let render_device = world.resource::<RenderDevice>();
+    let buffer = create_line_buffer(render_device, "block outline buffer", lines.vertices());
+    world
+        .resource::<RenderStatsCollector>()
+        .record_upload(std::mem::size_of_val(lines.vertices()));
+    world.insert_resource(BlockOutlineBuffer(buffer));
+}
@@ -0,0 +1,201 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferInitDescriptor, BufferUsages, PipelineLayout, RenderPipeline,
+            TextureFormat, VertexAttribute, VertexFormat,
+        },
+        renderer::RenderDevice,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{post_process, shader::ShaderSources, stats::RenderStatsCollector};
+
+/// Small textured squares facing the camera, such as the debris of a broken
+/// block. Cleared at the start of every frame, so whatever simulates the
+/// particles adds the ones alive each frame.
+#[derive(Resource, Clone, Default)]
+pub struct Particles {
+    instances: Vec<ParticleInstance>,
+}
+
+/// A single particle, textured with part of a terrain texture
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    /// Centre of the particle
+    pub position: Vec3,
+    /// Width and height, in blocks
+    pub size: f32,
+    /// Layer of the terrain texture array, as given by
+    /// `TerrainColorTextureIndices`
+    pub texture_index: u32,
+    /// Corner of the part of the texture shown, from 0.0 to 1.0
+    pub uv_min: Vec2,
+    /// Size of the part of the texture shown, from 0.0 to 1.0
+    pub uv_size: Vec2,
+    /// Multiplies the texture, like `TerrainTint` does for tinted terrain
+    pub tint: Color,
+}
+
+impl Particles {
+    pub fn add(&mut self, particle: Particle) {
+        self.instances.push(ParticleInstance {
+            position: particle.position.to_array(),
+            size: particle.size,
+            uv_min: particle.uv_min.to_array(),
+            uv_size: particle.uv_size.to_array(),
+            tint: particle.tint.to_linear().to_f32_array(),
+            texture_index: particle.texture_index,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+}
+
+pub(crate) fn clear_particles(mut particles: ResMut<Particles>) {
+    particles.clear();
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ParticleInstance {
+    position: [f32; 3],
+    size: f32,
+    uv_min: [f32; 2],
+    uv_size: [f32; 2],
+    tint: [f32; 4],
+    texture_index: u32,
+}
+
+/// Draws `Particles` lit like the terrain, with the terrain's bind groups
+#[derive(Resource)]
+pub(crate) struct ParticlePipeline {
+    pub pipeline: RenderPipeline,
+}
+
+/// Instances of this frame's particles
+#[derive(Resource)]
+pub(crate) struct ParticleBuffer {
+    pub buffer: Buffer,
+    pub instance_count: u32,
+}
+
+/// Corners of each particle, drawn as a triangle strip
+pub(crate) const PARTICLE_VERTEX_COUNT: u32 = 4;
+
+pub(crate) fn create_particle_pipeline(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+    layout: &PipelineLayout,
+    shader_defs: &[&str],
+    depth_format: TextureFormat,
+    sample_count: u32,
+) -> Result<ParticlePipeline, String> {
+    let shader = shader_sources.create_module(render_device, "particles.wgsl", shader_defs)?;
+    let attributes = [
+        VertexAttribute {
+            format: VertexFormat::Float32x3,
+            offset: std::mem::offset_of!(ParticleInstance, position) as _,
+            shader_location: 0,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32,
+            offset: std::mem::offset_of!(ParticleInstance, size) as _,
+            shader_location: 1,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x2,
+            offset: std::mem::offset_of!(ParticleInstance, uv_min) as _,
+            shader_location: 2,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x2,
+            offset: std::mem::offset_of!(ParticleInstance, uv_size) as _,
+            shader_location: 3,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32x4,
+            offset: std::mem::offset_of!(ParticleInstance, tint) as _,
+            shader_location: 4,
+        },
+        VertexAttribute {
+            format: VertexFormat::Uint32,
+            offset: std::mem::offset_of!(ParticleInstance, texture_index) as _,
+            shader_location: 5,
+        },
+    ];
+    let pipeline = render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some("particle pipeline"),
+            layout: Some(layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &shader,
+                entry_point: Some("vs_particle"),
+                buffers: &[bevy::render::render_resource::RawVertexBufferLayout {
+                    array_stride: std::mem::size_of::<ParticleInstance>() as _,
+                    step_mode: bevy::render::render_resource::VertexStepMode::Instance,
+                    attributes: &attributes,
+                }],
+                compilation_options: default(),
+            },
+            fragment: Some(bevy::render::render_resource::RawFragmentState {
+                module: &shader,
+                entry_point: Some("fs_particle"),
+                targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                    format: post_process::HDR_TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                })],
+                compilation_options: default(),
+            }),
+            primitive: bevy::render::render_resource::PrimitiveState {
+                topology: bevy::render::mesh::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            // Alpha tested like cutout terrain, so particles hide each other
+            // without sorting
+            depth_stencil: Some(bevy::render::render_resource::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: true,
+                depth_compare: bevy::render::render_resource::CompareFunction::Greater,
+                stencil: bevy::render::render_resource::StencilState::default(),
+                bias: bevy::render::render_resource::DepthBiasState::default(),
+            }),
+            multisample: bevy::render::render_resource::MultisampleState {
+                count: sample_count,
+                ..default()
+            },
+            multiview: None,
+            cache: None,
+        },
+    );
+    Ok(ParticlePipeline { pipeline })
+}
+
+/// Uploads this frame's particles, if there are any
+pub(crate) fn prepare_particles(world: &mut World) {
+    let Some(particles) = world
+        .get_resource::<Particles>()
+        .filter(|particles| !particles.instances.is_empty())
+    else {
+        world.remove_resource::<ParticleBuffer>();
+        return;
+    };
+    let render_device = world.resource::<RenderDevice>();
+    let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("particle buffer"),
+        contents: bytemuck::cast_slice(&particles.instances),
+        usage: BufferUsages::VERTEX,
+    });
+    let instance_count = particles.instances.len() as u32;
+    world
+        .resource::<RenderStatsCollector>()
+        .record_upload(std::mem::size_of_val(particles.instances.as_slice()));
+    world.insert_resource(ParticleBuffer {
+        buffer,
+        instance_count,
+    });
+}
@@ -1,64 +1,172 @@
+use std::collections::HashMap;
+
 use bevy::{
     prelude::*,
     render::{
         Extract,
         render_resource::{
-            AddressMode, BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry,
-            BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor,
-            BufferUsages, FilterMode, RenderPipeline, ShaderStages, TextureFormat, TextureUsages,
-            TextureView,
+            AddressMode, BindGroup, BindGroupLayout, BindGroupLayoutEntry, BindingType, Buffer,
+            BufferBindingType, BufferDescriptor, BufferUsages, FilterMode, PipelineLayout,
+            RawVertexBufferLayout, RenderPipeline, ShaderStages, Texture, TextureFormat,
+            TextureUsages, TextureView, VertexAttribute,
         },
-        renderer::RenderDevice,
+        renderer::{RenderAdapter, RenderDevice},
     },
 };
 
 use crate::{
-    globals::GlobalsData,
+    bind_group_cache::{BindGroupCache, Binding},
+    camera::RenderCamera,
+    debug_lines::{self, DebugLinePipeline},
+    globals::{GlobalsData, PointLightsData, RenderFeatures, ShadowSettings},
     instance::RawInstance,
+    oit,
+    outline::{self, BlockOutlinePipeline},
+    particles::{self, ParticlePipeline},
+    post_process,
+    retry::{PipelineRetry, catch_validation_errors},
+    shader::ShaderSources,
+    ssao,
     texture::TextureBindGroup,
-    vertex::{INDICES, ModelVertex},
 };
 
 #[derive(Resource)]
 pub struct MyRenderPipeline {
     pub(crate) pipeline: RenderPipeline,
+    /// Variant of `pipeline` for use after the depth prepass, which only
+    /// shades fragments whose depth matches the prepass
+    pub(crate) depth_equal_pipeline: RenderPipeline,
 }
 
+/// Draws the sky, stars and moon behind everything else
 #[derive(Resource)]
-pub(crate) struct MyShadowMapPipeline {
+pub(crate) struct MySkyPipeline {
     pub pipeline: RenderPipeline,
 }
 
+/// Writes the depth of opaque quads ahead of the main pass
 #[derive(Resource)]
-pub(crate) struct GlobalsUniformBuffer {
-    pub buffer: Buffer,
+pub(crate) struct MyDepthPrepassPipeline {
+    pub pipeline: RenderPipeline,
 }
 
+/// Alpha tested variant of the main pipeline
 #[derive(Resource)]
-pub(crate) struct GlobalsUniformBindGroup {
-    pub bind_group: BindGroup,
+pub(crate) struct MyCutoutPipeline {
+    pub pipeline: RenderPipeline,
 }
 
+/// Alpha blended variant of the main pipeline, which tests against but does
+/// not write to the depth buffer
 #[derive(Resource)]
-pub(crate) struct ShadowPassGlobalsUniformBuffer {
-    pub buffer: Buffer,
+pub(crate) struct MyTransparentPipeline {
+    pub pipeline: RenderPipeline,
 }
 
+/// Accumulates transparent quads for order independent transparency, in
+/// place of `MyTransparentPipeline`. Only present while
+/// `RenderFeatures::order_independent_transparency` is in use.
 #[derive(Resource)]
-pub(crate) struct ShadowPassGlobalsUniformBindGroup {
+pub(crate) struct MyOitAccumulatePipeline {
+    pub pipeline: RenderPipeline,
+}
+
+#[derive(Resource)]
+pub(crate) struct MyShadowMapPipeline {
+    pub pipeline: RenderPipeline,
+}
+
+/// Globals uniforms of a single view, for its main passes and for its shadow
+/// pass. Each view needs its own buffers, since every write to a buffer in a
+/// frame lands before any of the frame's passes run.
+#[derive(Component)]
+pub(crate) struct ViewGlobals {
+    pub buffer: Buffer,
     pub bind_group: BindGroup,
+    pub shadow_pass_buffer: Buffer,
+    pub shadow_pass_bind_group: BindGroup,
+    /// Shared by both bind groups
+    pub point_lights_buffer: Buffer,
+}
+
+impl ViewGlobals {
+    pub fn new(
+        render_device: &RenderDevice,
+        bind_group_cache: &mut BindGroupCache,
+        layout: &BindGroupLayout,
+        chunk_offsets: &ChunkOffsetsBuffer,
+    ) -> Self {
+        let point_lights_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("point lights"),
+            size: std::mem::size_of::<PointLightsData>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut create_globals = |label| {
+            let buffer = render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: std::mem::size_of::<GlobalsData>() as u64,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = bind_group_cache.bind_group(
+                render_device,
+                label,
+                layout,
+                &[
+                    Binding::Buffer(&buffer),
+                    Binding::Buffer(&chunk_offsets.buffer),
+                    Binding::Buffer(&point_lights_buffer),
+                ],
+            );
+            (buffer, bind_group)
+        };
+        let (buffer, bind_group) = create_globals("view globals");
+        let (shadow_pass_buffer, shadow_pass_bind_group) = create_globals("shadow pass globals");
+        Self {
+            buffer,
+            bind_group,
+            shadow_pass_buffer,
+            shadow_pass_bind_group,
+            point_lights_buffer,
+        }
+    }
 }
 
+/// Width and height of the shadow map texture, in texels
+pub(crate) const SHADOW_MAP_SIZE: u32 = 4096;
+
+/// Upper bound on the number of chunks with instances at any one time
+pub(crate) const MAX_CHUNKS: u32 = 1 << 16;
+
+/// Position of each chunk (xyz) and its tint packed as RGBA8 (w), indexed by
+/// the chunk slot stored alongside its instances.
 #[derive(Resource)]
-pub(crate) struct IndexBuffer {
+pub(crate) struct ChunkOffsetsBuffer {
     pub buffer: Buffer,
-    pub num_indices: u32,
 }
 
+pub(crate) fn init_chunk_offsets_buffer(mut commands: Commands, render_device: Res<RenderDevice>) {
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("chunk offsets buffer"),
+        size: MAX_CHUNKS as u64 * std::mem::size_of::<[i32; 4]>() as u64,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    commands.insert_resource(ChunkOffsetsBuffer { buffer });
+}
+
+/// Format of the main pass and shadow map depth textures
+pub(crate) const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Quads are drawn as a triangle strip whose corners are generated in the
+/// vertex shader from the vertex index.
+pub(crate) const QUAD_VERTEX_COUNT: u32 = 4;
+
 pub(crate) struct DepthTexture {
+    pub texture: Texture,
     pub view: TextureView,
     pub format: TextureFormat,
-    #[allow(unused)]
     pub size: UVec2,
 }
 
@@ -75,133 +183,184 @@ pub(crate) struct ShadowMapTextureBindGroup {
     pub layout: BindGroupLayout,
 }
 
+/// Layouts shared by every permutation of the pipelines that depend on
+/// `RenderFeatures`, and the permutations built so far
+#[derive(Resource)]
+pub(crate) struct SpecializedPipelines {
+    pub(crate) globals_layout: BindGroupLayout,
+    /// Layout of the pipelines that only bind the globals
+    globals_pipeline_layout: PipelineLayout,
+    /// Layout of the pipelines that shade terrain
+    main_pipeline_layout: PipelineLayout,
+    terrain: HashMap<RenderFeatures, TerrainPipelines>,
+    by_sample_count: HashMap<u32, SampleCountPipelines>,
+    /// Features of the pipelines currently inserted as resources
+    active: Option<RenderFeatures>,
+}
+
+impl SpecializedPipelines {
+    pub fn active_features(&self) -> Option<RenderFeatures> {
+        self.active
+    }
+}
+
+/// Pipelines drawing terrain, which depend on every `RenderFeatures` field
+#[derive(Clone)]
+struct TerrainPipelines {
+    main: RenderPipeline,
+    depth_equal: RenderPipeline,
+    cutout: RenderPipeline,
+    transparent: RenderPipeline,
+    oit_accumulate: Option<RenderPipeline>,
+    particle: RenderPipeline,
+}
+
+/// Pipelines which only depend on `RenderFeatures::msaa_samples`
+#[derive(Clone)]
+struct SampleCountPipelines {
+    sky: RenderPipeline,
+    depth_prepass: RenderPipeline,
+    debug_line: RenderPipeline,
+    block_outline: RenderPipeline,
+}
+
+/// Multisampled targets of the passes drawing into the HDR texture while MSAA
+/// is enabled. Each pass resolves into the HDR texture.
+#[derive(Resource)]
+pub(crate) struct MsaaTextures {
+    pub color: TextureView,
+    pub depth: DepthTexture,
+    pub sample_count: u32,
+}
+
 pub(crate) fn init_pipeline(
     mut commands: Commands,
     render_device: Res<RenderDevice>,
-    windows: Extract<Query<&Window>>,
+    cameras: Extract<Query<&Camera, With<RenderCamera>>>,
     texture_bind_group: Option<Res<TextureBindGroup>>,
+    shadow_settings: Extract<Option<Res<ShadowSettings>>>,
+    shader_sources: Extract<Res<ShaderSources>>,
+    mut retry: ResMut<PipelineRetry>,
+    mut bind_group_cache: ResMut<BindGroupCache>,
 ) {
     let Some(texture_bind_group) = texture_bind_group else {
         return;
     };
+    if !shader_sources.is_loaded() || !retry.is_ready() {
+        return;
+    }
+    let Some(target_size) = render_target_size(&cameras) else {
+        return;
+    };
+    let shadow_settings = shadow_settings.as_deref().copied().unwrap_or_default();
 
-    let window = windows.single().expect("Main window");
+    let result = catch_validation_errors(&render_device, || {
+        create_pipeline_resources(
+            &mut commands,
+            &render_device,
+            &mut bind_group_cache,
+            target_size,
+            &texture_bind_group,
+            shadow_settings,
+            &shader_sources,
+        )
+    });
+    match result {
+        Ok(specialized) => {
+            retry.succeeded();
+            commands.insert_resource(specialized);
+        }
+        Err(e) => retry.failed("render pipelines", &e),
+    }
+}
+
+/// Inserts the resources shared by every `RenderFeatures` permutation,
+/// returning what's needed to build the permutations themselves
+fn create_pipeline_resources(
+    commands: &mut Commands,
+    render_device: &RenderDevice,
+    bind_group_cache: &mut BindGroupCache,
+    target_size: UVec2,
+    texture_bind_group: &TextureBindGroup,
+    shadow_settings: ShadowSettings,
+    shader_sources: &ShaderSources,
+) -> Result<SpecializedPipelines, String> {
     let depth_texture = create_depth_texture(
         "depth texture",
-        &render_device,
-        window.physical_width(),
-        window.physical_height(),
+        render_device,
+        target_size.x,
+        target_size.y,
+        1,
     );
-    const SHADOW_MAP_SIZE: u32 = 4096;
     let shadow_map = create_depth_texture(
         "shadow map",
-        &render_device,
+        render_device,
         SHADOW_MAP_SIZE,
         SHADOW_MAP_SIZE,
+        1,
     );
 
-    let globals_bind_group_layout = render_device.create_bind_group_layout(
-        Some("Globals bind group layout"),
-        &[BindGroupLayoutEntry {
-            binding: 0,
-            visibility: ShaderStages::VERTEX_FRAGMENT,
-            ty: BindingType::Buffer {
-                ty: BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
+    let globals_bind_group_layout = bind_group_cache.layout(
+        render_device,
+        "Globals bind group layout",
+        &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
             },
-            count: None,
-        }],
-    );
-
-    let globals_buffer = render_device.create_buffer(&BufferDescriptor {
-        label: Some("globals buffer"),
-        size: std::mem::size_of::<GlobalsData>() as u64,
-        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-    let globals_bind_group = render_device.create_bind_group(
-        Some("Globals bind group"),
-        &globals_bind_group_layout,
-        &[BindGroupEntry {
-            binding: 0,
-            resource: globals_buffer.as_entire_binding(),
-        }],
-    );
-
-    let shadow_pass_globals_buffer = render_device.create_buffer(&BufferDescriptor {
-        label: Some("globals buffer"),
-        size: std::mem::size_of::<GlobalsData>() as u64,
-        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-    let shadow_pass_globals_bind_group = render_device.create_bind_group(
-        Some("Shadow pass globals bind group"),
-        &globals_bind_group_layout,
-        &[BindGroupEntry {
-            binding: 0,
-            resource: shadow_pass_globals_buffer.as_entire_binding(),
-        }],
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
     );
 
-    commands.insert_resource(GlobalsUniformBuffer {
-        buffer: globals_buffer,
-    });
-    commands.insert_resource(GlobalsUniformBindGroup {
-        bind_group: globals_bind_group,
-    });
-
-    commands.insert_resource(ShadowPassGlobalsUniformBuffer {
-        buffer: shadow_pass_globals_buffer,
-    });
-    commands.insert_resource(ShadowPassGlobalsUniformBindGroup {
-        bind_group: shadow_pass_globals_bind_group,
-    });
-
-    let shader = render_device.create_and_validate_shader_module(
-        bevy::render::render_resource::ShaderModuleDescriptor {
-            label: Some("triangle shader"),
-            source: bevy::render::render_resource::ShaderSource::Wgsl(
-                include_str!("shaders/triangle.wgsl").into(),
-            ),
-        },
+    let ssao_pipeline =
+        ssao::create_ssao_pipeline(render_device, shader_sources, &globals_bind_group_layout)?;
+    let ssao_textures = ssao::create_ssao_textures(render_device, &ssao_pipeline, &depth_texture);
+    let post_process_pipelines =
+        post_process::create_post_process_pipelines(render_device, shader_sources)?;
+    let post_process_textures = post_process::create_post_process_textures(
+        render_device,
+        &post_process_pipelines,
+        depth_texture.size,
     );
+    let oit_pipeline = oit::create_oit_pipeline(render_device, shader_sources)?;
+    let oit_textures = oit::create_oit_textures(render_device, &oit_pipeline, depth_texture.size);
 
-    let vertex_layout = bevy::render::render_resource::RawVertexBufferLayout {
-        array_stride: std::mem::size_of::<ModelVertex>() as _,
-        step_mode: bevy::render::render_resource::VertexStepMode::Vertex,
-        attributes: &ModelVertex::desc(),
-    };
-
-    let instance_layout = bevy::render::render_resource::RawVertexBufferLayout {
-        array_stride: std::mem::size_of::<RawInstance>() as _,
-        step_mode: bevy::render::render_resource::VertexStepMode::Instance,
-        attributes: &RawInstance::desc(),
-    };
-
-    let index_buffer = render_device.create_buffer_with_data(
-        &bevy::render::render_resource::BufferInitDescriptor {
-            label: Some("Index buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: BufferUsages::INDEX,
-        },
-    );
-    let num_indices = INDICES.len() as u32;
-    commands.insert_resource(IndexBuffer {
-        buffer: index_buffer,
-        num_indices,
-    });
+    // The shadow pass has no fragment stage, so none of the features affect it
+    let shader = shader_sources.create_module(render_device, "triangle.wgsl", &[])?;
+    let instance_attributes = RawInstance::desc();
+    let chunk_slot_attributes = RawInstance::chunk_slot_desc();
+    let vertex_buffers = quad_vertex_buffers(&instance_attributes, &chunk_slot_attributes);
 
     let shadow_pipeline_layout = render_device.create_pipeline_layout(
         &bevy::render::render_resource::PipelineLayoutDescriptor {
             label: Some("shadow pipeline layout"),
             bind_group_layouts: &[&globals_bind_group_layout],
-            push_constant_ranges: &[bevy::render::render_resource::PushConstantRange {
-                stages: ShaderStages::VERTEX,
-                range: 0..12,
-            }],
+            push_constant_ranges: &[],
         },
     );
 
@@ -212,7 +371,7 @@ pub(crate) fn init_pipeline(
             vertex: bevy::render::render_resource::RawVertexState {
                 module: &shader,
                 entry_point: Some("vs_main"),
-                buffers: &[vertex_layout.clone(), instance_layout.clone()],
+                buffers: &vertex_buffers,
                 compilation_options: default(),
             },
             fragment: None,
@@ -226,7 +385,13 @@ pub(crate) fn init_pipeline(
                 depth_write_enabled: true,
                 depth_compare: bevy::render::render_resource::CompareFunction::Greater,
                 stencil: bevy::render::render_resource::StencilState::default(),
-                bias: bevy::render::render_resource::DepthBiasState::default(),
+                // Depth is reversed, so casters are pushed away from the light
+                // with a negative bias
+                bias: bevy::render::render_resource::DepthBiasState {
+                    constant: 0,
+                    slope_scale: -shadow_settings.slope_scale_bias,
+                    clamp: 0.0,
+                },
             }),
             multisample: default(),
             multiview: None,
@@ -245,8 +410,9 @@ pub(crate) fn init_pipeline(
             address_mode_v: AddressMode::Repeat,
             ..Default::default()
         });
-    let shadow_map_bind_group_layout = render_device.create_bind_group_layout(
-        Some("shadow map bind group layout"),
+    let shadow_map_bind_group_layout = bind_group_cache.layout(
+        render_device,
+        "shadow map bind group layout",
         &[
             // Texture binding
             BindGroupLayoutEntry {
@@ -270,18 +436,13 @@ pub(crate) fn init_pipeline(
             },
         ],
     );
-    let shadow_map_bind_group = render_device.create_bind_group(
-        Some("My texture bind group"),
+    let shadow_map_bind_group = bind_group_cache.bind_group(
+        render_device,
+        "shadow map bind group",
         &shadow_map_bind_group_layout,
         &[
-            BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::TextureView(&shadow_map.view),
-            },
-            BindGroupEntry {
-                binding: 1,
-                resource: BindingResource::Sampler(&shadow_map_sampler),
-            },
+            Binding::TextureView(&shadow_map.view),
+            Binding::Sampler(&shadow_map_sampler),
         ],
     );
 
@@ -292,29 +453,199 @@ pub(crate) fn init_pipeline(
                 &globals_bind_group_layout,
                 &texture_bind_group.layout,
                 &shadow_map_bind_group_layout,
+                &ssao_pipeline.output_layout,
             ],
-            push_constant_ranges: &[bevy::render::render_resource::PushConstantRange {
-                stages: ShaderStages::VERTEX,
-                range: 0..12,
-            }],
+            push_constant_ranges: &[],
         },
     );
 
+    commands.insert_resource(MainPassDepth(depth_texture));
+    commands.insert_resource(ssao_pipeline);
+    commands.insert_resource(ssao_textures);
+    commands.insert_resource(post_process_pipelines);
+    commands.insert_resource(post_process_textures);
+    commands.insert_resource(oit_pipeline);
+    commands.insert_resource(oit_textures);
+    commands.insert_resource(ShadowPassDepth(shadow_map));
+    commands.insert_resource(ShadowMapTextureBindGroup {
+        bind_group: shadow_map_bind_group,
+        layout: shadow_map_bind_group_layout,
+    });
+    commands.insert_resource(MyShadowMapPipeline {
+        pipeline: shadow_pass_pipeline,
+    });
+    Ok(SpecializedPipelines {
+        globals_layout: globals_bind_group_layout,
+        globals_pipeline_layout: shadow_pipeline_layout,
+        main_pipeline_layout: layout,
+        terrain: HashMap::new(),
+        by_sample_count: HashMap::new(),
+        active: None,
+    })
+}
+
+/// Makes the pipelines matching `RenderFeatures` the ones in use, building
+/// them the first time each combination is used
+pub(crate) fn specialize_pipelines(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    render_adapter: Res<RenderAdapter>,
+    mut specialized: ResMut<SpecializedPipelines>,
+    depth: Res<MainPassDepth>,
+    render_features: Extract<Option<Res<RenderFeatures>>>,
+    shader_sources: Extract<Res<ShaderSources>>,
+    mut retry: ResMut<PipelineRetry>,
+) {
+    let mut features = render_features.as_deref().copied().unwrap_or_default();
+    if !is_sample_count_supported(&render_adapter, features.msaa_samples) {
+        features.msaa_samples = 1;
+    }
+    if specialized.active == Some(features) || !retry.is_ready() {
+        return;
+    }
+    if features.msaa_samples
+        != render_features
+            .as_deref()
+            .map_or(1, |requested| requested.msaa_samples)
+    {
+        warn!("MSAA sample count not supported by this GPU. Disabling MSAA.");
+    }
+
+    // A failed permutation keeps the previous one in use until it builds
+    if !specialized.terrain.contains_key(&features) {
+        info!("Building terrain pipelines for {features:?}");
+        let result = catch_validation_errors(&render_device, || {
+            create_terrain_pipelines(&render_device, &shader_sources, &specialized, features)
+        });
+        match result {
+            Ok(pipelines) => specialized.terrain.insert(features, pipelines),
+            Err(e) => return retry.failed("terrain pipelines", &e),
+        };
+    }
+    let sample_count = features.msaa_samples;
+    if !specialized.by_sample_count.contains_key(&sample_count) {
+        let result = catch_validation_errors(&render_device, || {
+            create_sample_count_pipelines(
+                &render_device,
+                &shader_sources,
+                &specialized,
+                sample_count,
+            )
+        });
+        match result {
+            Ok(pipelines) => specialized.by_sample_count.insert(sample_count, pipelines),
+            Err(e) => return retry.failed("sky and line pipelines", &e),
+        };
+    }
+    retry.succeeded();
+    let terrain = specialized.terrain[&features].clone();
+    let by_sample_count = specialized.by_sample_count[&sample_count].clone();
+
+    commands.insert_resource(MyRenderPipeline {
+        pipeline: terrain.main,
+        depth_equal_pipeline: terrain.depth_equal,
+    });
+    commands.insert_resource(MyCutoutPipeline {
+        pipeline: terrain.cutout,
+    });
+    commands.insert_resource(MyTransparentPipeline {
+        pipeline: terrain.transparent,
+    });
+    match terrain.oit_accumulate {
+        Some(pipeline) => commands.insert_resource(MyOitAccumulatePipeline { pipeline }),
+        None => commands.remove_resource::<MyOitAccumulatePipeline>(),
+    }
+    commands.insert_resource(ParticlePipeline {
+        pipeline: terrain.particle,
+    });
+    commands.insert_resource(MySkyPipeline {
+        pipeline: by_sample_count.sky,
+    });
+    commands.insert_resource(MyDepthPrepassPipeline {
+        pipeline: by_sample_count.depth_prepass,
+    });
+    commands.insert_resource(DebugLinePipeline {
+        pipeline: by_sample_count.debug_line,
+    });
+    commands.insert_resource(BlockOutlinePipeline {
+        pipeline: by_sample_count.block_outline,
+    });
+    if sample_count > 1 {
+        commands.insert_resource(create_msaa_textures(
+            &render_device,
+            sample_count,
+            depth.0.size,
+        ));
+    } else {
+        commands.remove_resource::<MsaaTextures>();
+    }
+    specialized.active = Some(features);
+}
+
+/// Whether both the HDR and depth textures can have `sample_count` samples
+fn is_sample_count_supported(render_adapter: &RenderAdapter, sample_count: u32) -> bool {
+    [post_process::HDR_TEXTURE_FORMAT, DEPTH_FORMAT]
+        .into_iter()
+        .all(|format| {
+            render_adapter
+                .get_texture_format_features(format)
+                .flags
+                .sample_count_supported(sample_count)
+        })
+}
+
+/// Vertex buffers of the pipelines drawing quads: the instances, then the
+/// chunk slot of each instance
+fn quad_vertex_buffers<'a>(
+    instance_attributes: &'a [VertexAttribute],
+    chunk_slot_attributes: &'a [VertexAttribute],
+) -> [RawVertexBufferLayout<'a>; 2] {
+    [
+        RawVertexBufferLayout {
+            array_stride: std::mem::size_of::<RawInstance>() as _,
+            step_mode: bevy::render::render_resource::VertexStepMode::Instance,
+            attributes: instance_attributes,
+        },
+        RawVertexBufferLayout {
+            array_stride: std::mem::size_of::<u32>() as _,
+            step_mode: bevy::render::render_resource::VertexStepMode::Instance,
+            attributes: chunk_slot_attributes,
+        },
+    ]
+}
+
+fn create_terrain_pipelines(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+    specialized: &SpecializedPipelines,
+    features: RenderFeatures,
+) -> Result<TerrainPipelines, String> {
+    let shader =
+        shader_sources.create_module(render_device, "triangle.wgsl", &features.shader_defs())?;
+    let instance_attributes = RawInstance::desc();
+    let chunk_slot_attributes = RawInstance::chunk_slot_desc();
+    let vertex_buffers = quad_vertex_buffers(&instance_attributes, &chunk_slot_attributes);
+    let layout = &specialized.main_pipeline_layout;
+    let multisample = bevy::render::render_resource::MultisampleState {
+        count: features.msaa_samples,
+        ..default()
+    };
+
     let pipeline = render_device.create_render_pipeline(
         &bevy::render::render_resource::RawRenderPipelineDescriptor {
             label: Some("main pipeline"),
-            layout: Some(&layout),
+            layout: Some(layout),
             vertex: bevy::render::render_resource::RawVertexState {
                 module: &shader,
                 entry_point: Some("vs_main"),
-                buffers: &[vertex_layout.clone(), instance_layout.clone()],
+                buffers: &vertex_buffers,
                 compilation_options: default(),
             },
             fragment: Some(bevy::render::render_resource::RawFragmentState {
                 module: &shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(bevy::render::render_resource::ColorTargetState {
-                    format: TextureFormat::bevy_default(),
+                    format: post_process::HDR_TEXTURE_FORMAT,
                     blend: None,
                     write_mask: bevy::render::render_resource::ColorWrites::ALL,
                 })],
@@ -326,42 +657,386 @@ pub(crate) fn init_pipeline(
                 ..Default::default()
             },
             depth_stencil: Some(bevy::render::render_resource::DepthStencilState {
-                format: depth_texture.format,
+                format: DEPTH_FORMAT,
                 depth_write_enabled: true,
                 depth_compare: bevy::render::render_resource::CompareFunction::Greater,
                 stencil: bevy::render::render_resource::StencilState::default(),
                 bias: bevy::render::render_resource::DepthBiasState::default(),
             }),
-            multisample: default(),
+            multisample,
             multiview: None,
             cache: None,
         },
     );
 
-    commands.insert_resource(MainPassDepth(depth_texture));
-    commands.insert_resource(MyRenderPipeline { pipeline });
-    commands.insert_resource(ShadowPassDepth(shadow_map));
-    commands.insert_resource(ShadowMapTextureBindGroup {
-        bind_group: shadow_map_bind_group,
-        layout: shadow_map_bind_group_layout,
-    });
-    commands.insert_resource(MyShadowMapPipeline {
-        pipeline: shadow_pass_pipeline,
+    let depth_equal_pipeline = render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some("main pipeline (depth equal)"),
+            layout: Some(layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &vertex_buffers,
+                compilation_options: default(),
+            },
+            fragment: Some(bevy::render::render_resource::RawFragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                    format: post_process::HDR_TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                })],
+                compilation_options: default(),
+            }),
+            primitive: bevy::render::render_resource::PrimitiveState {
+                topology: bevy::render::mesh::PrimitiveTopology::TriangleStrip,
+                cull_mode: Some(bevy::render::render_resource::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(bevy::render::render_resource::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: bevy::render::render_resource::CompareFunction::Equal,
+                stencil: bevy::render::render_resource::StencilState::default(),
+                bias: bevy::render::render_resource::DepthBiasState::default(),
+            }),
+            multisample,
+            multiview: None,
+            cache: None,
+        },
+    );
+
+    let cutout_pipeline = render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some("cutout pipeline"),
+            layout: Some(layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &vertex_buffers,
+                compilation_options: default(),
+            },
+            fragment: Some(bevy::render::render_resource::RawFragmentState {
+                module: &shader,
+                entry_point: Some("fs_cutout"),
+                targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                    format: post_process::HDR_TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                })],
+                compilation_options: default(),
+            }),
+            primitive: bevy::render::render_resource::PrimitiveState {
+                topology: bevy::render::mesh::PrimitiveTopology::TriangleStrip,
+                cull_mode: Some(bevy::render::render_resource::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(bevy::render::render_resource::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: bevy::render::render_resource::CompareFunction::Greater,
+                stencil: bevy::render::render_resource::StencilState::default(),
+                bias: bevy::render::render_resource::DepthBiasState::default(),
+            }),
+            multisample,
+            multiview: None,
+            cache: None,
+        },
+    );
+
+    let transparent_pipeline = render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some("transparent pipeline"),
+            layout: Some(layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &vertex_buffers,
+                compilation_options: default(),
+            },
+            fragment: Some(bevy::render::render_resource::RawFragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                    format: post_process::HDR_TEXTURE_FORMAT,
+                    blend: Some(bevy::render::render_resource::BlendState::ALPHA_BLENDING),
+                    write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                })],
+                compilation_options: default(),
+            }),
+            primitive: bevy::render::render_resource::PrimitiveState {
+                topology: bevy::render::mesh::PrimitiveTopology::TriangleStrip,
+                cull_mode: Some(bevy::render::render_resource::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(bevy::render::render_resource::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: bevy::render::render_resource::CompareFunction::Greater,
+                stencil: bevy::render::render_resource::StencilState::default(),
+                bias: bevy::render::render_resource::DepthBiasState::default(),
+            }),
+            multisample,
+            multiview: None,
+            cache: None,
+        },
+    );
+
+    // The accumulation targets aren't multisampled, so they can't share the
+    // MSAA depth texture
+    let oit_accumulate_pipeline =
+        (features.order_independent_transparency && features.msaa_samples == 1).then(|| {
+            render_device.create_render_pipeline(
+                &bevy::render::render_resource::RawRenderPipelineDescriptor {
+                    label: Some("oit accumulate pipeline"),
+                    layout: Some(layout),
+                    vertex: bevy::render::render_resource::RawVertexState {
+                        module: &shader,
+                        entry_point: Some("vs_main"),
+                        buffers: &vertex_buffers,
+                        compilation_options: default(),
+                    },
+                    fragment: Some(bevy::render::render_resource::RawFragmentState {
+                        module: &shader,
+                        entry_point: Some("fs_oit"),
+                        targets: &[
+                            Some(bevy::render::render_resource::ColorTargetState {
+                                format: oit::ACCUMULATION_FORMAT,
+                                blend: Some(oit::ACCUMULATION_BLEND),
+                                write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                            }),
+                            Some(bevy::render::render_resource::ColorTargetState {
+                                format: oit::REVEALAGE_FORMAT,
+                                blend: Some(oit::REVEALAGE_BLEND),
+                                write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                            }),
+                        ],
+                        compilation_options: default(),
+                    }),
+                    primitive: bevy::render::render_resource::PrimitiveState {
+                        topology: bevy::render::mesh::PrimitiveTopology::TriangleStrip,
+                        cull_mode: Some(bevy::render::render_resource::Face::Back),
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(bevy::render::render_resource::DepthStencilState {
+                        format: DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: bevy::render::render_resource::CompareFunction::Greater,
+                        stencil: bevy::render::render_resource::StencilState::default(),
+                        bias: bevy::render::render_resource::DepthBiasState::default(),
+                    }),
+                    multisample,
+                    multiview: None,
+                    cache: None,
+                },
+            )
+        });
+
+    // Lit like the terrain, so it shares the terrain's layout and features
+    let particle_pipeline = particles::create_particle_pipeline(
+        render_device,
+        shader_sources,
+        layout,
+        &features.shader_defs(),
+        DEPTH_FORMAT,
+        features.msaa_samples,
+    )?;
+
+    Ok(TerrainPipelines {
+        main: pipeline,
+        depth_equal: depth_equal_pipeline,
+        cutout: cutout_pipeline,
+        transparent: transparent_pipeline,
+        oit_accumulate: oit_accumulate_pipeline,
+        particle: particle_pipeline.pipeline,
+    })
+}
+
+fn create_sample_count_pipelines(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+    specialized: &SpecializedPipelines,
+    sample_count: u32,
+) -> Result<SampleCountPipelines, String> {
+    let multisample = bevy::render::render_resource::MultisampleState {
+        count: sample_count,
+        ..default()
+    };
+
+    let sky_shader = shader_sources.create_module(render_device, "sky.wgsl", &[])?;
+    // Only needs the globals, like the shadow pipeline
+    let sky_pipeline = render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some("sky pipeline"),
+            layout: Some(&specialized.globals_pipeline_layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &sky_shader,
+                entry_point: Some("vs_fullscreen"),
+                buffers: &[],
+                compilation_options: default(),
+            },
+            fragment: Some(bevy::render::render_resource::RawFragmentState {
+                module: &sky_shader,
+                entry_point: Some("fs_sky"),
+                targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                    format: post_process::HDR_TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                })],
+                compilation_options: default(),
+            }),
+            primitive: default(),
+            depth_stencil: None,
+            multisample,
+            multiview: None,
+            cache: None,
+        },
+    );
+
+    let shader = shader_sources.create_module(render_device, "triangle.wgsl", &[])?;
+    let instance_attributes = RawInstance::desc();
+    let chunk_slot_attributes = RawInstance::chunk_slot_desc();
+    let vertex_buffers = quad_vertex_buffers(&instance_attributes, &chunk_slot_attributes);
+    // Only needs the globals, like the shadow pipeline
+    let depth_prepass_pipeline = render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some("depth prepass pipeline"),
+            layout: Some(&specialized.globals_pipeline_layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &vertex_buffers,
+                compilation_options: default(),
+            },
+            fragment: None,
+            primitive: bevy::render::render_resource::PrimitiveState {
+                topology: bevy::render::mesh::PrimitiveTopology::TriangleStrip,
+                cull_mode: Some(bevy::render::render_resource::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(bevy::render::render_resource::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: bevy::render::render_resource::CompareFunction::Greater,
+                stencil: bevy::render::render_resource::StencilState::default(),
+                bias: bevy::render::render_resource::DepthBiasState::default(),
+            }),
+            multisample,
+            multiview: None,
+            cache: None,
+        },
+    );
+
+    let debug_line_pipeline = debug_lines::create_debug_line_pipeline(
+        render_device,
+        shader_sources,
+        &specialized.globals_layout,
+        DEPTH_FORMAT,
+        sample_count,
+    )?;
+    let block_outline_pipeline = outline::create_block_outline_pipeline(
+        render_device,
+        shader_sources,
+        &specialized.globals_layout,
+        DEPTH_FORMAT,
+        sample_count,
+    )?;
+
+    Ok(SampleCountPipelines {
+        sky: sky_pipeline,
+        depth_prepass: depth_prepass_pipeline,
+        debug_line: debug_line_pipeline.pipeline,
+        block_outline: block_outline_pipeline.pipeline,
+    })
+}
+
+pub(crate) fn create_msaa_textures(
+    render_device: &RenderDevice,
+    sample_count: u32,
+    size: UVec2,
+) -> MsaaTextures {
+    let color = render_device.create_texture(&bevy::render::render_resource::TextureDescriptor {
+        label: Some("msaa color texture"),
+        size: bevy::render::render_resource::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: bevy::render::render_resource::TextureDimension::D2,
+        format: post_process::HDR_TEXTURE_FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
     });
+    MsaaTextures {
+        color: color.create_view(&bevy::render::render_resource::TextureViewDescriptor::default()),
+        depth: create_depth_texture(
+            "msaa depth texture",
+            render_device,
+            size.x,
+            size.y,
+            sample_count,
+        ),
+        sample_count,
+    }
+}
+
+/// Size of the target of the first active `RenderCamera`, which may be a
+/// window or an image. `None` while the target has no area, such as when its
+/// window is minimized.
+fn render_target_size(cameras: &Query<&Camera, With<RenderCamera>>) -> Option<UVec2> {
+    cameras
+        .iter()
+        .filter(|camera| camera.is_active)
+        .find_map(|camera| camera.physical_target_size())
+        .filter(|size| size.x > 0 && size.y > 0)
 }
 
 pub(crate) fn resize_depth_texture(
-    mut resize_events: Extract<EventReader<bevy::window::WindowResized>>,
-    mut depth: Option<ResMut<MainPassDepth>>,
+    mut commands: Commands,
+    cameras: Extract<Query<&Camera, With<RenderCamera>>>,
+    depth: Option<ResMut<MainPassDepth>>,
+    ssao_pipeline: Option<Res<ssao::SsaoPipeline>>,
+    post_process_pipelines: Option<Res<post_process::PostProcessPipelines>>,
+    oit_pipeline: Option<Res<oit::OitPipeline>>,
+    msaa_textures: Option<Res<MsaaTextures>>,
     render_device: Res<RenderDevice>,
 ) {
-    let Some(ref mut depth) = depth else {
+    let (Some(mut depth), Some(ssao_pipeline), Some(post_process_pipelines), Some(oit_pipeline)) =
+        (depth, ssao_pipeline, post_process_pipelines, oit_pipeline)
+    else {
+        return;
+    };
+    // Keep the old textures while minimized, since textures can't be empty
+    let Some(size) = render_target_size(&cameras) else {
         return;
     };
-    for event in resize_events.read() {
-        let width = event.width as u32;
-        let height = event.height as u32;
-        depth.0 = create_depth_texture("depth texture", &render_device, width, height);
+    if size != depth.0.size {
+        depth.0 = create_depth_texture("depth texture", &render_device, size.x, size.y, 1);
+        commands.insert_resource(ssao::create_ssao_textures(
+            &render_device,
+            &ssao_pipeline,
+            &depth.0,
+        ));
+        commands.insert_resource(post_process::create_post_process_textures(
+            &render_device,
+            &post_process_pipelines,
+            depth.0.size,
+        ));
+        commands.insert_resource(oit::create_oit_textures(
+            &render_device,
+            &oit_pipeline,
+            depth.0.size,
+        ));
+        if let Some(msaa_textures) = &msaa_textures {
+            commands.insert_resource(create_msaa_textures(
+                &render_device,
+                msaa_textures.sample_count,
+                depth.0.size,
+            ));
+        }
     }
 }
 
@@ -370,8 +1045,9 @@ pub(crate) fn create_depth_texture(
     device: &RenderDevice,
     width: u32,
     height: u32,
+    sample_count: u32,
 ) -> DepthTexture {
-    let format = TextureFormat::Depth32Float;
+    let format = DEPTH_FORMAT;
     let size = bevy::render::render_resource::Extent3d {
         width,
         height,
@@ -382,10 +1058,13 @@ pub(crate) fn create_depth_texture(
         label: Some(name),
         size,
         mip_level_count: 1,
-        sample_count: 1,
+        sample_count,
         dimension: bevy::render::render_resource::TextureDimension::D2,
         format,
-        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        // Copied into the view's depth texture for Bevy's passes
+        usage: TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_SRC,
         view_formats: &[],
     };
 
@@ -394,6 +1073,7 @@ pub(crate) fn create_depth_texture(
         texture.create_view(&bevy::render::render_resource::TextureViewDescriptor::default());
 
     DepthTexture {
+        texture,
         view,
         format,
         size: UVec2::new(width, height),
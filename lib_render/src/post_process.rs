@@ -0,0 +1,445 @@
+use bevy::{
+    prelude::*,
+    render::{
+        camera::Viewport,
+        render_resource::{
+            AddressMode, BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry,
+            BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor,
+            BufferUsages, FilterMode, LoadOp, Operations, RenderPassColorAttachment,
+            RenderPassDescriptor, RenderPipeline, Sampler, SamplerBindingType, ShaderStages,
+            StoreOp, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+            TextureViewDimension,
+        },
+        renderer::{RenderContext, RenderDevice},
+    },
+};
+
+use crate::shader::ShaderSources;
+
+/// Format the main pass renders into, so lighting isn't clamped before bloom
+pub(crate) const HDR_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Number of progressively halved textures in the bloom chain, starting at
+/// half the screen size
+const BLOOM_MIP_COUNT: u32 = 6;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Default)]
+pub(crate) struct PostProcessData {
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// Linear multiplier applied before tonemapping
+    pub exposure: f32,
+    /// `TonemappingOperator` discriminant
+    pub tonemapping_operator: u32,
+}
+
+/// Pipelines that take the HDR main pass to the view target
+#[derive(Resource)]
+pub(crate) struct PostProcessPipelines {
+    downsample_first: RenderPipeline,
+    downsample: RenderPipeline,
+    upsample: RenderPipeline,
+    composite: RenderPipeline,
+    layout: BindGroupLayout,
+    composite_layout: BindGroupLayout,
+    sampler: Sampler,
+    pub settings_buffer: Buffer,
+}
+
+/// Screen sized textures of the post processing passes, recreated whenever
+/// the main pass depth texture is
+#[derive(Resource)]
+pub(crate) struct PostProcessTextures {
+    /// Render target of the main pass
+    pub hdr: TextureView,
+    /// Views of each mip of the bloom texture
+    bloom_mips: Vec<TextureView>,
+    /// Reads the HDR target for the first mip, and the previous mip for the
+    /// rest
+    downsample_bind_groups: Vec<BindGroup>,
+    /// Reads mip `i + 1` for upsampling into mip `i`
+    upsample_bind_groups: Vec<BindGroup>,
+    composite_bind_group: BindGroup,
+}
+
+pub(crate) fn create_post_process_pipelines(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+) -> Result<PostProcessPipelines, String> {
+    let source_entries = [
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        },
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+    let layout = render_device
+        .create_bind_group_layout(Some("post process bind group layout"), &source_entries);
+    let composite_layout = render_device.create_bind_group_layout(
+        Some("composite bind group layout"),
+        &[
+            source_entries[0],
+            source_entries[1],
+            source_entries[2],
+            BindGroupLayoutEntry {
+                binding: 3,
+                ..source_entries[0]
+            },
+        ],
+    );
+
+    let sampler = render_device.create_sampler(&bevy::render::render_resource::SamplerDescriptor {
+        label: Some("post process sampler"),
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Nearest,
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        ..Default::default()
+    });
+
+    let settings_buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("post process settings buffer"),
+        size: std::mem::size_of::<PostProcessData>() as u64,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let shader = shader_sources.create_module(render_device, "post_process.wgsl", &[])?;
+    let pipeline_layout = render_device.create_pipeline_layout(
+        &bevy::render::render_resource::PipelineLayoutDescriptor {
+            label: Some("post process pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        },
+    );
+    let composite_pipeline_layout = render_device.create_pipeline_layout(
+        &bevy::render::render_resource::PipelineLayoutDescriptor {
+            label: Some("composite pipeline layout"),
+            bind_group_layouts: &[&composite_layout],
+            push_constant_ranges: &[],
+        },
+    );
+    let create_pipeline =
+        |label: &'static str,
+         layout: &bevy::render::render_resource::PipelineLayout,
+         entry_point: &'static str,
+         format: TextureFormat,
+         blend: Option<bevy::render::render_resource::BlendState>| {
+            render_device.create_render_pipeline(
+                &bevy::render::render_resource::RawRenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(layout),
+                    vertex: bevy::render::render_resource::RawVertexState {
+                        module: &shader,
+                        entry_point: Some("vs_fullscreen"),
+                        buffers: &[],
+                        compilation_options: default(),
+                    },
+                    fragment: Some(bevy::render::render_resource::RawFragmentState {
+                        module: &shader,
+                        entry_point: Some(entry_point),
+                        targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                            format,
+                            blend,
+                            write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                        })],
+                        compilation_options: default(),
+                    }),
+                    primitive: default(),
+                    depth_stencil: None,
+                    multisample: default(),
+                    multiview: None,
+                    cache: None,
+                },
+            )
+        };
+    let additive = bevy::render::render_resource::BlendState {
+        color: bevy::render::render_resource::BlendComponent {
+            src_factor: bevy::render::render_resource::BlendFactor::One,
+            dst_factor: bevy::render::render_resource::BlendFactor::One,
+            operation: bevy::render::render_resource::BlendOperation::Add,
+        },
+        alpha: bevy::render::render_resource::BlendComponent::REPLACE,
+    };
+
+    Ok(PostProcessPipelines {
+        downsample_first: create_pipeline(
+            "bloom downsample first pipeline",
+            &pipeline_layout,
+            "fs_downsample_first",
+            HDR_TEXTURE_FORMAT,
+            None,
+        ),
+        downsample: create_pipeline(
+            "bloom downsample pipeline",
+            &pipeline_layout,
+            "fs_downsample",
+            HDR_TEXTURE_FORMAT,
+            None,
+        ),
+        upsample: create_pipeline(
+            "bloom upsample pipeline",
+            &pipeline_layout,
+            "fs_upsample",
+            HDR_TEXTURE_FORMAT,
+            Some(additive),
+        ),
+        composite: create_pipeline(
+            "composite pipeline",
+            &composite_pipeline_layout,
+            "fs_composite",
+            TextureFormat::bevy_default(),
+            None,
+        ),
+        layout,
+        composite_layout,
+        sampler,
+        settings_buffer,
+    })
+}
+
+pub(crate) fn create_post_process_textures(
+    render_device: &RenderDevice,
+    pipelines: &PostProcessPipelines,
+    size: UVec2,
+) -> PostProcessTextures {
+    let hdr_texture =
+        render_device.create_texture(&bevy::render::render_resource::TextureDescriptor {
+            label: Some("hdr texture"),
+            size: bevy::render::render_resource::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: bevy::render::render_resource::TextureDimension::D2,
+            format: HDR_TEXTURE_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+    let hdr =
+        hdr_texture.create_view(&bevy::render::render_resource::TextureViewDescriptor::default());
+
+    let bloom_size = bevy::render::render_resource::Extent3d {
+        width: (size.x / 2).max(1),
+        height: (size.y / 2).max(1),
+        depth_or_array_layers: 1,
+    };
+    let mip_count = BLOOM_MIP_COUNT
+        .min(bloom_size.max_mips(bevy::render::render_resource::TextureDimension::D2));
+    let bloom_texture =
+        render_device.create_texture(&bevy::render::render_resource::TextureDescriptor {
+            label: Some("bloom texture"),
+            size: bloom_size,
+            mip_level_count: mip_count,
+            sample_count: 1,
+            dimension: bevy::render::render_resource::TextureDimension::D2,
+            format: HDR_TEXTURE_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+    let bloom_mips = (0..mip_count)
+        .map(|mip| {
+            bloom_texture.create_view(&bevy::render::render_resource::TextureViewDescriptor {
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let source_bind_group = |label: &'static str, source: &TextureView| {
+        render_device.create_bind_group(
+            Some(label),
+            &pipelines.layout,
+            &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(source),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&pipelines.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: pipelines.settings_buffer.as_entire_binding(),
+                },
+            ],
+        )
+    };
+    let downsample_bind_groups = std::iter::once(&hdr)
+        .chain(bloom_mips.iter())
+        .take(bloom_mips.len())
+        .map(|source| source_bind_group("bloom downsample bind group", source))
+        .collect();
+    let upsample_bind_groups = bloom_mips
+        .iter()
+        .skip(1)
+        .map(|source| source_bind_group("bloom upsample bind group", source))
+        .collect();
+    let composite_bind_group = render_device.create_bind_group(
+        Some("composite bind group"),
+        &pipelines.composite_layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&hdr),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(&pipelines.sampler),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: pipelines.settings_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(&bloom_mips[0]),
+            },
+        ],
+    );
+
+    PostProcessTextures {
+        hdr,
+        bloom_mips,
+        downsample_bind_groups,
+        upsample_bind_groups,
+        composite_bind_group,
+    }
+}
+
+impl PostProcessTextures {
+    /// Number of fullscreen draws done by `run_bloom`
+    pub fn bloom_draw_count(&self) -> u32 {
+        (self.downsample_bind_groups.len() + self.upsample_bind_groups.len()) as u32
+    }
+}
+
+/// Blurs everything brighter than the bloom threshold into the first bloom
+/// mip, by downsampling through the mip chain and adding it back up again
+pub(crate) fn run_bloom(
+    render_context: &mut RenderContext,
+    pipelines: &PostProcessPipelines,
+    textures: &PostProcessTextures,
+) {
+    for (mip, bind_group) in textures.downsample_bind_groups.iter().enumerate() {
+        let pipeline = if mip == 0 {
+            &pipelines.downsample_first
+        } else {
+            &pipelines.downsample
+        };
+        fullscreen_pass(
+            render_context,
+            "bloom_downsample_pass",
+            &textures.bloom_mips[mip],
+            None,
+            true,
+            pipeline,
+            bind_group,
+        );
+    }
+    for (mip, bind_group) in textures.upsample_bind_groups.iter().enumerate().rev() {
+        fullscreen_pass(
+            render_context,
+            "bloom_upsample_pass",
+            &textures.bloom_mips[mip],
+            None,
+            false,
+            &pipelines.upsample,
+            bind_group,
+        );
+    }
+}
+
+/// Writes the HDR main pass, plus bloom, to `target` after exposure and
+/// tonemapping
+pub(crate) fn run_composite(
+    render_context: &mut RenderContext,
+    pipelines: &PostProcessPipelines,
+    textures: &PostProcessTextures,
+    target: &TextureView,
+    viewport: Option<&Viewport>,
+) {
+    // The whole HDR texture is squeezed into the viewport, which keeps the
+    // aspect ratio since the view was rendered with the viewport's projection.
+    // Other views may share the target, so it's only cleared without one.
+    fullscreen_pass(
+        render_context,
+        "composite_pass",
+        target,
+        viewport,
+        viewport.is_none(),
+        &pipelines.composite,
+        &textures.composite_bind_group,
+    );
+}
+
+fn fullscreen_pass(
+    render_context: &mut RenderContext,
+    label: &'static str,
+    target: &TextureView,
+    viewport: Option<&Viewport>,
+    clear: bool,
+    pipeline: &RenderPipeline,
+    bind_group: &BindGroup,
+) {
+    let mut pass = render_context
+        .command_encoder()
+        .begin_render_pass(&RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: if clear {
+                        LoadOp::Clear(LinearRgba::BLACK.into())
+                    } else {
+                        LoadOp::Load
+                    },
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+    if let Some(viewport) = viewport {
+        pass.set_viewport(
+            viewport.physical_position.x as f32,
+            viewport.physical_position.y as f32,
+            viewport.physical_size.x as f32,
+            viewport.physical_size.y as f32,
+            viewport.depth.start,
+            viewport.depth.end,
+        );
+    }
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
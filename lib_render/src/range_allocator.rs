@@ -0,0 +1,137 @@
+use std::ops::Range;
+
+/// First-fit allocator handing out disjoint ranges of `0..size`.
+#[derive(Default)]
+pub(crate) struct RangeAllocator {
+    size: u32,
+    /// Sorted by start, never overlapping or touching
+    free_ranges: Vec<Range<u32>>,
+}
+
+impl RangeAllocator {
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn allocate(&mut self, len: u32) -> Option<Range<u32>> {
+        let index = self
+            .free_ranges
+            .iter()
+            .position(|free| free.end - free.start >= len)?;
+        let free = &mut self.free_ranges[index];
+        let range = free.start..free.start + len;
+        free.start += len;
+        if free.start == free.end {
+            self.free_ranges.remove(index);
+        }
+        Some(range)
+    }
+
+    pub fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        let index = self
+            .free_ranges
+            .partition_point(|free| free.start < range.start);
+        self.free_ranges.insert(index, range);
+        if index + 1 < self.free_ranges.len()
+            && self.free_ranges[index].end == self.free_ranges[index + 1].start
+        {
+            let next = self.free_ranges.remove(index + 1);
+            self.free_ranges[index].end = next.end;
+        }
+        if index > 0 && self.free_ranges[index - 1].end == self.free_ranges[index].start {
+            let current = self.free_ranges.remove(index);
+            self.free_ranges[index - 1].end = current.end;
+        }
+    }
+
+    /// Extends the allocatable space to `0..size`.
+    pub fn grow(&mut self, size: u32) {
+        let old_size = self.size;
+        self.size = size;
+        self.free(old_size..size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_size(size: u32) -> RangeAllocator {
+        let mut allocator = RangeAllocator::default();
+        allocator.grow(size);
+        allocator
+    }
+
+    #[test]
+    fn allocates_in_order_from_the_start() {
+        let mut allocator = with_size(10);
+        assert_eq!(allocator.allocate(3), Some(0..3));
+        assert_eq!(allocator.allocate(4), Some(3..7));
+        assert_eq!(allocator.free_ranges, [7..10]);
+    }
+
+    #[test]
+    fn reuses_freed_ranges() {
+        let mut allocator = with_size(10);
+        let first = allocator.allocate(5).unwrap();
+        allocator.allocate(5).unwrap();
+        allocator.free(first);
+        assert_eq!(allocator.allocate(5), Some(0..5));
+    }
+
+    #[test]
+    fn coalesces_freed_neighbours() {
+        let mut allocator = with_size(9);
+        let a = allocator.allocate(3).unwrap();
+        let b = allocator.allocate(3).unwrap();
+        let c = allocator.allocate(3).unwrap();
+        allocator.free(a);
+        allocator.free(c);
+        assert_eq!(allocator.free_ranges, [0..3, 6..9]);
+        // Touches the free ranges on both sides
+        allocator.free(b);
+        assert_eq!(allocator.free_ranges, [0..9]);
+        assert_eq!(allocator.allocate(9), Some(0..9));
+    }
+
+    #[test]
+    fn skips_free_ranges_too_small_to_fit() {
+        let mut allocator = with_size(10);
+        let ranges: Vec<_> = (0..5).map(|_| allocator.allocate(2).unwrap()).collect();
+        allocator.free(ranges[1].clone());
+        allocator.free(ranges[3].clone());
+        // 4 free in total, but no 4 in a row
+        assert_eq!(allocator.allocate(4), None);
+        assert_eq!(allocator.allocate(2), Some(2..4));
+        assert_eq!(allocator.allocate(2), Some(6..8));
+    }
+
+    #[test]
+    fn runs_out_until_grown() {
+        let mut allocator = with_size(4);
+        assert_eq!(allocator.allocate(4), Some(0..4));
+        assert_eq!(allocator.allocate(1), None);
+        allocator.grow(8);
+        assert_eq!(allocator.size(), 8);
+        assert_eq!(allocator.allocate(4), Some(4..8));
+    }
+
+    #[test]
+    fn growing_joins_the_free_space_at_the_end() {
+        let mut allocator = with_size(4);
+        allocator.allocate(2).unwrap();
+        allocator.grow(8);
+        assert_eq!(allocator.free_ranges, [2..8]);
+    }
+
+    #[test]
+    fn ignores_empty_frees() {
+        let mut allocator = with_size(4);
+        allocator.allocate(4).unwrap();
+        allocator.free(2..2);
+        assert!(allocator.free_ranges.is_empty());
+    }
+}
@@ -2,25 +2,49 @@ use std::ops::Deref;
 
 use bevy::ecs::query::QueryData;
 use bevy::render::camera::ExtractedCamera;
+use bevy::render::primitives::Frustum;
 use bevy::render::render_graph::{RenderGraphContext, ViewNode};
 use bevy::render::render_resource::{
-    IndexFormat, LoadOp, Operations, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
-    RenderPassDescriptor, StoreOp,
+    BindGroup, ComputePassDescriptor, LoadOp, Operations, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline, StoreOp, TextureView,
 };
 use bevy::render::renderer::RenderContext;
-use bevy::render::view::ViewTarget;
-use bevy::{prelude::*, render::renderer::RenderQueue};
+use bevy::render::view::{ExtractedView, ViewDepthTexture, ViewTarget};
+use bevy::{
+    prelude::*,
+    render::renderer::{RenderDevice, RenderQueue},
+};
 
+use crate::bind_group_cache::BindGroupCache;
+use crate::culling::{
+    GpuCullingBuffers, GpuCullingPipeline, ViewFrusta, VisibleChunks, chunk_aabb,
+    prepare_gpu_culling,
+};
+use crate::debug_lines::{
+    DebugLineBuffer, DebugLinePipeline, DebugLines, DebugOverlaySettings, create_line_buffer,
+};
+use crate::oit::{self, OitPipeline, OitTextures};
+use crate::outline::{BlockOutlineBuffer, BlockOutlinePipeline, prepare_block_outline};
+use crate::particles::{
+    PARTICLE_VERTEX_COUNT, ParticleBuffer, ParticlePipeline, prepare_particles,
+};
 use crate::pipeline::{
-    GlobalsUniformBindGroup, GlobalsUniformBuffer, IndexBuffer, MainPassDepth, MyShadowMapPipeline,
-    ShadowMapTextureBindGroup, ShadowPassDepth, ShadowPassGlobalsUniformBindGroup,
-    ShadowPassGlobalsUniformBuffer,
+    ChunkOffsetsBuffer, DepthTexture, MainPassDepth, MsaaTextures, MyCutoutPipeline,
+    MyDepthPrepassPipeline, MyOitAccumulatePipeline, MyShadowMapPipeline, MySkyPipeline,
+    MyTransparentPipeline, QUAD_VERTEX_COUNT, SHADOW_MAP_SIZE, ShadowMapTextureBindGroup,
+    ShadowPassDepth, SpecializedPipelines, ViewGlobals,
 };
+use crate::post_process::{self, PostProcessData, PostProcessPipelines, PostProcessTextures};
+use crate::ssao::{SsaoPipeline, SsaoTextures};
+use crate::stats::{RenderStats, RenderStatsCollector};
 use crate::texture::TextureBindGroup;
-use crate::vertex::VertexBuffer;
-use crate::{InstanceBuffer, InstanceBuffers};
+use crate::{InstanceBuffers, QuadBucket};
 use crate::{
-    globals::{AmbientLight, CameraData, DirectionalLight, FogSettings, GlobalsData, StartupTime},
+    globals::{
+        AmbientLight, BloomSettings, CameraInFluid, CloudSettings, DepthPrepass, DirectionalLight,
+        FogSettings, GlobalsData, MAX_POINT_LIGHTS, NightSky, PointLightData, PointLightsData,
+        ShadowSettings, SkyColor, SsaoSettings, StartupTime, TonemappingSettings,
+    },
     pipeline::MyRenderPipeline,
 };
 
@@ -28,107 +52,240 @@ use crate::{
 pub struct MyRenderNodeLabel;
 
 #[derive(Default)]
-pub struct MyRenderNode;
+pub(crate) struct MyRenderNode {
+    shadows: bool,
+    depth_prepass: bool,
+    ssao: bool,
+    bloom: bool,
+}
 
 impl ViewNode for MyRenderNode {
-    // choose the appropriate ViewQuery type for your node; `()` is a no-op
-    type ViewQuery = ();
+    // Views without `VisibleChunks` aren't rendered by a `RenderCamera`, and
+    // are skipped
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ExtractedCamera,
+        &'static ExtractedView,
+        &'static VisibleChunks,
+        &'static ViewGlobals,
+        &'static ViewFrusta,
+        &'static GpuCullingBuffers,
+        &'static ViewDepthTexture,
+    );
 
     fn update(&mut self, world: &mut World) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("terrain_render_node_update").entered();
         if !world.contains_resource::<MyRenderPipeline>() {
             return;
         }
-        // Update globals buffer
-        let CameraData {
-            projection_matrix,
-            position: camera_position,
-        } = world.resource::<CameraData>();
+        // Globals shared by every view
         let StartupTime(startup_time) = world.resource::<StartupTime>();
         let elapsed_seconds = startup_time.elapsed().as_secs_f32();
 
         let mut globals = GlobalsData::default();
         globals.elapsed_seconds = elapsed_seconds;
-        globals.projection_matrix = projection_matrix.to_cols_array_2d();
-        globals.camera_position = camera_position.to_array();
         if let Some(AmbientLight(colour)) = world.get_resource::<AmbientLight>() {
-            globals.ambient_light = colour.to_srgba().to_f32_array_no_alpha();
+            globals.ambient_light = colour.to_linear().to_f32_array_no_alpha();
         }
-        if let Some(directional_light) = world.get_resource::<DirectionalLight>() {
-            globals.directional_light = directional_light.color.to_srgba().to_f32_array_no_alpha();
+        let directional_light = world.get_resource::<DirectionalLight>().copied();
+        if let Some(directional_light) = directional_light {
+            globals.directional_light = directional_light.color.to_linear().to_f32_array_no_alpha();
             globals.directional_light_direction = directional_light.direction.to_array();
-            const SHADOW_SIZE: f32 = 128.0;
-            const NEGATIVE_Z: Mat4 = Mat4::from_cols_array_2d(&[
-                [1., 0., 0., 0.],
-                [0., 1., 0., 0.],
-                [0., 0., -1., 0.],
-                [0., 0., 1., 1.],
-            ]);
-            let shadow_projection = NEGATIVE_Z
-                * Mat4::orthographic_rh(
-                    -SHADOW_SIZE,
-                    SHADOW_SIZE,
-                    -SHADOW_SIZE,
-                    SHADOW_SIZE,
-                    -SHADOW_SIZE * 2.,
-                    SHADOW_SIZE * 2.,
-                )
-                * Transform::from_translation(globals.camera_position.into())
-                    .looking_to(directional_light.direction, Vec3::Y)
-                    .compute_matrix()
-                    .inverse();
-            globals.shadow_map_projection = shadow_projection.to_cols_array_2d();
         }
+        let shadow_settings = world
+            .get_resource::<ShadowSettings>()
+            .copied()
+            .unwrap_or_default();
+        globals.shadow_depth_bias = shadow_settings.depth_bias;
+        globals.shadow_normal_offset = shadow_settings.normal_offset;
+        globals.shadow_pcf_radius = shadow_settings.pcf_radius;
+        let features = world
+            .resource::<SpecializedPipelines>()
+            .active_features()
+            .unwrap_or_default();
+        self.shadows = features.shadows;
+        // The prepass depth isn't multisampled, so it can't be shared with
+        // the MSAA passes
+        self.depth_prepass = features.msaa_samples == 1
+            && world
+                .get_resource::<DepthPrepass>()
+                .is_some_and(|DepthPrepass(enabled)| *enabled);
+        let ssao_settings = world
+            .get_resource::<SsaoSettings>()
+            .copied()
+            .unwrap_or_default();
+        // SSAO reads the depth written by the prepass
+        self.ssao = self.depth_prepass
+            && features.ambient_occlusion
+            && ssao_settings.enabled
+            && ssao_settings.sample_count > 0;
+        if self.ssao {
+            globals.ssao_sample_count = ssao_settings.sample_count;
+        }
+        globals.ssao_radius = ssao_settings.radius;
+        globals.ssao_intensity = ssao_settings.intensity;
+        globals.ssao_bias = ssao_settings.bias;
+        let sky_color = world
+            .get_resource::<SkyColor>()
+            .map_or(crate::SKY_COLOR, |SkyColor(color)| *color);
+        globals.sky_color = sky_color.to_linear().to_f32_array_no_alpha();
+        let night_sky = world
+            .get_resource::<NightSky>()
+            .copied()
+            .unwrap_or_default();
+        globals.star_visibility = night_sky.star_visibility;
+        globals.moon_direction = night_sky.moon_direction.to_array();
+        let cloud_settings = world
+            .get_resource::<CloudSettings>()
+            .copied()
+            .unwrap_or_default();
+        globals.cloud_altitude = cloud_settings.altitude;
+        globals.cloud_wind = cloud_settings.wind.to_array();
+        globals.cloud_coverage = cloud_settings.coverage;
+        globals.cloud_density = cloud_settings.density;
+        globals.cloud_scale = cloud_settings.scale;
+        globals.camera_in_fluid = world
+            .get_resource::<CameraInFluid>()
+            .is_some_and(|in_fluid| in_fluid.0) as u32;
         if let Some(fog_settings) = world.get_resource::<FogSettings>() {
             globals.fog_color = fog_settings.color.to_linear().to_f32_array_no_alpha();
             globals.fog_b = fog_settings.b;
+            globals.fog_height_falloff = fog_settings.height_falloff;
+            globals.fog_base_height = fog_settings.base_height;
+            globals.underwater_fog_color = fog_settings
+                .underwater
+                .color
+                .to_linear()
+                .to_f32_array_no_alpha();
+            globals.underwater_fog_b = fog_settings.underwater.b;
+        }
+
+        let mut shadow_projections = Vec::new();
+        let views: Vec<_> = world
+            .query_filtered::<(Entity, &ExtractedView), With<VisibleChunks>>()
+            .iter(world)
+            .map(|(view, extracted_view)| {
+                let clip_from_world = extracted_view.clip_from_world.unwrap_or_else(|| {
+                    extracted_view.clip_from_view
+                        * extracted_view.world_from_view.compute_matrix().inverse()
+                });
+                (
+                    view,
+                    clip_from_world,
+                    extracted_view.world_from_view.translation(),
+                )
+            })
+            .collect();
+        for (view, clip_from_world, camera_position) in views {
+            let mut globals = globals.clone();
+            globals.projection_matrix = clip_from_world.to_cols_array_2d();
+            globals.inverse_projection_matrix = clip_from_world.inverse().to_cols_array_2d();
+            globals.camera_position = camera_position.to_array();
+            let mut frusta = ViewFrusta {
+                view: Frustum::from_clip_from_world(&clip_from_world),
+                ..default()
+            };
+            if let Some(directional_light) = directional_light {
+                let shadow_projection =
+                    get_shadow_map_projection(camera_position, directional_light.direction);
+                globals.shadow_map_projection = shadow_projection.to_cols_array_2d();
+                frusta.shadow = Frustum::from_clip_from_world(&shadow_projection);
+                frusta.shadow_extent = directional_light.direction * SHADOW_DEPTH;
+                shadow_projections.push(shadow_projection);
+            }
+            let point_lights =
+                nearest_point_lights(world.resource::<InstanceBuffers>(), camera_position);
+            prepare_view_globals(world, view, &globals, &point_lights);
+            prepare_gpu_culling(world, view, &frusta.view, camera_position);
+            world.entity_mut(view).insert(frusta);
         }
 
         let render_queue = world.resource::<RenderQueue>();
-        let buffer = world.resource::<GlobalsUniformBuffer>();
-        render_queue.write_buffer(&buffer.buffer, 0, bytemuck::bytes_of(&globals));
+        let stats = world.resource::<RenderStatsCollector>();
 
-        let mut shadow_pass_globals = globals.clone();
-        shadow_pass_globals.projection_matrix = globals.shadow_map_projection;
-        let shadow_pass_buffer = world.resource::<ShadowPassGlobalsUniformBuffer>();
+        let bloom_settings = world
+            .get_resource::<BloomSettings>()
+            .copied()
+            .unwrap_or_default();
+        self.bloom = bloom_settings.intensity > 0.0;
+        let mut post_process_data = PostProcessData::default();
+        post_process_data.bloom_threshold = bloom_settings.threshold;
+        post_process_data.bloom_intensity = bloom_settings.intensity;
+        let tonemapping_settings = world
+            .get_resource::<TonemappingSettings>()
+            .copied()
+            .unwrap_or_default();
+        post_process_data.exposure = tonemapping_settings.exposure.exp2();
+        post_process_data.tonemapping_operator = tonemapping_settings.operator as u32;
+        let post_process_pipelines = world.resource::<PostProcessPipelines>();
         render_queue.write_buffer(
-            &shadow_pass_buffer.buffer,
+            &post_process_pipelines.settings_buffer,
             0,
-            bytemuck::bytes_of(&shadow_pass_globals),
+            bytemuck::bytes_of(&post_process_data),
         );
+        stats.record_upload(std::mem::size_of::<PostProcessData>());
+
+        prepare_debug_lines(world, &shadow_projections);
+        prepare_block_outline(world);
+        prepare_particles(world);
     }
 
     fn run<'w>(
         &self,
         _graph: &mut RenderGraphContext<'_>,
         render_context: &mut RenderContext<'_>,
-        _view_query: <Self::ViewQuery as QueryData>::Item<'_>,
+        view_query: <Self::ViewQuery as QueryData>::Item<'_>,
         world: &'_ World,
     ) -> std::result::Result<(), bevy::render::render_graph::NodeRunError> {
-        if !world.contains_resource::<MyRenderPipeline>() {
-            return Ok(());
-        }
-        let shadow_pipeline = world.resource::<MyShadowMapPipeline>();
-        let shadow_depth = world.resource::<ShadowPassDepth>();
-        let main_pipeline = world.resource::<MyRenderPipeline>();
-        let VertexBuffer { vertex_buffer, .. } = world.resource::<VertexBuffer>();
-        let IndexBuffer {
-            buffer: index_buffer,
-            num_indices,
-        } = world.resource::<IndexBuffer>();
-        let depth = world.resource::<MainPassDepth>();
-
-        let Some(mut query) =
-            world.try_query_filtered::<(&ViewTarget, &ExtractedCamera), With<Camera>>()
+        let (
+            view_target,
+            camera,
+            view,
+            VisibleChunks(visible_chunks),
+            view_globals,
+            frusta,
+            culling_buffers,
+            view_depth,
+        ) = view_query;
+        #[cfg(feature = "trace")]
+        let _span =
+            info_span!("terrain_render_node", visible_chunks = visible_chunks.len()).entered();
+        // Anything missing is still being (re)built, so skip the frame
+        // rather than drawing with half of the pipelines
+        let (
+            Some(shadow_pipeline),
+            Some(shadow_depth),
+            Some(main_pipeline),
+            Some(depth_prepass_pipeline),
+            Some(sky_pipeline),
+            Some(cutout_pipeline),
+            Some(transparent_pipeline),
+            Some(depth),
+            Some(ssao_pipeline),
+            Some(ssao_textures),
+            Some(post_process_pipelines),
+            Some(post_process_textures),
+        ) = (
+            world.get_resource::<MyShadowMapPipeline>(),
+            world.get_resource::<ShadowPassDepth>(),
+            world.get_resource::<MyRenderPipeline>(),
+            world.get_resource::<MyDepthPrepassPipeline>(),
+            world.get_resource::<MySkyPipeline>(),
+            world.get_resource::<MyCutoutPipeline>(),
+            world.get_resource::<MyTransparentPipeline>(),
+            world.get_resource::<MainPassDepth>(),
+            world.get_resource::<SsaoPipeline>(),
+            world.get_resource::<SsaoTextures>(),
+            world.get_resource::<PostProcessPipelines>(),
+            world.get_resource::<PostProcessTextures>(),
+        )
         else {
-            panic!("Failed query for view target and extracted camera");
+            return Ok(());
         };
 
-        let GlobalsUniformBindGroup {
-            bind_group: globals_uniform_bind_group,
-        } = world.resource::<GlobalsUniformBindGroup>();
-        let ShadowPassGlobalsUniformBindGroup {
-            bind_group: shadow_pass_globals_uniform_bind_group,
-        } = world.resource::<ShadowPassGlobalsUniformBindGroup>();
+        let globals_uniform_bind_group = &view_globals.bind_group;
+        let shadow_pass_globals_uniform_bind_group = &view_globals.shadow_pass_bind_group;
         let TextureBindGroup {
             bind_group: texture_bind_group,
             ..
@@ -138,7 +295,34 @@ impl ViewNode for MyRenderNode {
             ..
         } = world.resource::<ShadowMapTextureBindGroup>();
 
-        for (view_target, _cam) in query.iter(&world) {
+        let Some(culling_pipeline) = world.get_resource::<GpuCullingPipeline>() else {
+            return Ok(());
+        };
+
+        if let Some(culling_bind_group) = &culling_buffers.bind_group {
+            let mut culling_pass =
+                render_context
+                    .command_encoder()
+                    .begin_compute_pass(&ComputePassDescriptor {
+                        label: Some("culling_pass"),
+                        timestamp_writes: None,
+                    });
+            culling_pass.set_pipeline(&culling_pipeline.pipeline);
+            culling_pass.set_bind_group(0, culling_bind_group, &[]);
+            let num_workgroups = culling_buffers.num_workgroups();
+            culling_pass.dispatch_workgroups(num_workgroups.x, num_workgroups.y, 1);
+        }
+
+        let instance_buffers = world.resource::<InstanceBuffers>();
+        let mut stats = RenderStats::default();
+        // Counts whole chunks, since the culling pass doesn't report which
+        // quads it dropped
+        let mut opaque_stats = RenderStats::default();
+        for (pos, instances) in instance_buffers.iter() {
+            let range = instances.bucket(QuadBucket::Opaque);
+            opaque_stats.record_draw(range.end - range.start, visible_chunks.contains(pos));
+        }
+        if self.shadows {
             let shadow_pass_desc = RenderPassDescriptor {
                 label: Some("shadow_pass"),
                 color_attachments: &[],
@@ -159,47 +343,36 @@ impl ViewNode for MyRenderNode {
                     .begin_render_pass(&shadow_pass_desc);
                 shadow_pass.set_pipeline(&shadow_pipeline.pipeline);
                 shadow_pass.set_bind_group(0, shadow_pass_globals_uniform_bind_group, &[]);
-                shadow_pass.set_index_buffer(*index_buffer.slice(..).deref(), IndexFormat::Uint16);
-                shadow_pass.set_vertex_buffer(0, *vertex_buffer.slice(..).deref());
-
-                for (
-                    pos,
-                    InstanceBuffer {
-                        buffer: instance_buffer,
-                        num_instances,
-                    },
-                ) in world
-                    .resource::<InstanceBuffers>()
-                    .chunk_pos_to_buffer
-                    .iter()
-                {
-                    if num_instances == &0 {
+                if let (Some(instance_buffer), Some(chunk_slot_buffer)) = (
+                    instance_buffers.buffer(),
+                    instance_buffers.chunk_slot_buffer(),
+                ) {
+                    shadow_pass.set_vertex_buffer(0, *instance_buffer.slice(..).deref());
+                    shadow_pass.set_vertex_buffer(1, *chunk_slot_buffer.slice(..).deref());
+                }
+                // Transparent quads don't cast shadows. Cutout quads cast
+                // shadows of their whole quad, since the shadow pass has no
+                // fragment stage to alpha test with.
+                for (pos, instances) in instance_buffers.iter() {
+                    let range = instances.bucket(QuadBucket::Opaque).start
+                        ..instances.bucket(QuadBucket::Cutout).end;
+                    if range.is_empty() {
                         continue;
                     }
-                    let chunk_pos_array = pos.to_array();
-                    shadow_pass.set_push_constants(
-                        bevy::render::render_resource::ShaderStages::VERTEX,
-                        0, // offset
-                        bytemuck::cast_slice(&[chunk_pos_array]),
-                    );
-                    shadow_pass.set_vertex_buffer(1, *instance_buffer.slice(..).deref());
-                    shadow_pass.draw_indexed(0..*num_indices, 0, 0..*num_instances);
+                    let visible = frusta.is_shadow_caster_visible(*pos);
+                    stats.record_draw(range.end - range.start, visible);
+                    if visible {
+                        shadow_pass.draw(0..QUAD_VERTEX_COUNT, range);
+                    }
                 }
             }
+        }
 
-            let view = view_target.main_texture_view();
-            let color_attachment = RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: Operations {
-                    load: LoadOp::Clear(crate::SKY_COLOR.to_linear().into()),
-                    store: StoreOp::Store,
-                },
-            };
-
-            let desc = RenderPassDescriptor {
-                label: Some("triangle_pass"),
-                color_attachments: &[Some(color_attachment)],
+        let depth_prepass = self.depth_prepass;
+        if depth_prepass {
+            let prepass_desc = RenderPassDescriptor {
+                label: Some("depth_prepass"),
+                color_attachments: &[],
                 depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
                     view: &depth.0.view,
                     depth_ops: Some(Operations {
@@ -211,42 +384,495 @@ impl ViewNode for MyRenderNode {
                 timestamp_writes: None,
                 occlusion_query_set: None,
             };
+            let mut prepass = render_context
+                .command_encoder()
+                .begin_render_pass(&prepass_desc);
+            prepass.set_pipeline(&depth_prepass_pipeline.pipeline);
+            prepass.set_bind_group(0, globals_uniform_bind_group, &[]);
+            prepass.set_vertex_buffer(0, *culling_buffers.instances.slice(..).deref());
+            prepass.set_vertex_buffer(1, *culling_buffers.chunk_slots.slice(..).deref());
+            prepass.draw_indirect(&culling_buffers.draw, 0);
+            stats += opaque_stats;
+        }
 
-            {
-                let mut pass = render_context.command_encoder().begin_render_pass(&desc);
-                pass.set_pipeline(&main_pipeline.pipeline);
-                pass.set_bind_group(0, globals_uniform_bind_group, &[]);
-                pass.set_bind_group(1, texture_bind_group, &[]);
-                pass.set_bind_group(2, shadow_map_bind_group, &[]);
-                pass.set_index_buffer(*index_buffer.slice(..).deref(), IndexFormat::Uint16);
-                pass.set_vertex_buffer(0, *vertex_buffer.slice(..).deref());
-
-                for (
-                    pos,
-                    InstanceBuffer {
-                        buffer: instance_buffer,
-                        num_instances,
+        if self.ssao {
+            let ssao_desc = RenderPassDescriptor {
+                label: Some("ssao_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &ssao_textures.target,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(LinearRgba::WHITE.into()),
+                        store: StoreOp::Store,
                     },
-                ) in world
-                    .resource::<InstanceBuffers>()
-                    .chunk_pos_to_buffer
-                    .iter()
-                {
-                    if num_instances == &0 {
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            };
+            let mut ssao_pass = render_context
+                .command_encoder()
+                .begin_render_pass(&ssao_desc);
+            ssao_pass.set_pipeline(&ssao_pipeline.pipeline);
+            ssao_pass.set_bind_group(0, globals_uniform_bind_group, &[]);
+            ssao_pass.set_bind_group(1, &ssao_textures.depth_bind_group, &[]);
+            ssao_pass.draw(0..3, 0..1);
+            stats.draw_calls += 1;
+        }
+
+        let targets = match world.get_resource::<MsaaTextures>() {
+            Some(msaa) => MainPassTargets {
+                color: &msaa.color,
+                resolve_target: Some(&post_process_textures.hdr),
+                depth: &msaa.depth.view,
+            },
+            None => MainPassTargets {
+                color: &post_process_textures.hdr,
+                resolve_target: None,
+                depth: &depth.0.view,
+            },
+        };
+        {
+            let sky_desc = RenderPassDescriptor {
+                label: Some("sky_pass"),
+                color_attachments: &[Some(targets.color_attachment(Some(LinearRgba::BLACK)))],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            };
+            let mut sky_pass = render_context
+                .command_encoder()
+                .begin_render_pass(&sky_desc);
+            sky_pass.set_pipeline(&sky_pipeline.pipeline);
+            sky_pass.set_bind_group(0, globals_uniform_bind_group, &[]);
+            sky_pass.draw(0..3, 0..1);
+            stats.draw_calls += 1;
+        }
+
+        let desc = RenderPassDescriptor {
+            label: Some("triangle_pass"),
+            color_attachments: &[Some(targets.color_attachment(None))],
+            depth_stencil_attachment: Some(targets.depth_attachment(if depth_prepass {
+                None
+            } else {
+                Some(0.0)
+            })),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        };
+
+        {
+            let mut pass = render_context.command_encoder().begin_render_pass(&desc);
+            pass.set_pipeline(if depth_prepass {
+                &main_pipeline.depth_equal_pipeline
+            } else {
+                &main_pipeline.pipeline
+            });
+            pass.set_bind_group(0, globals_uniform_bind_group, &[]);
+            pass.set_bind_group(1, texture_bind_group, &[]);
+            pass.set_bind_group(2, shadow_map_bind_group, &[]);
+            pass.set_bind_group(3, &ssao_textures.output_bind_group, &[]);
+            // Visibility is decided by the culling pass, which packs the
+            // opaque quads that survive it into its own buffers
+            pass.set_vertex_buffer(0, *culling_buffers.instances.slice(..).deref());
+            pass.set_vertex_buffer(1, *culling_buffers.chunk_slots.slice(..).deref());
+            pass.draw_indirect(&culling_buffers.draw, 0);
+            stats += opaque_stats;
+            if let (Some(instance_buffer), Some(chunk_slot_buffer)) = (
+                instance_buffers.buffer(),
+                instance_buffers.chunk_slot_buffer(),
+            ) {
+                pass.set_vertex_buffer(0, *instance_buffer.slice(..).deref());
+                pass.set_vertex_buffer(1, *chunk_slot_buffer.slice(..).deref());
+                pass.set_pipeline(&cutout_pipeline.pipeline);
+                for (pos, instances) in instance_buffers.iter() {
+                    let range = instances.bucket(QuadBucket::Cutout);
+                    if range.is_empty() {
                         continue;
                     }
-                    let chunk_pos_array = pos.to_array();
-                    pass.set_push_constants(
-                        bevy::render::render_resource::ShaderStages::VERTEX,
-                        0, // offset
-                        bytemuck::cast_slice(&[chunk_pos_array]),
-                    );
-                    pass.set_vertex_buffer(1, *instance_buffer.slice(..).deref());
-                    pass.draw_indexed(0..*num_indices, 0, 0..*num_instances);
+                    let visible = visible_chunks.contains(pos);
+                    stats.record_draw(range.end - range.start, visible);
+                    if visible {
+                        pass.draw(0..QUAD_VERTEX_COUNT, range);
+                    }
+                }
+            }
+        }
+
+        if let (Some(outline_pipeline), Some(outline_buffer)) = (
+            world.get_resource::<BlockOutlinePipeline>(),
+            world.get_resource::<BlockOutlineBuffer>(),
+        ) {
+            draw_lines(
+                render_context,
+                "block_outline_pass",
+                &targets,
+                &outline_pipeline.pipeline,
+                globals_uniform_bind_group,
+                &outline_buffer.0,
+            );
+            stats.draw_calls += 1;
+        }
+
+        // Drawn before transparent terrain, so debris is seen through water
+        if let (Some(particle_pipeline), Some(particle_buffer)) = (
+            world.get_resource::<ParticlePipeline>(),
+            world.get_resource::<ParticleBuffer>(),
+        ) {
+            let particle_desc = RenderPassDescriptor {
+                label: Some("particle_pass"),
+                color_attachments: &[Some(targets.color_attachment(None))],
+                depth_stencil_attachment: Some(targets.depth_attachment(None)),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            };
+            let mut pass = render_context
+                .command_encoder()
+                .begin_render_pass(&particle_desc);
+            pass.set_pipeline(&particle_pipeline.pipeline);
+            pass.set_bind_group(0, globals_uniform_bind_group, &[]);
+            pass.set_bind_group(1, texture_bind_group, &[]);
+            pass.set_bind_group(2, shadow_map_bind_group, &[]);
+            pass.set_bind_group(3, &ssao_textures.output_bind_group, &[]);
+            pass.set_vertex_buffer(0, *particle_buffer.buffer.slice(..).deref());
+            pass.draw(0..PARTICLE_VERTEX_COUNT, 0..particle_buffer.instance_count);
+            stats.draw_calls += 1;
+        }
+
+        // With order independent transparency, chunks are accumulated in any
+        // order. Otherwise they're blended back to front, without sorting the
+        // quads within each chunk.
+        let oit = match (
+            world.get_resource::<MyOitAccumulatePipeline>(),
+            world.get_resource::<OitPipeline>(),
+            world.get_resource::<OitTextures>(),
+        ) {
+            (Some(accumulate_pipeline), Some(oit_pipeline), Some(oit_textures)) => {
+                Some((accumulate_pipeline, oit_pipeline, oit_textures))
+            }
+            _ => None,
+        };
+        let camera_position = view.world_from_view.translation();
+        let mut transparent_chunks = Vec::new();
+        for (pos, instances) in instance_buffers.iter() {
+            let range = instances.bucket(QuadBucket::Transparent);
+            if range.is_empty() {
+                continue;
+            }
+            let visible = visible_chunks.contains(pos);
+            stats.record_draw(range.end - range.start, visible);
+            if visible {
+                let center = Vec3::from(chunk_aabb(*pos).center);
+                transparent_chunks.push((center.distance_squared(camera_position), range));
+            }
+        }
+        if oit.is_none() {
+            transparent_chunks.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        }
+
+        let blended_attachments = [Some(targets.color_attachment(None))];
+        let accumulation_attachments;
+        let (transparent_pipeline, color_attachments): (&RenderPipeline, &[_]) = match oit {
+            Some((accumulate_pipeline, _, oit_textures)) => {
+                accumulation_attachments = oit::accumulation_attachments(oit_textures);
+                (&accumulate_pipeline.pipeline, &accumulation_attachments)
+            }
+            None => (&transparent_pipeline.pipeline, &blended_attachments),
+        };
+        let transparent_desc = RenderPassDescriptor {
+            label: Some("transparent_pass"),
+            color_attachments,
+            depth_stencil_attachment: Some(targets.depth_attachment(None)),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        };
+
+        {
+            let mut pass = render_context
+                .command_encoder()
+                .begin_render_pass(&transparent_desc);
+            pass.set_pipeline(transparent_pipeline);
+            pass.set_bind_group(0, globals_uniform_bind_group, &[]);
+            pass.set_bind_group(1, texture_bind_group, &[]);
+            pass.set_bind_group(2, shadow_map_bind_group, &[]);
+            pass.set_bind_group(3, &ssao_textures.output_bind_group, &[]);
+            if let (Some(instance_buffer), Some(chunk_slot_buffer)) = (
+                instance_buffers.buffer(),
+                instance_buffers.chunk_slot_buffer(),
+            ) {
+                pass.set_vertex_buffer(0, *instance_buffer.slice(..).deref());
+                pass.set_vertex_buffer(1, *chunk_slot_buffer.slice(..).deref());
+                for (_, range) in transparent_chunks {
+                    pass.draw(0..QUAD_VERTEX_COUNT, range);
                 }
             }
         }
+        if let Some((_, oit_pipeline, oit_textures)) = oit {
+            oit::run_composite(
+                render_context,
+                oit_pipeline,
+                oit_textures,
+                &post_process_textures.hdr,
+            );
+            stats.draw_calls += 1;
+        }
+
+        if let (Some(debug_line_pipeline), Some(debug_line_buffer)) = (
+            world.get_resource::<DebugLinePipeline>(),
+            world.get_resource::<DebugLineBuffer>(),
+        ) {
+            draw_lines(
+                render_context,
+                "debug_line_pass",
+                &targets,
+                &debug_line_pipeline.pipeline,
+                globals_uniform_bind_group,
+                debug_line_buffer,
+            );
+            stats.draw_calls += 1;
+        }
+
+        let terrain_depth = world
+            .get_resource::<MsaaTextures>()
+            .map_or(&depth.0, |msaa| &msaa.depth);
+        share_depth(render_context, terrain_depth, view_depth);
+
+        if self.bloom {
+            post_process::run_bloom(
+                render_context,
+                post_process_pipelines,
+                post_process_textures,
+            );
+            stats.draw_calls += post_process_textures.bloom_draw_count();
+        }
+        post_process::run_composite(
+            render_context,
+            post_process_pipelines,
+            post_process_textures,
+            view_target.main_texture_view(),
+            camera.viewport.as_ref(),
+        );
+        stats.draw_calls += 1;
+
+        world.resource::<RenderStatsCollector>().record(stats);
 
         Ok(())
     }
 }
+
+/// Attachments of the passes drawing into the HDR texture. With MSAA these
+/// draw into the multisampled textures and resolve into the HDR texture.
+struct MainPassTargets<'a> {
+    color: &'a TextureView,
+    resolve_target: Option<&'a TextureView>,
+    depth: &'a TextureView,
+}
+
+impl<'a> MainPassTargets<'a> {
+    /// Loads the existing color, or clears it to `clear`
+    fn color_attachment(&self, clear: Option<LinearRgba>) -> RenderPassColorAttachment<'a> {
+        RenderPassColorAttachment {
+            view: self.color,
+            resolve_target: self.resolve_target.map(|view| &**view),
+            ops: Operations {
+                load: clear.map_or(LoadOp::Load, |color| LoadOp::Clear(color.into())),
+                store: StoreOp::Store,
+            },
+        }
+    }
+
+    /// Loads the existing depth, or clears it to `clear`
+    fn depth_attachment(&self, clear: Option<f32>) -> RenderPassDepthStencilAttachment<'a> {
+        RenderPassDepthStencilAttachment {
+            view: self.depth,
+            depth_ops: Some(Operations {
+                load: clear.map_or(LoadOp::Load, LoadOp::Clear),
+                store: StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }
+    }
+}
+
+/// Copies the terrain's depth into the view's depth texture, for Bevy's main
+/// passes to test meshes against. Skipped while the two don't match, such as
+/// on the frame after the target is resized.
+fn share_depth(
+    render_context: &mut RenderContext<'_>,
+    terrain_depth: &DepthTexture,
+    view_depth: &ViewDepthTexture,
+) {
+    let source = &terrain_depth.texture;
+    let target = &view_depth.texture;
+    if source.size() != target.size()
+        || source.sample_count() != target.sample_count()
+        || source.format() != target.format()
+    {
+        return;
+    }
+    render_context.command_encoder().copy_texture_to_texture(
+        source.as_image_copy(),
+        target.as_image_copy(),
+        target.size(),
+    );
+}
+
+/// Draws `lines` over the terrain
+fn draw_lines(
+    render_context: &mut RenderContext<'_>,
+    label: &'static str,
+    targets: &MainPassTargets,
+    pipeline: &RenderPipeline,
+    globals_bind_group: &BindGroup,
+    lines: &DebugLineBuffer,
+) {
+    let desc = RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(targets.color_attachment(None))],
+        depth_stencil_attachment: Some(targets.depth_attachment(None)),
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    };
+    let mut pass = render_context.command_encoder().begin_render_pass(&desc);
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, globals_bind_group, &[]);
+    pass.set_vertex_buffer(0, *lines.buffer.slice(..).deref());
+    pass.draw(0..lines.vertex_count, 0..1);
+}
+
+/// Writes the globals of `view` and its shadow pass, creating the view's
+/// buffers the first time it's rendered
+fn prepare_view_globals(
+    world: &mut World,
+    view: Entity,
+    globals: &GlobalsData,
+    point_lights: &PointLightsData,
+) {
+    if !world.entity(view).contains::<ViewGlobals>() {
+        let view_globals = world.resource_scope(|world, mut cache: Mut<BindGroupCache>| {
+            let specialized = world.get_resource::<SpecializedPipelines>()?;
+            let chunk_offsets = world.get_resource::<ChunkOffsetsBuffer>()?;
+            Some(ViewGlobals::new(
+                world.resource::<RenderDevice>(),
+                &mut cache,
+                &specialized.globals_layout,
+                chunk_offsets,
+            ))
+        });
+        let Some(view_globals) = view_globals else {
+            return;
+        };
+        world.entity_mut(view).insert(view_globals);
+    }
+    let view_globals = world.get::<ViewGlobals>(view).unwrap();
+    let render_queue = world.resource::<RenderQueue>();
+    render_queue.write_buffer(&view_globals.buffer, 0, bytemuck::bytes_of(globals));
+
+    let mut shadow_pass_globals = globals.clone();
+    shadow_pass_globals.projection_matrix = globals.shadow_map_projection;
+    render_queue.write_buffer(
+        &view_globals.shadow_pass_buffer,
+        0,
+        bytemuck::bytes_of(&shadow_pass_globals),
+    );
+    render_queue.write_buffer(
+        &view_globals.point_lights_buffer,
+        0,
+        bytemuck::bytes_of(point_lights),
+    );
+    world.resource::<RenderStatsCollector>().record_upload(
+        2 * std::mem::size_of::<GlobalsData>() + std::mem::size_of::<PointLightsData>(),
+    );
+}
+
+/// The lights of the emissive blocks closest to `camera_position`
+fn nearest_point_lights(
+    instance_buffers: &InstanceBuffers,
+    camera_position: Vec3,
+) -> PointLightsData {
+    let mut lights = instance_buffers
+        .iter()
+        .flat_map(|(_, instances)| instances.lights.iter().copied())
+        .collect::<Vec<_>>();
+    let distance =
+        |light: &PointLightData| Vec3::from(light.position).distance_squared(camera_position);
+    if lights.len() > MAX_POINT_LIGHTS {
+        lights.select_nth_unstable_by(MAX_POINT_LIGHTS, |a, b| distance(a).total_cmp(&distance(b)));
+    }
+    PointLightsData::new(&lights)
+}
+
+/// Uploads this frame's debug lines, including the renderer's own overlays
+fn prepare_debug_lines(world: &mut World, shadow_projections: &[Mat4]) {
+    let mut debug_lines = world
+        .get_resource::<DebugLines>()
+        .cloned()
+        .unwrap_or_default();
+    let overlay_settings = world
+        .get_resource::<DebugOverlaySettings>()
+        .copied()
+        .unwrap_or_default();
+    if overlay_settings.shadow_frustum {
+        for shadow_projection in shadow_projections {
+            // Depth is reversed, so the near plane is at 1.0
+            let clip_to_world = shadow_projection.inverse();
+            let corners = std::array::from_fn(|i| {
+                let ndc = Vec3::new(
+                    if i & 1 == 0 { -1. } else { 1. },
+                    if i & 2 == 0 { -1. } else { 1. },
+                    if i & 4 == 0 { 1. } else { 0. },
+                );
+                clip_to_world.project_point3(ndc)
+            });
+            debug_lines.cuboid(corners, Color::srgb(1.0, 0.9, 0.2));
+        }
+    }
+
+    if debug_lines.vertices().is_empty() {
+        world.remove_resource::<DebugLineBuffer>();
+        return;
+    }
+    let render_device = world.resource::<RenderDevice>();
+    let buffer = create_line_buffer(render_device, "debug line buffer", debug_lines.vertices());
+    world
+        .resource::<RenderStatsCollector>()
+        .record_upload(std::mem::size_of_val(debug_lines.vertices()));
+    world.insert_resource(buffer);
+}
+
+/// Half the width of the area covered by the shadow map, in blocks
+const SHADOW_SIZE: f32 = 128.0;
+/// Depth of the shadow map along the light direction, in blocks
+const SHADOW_DEPTH: f32 = SHADOW_SIZE * 4.;
+
+fn get_shadow_map_projection(camera_position: Vec3, light_direction: Dir3) -> Mat4 {
+    const NEGATIVE_Z: Mat4 = Mat4::from_cols_array_2d(&[
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., -1., 0.],
+        [0., 0., 1., 1.],
+    ]);
+    // Move the shadow map with the camera, but only in whole texel steps.
+    // Otherwise every sub-texel camera movement rasterizes the shadow casters
+    // slightly differently and shadow edges crawl and shimmer.
+    let light_rotation = Transform::default()
+        .looking_to(light_direction, Vec3::Y)
+        .rotation;
+    let texel_size = 2. * SHADOW_SIZE / SHADOW_MAP_SIZE as f32;
+    let camera_in_light_space = light_rotation.inverse() * camera_position;
+    let snapped_origin =
+        light_rotation * ((camera_in_light_space / texel_size).round() * texel_size);
+    NEGATIVE_Z
+        * Mat4::orthographic_rh(
+            -SHADOW_SIZE,
+            SHADOW_SIZE,
+            -SHADOW_SIZE,
+            SHADOW_SIZE,
+            -SHADOW_DEPTH / 2.,
+            SHADOW_DEPTH / 2.,
+        )
+        * Transform::from_translation(snapped_origin)
+            .looking_to(light_direction, Vec3::Y)
+            .compute_matrix()
+            .inverse()
+}
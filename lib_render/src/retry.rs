@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+use bevy::{prelude::*, render::renderer::RenderDevice};
+
+/// Longest wait between attempts, reached after a few failures in a row
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Spaces out attempts to create GPU resources after a failure, doubling the
+/// wait each time so a persistent error doesn't flood the log
+#[derive(Resource, Default)]
+pub(crate) struct PipelineRetry {
+    failures: u32,
+    next_attempt: Option<Instant>,
+}
+
+impl PipelineRetry {
+    pub fn is_ready(&self) -> bool {
+        self.next_attempt
+            .is_none_or(|next_attempt| Instant::now() >= next_attempt)
+    }
+
+    pub fn succeeded(&mut self) {
+        *self = default();
+    }
+
+    pub fn failed(&mut self, what: &str, error: &str) {
+        let delay = (Duration::from_secs(1) * 2u32.pow(self.failures.min(5))).min(MAX_RETRY_DELAY);
+        self.failures += 1;
+        self.next_attempt = Some(Instant::now() + delay);
+        error!("Failed to create {what}. Retrying in {delay:?}.\n{error}");
+    }
+}
+
+/// Runs `f`, turning any validation errors raised by the GPU resources it
+/// creates into an `Err` instead of a panic, like the errors `f` returns
+pub(crate) fn catch_validation_errors<T>(
+    render_device: &RenderDevice,
+    f: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let device = render_device.wgpu_device();
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = f();
+    match bevy::tasks::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string()),
+        None => result,
+    }
+}
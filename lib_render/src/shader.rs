@@ -0,0 +1,366 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use bevy::{
+    asset::embedded_asset,
+    prelude::*,
+    render::{
+        Extract,
+        render_resource::{ShaderModule, ShaderModuleDescriptor, ShaderSource, Source},
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{
+    culling::{GpuCullingBuffers, GpuCullingPipeline},
+    pipeline::{MyRenderPipeline, SpecializedPipelines, ViewGlobals},
+};
+
+/// Shaders that pipelines are built from
+const ENTRY_SHADERS: [&str; 8] = [
+    "triangle.wgsl",
+    "sky.wgsl",
+    "ssao.wgsl",
+    "post_process.wgsl",
+    "cull.wgsl",
+    "debug_lines.wgsl",
+    "oit.wgsl",
+    "particles.wgsl",
+];
+
+/// Shaders that only exist to be `#include`d by others
+const INCLUDE_SHADERS: [&str; 6] = [
+    "globals.wgsl",
+    "quad.wgsl",
+    "clouds.wgsl",
+    "shadow.wgsl",
+    "lighting.wgsl",
+    "fog.wgsl",
+];
+
+/// Every WGSL file under `shaders/`. Pipelines are only built once all of
+/// them have loaded.
+fn all_shaders() -> impl Iterator<Item = &'static str> {
+    ENTRY_SHADERS.into_iter().chain(INCLUDE_SHADERS)
+}
+
+/// Every def tested by `#ifdef` or `#ifndef` in the shaders. Each entry shader
+/// is validated with every combination of them set, as `RenderFeatures` can
+/// ask for any.
+const SHADER_DEFS: [&str; 4] = ["SHADOWS", "SSAO", "FOG", "FLOAT_INSTANCES"];
+
+/// Loads the shaders through the asset server, so that with Bevy's
+/// `embedded_watcher` feature enabled, saving a shader rebuilds the pipelines
+/// without restarting
+pub(crate) struct ShaderPlugin;
+
+impl Plugin for ShaderPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "shaders/triangle.wgsl");
+        embedded_asset!(app, "shaders/sky.wgsl");
+        embedded_asset!(app, "shaders/ssao.wgsl");
+        embedded_asset!(app, "shaders/post_process.wgsl");
+        embedded_asset!(app, "shaders/cull.wgsl");
+        embedded_asset!(app, "shaders/debug_lines.wgsl");
+        embedded_asset!(app, "shaders/oit.wgsl");
+        embedded_asset!(app, "shaders/particles.wgsl");
+        embedded_asset!(app, "shaders/globals.wgsl");
+        embedded_asset!(app, "shaders/quad.wgsl");
+        embedded_asset!(app, "shaders/clouds.wgsl");
+        embedded_asset!(app, "shaders/shadow.wgsl");
+        embedded_asset!(app, "shaders/lighting.wgsl");
+        embedded_asset!(app, "shaders/fog.wgsl");
+        app.init_resource::<ShaderSources>()
+            .add_systems(Startup, load_shaders)
+            .add_systems(Update, update_shader_sources)
+            .sub_app_mut(bevy::render::RenderApp)
+            .add_systems(ExtractSchedule, rebuild_pipelines_on_shader_change);
+    }
+}
+
+#[derive(Resource)]
+struct ShaderHandles(Vec<(&'static str, Handle<Shader>)>);
+
+/// The latest source of each shader that compiled
+#[derive(Resource, Default)]
+pub(crate) struct ShaderSources {
+    sources: HashMap<&'static str, Arc<str>>,
+    /// Bumped whenever a shader is reloaded, so the render world knows to
+    /// rebuild the pipelines
+    generation: u32,
+}
+
+impl ShaderSources {
+    pub fn is_loaded(&self) -> bool {
+        all_loaded(&self.sources)
+    }
+
+    /// Compiles `name` with its includes expanded and `defs` set. Fails if
+    /// the shaders haven't loaded, so check `is_loaded` first, or if they
+    /// can't be expanded with these defs.
+    pub fn create_module(
+        &self,
+        render_device: &RenderDevice,
+        name: &'static str,
+        defs: &[&str],
+    ) -> Result<ShaderModule, String> {
+        let mut defs = defs.to_vec();
+        if cfg!(feature = "float_instances") {
+            defs.push("FLOAT_INSTANCES");
+        }
+        let source = preprocess(&self.sources, name, &defs)
+            .map_err(|e| format!("Failed to preprocess shader {name}: {e}"))?;
+        Ok(
+            render_device.create_and_validate_shader_module(ShaderModuleDescriptor {
+                label: Some(name),
+                source: ShaderSource::Wgsl(source.into()),
+            }),
+        )
+    }
+}
+
+fn all_loaded(sources: &HashMap<&'static str, Arc<str>>) -> bool {
+    all_shaders().all(|name| sources.contains_key(name))
+}
+
+/// Expands the directives in shader `name`:
+/// - `#include "file.wgsl"` pastes in another shader, at most once per module
+/// - `#ifdef DEF`, `#ifndef DEF`, `#else` and `#endif` keep or drop lines
+///   depending on whether `DEF` is in `defs`
+fn preprocess(
+    sources: &HashMap<&'static str, Arc<str>>,
+    name: &str,
+    defs: &[&str],
+) -> Result<String, String> {
+    let mut output = String::new();
+    let mut included = HashSet::new();
+    expand(sources, name, defs, &mut included, &mut output)?;
+    Ok(output)
+}
+
+fn expand(
+    sources: &HashMap<&'static str, Arc<str>>,
+    name: &str,
+    defs: &[&str],
+    included: &mut HashSet<String>,
+    output: &mut String,
+) -> Result<(), String> {
+    if !included.insert(name.to_string()) {
+        return Ok(());
+    }
+    let source = sources
+        .get(name)
+        .ok_or_else(|| format!("No shader named {name}"))?;
+    // Whether each enclosing `#ifdef` block is active. Lines are only kept
+    // when all of them are.
+    let mut conditions: Vec<bool> = Vec::new();
+    for (line_index, line) in source.lines().enumerate() {
+        let location = || format!("{name}:{}", line_index + 1);
+        let trimmed = line.trim();
+        let active = conditions.iter().all(|active| *active);
+        if let Some(def) = trimmed.strip_prefix("#ifdef ") {
+            conditions.push(defs.contains(&def.trim()));
+        } else if let Some(def) = trimmed.strip_prefix("#ifndef ") {
+            conditions.push(!defs.contains(&def.trim()));
+        } else if trimmed == "#else" {
+            let condition = conditions
+                .last_mut()
+                .ok_or_else(|| format!("{}: #else without #ifdef", location()))?;
+            *condition = !*condition;
+        } else if trimmed == "#endif" {
+            conditions
+                .pop()
+                .ok_or_else(|| format!("{}: #endif without #ifdef", location()))?;
+        } else if let Some(path) = trimmed.strip_prefix("#include ") {
+            if active {
+                let path = path
+                    .trim()
+                    .strip_prefix('"')
+                    .and_then(|path| path.strip_suffix('"'))
+                    .ok_or_else(|| format!("{}: expected #include \"file.wgsl\"", location()))?;
+                expand(sources, path, defs, included, output)?;
+            }
+        } else if trimmed.starts_with('#') {
+            return Err(format!("{}: unknown directive {trimmed}", location()));
+        } else if active {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+    if !conditions.is_empty() {
+        return Err(format!("{name}: #ifdef without #endif"));
+    }
+    Ok(())
+}
+
+fn load_shaders(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let handles = all_shaders()
+        .map(|name| {
+            let path = format!("embedded://lib_render/shaders/{name}");
+            (name, asset_server.load(path))
+        })
+        .collect();
+    commands.insert_resource(ShaderHandles(handles));
+}
+
+/// Shaders are validated here rather than when the pipelines are built, so
+/// that a typo in a reloaded shader is logged and the previous version kept
+/// instead of panicking in the render world. Since a change to an included
+/// shader affects every shader including it, all entry shaders are validated
+/// against the changed sources.
+fn update_shader_sources(
+    mut asset_events: EventReader<AssetEvent<Shader>>,
+    shader_assets: Res<Assets<Shader>>,
+    shader_handles: Option<Res<ShaderHandles>>,
+    mut shader_sources: ResMut<ShaderSources>,
+) {
+    let Some(shader_handles) = shader_handles else {
+        return;
+    };
+    let mut changed = Vec::new();
+    for event in asset_events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event
+        else {
+            continue;
+        };
+        let Some(name) = shader_handles
+            .0
+            .iter()
+            .find(|(_, handle)| handle.id() == *id)
+            .map(|(name, _)| *name)
+        else {
+            continue;
+        };
+        let Some(shader) = shader_assets.get(*id) else {
+            continue;
+        };
+        let Source::Wgsl(source) = &shader.source else {
+            error!("Shader {name} is not WGSL");
+            continue;
+        };
+        let source = Arc::<str>::from(source.as_ref());
+        if shader_sources
+            .sources
+            .get(name)
+            .is_some_and(|old_source| *old_source == source)
+        {
+            continue;
+        }
+        changed.push((name, source));
+    }
+    if changed.is_empty() {
+        return;
+    }
+
+    let was_loaded = shader_sources.is_loaded();
+    let mut sources = shader_sources.sources.clone();
+    sources.extend(changed.iter().cloned());
+    // Includes may arrive after the shaders that use them, so nothing can be
+    // validated until every shader has loaded
+    let validation = if all_loaded(&sources) {
+        validate_all(&sources)
+    } else {
+        Ok(())
+    };
+    if let Err(e) = validation {
+        let names: Vec<_> = changed.iter().map(|(name, _)| *name).collect();
+        error!(
+            "Failed to compile shaders after changes to {}. Keeping the previous version.\n{e}",
+            names.join(", ")
+        );
+        return;
+    }
+    shader_sources.sources = sources;
+    if was_loaded {
+        for (name, _) in &changed {
+            info!("Shader {name} modified. Rebuilding pipelines.");
+        }
+        shader_sources.generation += 1;
+    }
+}
+
+/// Checks each entry shader with every combination of `SHADER_DEFS` set.
+/// Combinations that expand to the same source as an earlier one, because
+/// the shader doesn't test those defs, are only checked once.
+fn validate_all(sources: &HashMap<&'static str, Arc<str>>) -> Result<(), String> {
+    let mut validated = HashSet::new();
+    for name in ENTRY_SHADERS {
+        for combination in 0..1u32 << SHADER_DEFS.len() {
+            let defs: Vec<&str> = SHADER_DEFS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| combination & (1 << i) != 0)
+                .map(|(_, def)| *def)
+                .collect();
+            let source = preprocess(sources, name, &defs)?;
+            if !validated.insert(source.clone()) {
+                continue;
+            }
+            validate_wgsl(&source).map_err(|e| format!("{name} with {defs:?}:\n{e}"))?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_wgsl(source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| e.emit_to_string(source))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| e.emit_to_string(source))?;
+    Ok(())
+}
+
+/// Removes the pipelines after a shader reload, so that they are created
+/// again from the new sources
+fn rebuild_pipelines_on_shader_change(
+    mut commands: Commands,
+    shader_sources: Extract<Res<ShaderSources>>,
+    mut built_generation: Local<Option<u32>>,
+    q_views: Query<Entity, Or<(With<ViewGlobals>, With<GpuCullingBuffers>)>>,
+) {
+    if !shader_sources.is_loaded() {
+        return;
+    }
+    let generation = shader_sources.generation;
+    if built_generation.is_some_and(|built| built != generation) {
+        commands.remove_resource::<SpecializedPipelines>();
+        commands.remove_resource::<MyRenderPipeline>();
+        commands.remove_resource::<GpuCullingPipeline>();
+        // Their bind groups use the layouts of the removed pipelines
+        for view in q_views.iter() {
+            commands
+                .entity(view)
+                .remove::<(ViewGlobals, GpuCullingBuffers)>();
+        }
+    }
+    *built_generation = Some(generation);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every shader, read from `shaders/` like `embedded_asset!` embeds them
+    fn shader_sources() -> HashMap<&'static str, Arc<str>> {
+        all_shaders()
+            .map(|name| {
+                let path = format!("{}/src/shaders/{name}", env!("CARGO_MANIFEST_DIR"));
+                let source = std::fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("Couldn't read {path}: {e}"));
+                (name, source.into())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_shader_compiles_with_every_def() {
+        if let Err(e) = validate_all(&shader_sources()) {
+            panic!("{e}");
+        }
+    }
+}
@@ -0,0 +1,39 @@
+#include "globals.wgsl"
+
+// Opacity of the cloud layer where it is crossed at `xz`
+fn cloud_density(xz: vec2<f32>) -> f32 {
+    let p = (xz - globals.cloud_wind * globals.time_seconds) * globals.cloud_scale;
+    let coverage_start = 1.0 - globals.cloud_coverage;
+    return smoothstep(coverage_start, coverage_start + 0.2, fbm(p)) * globals.cloud_density;
+}
+
+// Fractal value noise in [0, 1]
+fn fbm(p: vec2<f32>) -> f32 {
+    var total = 0.0;
+    var amplitude = 0.5;
+    var frequency = 1.0;
+    for (var octave = 0u; octave < 4u; octave++) {
+        total += amplitude * value_noise(p * frequency + f32(octave) * 17.0);
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    return total / 0.9375;
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let cell = floor(p);
+    let t = smoothstep(vec2(0.0), vec2(1.0), p - cell);
+    let a = hash_2d(cell);
+    let b = hash_2d(cell + vec2(1.0, 0.0));
+    let c = hash_2d(cell + vec2(0.0, 1.0));
+    let d = hash_2d(cell + vec2(1.0, 1.0));
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y);
+}
+
+fn hash_2d(p: vec2<f32>) -> f32 {
+    let v = vec2<u32>(vec2<i32>(p) + 1000000) * vec2(1664525u, 1013904223u);
+    var h = v.x ^ (v.y >> 3u) ^ (v.y << 7u);
+    h = h * 747796405u + 2891336453u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    return f32((h >> 22u) ^ h) / 4294967295.0;
+}
@@ -0,0 +1,174 @@
+#include "quad.wgsl"
+
+// Visible chunk whose opaque instances are tested by the culling pass
+struct ChunkRange {
+    position: vec3<i32>,
+    first_instance: u32,
+    num_instances: u32,
+    /// Sum of `num_instances` of every earlier chunk, so that chunk's first
+    /// instance is handled by this thread
+    first_thread: u32,
+}
+
+struct DrawIndirectArgs {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+struct CullingParams {
+    /// Frustum half-spaces as (normal, distance), excluding the far plane
+    planes: array<vec4<f32>, 5>,
+    camera_position: vec3<f32>,
+    num_chunks: u32,
+    num_instances: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: CullingParams;
+@group(0) @binding(1)
+var<storage, read> chunks: array<ChunkRange>;
+/// `RawInstance`s of every chunk, `INSTANCE_WORDS` words each
+@group(0) @binding(2)
+var<storage, read> instances: array<u32>;
+@group(0) @binding(3)
+var<storage, read> chunk_slots: array<u32>;
+/// The surviving instances, packed together from the start
+@group(0) @binding(4)
+var<storage, read_write> compacted_instances: array<u32>;
+@group(0) @binding(5)
+var<storage, read_write> compacted_chunk_slots: array<u32>;
+/// Reset to no instances before each dispatch
+@group(0) @binding(6)
+var<storage, read_write> draw: DrawIndirectArgs;
+
+const CHUNK_SIZE: f32 = 32.0;
+const WORKGROUP_SIZE: u32 = 64u;
+
+fn is_box_visible(aabb_min: vec3<f32>, aabb_max: vec3<f32>) -> bool {
+    for (var i = 0u; i < 5u; i++) {
+        let plane = params.planes[i];
+        // Corner of the box furthest along the plane normal
+        let corner = select(aabb_min, aabb_max, plane.xyz > vec3(0.0));
+        if (dot(plane.xyz, corner) + plane.w < 0.0) {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn is_chunk_visible(chunk_position: vec3<i32>) -> bool {
+    // Quads are centred on their block position
+    let aabb_min = vec3<f32>(chunk_position) * CHUNK_SIZE - 0.5;
+    return is_box_visible(aabb_min, aabb_min + CHUNK_SIZE);
+}
+
+fn load_quad(index: u32) -> Quad {
+    let base = index * INSTANCE_WORDS;
+#ifdef FLOAT_INSTANCES
+    var quad: Quad;
+    quad.local_pos = vec3<f32>(
+        bitcast<f32>(instances[base]),
+        bitcast<f32>(instances[base + 1u]),
+        bitcast<f32>(instances[base + 2u]),
+    );
+    quad.normal = instances[base + 3u];
+    quad.size = vec2<f32>(bitcast<f32>(instances[base + 4u]), bitcast<f32>(instances[base + 5u]));
+    quad.ambient_occlusion = vec4<f32>(
+        bitcast<f32>(instances[base + 6u]),
+        bitcast<f32>(instances[base + 7u]),
+        bitcast<f32>(instances[base + 8u]),
+        bitcast<f32>(instances[base + 9u]),
+    );
+    quad.material_index = instances[base + 10u];
+    quad.shape = instances[base + 11u];
+    return quad;
+#else
+    return unpack_quad(instances[base], instances[base + 1u]);
+#endif
+}
+
+// Whether the quad is inside the frustum and faces the camera. Back faces are
+// culled by the terrain pipelines anyway, but dropping them here saves
+// running their vertices.
+fn is_quad_visible(chunk: ChunkRange, quad: Quad) -> bool {
+    let block_pos = vec3<f32>(chunk.position) * CHUNK_SIZE + quad.local_pos;
+    let v0 = block_pos + quad_corner_offset(quad, 0u);
+    let v1 = block_pos + quad_corner_offset(quad, 1u);
+    let v2 = block_pos + quad_corner_offset(quad, 2u);
+    let v3 = block_pos + quad_corner_offset(quad, 3u);
+    // Counter-clockwise triangles are front facing
+    let facing = cross(v1 - v0, v2 - v0);
+    if (dot(facing, params.camera_position - v0) <= 0.0) {
+        return false;
+    }
+    return is_box_visible(min(min(v0, v1), min(v2, v3)), max(max(v0, v1), max(v2, v3)));
+}
+
+// Index of the chunk whose instances include thread `thread`
+fn find_chunk(thread: u32) -> u32 {
+    var low = 0u;
+    var high = params.num_chunks;
+    while (high - low > 1u) {
+        let mid = (low + high) / 2u;
+        if (chunks[mid].first_thread <= thread) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    return low;
+}
+
+/// Inclusive prefix sum of the survivors of the workgroup
+var<workgroup> survivors: array<u32, WORKGROUP_SIZE>;
+/// Index in the compacted buffers of the workgroup's first survivor
+var<workgroup> workgroup_offset: u32;
+
+// Tests one instance per thread, then packs the survivors of each workgroup
+// into the compacted buffers at an offset reserved from `draw`
+@compute @workgroup_size(64)
+fn cull_instances(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    // Large dispatches are split along y to stay under the dispatch size limit
+    let thread = (workgroup_id.y * num_workgroups.x + workgroup_id.x) * WORKGROUP_SIZE
+        + local_index;
+    var visible = false;
+    var instance_index = 0u;
+    if (thread < params.num_instances) {
+        let chunk = chunks[find_chunk(thread)];
+        instance_index = chunk.first_instance + thread - chunk.first_thread;
+        visible = is_chunk_visible(chunk.position)
+            && is_quad_visible(chunk, load_quad(instance_index));
+    }
+
+    // Hillis-Steele scan over the workgroup
+    survivors[local_index] = select(0u, 1u, visible);
+    workgroupBarrier();
+    for (var stride = 1u; stride < WORKGROUP_SIZE; stride *= 2u) {
+        var sum = survivors[local_index];
+        if (local_index >= stride) {
+            sum += survivors[local_index - stride];
+        }
+        workgroupBarrier();
+        survivors[local_index] = sum;
+        workgroupBarrier();
+    }
+    if (local_index == WORKGROUP_SIZE - 1u) {
+        workgroup_offset = atomicAdd(&draw.instance_count, survivors[local_index]);
+    }
+    workgroupBarrier();
+
+    if (visible) {
+        let compacted_index = workgroup_offset + survivors[local_index] - 1u;
+        for (var word = 0u; word < INSTANCE_WORDS; word++) {
+            compacted_instances[compacted_index * INSTANCE_WORDS + word] =
+                instances[instance_index * INSTANCE_WORDS + word];
+        }
+        compacted_chunk_slots[compacted_index] = chunk_slots[instance_index];
+    }
+}
@@ -0,0 +1,43 @@
+#include "globals.wgsl"
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+fn line_vertex(position: vec3<f32>, color: vec4<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_pos = globals.world_to_clip * vec4(position, 1.0);
+    out.color = color;
+    return out;
+}
+
+@vertex
+fn vs_line(
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+) -> VertexOutput {
+    return line_vertex(position, color);
+}
+
+// Fraction of its distance by which the block outline is pulled towards the
+// camera, so it wins the depth test against the faces it lies on. A
+// pipeline depth bias would do the same, but isn't allowed for lines.
+const OUTLINE_DEPTH_BIAS: f32 = 1e-3;
+
+@vertex
+fn vs_outline(
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+) -> VertexOutput {
+    // Entry points can't call each other
+    var out = line_vertex(position, color);
+    // Depth is reversed, so a larger depth is closer
+    out.clip_pos.z *= 1.0 + OUTLINE_DEPTH_BIAS;
+    return out;
+}
+
+@fragment
+fn fs_line(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,29 @@
+#include "globals.wgsl"
+
+fn fog_color(color: vec4<f32>, world_pos: vec3<f32>) -> vec4<f32> {
+#ifdef FOG
+    let camera_distance = distance(globals.camera_position, world_pos);
+    if (globals.camera_in_fluid != 0u) {
+        let fog_amount = 1.0 - exp(-camera_distance * globals.underwater_fog_b);
+        return vec4(mix(color.xyz, globals.underwater_fog_color, fog_amount), color.w);
+    }
+    let density = height_fog_density(globals.camera_position, world_pos);
+    let fog_amount = 1.0 - exp(-density * camera_distance);
+    let fogged_color = mix(color.xyz, globals.fog_color, fog_amount);
+    return vec4(fogged_color, color.w);
+#else
+    return color;
+#endif
+}
+
+// Average fog density along the ray from `start` to `end`, where density
+// falls off exponentially with height above `fog_base_height`
+fn height_fog_density(start: vec3<f32>, end: vec3<f32>) -> f32 {
+    let falloff = globals.fog_height_falloff;
+    let start_density = globals.fog_b * exp(-falloff * (start.y - globals.fog_base_height));
+    let climb = falloff * (end.y - start.y);
+    if (abs(climb) < 1e-4) {
+        return start_density;
+    }
+    return start_density * (1.0 - exp(-climb)) / climb;
+}
@@ -0,0 +1,36 @@
+// Keep in sync with `GlobalsData` in globals.rs
+struct Globals {
+    time_seconds: f32,
+    world_to_clip: mat4x4<f32>,
+    camera_position: vec3<f32>,
+    ambient_light: vec3<f32>,
+    directional_light: vec3<f32>,
+    directional_light_direction: vec3<f32>,
+    fog_color: vec3<f32>,
+    fog_b: f32,
+    shadow_map_projection: mat4x4<f32>,
+    shadow_depth_bias: f32,
+    shadow_normal_offset: f32,
+    shadow_pcf_radius: u32,
+    ssao_sample_count: u32,
+    clip_to_world: mat4x4<f32>,
+    ssao_radius: f32,
+    ssao_intensity: f32,
+    ssao_bias: f32,
+    sky_color: vec3<f32>,
+    star_visibility: f32,
+    moon_direction: vec3<f32>,
+    cloud_altitude: f32,
+    cloud_wind: vec2<f32>,
+    cloud_coverage: f32,
+    cloud_density: f32,
+    cloud_scale: f32,
+    fog_height_falloff: f32,
+    fog_base_height: f32,
+    camera_in_fluid: u32,
+    underwater_fog_color: vec3<f32>,
+    underwater_fog_b: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> globals: Globals;
@@ -0,0 +1,104 @@
+#include "globals.wgsl"
+#include "shadow.wgsl"
+
+@group(3) @binding(0)
+var ssao_texture: texture_2d<f32>;
+
+const MAX_POINT_LIGHTS: u32 = 32u;
+
+struct PointLight {
+    position: vec3<f32>,
+    radius: f32,
+    color: vec3<f32>,
+}
+
+// Keep in sync with `PointLightsData` in globals.rs
+struct PointLights {
+    count: u32,
+    lights: array<PointLight, MAX_POINT_LIGHTS>,
+}
+
+@group(0) @binding(2)
+var<uniform> point_lights: PointLights;
+
+// Light reaching a surface at `world_pos` facing `normal`, from the sky and
+// the sun or moon. Shadows are looked up with the geometric `normal`, and
+// shading uses `shading_normal`, which may be perturbed by a normal map.
+fn incoming_light(
+    world_pos: vec3<f32>,
+    normal: vec3<f32>,
+    shading_normal: vec3<f32>,
+    frag_coord: vec2<f32>,
+) -> vec3<f32> {
+    let sunlight_factor = get_sunlight_factor(world_pos, normal) * cloud_shadow(world_pos);
+    let directional_illumination = (
+        sunlight_factor
+        * max(0.0, dot(shading_normal, globals.directional_light_direction))
+        * globals.directional_light
+    );
+    let ambient = globals.ambient_light * screen_space_ambient_occlusion(frag_coord);
+    return ambient + directional_illumination + point_light_illumination(world_pos, shading_normal);
+}
+
+// Light from emissive blocks, which fades to nothing at each light's radius.
+// Point lights don't cast shadows.
+fn point_light_illumination(world_pos: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = vec3(0.0);
+    for (var i = 0u; i < min(point_lights.count, MAX_POINT_LIGHTS); i++) {
+        let light = point_lights.lights[i];
+        let to_light = light.position - world_pos;
+        let distance = length(to_light);
+        let falloff = saturate(1.0 - distance / light.radius);
+        let facing = max(0.0, dot(normal, to_light / max(distance, 0.001)));
+        total += light.color * facing * falloff * falloff;
+    }
+    return total;
+}
+
+// Blinn-Phong highlight of the sun or moon, which fades out entirely on fully
+// rough surfaces
+fn specular_light(
+    world_pos: vec3<f32>,
+    normal: vec3<f32>,
+    shading_normal: vec3<f32>,
+    roughness: f32,
+) -> vec3<f32> {
+    if (roughness >= 1.0) {
+        return vec3(0.0);
+    }
+    let view_direction = normalize(globals.camera_position - world_pos);
+    let half_vector = normalize(view_direction + globals.directional_light_direction);
+    let shininess = exp2(12.0 * (1.0 - roughness));
+    // Keeps the total reflected light roughly constant as the highlight
+    // narrows
+    let normalization = (shininess + 8.0) / (8.0 * 3.14159265);
+    let highlight = pow(max(0.0, dot(shading_normal, half_vector)), shininess) * normalization;
+    let facing_light = step(0.0, dot(normal, globals.directional_light_direction));
+    let sunlight_factor = get_sunlight_factor(world_pos, normal) * cloud_shadow(world_pos);
+    return globals.directional_light
+        * highlight
+        * facing_light
+        * sunlight_factor
+        * (1.0 - roughness);
+}
+
+fn screen_space_ambient_occlusion(frag_coord: vec2<f32>) -> f32 {
+#ifndef SSAO
+    return 1.0;
+#else
+    if (globals.ssao_sample_count == 0u) {
+        return 1.0;
+    }
+    // Box blur to hide the noise from the per-pixel random samples
+    let size = vec2<i32>(textureDimensions(ssao_texture));
+    let center = vec2<i32>(frag_coord);
+    var total = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let pixel = clamp(center + vec2(x, y), vec2(0), size - 1);
+            total += textureLoad(ssao_texture, pixel, 0).r;
+        }
+    }
+    return total / 9.0;
+#endif
+}
@@ -0,0 +1,25 @@
+@group(0) @binding(0)
+var accumulation_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var revealage_texture: texture_2d<f32>;
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Weighted average of the transparent fragments, covering the opaque scene
+// by however much of it isn't revealed
+@fragment
+fn fs_oit_composite(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(frag_coord.xy);
+    let revealage = textureLoad(revealage_texture, pixel, 0).r;
+    if (revealage >= 1.0) {
+        discard;
+    }
+    let accumulation = textureLoad(accumulation_texture, pixel, 0);
+    let average_color = accumulation.rgb / max(accumulation.a, 1e-5);
+    return vec4(average_color, 1.0 - revealage);
+}
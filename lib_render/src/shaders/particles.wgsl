@@ -0,0 +1,81 @@
+#include "globals.wgsl"
+#include "lighting.wgsl"
+#include "fog.wgsl"
+
+// Same bindings as the terrain, so particles can share its bind groups
+@group(1) @binding(0)
+var my_texture: texture_2d_array<f32>;
+@group(1) @binding(1)
+var my_sampler: sampler;
+
+// Keep in sync with `ParticleInstance` in particles.rs
+struct ParticleInput {
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) uv_min: vec2<f32>,
+    @location(3) uv_size: vec2<f32>,
+    @location(4) tint: vec4<f32>,
+    @location(5) texture_index: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) texture_index: u32,
+    @location(2) world_pos: vec3<f32>,
+    /// Points away from the camera, into the particle, like the terrain's
+    /// normals point into their blocks
+    @location(3) @interpolate(flat) normal: vec3<f32>,
+    @location(4) @interpolate(flat) tint: vec4<f32>,
+}
+
+const ALPHA_CUTOFF: f32 = 0.5;
+
+@vertex
+fn vs_particle(
+    @builtin(vertex_index) vertex_index: u32,
+    particle: ParticleInput,
+) -> VertexOutput {
+    // Top left, bottom left, top right, bottom right
+    let corner_uv = vec2<f32>(f32(vertex_index >> 1u), f32(vertex_index & 1u));
+    let normal = normalize(particle.position - globals.camera_position);
+    // Upright while looking straight up or down, where any right will do
+    var right = cross(normal, vec3(0.0, 1.0, 0.0));
+    if (dot(right, right) < 1e-6) {
+        right = vec3(1.0, 0.0, 0.0);
+    }
+    right = normalize(right);
+    let up = cross(right, normal);
+    let offset = (corner_uv.x - 0.5) * right + (0.5 - corner_uv.y) * up;
+    let world_pos = particle.position + offset * particle.size;
+
+    var out: VertexOutput;
+    out.clip_pos = globals.world_to_clip * vec4(world_pos, 1.0);
+    out.uv = particle.uv_min + corner_uv * particle.uv_size;
+    out.texture_index = particle.texture_index;
+    out.world_pos = world_pos;
+    out.normal = normal;
+    out.tint = particle.tint;
+    return out;
+}
+
+@fragment
+fn fs_particle(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let texture_color = textureSample(
+        my_texture,
+        my_sampler,
+        vertex.uv,
+        vertex.texture_index
+    ) * vertex.tint;
+    if (texture_color.a < ALPHA_CUTOFF) {
+        discard;
+    }
+    let light = incoming_light(
+        vertex.world_pos,
+        vertex.normal,
+        vertex.normal,
+        vertex.clip_pos.xy
+    );
+    let lit_color = vec4(texture_color.rgb * light, 1.0);
+    return fog_color(lit_color, vertex.world_pos);
+}
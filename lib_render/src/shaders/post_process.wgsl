@@ -0,0 +1,99 @@
+struct PostProcessSettings {
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    exposure: f32,
+    tonemapping_operator: u32,
+}
+
+const TONEMAPPING_NONE: u32 = 0u;
+const TONEMAPPING_REINHARD: u32 = 1u;
+const TONEMAPPING_ACES: u32 = 2u;
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> settings: PostProcessSettings;
+// Only bound for `fs_composite`
+@group(0) @binding(3)
+var bloom_texture: texture_2d<f32>;
+
+struct FullscreenOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> FullscreenOutput {
+    let uv = vec2(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: FullscreenOutput;
+    out.clip_pos = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Box filter over 4x4 source texels, using bilinear filtering to take 4 taps
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    let a = textureSample(source_texture, source_sampler, uv + texel * vec2(-1.0, -1.0)).rgb;
+    let b = textureSample(source_texture, source_sampler, uv + texel * vec2(1.0, -1.0)).rgb;
+    let c = textureSample(source_texture, source_sampler, uv + texel * vec2(-1.0, 1.0)).rgb;
+    let d = textureSample(source_texture, source_sampler, uv + texel * vec2(1.0, 1.0)).rgb;
+    return (a + b + c + d) * 0.25;
+}
+
+// First step of the bloom chain, which only keeps what is brighter than the
+// threshold
+@fragment
+fn fs_downsample_first(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = downsample(in.uv);
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - settings.bloom_threshold, 0.0) / max(brightness, 1e-4);
+    return vec4(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4(downsample(in.uv), 1.0);
+}
+
+// 3x3 tent filter, added onto the next larger mip
+@fragment
+fn fs_upsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    var color = vec3(0.0);
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let weight = f32((2 - abs(x)) * (2 - abs(y))) / 16.0;
+            let offset = texel * vec2(f32(x), f32(y));
+            color += weight * textureSample(source_texture, source_sampler, in.uv + offset).rgb;
+        }
+    }
+    return vec4(color, 1.0);
+}
+
+// Combines the HDR main pass with the bloom into the view target
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let hdr = textureSample(source_texture, source_sampler, in.uv).rgb;
+    let bloom = textureSample(bloom_texture, source_sampler, in.uv).rgb;
+    let color = (hdr + bloom * settings.bloom_intensity) * settings.exposure;
+    return vec4(tonemap(color), 1.0);
+}
+
+fn tonemap(color: vec3<f32>) -> vec3<f32> {
+    switch settings.tonemapping_operator {
+        case TONEMAPPING_REINHARD: {
+            return color / (1.0 + color);
+        }
+        case TONEMAPPING_ACES: {
+            let mapped = (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+            return clamp(mapped, vec3(0.0), vec3(1.0));
+        }
+        case TONEMAPPING_NONE, default: {
+            return clamp(color, vec3(0.0), vec3(1.0));
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// Layout of the quad instances shared by the terrain shaders and the culling
+// pass. Keep in sync with `RawInstance` in instance.rs.
+
+#ifdef FLOAT_INSTANCES
+const INSTANCE_WORDS: u32 = 12u;
+#else
+const INSTANCE_WORDS: u32 = 2u;
+#endif
+
+// A quad instance with its fields unpacked
+struct Quad {
+    local_pos: vec3<f32>,
+    normal: u32,
+    /// Width and height in blocks
+    size: vec2<f32>,
+    /// Column-wise, starting with top right (0-4 each)
+    ambient_occlusion: vec4<f32>,
+    /// Packed as in `RawInstance::material_index`
+    material_index: u32,
+    /// One of the `SHAPE_*` constants, as in `QuadShape`
+    shape: u32,
+}
+
+const SHAPE_BOTTOM_HALF: u32 = 1u;
+const SHAPE_TOP_HALF: u32 = 2u;
+const SHAPE_DIAGONAL: u32 = 3u;
+
+const ROTATION_BY_NORMAL = array<mat3x3<f32>, 6>(
+    mat3x3<f32>(
+        vec3<f32>(0.0, 0.0, -1.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(1.0, 0.0, 0.0),
+    ),
+    mat3x3<f32>(
+        vec3<f32>(0.0, 0.0, 1.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(-1.0, 0.0, 0.0),
+    ),
+    mat3x3<f32>(
+        vec3<f32>(0.0, 0.0, -1.0),
+        vec3<f32>(-1.0, 0.0, 0.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+    ),
+    mat3x3<f32>(
+        vec3<f32>(0.0, 0.0, -1.0),
+        vec3<f32>(1.0, 0.0, 0.0),
+        vec3<f32>(0.0, -1.0, 0.0),
+    ),
+    mat3x3<f32>(
+        vec3<f32>(1.0, 0.0, 0.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(0.0, 0.0, 1.0),
+    ),
+    mat3x3<f32>(
+        vec3<f32>(-1.0, 0.0, 0.0),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(0.0, 0.0, -1.0),
+    ),
+);
+
+struct QuadCorner {
+    position: vec3<f32>,
+    uv: vec2<f32>,
+}
+
+// Corner of the unit quad facing +Z, in triangle strip order:
+// top left, bottom left, top right, bottom right
+fn quad_corner(vertex_index: u32) -> QuadCorner {
+    let uv = vec2<f32>(f32(vertex_index >> 1u), f32(vertex_index & 1u));
+    var corner: QuadCorner;
+    corner.position = vec3<f32>(uv.x - 0.5, 0.5 - uv.y, 0.5);
+    corner.uv = uv;
+    return corner;
+}
+
+fn unpack_local_pos(data: u32) -> vec3<f32> {
+    let x = f32((data >> 0u) & 0x1Fu);
+    let y = f32((data >> 5u) & 0x1Fu);
+    let z = f32((data >> 10u) & 0x1Fu);
+    return vec3<f32>(x, y, z);
+}
+
+fn unpack_normal(data: u32) -> u32 {
+    return (data >> 27u) & 0x7u; // 3 bits for 0–5
+}
+
+fn unpack_shape(data: u32) -> u32 {
+    return (data >> 30u) & 0x3u;
+}
+
+fn unpack_size(material_index: u32) -> vec2<f32> {
+    let width = f32(((material_index >> 16u) & 0x1Fu) + 1u);
+    let height = f32(((material_index >> 21u) & 0x1Fu) + 1u);
+    return vec2<f32>(width, height);
+}
+
+// Stretch the unit quad so that it covers `size` blocks, keeping the corner
+// at uv (0, 0) in place.
+fn scale_quad_position(position: vec3<f32>, size: vec2<f32>) -> vec3<f32> {
+    let x = (position.x + 0.5) * size.x - 0.5;
+    let y = (position.y - 0.5) * size.y + 0.5;
+    return vec3<f32>(x, y, position.z);
+}
+
+fn unpack_quad(data: u32, material_index: u32) -> Quad {
+    var quad: Quad;
+    quad.local_pos = unpack_local_pos(data);
+    quad.normal = unpack_normal(data);
+    quad.size = unpack_size(material_index);
+    quad.ambient_occlusion = vec4<f32>(
+        f32((data >> 15u) & 7u),
+        f32((data >> 18u) & 7u),
+        f32((data >> 21u) & 7u),
+        f32((data >> 24u) & 7u),
+    );
+    quad.material_index = material_index;
+    quad.shape = unpack_shape(data);
+    return quad;
+}
+
+// Moves a point on a face of the block, relative to its centre, to where the
+// quad's shape puts it
+fn shape_offset(offset: vec3<f32>, normal: u32, shape: u32) -> vec3<f32> {
+    switch shape {
+        case SHAPE_BOTTOM_HALF: {
+            return vec3<f32>(offset.x, offset.y * 0.5 - 0.25, offset.z);
+        }
+        case SHAPE_TOP_HALF: {
+            return vec3<f32>(offset.x, offset.y * 0.5 + 0.25, offset.z);
+        }
+        case SHAPE_DIAGONAL: {
+            // The offset along the normal follows the one across the quad
+            if (normal < 2u) {
+                return vec3<f32>(-offset.z, offset.y, offset.z);
+            }
+            return vec3<f32>(offset.x, offset.y, offset.x);
+        }
+        default: {
+            return offset;
+        }
+    }
+}
+
+// Direction `direction` on a face of the block points in once shaped
+fn shape_direction(direction: vec3<f32>, normal: u32, shape: u32) -> vec3<f32> {
+    return normalize(
+        shape_offset(direction, normal, shape) - shape_offset(vec3<f32>(0.0), normal, shape)
+    );
+}
+
+// Position of corner `vertex_index` of the quad relative to the centre of the
+// block it starts from, as in `Quad::pos`
+fn quad_corner_offset(quad: Quad, vertex_index: u32) -> vec3<f32> {
+    let rotation = ROTATION_BY_NORMAL[quad.normal];
+    let offset = rotation * scale_quad_position(quad_corner(vertex_index).position, quad.size);
+    return shape_offset(offset, quad.normal, quad.shape);
+}
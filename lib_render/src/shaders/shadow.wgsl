@@ -0,0 +1,63 @@
+#include "globals.wgsl"
+#include "clouds.wgsl"
+
+@group(2) @binding(0)
+var shadow_map: texture_depth_2d;
+@group(2) @binding(1)
+var shadow_map_sampler: sampler_comparison;
+
+// 0.0 -> Shadow
+// 1.0 -> Lit
+fn get_sunlight_factor(world_pos: vec3<f32>, normal: vec3<f32>) -> f32 {
+#ifndef SHADOWS
+    return 1.0;
+#else
+    // `normal` points into the surface, so this moves the receiver towards
+    // the outside to avoid self-shadowing acne on lit faces
+    let offset_pos = world_pos - normal * globals.shadow_normal_offset;
+    let shadow_clip = globals.shadow_map_projection * vec4(offset_pos, 1.0);
+    let ndc = shadow_clip.xyz / shadow_clip.w;
+    // [-1, 1] -> [0, 1]
+    let uv = vec2(ndc.x, -ndc.y) * 0.5 + vec2(0.5);
+    let receiver_depth = ndc.z;
+    if (
+        uv.x < 0.
+        || uv.x > 1.
+        || uv.y < 0.
+        || uv.y > 1.
+        || receiver_depth < 0.
+        || receiver_depth > 1.
+    ) {
+        return 1.0;
+    }
+    // Percentage-closer filtering over a square kernel
+    let texel_size = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    let radius = i32(globals.shadow_pcf_radius);
+    var lit = 0.0;
+    for (var x = -radius; x <= radius; x++) {
+        for (var y = -radius; y <= radius; y++) {
+            lit += textureSampleCompareLevel(
+                shadow_map,
+                shadow_map_sampler,
+                uv + vec2(f32(x), f32(y)) * texel_size,
+                receiver_depth + globals.shadow_depth_bias
+            );
+        }
+    }
+    let kernel_width = f32(2 * radius + 1);
+    return lit / (kernel_width * kernel_width);
+#endif
+}
+
+const CLOUD_SHADOW_STRENGTH: f32 = 0.6;
+
+// Fraction of the directional light let through by the clouds above
+fn cloud_shadow(world_pos: vec3<f32>) -> f32 {
+    let to_light = -globals.directional_light_direction;
+    let height = globals.cloud_altitude - world_pos.y;
+    if (to_light.y <= 0.0 || height <= 0.0) {
+        return 1.0;
+    }
+    let hit = world_pos + to_light * (height / to_light.y);
+    return 1.0 - cloud_density(hit.xz) * CLOUD_SHADOW_STRENGTH;
+}
@@ -0,0 +1,89 @@
+#include "globals.wgsl"
+#include "clouds.wgsl"
+
+struct FullscreenOutput {
+    @builtin(position) clip_pos: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+}
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> FullscreenOutput {
+    let uv = vec2(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: FullscreenOutput;
+    out.ndc = uv * 2.0 - 1.0;
+    out.clip_pos = vec4(out.ndc, 0.0, 1.0);
+    return out;
+}
+
+// Angular radius of the moon, in radians
+const MOON_RADIUS: f32 = 0.03;
+const MOON_COLOR: vec3<f32> = vec3(1.6, 1.6, 1.8);
+// Stars are placed in cells of a grid around the camera, one in each of a
+// small fraction of the cells
+const STAR_GRID_SIZE: f32 = 300.0;
+const STAR_DENSITY: f32 = 0.02;
+const STAR_COLOR: vec3<f32> = vec3(1.2);
+
+@fragment
+fn fs_sky(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    // Depth is reversed, so 1.0 is the near plane
+    let near = globals.clip_to_world * vec4(in.ndc, 1.0, 1.0);
+    let direction = normalize(near.xyz / near.w - globals.camera_position);
+    if (globals.camera_in_fluid != 0u) {
+        return vec4(globals.underwater_fog_color, 1.0);
+    }
+
+    // Stars and moon set with the horizon
+    let above_horizon = smoothstep(-0.05, 0.05, direction.y);
+    let night = globals.star_visibility * above_horizon;
+    let moon = smoothstep(
+        cos(MOON_RADIUS),
+        cos(MOON_RADIUS * 0.9),
+        dot(direction, globals.moon_direction)
+    );
+    let sky = globals.sky_color + night * (star(direction) * STAR_COLOR + moon * MOON_COLOR);
+    return vec4(clouds(sky, direction), 1.0);
+}
+
+// Draws the cloud layer over `sky` where `direction` crosses it
+fn clouds(sky: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    let height = globals.cloud_altitude - globals.camera_position.y;
+    if (direction.y <= 0.0 || height <= 0.0) {
+        return sky;
+    }
+    let distance = height / direction.y;
+    let hit = globals.camera_position + direction * distance;
+    // Distant clouds fade into the haze like terrain does into fog
+    let fade = exp(-distance * globals.fog_b);
+    let opacity = cloud_density(hit.xz) * fade;
+    let cloud_color = globals.ambient_light + globals.directional_light;
+    return mix(sky, cloud_color, opacity);
+}
+
+fn star(direction: vec3<f32>) -> f32 {
+    let grid_pos = direction * STAR_GRID_SIZE;
+    let cell = floor(grid_pos);
+    let random = random_vec3(vec3<u32>(vec3<i32>(cell) + 1000000));
+    if (random.x > STAR_DENSITY) {
+        return 0.0;
+    }
+    // Small point somewhere in the cell, twinkling slowly
+    let star_pos = cell + vec3(0.25) + random.yzx * 0.5;
+    let point = 1.0 - smoothstep(0.0, 0.15, distance(grid_pos, star_pos));
+    let twinkle = 0.75 + 0.25 * sin(globals.time_seconds * (1.0 + random.y * 2.0) + random.z * 6.2831853);
+    return point * twinkle;
+}
+
+fn random_vec3(seed: vec3<u32>) -> vec3<f32> {
+    // PCG3D hash
+    var v = seed * 1664525u + 1013904223u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    v ^= v >> vec3(16u);
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    return vec3<f32>(v) / 4294967295.0;
+}
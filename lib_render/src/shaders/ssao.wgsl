@@ -0,0 +1,110 @@
+#include "globals.wgsl"
+
+@group(1) @binding(0)
+var depth_texture: texture_depth_2d;
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_ssao(@builtin(position) frag_coord: vec4<f32>) -> @location(0) f32 {
+    let pixel = vec2<i32>(frag_coord.xy);
+    // Depth is reversed, so 0 means nothing was drawn here
+    if (textureLoad(depth_texture, pixel, 0) == 0.0) {
+        return 1.0;
+    }
+    let position = world_pos_at(pixel);
+    let normal = surface_normal(pixel, position);
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < globals.ssao_sample_count; i++) {
+        // Random point in the hemisphere around the normal, denser towards
+        // the centre
+        let random = random_vec3(vec3(vec2<u32>(pixel), i));
+        var direction = random_unit_vector(random.xy);
+        if (dot(direction, normal) < 0.0) {
+            direction = -direction;
+        }
+        let t = (f32(i) + random.z) / f32(globals.ssao_sample_count);
+        let sample_pos = position + direction * globals.ssao_radius * mix(0.1, 1.0, t * t);
+
+        let sample_clip = globals.world_to_clip * vec4(sample_pos, 1.0);
+        if (sample_clip.w <= 0.0) {
+            continue;
+        }
+        let sample_ndc = sample_clip.xy / sample_clip.w;
+        let sample_uv = vec2(sample_ndc.x, -sample_ndc.y) * 0.5 + vec2(0.5);
+        let size = vec2<i32>(textureDimensions(depth_texture));
+        let sample_pixel = clamp(vec2<i32>(sample_uv * vec2<f32>(size)), vec2(0), size - 1);
+        if (textureLoad(depth_texture, sample_pixel, 0) == 0.0) {
+            continue;
+        }
+        let scene_pos = world_pos_at(sample_pixel);
+
+        let is_occluded = distance(globals.camera_position, scene_pos)
+            < distance(globals.camera_position, sample_pos) - globals.ssao_bias;
+        // Ignore occluders far outside the sampling radius, such as the
+        // foreground in front of a distant surface
+        let range = smoothstep(0.0, 1.0, globals.ssao_radius / distance(scene_pos, position));
+        occlusion += select(0.0, range, is_occluded);
+    }
+    let ao = 1.0 - globals.ssao_intensity * occlusion / f32(globals.ssao_sample_count);
+    return clamp(ao, 0.0, 1.0);
+}
+
+fn world_pos_at(pixel: vec2<i32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let depth = textureLoad(depth_texture, pixel, 0);
+    let uv = (vec2<f32>(pixel) + 0.5) / size;
+    let ndc = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = globals.clip_to_world * ndc;
+    return world.xyz / world.w;
+}
+
+// Reconstructed from neighbouring depths, facing the camera
+fn surface_normal(pixel: vec2<i32>, position: vec3<f32>) -> vec3<f32> {
+    let horizontal = surface_tangent(pixel, position, vec2(1, 0));
+    let vertical = surface_tangent(pixel, position, vec2(0, 1));
+    let normal = normalize(cross(vertical, horizontal));
+    if (dot(normal, globals.camera_position - position) < 0.0) {
+        return -normal;
+    }
+    return normal;
+}
+
+fn surface_tangent(pixel: vec2<i32>, position: vec3<f32>, offset: vec2<i32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(depth_texture));
+    let forward = world_pos_at(clamp(pixel + offset, vec2(0), size - 1));
+    let backward = world_pos_at(clamp(pixel - offset, vec2(0), size - 1));
+    // The neighbour closest in depth is most likely on the same surface. This
+    // also skips neighbours where nothing was drawn, whose positions are at
+    // infinity.
+    if (distance(forward, position) < distance(backward, position)) {
+        return forward - position;
+    }
+    return position - backward;
+}
+
+fn random_vec3(seed: vec3<u32>) -> vec3<f32> {
+    // PCG3D hash
+    var v = seed * 1664525u + 1013904223u;
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    v ^= v >> vec3(16u);
+    v.x += v.y * v.z;
+    v.y += v.z * v.x;
+    v.z += v.x * v.y;
+    return vec3<f32>(v) / 4294967295.0;
+}
+
+fn random_unit_vector(random: vec2<f32>) -> vec3<f32> {
+    let z = random.x * 2.0 - 1.0;
+    let angle = random.y * 6.2831853;
+    let radius = sqrt(1.0 - z * z);
+    return vec3(radius * cos(angle), radius * sin(angle), z);
+}
@@ -1,138 +1,115 @@
-var<push_constant> chunk_position: ChunkPosition;
-
-struct ChunkPosition {
-    pos: vec3<i32>,
-}
-
-const ROTATION_BY_NORMAL = array<mat4x4<f32>, 6>(
-    mat4x4<f32>(
-        vec4<f32>(0.0, 0.0, -1.0, 0.0),
-        vec4<f32>(0.0, 1.0, 0.0, 0.0),
-        vec4<f32>(1.0, 0.0, 0.0, 0.0),
-        vec4<f32>(0.0, 0.0, 0.0, 1.0),
-    ),
-    mat4x4<f32>(
-        vec4<f32>(0.0, 0.0, 1.0, 0.0),
-        vec4<f32>(0.0, 1.0, 0.0, 0.0),
-        vec4<f32>(-1.0, 0.0, 0.0, 0.0),
-        vec4<f32>(0.0, 0.0, 0.0, 1.0),
-    ),
-    mat4x4<f32>(
-        vec4<f32>(0.0, 0.0, -1.0, 0.0),
-        vec4<f32>(-1.0, 0.0, 0.0, 0.0),
-        vec4<f32>(0.0, 1.0, 0.0, 0.0),
-        vec4<f32>(0.0, 0.0, 0.0, 1.0),
-    ),
-    mat4x4<f32>(
-        vec4<f32>(0.0, 0.0, -1.0, 0.0),
-        vec4<f32>(1.0, 0.0, 0.0, 0.0),
-        vec4<f32>(0.0, -1.0, 0.0, 0.0),
-        vec4<f32>(0.0, 0.0, 0.0, 1.0),
-    ),
-    mat4x4<f32>(
-        vec4<f32>(1.0, 0.0, 0.0, 0.0),
-        vec4<f32>(0.0, 1.0, 0.0, 0.0),
-        vec4<f32>(0.0, 0.0, 1.0, 0.0),
-        vec4<f32>(0.0, 0.0, 0.0, 1.0),
-    ),
-    mat4x4<f32>(
-        vec4<f32>(-1.0, 0.0, 0.0, 0.0),
-        vec4<f32>(0.0, 1.0, 0.0, 0.0),
-        vec4<f32>(0.0, 0.0, -1.0, 0.0),
-        vec4<f32>(0.0, 0.0, 0.0, 1.0),
-    ),
-);
-
-struct Globals {
-    time_seconds: f32,
-    world_to_clip: mat4x4<f32>,
-    camera_position: vec3<f32>,
-    ambient_light: vec3<f32>,
-    directional_light: vec3<f32>,
-    directional_light_direction: vec3<f32>,
-    fog_color: vec3<f32>,
-    fog_b: f32,
-    shadow_map_projection: mat4x4<f32>,
-}
-
-@group(0) @binding(0)
-var<uniform> globals: Globals;
+#include "globals.wgsl"
+#include "lighting.wgsl"
+#include "fog.wgsl"
+#include "quad.wgsl"
+
+/// Position of each chunk in chunks (xyz) and its tint packed as RGBA8 (w),
+/// indexed by chunk slot
+@group(0) @binding(1)
+var<storage, read> chunk_offsets: array<vec4<i32>>;
 @group(1) @binding(0)
 var my_texture: texture_2d_array<f32>;
 @group(1) @binding(1)
 var my_sampler: sampler;
-@group(2) @binding(0)
-var shadow_map: texture_depth_2d;
-@group(2) @binding(1)
-var shadow_map_sampler: sampler_comparison;
+/// Tangent-space normals, with a flat normal for textures without a map
+@group(1) @binding(2)
+var normal_map_texture: texture_2d_array<f32>;
 
 // Vertex shader
 
 struct VertexInput {
     @builtin(vertex_index) index: u32,
-    @location(0) position: vec3<f32>,
-    @location(1) normal: vec3<f32>,
-    @location(2) color: vec3<f32>,
-    @location(3) uv: vec2<f32>,
 }
 
 struct InstanceInput {
+#ifdef FLOAT_INSTANCES
+    @location(0) local_pos: vec3<f32>,
+    @location(1) material_index: u32,
+    @location(2) chunk_slot: u32,
+    @location(3) normal: u32,
+    @location(4) size: vec2<f32>,
+    @location(5) ambient_occlusion: vec4<f32>,
+    @location(6) shape: u32,
+#else
     /// Bits:
     /// - 0-4: Local x (5 bits, 0-31)
     /// - 5-9: Local y (5 bits, 0-31)
     /// - 10-14: Local z (5 bits, 0-31)
     /// - 15-26: Ambient occlusion factors (3 bits each, 4 values, 0-4)
     /// - 27-29: Normal
-    @location(4) data: u32,
-    @location(5) material_index: u32,
+    /// - 30-31: Shape
+    @location(0) data: u32,
+    /// Bits:
+    /// - 0-11: Texture index
+    /// - 12-15: Roughness (4 bits, 0-15)
+    /// - 16-20: Width - 1 (5 bits, 0-31)
+    /// - 21-25: Height - 1 (5 bits, 0-31)
+    /// - 26-29: Emission (4 bits, 0-15)
+    /// - 30: Ripples
+    /// - 31: Tinted
+    @location(1) material_index: u32,
+    @location(2) chunk_slot: u32,
+#endif
 };
 
+fn instance_quad(instance: InstanceInput) -> Quad {
+#ifdef FLOAT_INSTANCES
+    return Quad(
+        instance.local_pos,
+        instance.normal,
+        instance.size,
+        instance.ambient_occlusion,
+        instance.material_index,
+        instance.shape,
+    );
+#else
+    return unpack_quad(instance.data, instance.material_index);
+#endif
+}
+
 struct VertexOutput {
-    @builtin(position) clip_pos: vec4<f32>,
+    // Invariant so the depth prepass and main pass produce identical depth
+    @builtin(position) @invariant clip_pos: vec4<f32>,
     @location(0) material_index: u32,
-    @location(1) color: vec4<f32>,
-    @location(2) normal: vec3<f32>,
-    @location(3) uv: vec2<f32>,
-    @location(4) world_pos: vec3<f32>,
-    @location(5) ambient_occlusion_factor: f32,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) world_pos: vec3<f32>,
+    /// Brightness at each corner of a single block of the quad. Merged quads
+    /// repeat it across every block they cover.
+    @location(4) @interpolate(flat) ambient_occlusion: vec4<f32>,
+    @location(5) emission: f32,
+    /// World space directions in which u increases and v decreases
+    @location(6) tangent: vec3<f32>,
+    @location(7) bitangent: vec3<f32>,
+    @location(8) roughness: f32,
+    @location(9) ripples: f32,
+    /// Multiplies the texture colour, white for untinted quads
+    @location(10) @interpolate(flat) tint: vec3<f32>,
 }
 
-fn unpack_local_pos(data: u32) -> vec3<f32> {
-    let x = f32((data >> 0u) & 0x1Fu);
-    let y = f32((data >> 5u) & 0x1Fu);
-    let z = f32((data >> 10u) & 0x1Fu);
-    return vec3<f32>(x, y, z);
-}
+// Shading normal of the unit quad before rotation
+const QUAD_NORMAL = vec3<f32>(0.0, 0.0, -1.0);
+// Directions of increasing u and decreasing v on the unit quad, matching
+// `quad_corner`
+const QUAD_TANGENT = vec3<f32>(1.0, 0.0, 0.0);
+const QUAD_BITANGENT = vec3<f32>(0.0, 1.0, 0.0);
 
-fn unpack_normal(data: u32) -> u32 {
-    return (data >> 27u) & 0x7u; // 3 bits for 0–5
+fn block_world_pos(quad: Quad, chunk_slot: u32) -> vec3<f32> {
+    let chunk_world = vec3<f32>(chunk_offsets[chunk_slot].xyz) * 32.0;
+    return chunk_world + quad.local_pos;
 }
 
-fn build_model_matrix(face: InstanceInput) -> mat4x4<f32> {
-    // --- Unpack local block position inside chunk
-    let local_block = unpack_local_pos(face.data);
-
-    // --- Fetch chunk world position
-    // let chunk = chunks[face.chunk_id];
-    let chunk_world = vec3<f32>(chunk_position.pos) * 32.0;
-
-    // --- Compute final block world position
-    let block_world = chunk_world + local_block;
-
-    // --- Select face rotation
-    let normal = unpack_normal(face.data);
-    let rotation = ROTATION_BY_NORMAL[normal];
-
-    // --- Translation matrix for block position
-    let translation = mat4x4<f32>(
-        vec4<f32>(1.0, 0.0, 0.0, 0.0),
-        vec4<f32>(0.0, 1.0, 0.0, 0.0),
-        vec4<f32>(0.0, 0.0, 1.0, 0.0),
-        vec4<f32>(block_world, 1.0),
-    );
-
-    // --- Final model matrix
-    return translation * rotation;
+// Side faces of half blocks show the matching half of their texture, rather
+// than all of it squashed
+fn shaped_uv(uv: vec2<f32>, quad: Quad) -> vec2<f32> {
+    let is_side = quad.normal != 2u && quad.normal != 3u;
+    if (is_side && quad.shape == SHAPE_BOTTOM_HALF) {
+        return vec2<f32>(uv.x, uv.y * 0.5 + 0.5);
+    }
+    if (is_side && quad.shape == SHAPE_TOP_HALF) {
+        return vec2<f32>(uv.x, uv.y * 0.5);
+    }
+    return uv;
 }
 
 @vertex
@@ -140,25 +117,34 @@ fn vs_main(
     in: VertexInput,
     instance: InstanceInput,
 ) -> VertexOutput {
-    let local_to_world = build_model_matrix(instance);
-    let local_normal_to_world = mat3x3<f32>(
-        local_to_world[0].xyz,
-        local_to_world[1].xyz,
-        local_to_world[2].xyz,
-    );
-    let world_pos = local_to_world * vec4(in.position, 1.0);
+    let quad = instance_quad(instance);
+    let corner = quad_corner(in.index);
+    let rotation = ROTATION_BY_NORMAL[quad.normal];
+    let world_pos = block_world_pos(quad, instance.chunk_slot)
+        + quad_corner_offset(quad, in.index);
     var out: VertexOutput;
-    out.clip_pos = globals.world_to_clip * world_pos;
-    out.color = vec4(in.color, 1.0);
-    out.uv = in.uv;
-    out.normal = local_normal_to_world * in.normal;
-    out.world_pos = world_pos.xyz;
-    let a0 = ambient_occlusion_factor(f32((instance.data >> 15) & 7));
-    let a1 = ambient_occlusion_factor(f32((instance.data >> 18) & 7));
-    let a2 = ambient_occlusion_factor(f32((instance.data >> 21) & 7));
-    let a3 = ambient_occlusion_factor(f32((instance.data >> 24) & 7));
-    out.ambient_occlusion_factor = bilerp(a0, a2, a1, a3, in.uv.x, in.uv.y);
-    out.material_index = instance.material_index;
+    out.clip_pos = globals.world_to_clip * vec4(world_pos, 1.0);
+    // Repeat the texture once per block across merged quads
+    out.uv = shaped_uv(corner.uv * quad.size, quad);
+    // The tangent frame is rotated and shaped along with the quad, and the
+    // normal kept on the side it faces
+    out.tangent = shape_direction(rotation * QUAD_TANGENT, quad.normal, quad.shape);
+    out.bitangent = shape_direction(rotation * QUAD_BITANGENT, quad.normal, quad.shape);
+    let normal = normalize(cross(out.tangent, out.bitangent));
+    out.normal = select(normal, -normal, dot(normal, rotation * QUAD_NORMAL) < 0.0);
+    out.world_pos = world_pos;
+    out.ambient_occlusion = vec4<f32>(
+        ambient_occlusion_factor(quad.ambient_occlusion.x),
+        ambient_occlusion_factor(quad.ambient_occlusion.y),
+        ambient_occlusion_factor(quad.ambient_occlusion.z),
+        ambient_occlusion_factor(quad.ambient_occlusion.w),
+    );
+    out.material_index = quad.material_index & 0xFFFu;
+    out.roughness = f32((quad.material_index >> 12) & 0xFu) / 15.0;
+    out.emission = f32((quad.material_index >> 26) & 0xFu) / 15.0;
+    out.ripples = f32((quad.material_index >> 30) & 1u);
+    let chunk_tint = unpack4x8unorm(bitcast<u32>(chunk_offsets[instance.chunk_slot].w)).rgb;
+    out.tint = select(vec3(1.0), chunk_tint, ((quad.material_index >> 31) & 1u) == 1u);
     return out;
 }
 
@@ -174,34 +160,114 @@ fn bilerp(a: f32, b: f32, c: f32, d: f32, t0: f32, t1: f32) -> f32 {
 
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    let sunlight_factor = get_sunlight_factor(vertex.world_pos);
+    return shade(vertex);
+}
+
+const ALPHA_CUTOFF: f32 = 0.5;
+const EMISSIVE_INTENSITY: f32 = 4.0;
+
+// Alpha tested variant of `fs_main`, for foliage and other textures with
+// fully transparent holes
+@fragment
+fn fs_cutout(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shade(vertex);
+    if (color.a < ALPHA_CUTOFF) {
+        discard;
+    }
+    return vec4(color.rgb, 1.0);
+}
+
+struct OitOutput {
+    @location(0) accumulation: vec4<f32>,
+    @location(1) revealage: f32,
+}
+
+// Variant of `fs_main` for weighted blended order independent transparency.
+// Fragments are weighted so that closer and more opaque ones dominate the
+// average, which stands in for sorting them.
+@fragment
+fn fs_oit(vertex: VertexOutput) -> OitOutput {
+    let color = shade(vertex);
+    let z = distance(globals.camera_position, vertex.world_pos);
+    let weight = color.a * clamp(
+        10.0 / (1e-5 + pow(z / 5.0, 2.0) + pow(z / 200.0, 6.0)),
+        1e-2,
+        3e3
+    );
+    var out: OitOutput;
+    out.accumulation = vec4(color.rgb * color.a, color.a) * weight;
+    out.revealage = color.a;
+    return out;
+}
+
+fn shade(vertex: VertexOutput) -> vec4<f32> {
     let texture_color = textureSample(
         my_texture,
         my_sampler,
         vertex.uv,
         vertex.material_index
+    ) * vec4(vertex.tint, 1.0);
+    var shading_normal = mapped_normal(vertex);
+    if (vertex.ripples > 0.0) {
+        shading_normal = ripple_normal(vertex, shading_normal);
+    }
+    let light = incoming_light(
+        vertex.world_pos,
+        vertex.normal,
+        shading_normal,
+        vertex.clip_pos.xy
     );
-    let directional_illumination = (
-        sunlight_factor
-        * max(0.0, dot(vertex.normal, globals.directional_light_direction))
-        * globals.directional_light
-    );
-    let light = globals.ambient_light + directional_illumination;
-    let ao = vertex.ambient_occlusion_factor;
-    let illuminated_color = (
-        vertex.color
-        * texture_color
-        * vec4(light * ao, 1.0)
+    let ao = block_ambient_occlusion(vertex);
+    let specular = specular_light(
+        vertex.world_pos,
+        vertex.normal,
+        shading_normal,
+        vertex.roughness
     );
-    let camera_distance = distance(globals.camera_position, vertex.world_pos);
-    let color = fog_color(illuminated_color, camera_distance);
+    let lit_color = texture_color * vec4(light * ao, 1.0) + vec4(specular, 0.0);
+    // Emissive quads ignore lighting and go well past 1.0 so they bloom
+    let emissive_color = texture_color * vec4(vec3(EMISSIVE_INTENSITY), 1.0);
+    let illuminated_color = mix(lit_color, emissive_color, vertex.emission);
+    let color = fog_color(illuminated_color, vertex.world_pos);
     return color;
 }
 
-fn fog_color(color: vec4<f32>, distance: f32) -> vec4<f32> {
-    let fog_amount = 1.0 - exp(-distance * globals.fog_b);
-    let fogged_color = mix(color.xyz, globals.fog_color, fog_amount);
-    return vec4(fogged_color, color.w);
+// The quad's normal, perturbed by the texture's normal map
+fn mapped_normal(vertex: VertexOutput) -> vec3<f32> {
+    let tangent_space_normal = textureSample(
+        normal_map_texture,
+        my_sampler,
+        vertex.uv,
+        vertex.material_index
+    ).xyz * 2.0 - 1.0;
+    return normalize(
+        vertex.tangent * tangent_space_normal.x
+        + vertex.bitangent * tangent_space_normal.y
+        + vertex.normal * tangent_space_normal.z
+    );
+}
+
+const RIPPLE_STRENGTH: f32 = 0.08;
+
+// Tilts `normal` with a few moving waves, measured along the quad so every
+// face of a rippling block moves
+fn ripple_normal(vertex: VertexOutput, normal: vec3<f32>) -> vec3<f32> {
+    let p = vec2(dot(vertex.world_pos, vertex.tangent), dot(vertex.world_pos, vertex.bitangent));
+    let t = globals.time_seconds;
+    let slope = vec2(
+        cos(p.x * 2.1 + t * 1.3) + 0.7 * cos((p.x + p.y) * 1.7 + t * 0.9),
+        cos(p.y * 2.3 - t * 1.1) + 0.7 * cos((p.x - p.y) * 1.9 + t * 1.5),
+    ) * RIPPLE_STRENGTH;
+    return normalize(normal - vertex.tangent * slope.x - vertex.bitangent * slope.y);
+}
+
+// Interpolates the corner values within the block under the fragment. The
+// mesher only merges faces with identical corners, so this matches what
+// unmerged faces would show.
+fn block_ambient_occlusion(vertex: VertexOutput) -> f32 {
+    let t = fract(vertex.uv);
+    let a = vertex.ambient_occlusion;
+    return bilerp(a.x, a.z, a.y, a.w, t.x, t.y);
 }
 
 fn ambient_occlusion_factor(ambient_occlusion_factor: f32) -> f32 {
@@ -209,29 +275,3 @@ fn ambient_occlusion_factor(ambient_occlusion_factor: f32) -> f32 {
     return exp(-ambient_occlusion_factor * strength);
 }
 
-// 0.0 -> Shadow
-// 1.0 -> Lit
-fn get_sunlight_factor(world_pos: vec3<f32>) -> f32 {
-    let shadow_clip = globals.shadow_map_projection * vec4(world_pos, 1.0);
-    let ndc = shadow_clip.xyz / shadow_clip.w;
-    // [-1, 1] -> [0, 1]
-    let uv = vec2(ndc.x, -ndc.y) * 0.5 + vec2(0.5);
-    let receiver_depth = ndc.z;
-    if (
-        uv.x < 0.
-        || uv.x > 1.
-        || uv.y < 0.
-        || uv.y > 1.
-        || receiver_depth < 0.
-        || receiver_depth > 1.
-    ) {
-        return 1.0;
-    }
-    let lit = textureSampleCompare(
-        shadow_map,
-        shadow_map_sampler,
-        uv,
-        receiver_depth + 1e-3
-    );
-    return lit;
-}
\ No newline at end of file
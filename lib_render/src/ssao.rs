@@ -0,0 +1,157 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, BindingResource,
+            BindingType, RenderPipeline, ShaderStages, TextureFormat, TextureSampleType,
+            TextureUsages, TextureView, TextureViewDimension,
+        },
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{pipeline::DepthTexture, shader::ShaderSources};
+
+/// One occlusion factor per pixel, where 1 is unoccluded
+const SSAO_TEXTURE_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+/// Computes screen space ambient occlusion from the depth prepass
+#[derive(Resource)]
+pub(crate) struct SsaoPipeline {
+    pub pipeline: RenderPipeline,
+    depth_layout: BindGroupLayout,
+    /// Layout of `SsaoTextures::output_bind_group`, used by the main pipelines
+    pub output_layout: BindGroupLayout,
+}
+
+/// Screen sized resources of the SSAO pass, recreated whenever the main pass
+/// depth texture is
+#[derive(Resource)]
+pub(crate) struct SsaoTextures {
+    pub target: TextureView,
+    /// Binds the main pass depth texture for the SSAO pass
+    pub depth_bind_group: BindGroup,
+    /// Binds `target` for the main pass
+    pub output_bind_group: BindGroup,
+}
+
+pub(crate) fn create_ssao_pipeline(
+    render_device: &RenderDevice,
+    shader_sources: &ShaderSources,
+    globals_layout: &BindGroupLayout,
+) -> Result<SsaoPipeline, String> {
+    let depth_layout = render_device.create_bind_group_layout(
+        Some("ssao depth bind group layout"),
+        &[BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Depth,
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }],
+    );
+    let output_layout = render_device.create_bind_group_layout(
+        Some("ssao output bind group layout"),
+        &[BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }],
+    );
+
+    let shader = shader_sources.create_module(render_device, "ssao.wgsl", &[])?;
+    let layout = render_device.create_pipeline_layout(
+        &bevy::render::render_resource::PipelineLayoutDescriptor {
+            label: Some("ssao pipeline layout"),
+            bind_group_layouts: &[globals_layout, &depth_layout],
+            push_constant_ranges: &[],
+        },
+    );
+    let pipeline = render_device.create_render_pipeline(
+        &bevy::render::render_resource::RawRenderPipelineDescriptor {
+            label: Some("ssao pipeline"),
+            layout: Some(&layout),
+            vertex: bevy::render::render_resource::RawVertexState {
+                module: &shader,
+                entry_point: Some("vs_fullscreen"),
+                buffers: &[],
+                compilation_options: default(),
+            },
+            fragment: Some(bevy::render::render_resource::RawFragmentState {
+                module: &shader,
+                entry_point: Some("fs_ssao"),
+                targets: &[Some(bevy::render::render_resource::ColorTargetState {
+                    format: SSAO_TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: bevy::render::render_resource::ColorWrites::ALL,
+                })],
+                compilation_options: default(),
+            }),
+            primitive: default(),
+            depth_stencil: None,
+            multisample: default(),
+            multiview: None,
+            cache: None,
+        },
+    );
+
+    Ok(SsaoPipeline {
+        pipeline,
+        depth_layout,
+        output_layout,
+    })
+}
+
+pub(crate) fn create_ssao_textures(
+    render_device: &RenderDevice,
+    ssao_pipeline: &SsaoPipeline,
+    depth: &DepthTexture,
+) -> SsaoTextures {
+    let texture = render_device.create_texture(&bevy::render::render_resource::TextureDescriptor {
+        label: Some("ssao texture"),
+        size: bevy::render::render_resource::Extent3d {
+            width: depth.size.x,
+            height: depth.size.y,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: bevy::render::render_resource::TextureDimension::D2,
+        format: SSAO_TEXTURE_FORMAT,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let target =
+        texture.create_view(&bevy::render::render_resource::TextureViewDescriptor::default());
+
+    let depth_bind_group = render_device.create_bind_group(
+        Some("ssao depth bind group"),
+        &ssao_pipeline.depth_layout,
+        &[BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::TextureView(&depth.view),
+        }],
+    );
+    let output_bind_group = render_device.create_bind_group(
+        Some("ssao output bind group"),
+        &ssao_pipeline.output_layout,
+        &[BindGroupEntry {
+            binding: 0,
+            resource: BindingResource::TextureView(&target),
+        }],
+    );
+
+    SsaoTextures {
+        target,
+        depth_bind_group,
+        output_bind_group,
+    }
+}
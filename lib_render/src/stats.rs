@@ -0,0 +1,114 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bevy::{
+    prelude::*,
+    render::{Render, RenderApp, RenderSet},
+};
+
+/// Work done by the renderer in the last rendered frame
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    /// Draws recorded across every pass. Indirect draws count once per chunk
+    /// that survives culling.
+    pub draw_calls: u32,
+    /// Quad instances drawn, summed over every pass that draws terrain
+    pub instances_submitted: u64,
+    /// Quad instances skipped because their chunk was outside the view or
+    /// shadow frustum
+    pub instances_culled: u64,
+    /// Bytes written to GPU buffers
+    pub bytes_uploaded: u64,
+    /// Bytes allocated for quad instances and their chunk slots, whether or
+    /// not any chunk uses them
+    pub instance_buffer_bytes: u64,
+    /// CPU time spent extracting changed chunks and packing and uploading
+    /// their instances
+    pub extract_prepare_time: Duration,
+}
+
+impl std::ops::AddAssign for RenderStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.draw_calls += rhs.draw_calls;
+        self.instances_submitted += rhs.instances_submitted;
+        self.instances_culled += rhs.instances_culled;
+        self.bytes_uploaded += rhs.bytes_uploaded;
+        self.instance_buffer_bytes += rhs.instance_buffer_bytes;
+        self.extract_prepare_time += rhs.extract_prepare_time;
+    }
+}
+
+impl RenderStats {
+    /// Counts a draw of `instances`, or their culling when not `visible`
+    pub(crate) fn record_draw(&mut self, instances: u32, visible: bool) {
+        if visible {
+            self.draw_calls += 1;
+            self.instances_submitted += instances as u64;
+        } else {
+            self.instances_culled += instances as u64;
+        }
+    }
+}
+
+/// Shared between both worlds. The render world adds to the current frame's
+/// stats, and the main world reads the last finished frame's.
+#[derive(Resource, Clone, Default)]
+pub(crate) struct RenderStatsCollector(Arc<Mutex<CollectedStats>>);
+
+#[derive(Default)]
+struct CollectedStats {
+    current: RenderStats,
+    last_frame: RenderStats,
+}
+
+impl RenderStatsCollector {
+    pub fn record(&self, stats: RenderStats) {
+        self.0.lock().unwrap().current += stats;
+    }
+
+    pub fn record_upload(&self, bytes: usize) {
+        self.0.lock().unwrap().current.bytes_uploaded += bytes as u64;
+    }
+
+    pub fn record_instance_buffer_bytes(&self, bytes: u64) {
+        self.0.lock().unwrap().current.instance_buffer_bytes = bytes;
+    }
+
+    pub fn record_extract_prepare_time(&self, duration: Duration) {
+        self.0.lock().unwrap().current.extract_prepare_time += duration;
+    }
+
+    /// Called once every view's commands are recorded
+    fn finish_frame(&self) {
+        let mut collected = self.0.lock().unwrap();
+        collected.last_frame = std::mem::take(&mut collected.current);
+    }
+
+    fn last_frame(&self) -> RenderStats {
+        self.0.lock().unwrap().last_frame
+    }
+}
+
+pub(crate) struct RenderStatsPlugin;
+
+impl Plugin for RenderStatsPlugin {
+    fn build(&self, app: &mut App) {
+        let collector = RenderStatsCollector::default();
+        app.init_resource::<RenderStats>()
+            .insert_resource(collector.clone())
+            .add_systems(First, publish_render_stats)
+            .sub_app_mut(RenderApp)
+            .insert_resource(collector)
+            .add_systems(Render, finish_stats_frame.in_set(RenderSet::Cleanup));
+    }
+}
+
+fn finish_stats_frame(collector: Res<RenderStatsCollector>) {
+    collector.finish_frame();
+}
+
+fn publish_render_stats(collector: Res<RenderStatsCollector>, mut stats: ResMut<RenderStats>) {
+    *stats = collector.last_frame();
+}
@@ -1,17 +1,56 @@
-use std::marker::PhantomData;
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    time::{Duration, SystemTime},
+};
 
 use bevy::{
+    asset::AssetPath,
     prelude::*,
     render::render_resource::{
-        AddressMode, BindGroupEntry, BindGroupLayoutEntry, BindingResource, BindingType,
-        FilterMode, SamplerBindingType, ShaderStages, TextureSampleType, TextureUsages,
+        AddressMode, BindGroupLayoutEntry, BindingType, FilterMode, SamplerBindingType,
+        ShaderStages, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
         TextureViewDimension,
     },
 };
 use strum::IntoEnumIterator;
 
+use crate::bind_group_cache::{BindGroupCache, Binding};
+
 pub trait TextureIndex {
     fn get_name(&self) -> &'static str;
+
+    fn bucket(&self) -> crate::QuadBucket {
+        crate::QuadBucket::Opaque
+    }
+
+    /// Brightness of light given off by the terrain (0-15). Emissive terrain
+    /// is drawn unlit and bright enough to bloom.
+    fn emission(&self) -> u8 {
+        0
+    }
+
+    /// Colour of the light emissive terrain casts on its surroundings
+    fn light_color(&self) -> Color {
+        Color::srgb(1.0, 0.75, 0.45)
+    }
+
+    /// How rough the surface is, from 0.0 (mirror-like) to 1.0. Fully rough
+    /// terrain has no specular highlight.
+    fn roughness(&self) -> f32 {
+        1.0
+    }
+
+    /// Whether the surface ripples over time, like water
+    fn ripples(&self) -> bool {
+        false
+    }
+
+    /// Whether the texture is multiplied by its chunk's `TerrainTint`, so one
+    /// grayscale texture can take on a different colour in each biome
+    fn tinted(&self) -> bool {
+        false
+    }
 }
 
 pub(crate) struct TexturePlugin<TerrainType> {
@@ -31,23 +70,40 @@ impl<TerrainType: 'static + IntoEnumIterator + TextureIndex + Send + Sync> Plugi
 {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, load_terrain_colors::<TerrainType>)
-            .sub_app_mut(bevy::render::RenderApp)
             .add_systems(
-                ExtractSchedule,
-                prepare_texture_bind_group::<TerrainType>
-                    .run_if(not(resource_exists::<TextureBindGroup>)),
-            );
+                Update,
+                (watch_terrain_texture_files, reload_terrain_colors).chain(),
+            )
+            .sub_app_mut(bevy::render::RenderApp)
+            .add_systems(ExtractSchedule, prepare_texture_bind_group);
     }
 }
 
+/// Folder under the asset root holding one image per terrain texture. Every
+/// image in it becomes a layer of the texture array, named by its file stem.
+const TERRAIN_TEXTURE_FOLDER: &str = "textures/terrain";
+
+/// Folder under the asset root holding optional tangent-space normal maps,
+/// named after the terrain texture they belong to. Green points up the
+/// texture, as in OpenGL and Blender.
+const TERRAIN_NORMAL_MAP_FOLDER: &str = "textures/terrain_normal";
+
+/// Normal map texel of a surface without bumps
+const FLAT_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
 #[derive(Resource)]
 struct TerrainColorTextureHandles {
     handles: Vec<Handle<Image>>,
+    /// Normal map of each texture in `handles`, if it has one
+    normal_map_handles: Vec<Option<Handle<Image>>>,
+    /// Bumped whenever one of the images is reloaded, so the render world
+    /// knows to rebuild the texture array
+    generation: u32,
 }
 
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct TerrainColorTextureIndices {
-    indices_by_name: std::collections::HashMap<&'static str, usize>,
+    indices_by_name: std::collections::HashMap<String, usize>,
 }
 
 impl TerrainColorTextureIndices {
@@ -57,126 +113,304 @@ impl TerrainColorTextureIndices {
     }
 }
 
+/// Lists the image file stems in the terrain texture folder, sorted so that
+/// layer indices are stable between runs
+fn scan_terrain_texture_folder() -> Vec<String> {
+    let folder = asset_file_path(TERRAIN_TEXTURE_FOLDER);
+    let entries = match std::fs::read_dir(&folder) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read terrain texture folder {folder:?}: {e}");
+            return vec![];
+        }
+    };
+    let mut names = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+fn asset_file_path(path: &str) -> std::path::PathBuf {
+    bevy::asset::io::file::FileAssetReader::get_base_path()
+        .join("assets")
+        .join(path)
+}
+
 fn load_terrain_colors<TerrainType: 'static + IntoEnumIterator + TextureIndex + Send + Sync>(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
 ) {
-    let handles = TerrainType::iter()
-        .map(|t| t.get_name())
-        .map(|name| asset_server.load(format!("{name}.png")))
+    let names = scan_terrain_texture_folder();
+    let handles = names
+        .iter()
+        .map(|name| asset_server.load(format!("{TERRAIN_TEXTURE_FOLDER}/{name}.png")))
         .collect();
-    commands.insert_resource(TerrainColorTextureHandles { handles });
-    let indices_by_name = TerrainType::iter()
-        .enumerate()
-        .map(|(i, ty)| (ty.get_name(), i))
+    let normal_map_handles = names
+        .iter()
+        .map(|name| {
+            let path = format!("{TERRAIN_NORMAL_MAP_FOLDER}/{name}.png");
+            if !asset_file_path(&path).is_file() {
+                return None;
+            }
+            // Normals are stored linearly, unlike colours
+            let handle = asset_server
+                .load_with_settings(path, |settings: &mut bevy::image::ImageLoaderSettings| {
+                    settings.is_srgb = false
+                });
+            Some(handle)
+        })
         .collect();
+    commands.insert_resource(TerrainColorTextureHandles {
+        handles,
+        normal_map_handles,
+        generation: 0,
+    });
+    let indices_by_name = names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (name, i))
+        .collect::<std::collections::HashMap<_, _>>();
+    for name in TerrainType::iter().map(|t| t.get_name()) {
+        if !indices_by_name.contains_key(name) {
+            warn!("No texture named {name:?} in {TERRAIN_TEXTURE_FOLDER}");
+        }
+    }
     commands.insert_resource(TerrainColorTextureIndices { indices_by_name });
 }
 
+/// How often the terrain images are checked for changes on disk
+const TEXTURE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reloads terrain images whose files were modified since they were last
+/// checked, without needing Bevy's `file_watcher` feature. Images added to
+/// or removed from the folder still need a restart, as they'd change the
+/// layer indices.
+fn watch_terrain_texture_files(
+    time: Res<Time<Real>>,
+    asset_server: Res<AssetServer>,
+    texture_handles: Res<TerrainColorTextureHandles>,
+    mut since_last_poll: Local<Duration>,
+    mut modified_times: Local<HashMap<AssetPath<'static>, SystemTime>>,
+) {
+    *since_last_poll += time.delta();
+    if *since_last_poll < TEXTURE_POLL_INTERVAL {
+        return;
+    }
+    *since_last_poll = Duration::ZERO;
+    let paths = texture_handles
+        .handles
+        .iter()
+        .chain(texture_handles.normal_map_handles.iter().flatten())
+        .filter_map(|handle| handle.path());
+    for path in paths {
+        let file = asset_file_path(&path.path().to_string_lossy());
+        let Ok(modified) = std::fs::metadata(&file).and_then(|metadata| metadata.modified()) else {
+            continue;
+        };
+        let previous = modified_times.insert(path.clone_owned(), modified);
+        if previous.is_some_and(|previous| previous != modified) {
+            asset_server.reload(path.clone_owned());
+        }
+    }
+}
+
+/// Rebuilds the texture array once a terrain image is reloaded
+fn reload_terrain_colors(
+    mut asset_events: EventReader<AssetEvent<Image>>,
+    mut texture_handles: ResMut<TerrainColorTextureHandles>,
+) {
+    let reloaded = asset_events.read().any(|event| {
+        matches!(
+            event,
+            AssetEvent::Modified { id }
+                if texture_handles
+                    .handles
+                    .iter()
+                    .chain(texture_handles.normal_map_handles.iter().flatten())
+                    .any(|handle| handle.id() == *id)
+        )
+    });
+    if reloaded {
+        info!("Terrain texture modified. Rebuilding texture array.");
+        texture_handles.generation += 1;
+    }
+}
+
 #[derive(Resource)]
 pub(crate) struct TextureBindGroup {
     pub bind_group: bevy::render::render_resource::BindGroup,
     pub layout: bevy::render::render_resource::BindGroupLayout,
+    generation: u32,
 }
 
-fn prepare_texture_bind_group<TerrainType: Send + Sync + TextureIndex>(
+fn prepare_texture_bind_group(
     mut commands: Commands,
-    gpu_images: Res<bevy::render::render_asset::RenderAssets<bevy::render::texture::GpuImage>>,
-    texture_handles: bevy::render::Extract<Res<TerrainColorTextureHandles>>,
+    texture_handles: bevy::render::Extract<Option<Res<TerrainColorTextureHandles>>>,
+    texture_bind_group: Option<Res<TextureBindGroup>>,
     render_device: Res<bevy::render::renderer::RenderDevice>,
     render_queue: Res<bevy::render::renderer::RenderQueue>,
     image_assets: bevy::render::Extract<Res<Assets<Image>>>,
+    mut bind_group_cache: ResMut<BindGroupCache>,
 ) {
+    let Some(texture_handles) = texture_handles.as_deref() else {
+        return;
+    };
+    if texture_bind_group
+        .as_ref()
+        .is_some_and(|bind_group| bind_group.generation == texture_handles.generation)
+    {
+        return;
+    }
     let image_layers = texture_handles
         .handles
         .iter()
-        .flat_map(|handle| gpu_images.get(handle))
+        .flat_map(|handle| image_assets.get(handle))
         .collect::<Vec<_>>();
-    if image_layers.len() != texture_handles.handles.len() {
+    if image_layers.is_empty() || image_layers.len() != texture_handles.handles.len() {
         return;
     }
-    info!("Loaded GPU images. Creating texture array.");
+    let normal_map_layers = texture_handles
+        .normal_map_handles
+        .iter()
+        .map(|handle| handle.as_ref().map(|handle| image_assets.get(handle)))
+        .collect::<Vec<_>>();
+    if normal_map_layers
+        .iter()
+        .any(|layer| matches!(layer, Some(None)))
+    {
+        return;
+    }
+    info!("Loaded terrain images. Creating texture array.");
 
+    let layer_size = image_layers[0].texture_descriptor.size;
+    let layer_format = image_layers[0].texture_descriptor.format;
     let layer_count = image_layers.len() as u32;
     let extent = bevy::render::render_resource::Extent3d {
         depth_or_array_layers: layer_count,
-        ..image_layers[0].size
+        ..layer_size
     };
     let array_texture =
         render_device.create_texture(&bevy::render::render_resource::TextureDescriptor {
             label: Some("terrain_color_texture_array"),
             size: extent,
-            mip_level_count: 1,
+            mip_level_count: layer_size.max_mips(TextureDimension::D2),
             sample_count: 1,
             dimension: bevy::render::render_resource::TextureDimension::D2,
-            format: image_layers[0].texture_format,
+            format: layer_format,
             usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
             view_formats: &[],
         });
 
     for (i, img) in image_layers.iter().enumerate() {
-        let data = image_assets
-            .get(texture_handles.handles[i].id())
-            .cloned()
-            .expect("Texture should exist in CPU land")
-            .data;
-        let data = data.unwrap().clone();
-        let data = data.as_slice();
-        render_queue.write_texture(
-            bevy::render::render_resource::TexelCopyTextureInfo {
-                texture: &array_texture,
-                mip_level: 0,
-                origin: bevy::render::render_resource::Origin3d {
-                    x: 0,
-                    y: 0,
-                    z: i as _,
-                },
-                aspect: bevy::render::render_resource::TextureAspect::All,
-            },
+        let size = img.texture_descriptor.size;
+        if size != layer_size || img.texture_descriptor.format != layer_format {
+            warn!(
+                "Terrain texture {:?} is {}x{} {:?}, expected {}x{} {:?}. Leaving its layer empty.",
+                texture_handles.handles[i].path(),
+                size.width,
+                size.height,
+                img.texture_descriptor.format,
+                layer_size.width,
+                layer_size.height,
+                layer_format,
+            );
+            continue;
+        }
+        let Some(data) = img.data.as_deref() else {
+            warn!(
+                "Terrain texture {:?} has no CPU data. Leaving its layer empty.",
+                texture_handles.handles[i].path(),
+            );
+            continue;
+        };
+        write_texture_layer(
+            &render_queue,
+            &array_texture,
+            i as u32,
+            size,
             data,
-            bevy::render::render_resource::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(img.size.width * 4),
-                rows_per_image: None,
-            },
-            bevy::render::render_resource::Extent3d {
-                depth_or_array_layers: 1,
-                ..img.size
-            },
+            layer_format.is_srgb(),
         );
     }
 
-    let layout = render_device.create_bind_group_layout(
-        Some("my texture bind group layout"),
-        &[
-            // Texture binding
-            bevy::render::render_resource::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::FRAGMENT,
-                ty: bevy::render::render_resource::BindingType::Texture {
-                    sample_type: TextureSampleType::Float { filterable: true },
-                    view_dimension: TextureViewDimension::D2Array,
-                    multisampled: false,
-                },
-                count: None,
-            },
-            // Sampler binding
-            BindGroupLayoutEntry {
-                binding: 1,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                count: None,
-            },
-        ],
+    // Every layer needs a normal map, so textures without one get a flat one.
+    // The array is a single texel per layer when no texture has a normal map.
+    let normal_map_size = normal_map_layers.iter().flatten().flatten().next().map_or(
+        bevy::render::render_resource::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        |img| img.texture_descriptor.size,
     );
+    let normal_map_format = TextureFormat::Rgba8Unorm;
+    let normal_map_texture =
+        render_device.create_texture(&bevy::render::render_resource::TextureDescriptor {
+            label: Some("terrain_normal_map_texture_array"),
+            size: bevy::render::render_resource::Extent3d {
+                depth_or_array_layers: layer_count,
+                ..normal_map_size
+            },
+            mip_level_count: normal_map_size.max_mips(TextureDimension::D2),
+            sample_count: 1,
+            dimension: bevy::render::render_resource::TextureDimension::D2,
+            format: normal_map_format,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+    let flat_normal_map =
+        FLAT_NORMAL.repeat((normal_map_size.width * normal_map_size.height) as usize);
+    for (i, layer) in normal_map_layers.iter().enumerate() {
+        let data = match layer {
+            Some(Some(img)) => {
+                let size = img.texture_descriptor.size;
+                let format = img.texture_descriptor.format;
+                match img.data.as_deref() {
+                    Some(data) if size == normal_map_size && format == normal_map_format => data,
+                    _ => {
+                        warn!(
+                            "Normal map {:?} is {}x{} {:?}, expected {}x{} {:?}. Using a flat normal map.",
+                            texture_handles.normal_map_handles[i]
+                                .as_ref()
+                                .and_then(|handle| handle.path()),
+                            size.width,
+                            size.height,
+                            format,
+                            normal_map_size.width,
+                            normal_map_size.height,
+                            normal_map_format,
+                        );
+                        flat_normal_map.as_slice()
+                    }
+                }
+            }
+            _ => flat_normal_map.as_slice(),
+        };
+        write_texture_layer(
+            &render_queue,
+            &normal_map_texture,
+            i as u32,
+            normal_map_size,
+            data,
+            false,
+        );
+    }
+
+    // The cache returns the same layout on rebuilds, so pipelines created
+    // against it stay compatible with the new bind group
+    let layout = create_texture_bind_group_layout(&render_device, &mut bind_group_cache);
     let nearest_sampler =
         render_device.create_sampler(&bevy::render::render_resource::SamplerDescriptor {
             label: Some("nearest_sampler"),
             mag_filter: FilterMode::Nearest,
             min_filter: FilterMode::Linear,
             mipmap_filter: FilterMode::Nearest,
-            address_mode_u: AddressMode::ClampToEdge,
-            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
             address_mode_w: AddressMode::ClampToEdge,
             ..Default::default()
         });
@@ -187,21 +421,152 @@ fn prepare_texture_bind_group<TerrainType: Send + Sync + TextureIndex>(
             dimension: Some(TextureViewDimension::D2Array),
             ..Default::default()
         });
+    let normal_map_view =
+        normal_map_texture.create_view(&bevy::render::render_resource::TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
 
-    let bind_group = render_device.create_bind_group(
-        Some("My texture bind group"),
+    let bind_group = bind_group_cache.bind_group(
+        &render_device,
+        "terrain texture bind group",
         &layout,
         &[
-            BindGroupEntry {
+            Binding::TextureView(&texture_view),
+            Binding::Sampler(&nearest_sampler),
+            Binding::TextureView(&normal_map_view),
+        ],
+    );
+
+    commands.insert_resource(TextureBindGroup {
+        bind_group,
+        layout,
+        generation: texture_handles.generation,
+    });
+}
+
+fn create_texture_bind_group_layout(
+    render_device: &bevy::render::renderer::RenderDevice,
+    bind_group_cache: &mut BindGroupCache,
+) -> bevy::render::render_resource::BindGroupLayout {
+    bind_group_cache.layout(
+        render_device,
+        "terrain texture bind group layout",
+        &[
+            // Texture binding
+            bevy::render::render_resource::BindGroupLayoutEntry {
                 binding: 0,
-                resource: BindingResource::TextureView(&texture_view),
+                visibility: ShaderStages::FRAGMENT,
+                ty: bevy::render::render_resource::BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
             },
-            BindGroupEntry {
+            // Sampler binding
+            BindGroupLayoutEntry {
                 binding: 1,
-                resource: BindingResource::Sampler(&nearest_sampler),
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+            // Normal map binding
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
             },
         ],
-    );
+    )
+}
 
-    commands.insert_resource(TextureBindGroup { bind_group, layout });
+/// Copies a layer of 4 bytes per texel into `array_texture`, along with each
+/// of its mip levels the texture has room for
+fn write_texture_layer(
+    render_queue: &bevy::render::renderer::RenderQueue,
+    array_texture: &bevy::render::render_resource::Texture,
+    layer: u32,
+    size: bevy::render::render_resource::Extent3d,
+    data: &[u8],
+    srgb: bool,
+) {
+    let mut mip = data.to_vec();
+    for mip_level in 0..array_texture.mip_level_count() {
+        let mip_size = size.mip_level_size(mip_level, TextureDimension::D2);
+        if mip_level > 0 {
+            let last_size = size.mip_level_size(mip_level - 1, TextureDimension::D2);
+            mip = downsample(&mip, last_size, srgb);
+        }
+        render_queue.write_texture(
+            bevy::render::render_resource::TexelCopyTextureInfo {
+                texture: array_texture,
+                mip_level,
+                origin: bevy::render::render_resource::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+                aspect: bevy::render::render_resource::TextureAspect::All,
+            },
+            &mip,
+            bevy::render::render_resource::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(mip_size.width * 4),
+                rows_per_image: None,
+            },
+            bevy::render::render_resource::Extent3d {
+                depth_or_array_layers: 1,
+                ..mip_size
+            },
+        );
+    }
+}
+
+/// Halves a layer of 4 bytes per texel, averaging each 2x2 block of texels.
+/// sRGB colours are averaged in linear space, so mips don't darken, while
+/// alpha and linear data are averaged as they are.
+fn downsample(data: &[u8], size: bevy::render::render_resource::Extent3d, srgb: bool) -> Vec<u8> {
+    let (width, height) = (size.width, size.height);
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let to_linear = |value: u8| {
+        let value = value as f32 / 255.0;
+        if srgb {
+            Srgba::gamma_function(value)
+        } else {
+            value
+        }
+    };
+    let from_linear = |value: f32| {
+        let value = if srgb {
+            Srgba::gamma_function_inverse(value)
+        } else {
+            value
+        };
+        (value * 255.0).round().clamp(0.0, 255.0) as u8
+    };
+    let mut half = Vec::with_capacity((half_width * half_height * 4) as usize);
+    for y in 0..half_height {
+        for x in 0..half_width {
+            let mut sum = [0.0; 4];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                // Odd sizes repeat the last row or column
+                let texel_x = (x * 2 + dx).min(width - 1);
+                let texel_y = (y * 2 + dy).min(height - 1);
+                let i = ((texel_y * width + texel_x) * 4) as usize;
+                for channel in 0..3 {
+                    sum[channel] += to_linear(data[i + channel]);
+                }
+                sum[3] += data[i + 3] as f32 / 255.0;
+            }
+            half.extend(sum[..3].iter().map(|total| from_linear(total / 4.0)));
+            half.push((sum[3] / 4.0 * 255.0).round() as u8);
+        }
+    }
+    half
 }
@@ -29,3 +29,65 @@ where
 {
     it.clone().cartesian_product(it)
 }
+
+/// First voxel hit by a ray
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxelHit {
+    pub pos: [i32; 3],
+    /// Outward normal of the face the ray entered through. Zero when the ray
+    /// starts inside the hit voxel.
+    pub normal: [i32; 3],
+    /// Distance along the ray to where it entered the voxel, in units of
+    /// `direction`'s length
+    pub distance: f32,
+}
+
+/// Walks the unit voxels along a ray with a DDA, in the order the ray enters
+/// them, returning the first voxel where `is_hit` is true within
+/// `max_distance`
+pub fn voxel_raycast(
+    origin: [f32; 3],
+    direction: [f32; 3],
+    max_distance: f32,
+    mut is_hit: impl FnMut([i32; 3]) -> bool,
+) -> Option<VoxelHit> {
+    let mut pos = origin.map(|x| x.floor() as i32);
+    let step = direction.map(|d| if d > 0.0 { 1 } else { -1 });
+    // Distance along the ray between voxel boundaries on each axis, and to
+    // the first boundary
+    let delta = direction.map(|d| {
+        if d == 0.0 {
+            f32::INFINITY
+        } else {
+            1.0 / d.abs()
+        }
+    });
+    let mut next = [0, 1, 2].map(|axis| {
+        let d = direction[axis];
+        if d == 0.0 {
+            f32::INFINITY
+        } else if d > 0.0 {
+            (pos[axis] as f32 + 1.0 - origin[axis]) / d
+        } else {
+            (origin[axis] - pos[axis] as f32) / -d
+        }
+    });
+    let mut normal = [0; 3];
+    let mut distance = 0.0;
+    while distance <= max_distance {
+        if is_hit(pos) {
+            return Some(VoxelHit {
+                pos,
+                normal,
+                distance,
+            });
+        }
+        let axis = (0..3).min_by(|a, b| next[*a].total_cmp(&next[*b])).unwrap();
+        distance = next[axis];
+        next[axis] += delta[axis];
+        pos[axis] += step[axis];
+        normal = [0; 3];
+        normal[axis] = -step[axis];
+    }
+    None
+}
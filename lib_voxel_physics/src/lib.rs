@@ -0,0 +1,393 @@
+use bevy::math::{IVec3, Vec3, Vec3A, bounding::Aabb3d};
+use lib_utils::iter_3d;
+
+// Collision of boxes moving through a grid of unit cells. Each cell holds
+// any number of solid boxes, given by a `boxes_at` function in coordinates
+// relative to the cell's lowest corner, so full blocks, slabs and empty
+// cells all look the same from here.
+
+/// How far below a box is checked for ground
+const GROUND_PROBE: f32 = 1e-3;
+/// How far boxes can overlap and still count as touching, so that rounding
+/// after coming to rest against a box doesn't let the next move pass into it
+const CONTACT_TOLERANCE: f32 = 1e-4;
+
+/// How far a box moved through the grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Movement {
+    pub moved: Vec3,
+    /// Axes on which the box was stopped short
+    pub blocked: [bool; 3],
+}
+
+/// First box that a moving box runs into
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+    /// Fraction of the movement made before touching, from 0 to 1
+    pub time: f32,
+    /// Outward normal of the face that was hit
+    pub normal: IVec3,
+    /// Cell of the box that was hit
+    pub cell: IVec3,
+}
+
+/// Earliest box in the grid that `aabb` enters when moved by `delta`. Boxes
+/// it already overlaps by more than the contact tolerance are passed
+/// through.
+pub fn sweep<'a>(
+    aabb: Aabb3d,
+    delta: Vec3,
+    boxes_at: impl Fn(IVec3) -> &'a [Aabb3d],
+) -> Option<SweepHit> {
+    let moved = translated(aabb, delta);
+    let region = Aabb3d {
+        min: aabb.min.min(moved.min),
+        max: aabb.max.max(moved.max),
+    };
+    let mut first: Option<SweepHit> = None;
+    for cell in cells_overlapping(region) {
+        for block_box in boxes_at(cell) {
+            let block_box = translated(*block_box, cell.as_vec3());
+            let Some((time, axis)) = enter_time(aabb, delta, block_box) else {
+                continue;
+            };
+            if first.is_some_and(|hit| hit.time <= time) {
+                continue;
+            }
+            let mut normal = IVec3::ZERO;
+            normal[axis] = -delta[axis].signum() as i32;
+            first = Some(SweepHit { time, normal, cell });
+        }
+    }
+    first
+}
+
+/// Moves `aabb` by `delta` one axis at a time, vertical first, stopping each
+/// axis at the first box in the way. Movement along the other axes carries
+/// on, so the box slides along whatever it hits.
+pub fn move_and_slide<'a>(
+    mut aabb: Aabb3d,
+    delta: Vec3,
+    boxes_at: impl Fn(IVec3) -> &'a [Aabb3d],
+) -> Movement {
+    let mut moved = Vec3::ZERO;
+    let mut blocked = [false; 3];
+    for axis in [1, 0, 2] {
+        let d = delta[axis];
+        if d == 0.0 {
+            continue;
+        }
+        let mut axis_delta = Vec3::ZERO;
+        axis_delta[axis] = d;
+        let allowed = sweep(aabb, axis_delta, &boxes_at).map_or(d, |hit| d * hit.time);
+        if allowed != d {
+            blocked[axis] = true;
+        }
+        aabb.min[axis] += allowed;
+        aabb.max[axis] += allowed;
+        moved[axis] = allowed;
+    }
+    Movement { moved, blocked }
+}
+
+/// Whether `aabb` is resting on top of a box
+pub fn is_grounded<'a>(aabb: Aabb3d, boxes_at: impl Fn(IVec3) -> &'a [Aabb3d]) -> bool {
+    move_and_slide(aabb, Vec3::NEG_Y * GROUND_PROBE, boxes_at).blocked[1]
+}
+
+/// Moves `aabb` by `delta` as if it were first raised by up to `max_height`,
+/// then lowered back onto whatever it moved over, like stepping up onto a
+/// ledge. `None` if it didn't land on anything, or ended up no higher.
+pub fn step_up<'a>(
+    aabb: Aabb3d,
+    delta: Vec3,
+    max_height: f32,
+    boxes_at: impl Fn(IVec3) -> &'a [Aabb3d],
+) -> Option<Movement> {
+    let up = move_and_slide(aabb, Vec3::Y * max_height, &boxes_at).moved;
+    let raised = translated(aabb, up);
+    let across = move_and_slide(raised, delta.with_y(0.0), &boxes_at);
+    let moved_across = translated(raised, across.moved);
+    let fall = Vec3::Y * (delta.y.min(0.0) - up.y);
+    let down = move_and_slide(moved_across, fall, &boxes_at);
+    let moved = up + across.moved + down.moved;
+    if !down.blocked[1] || moved.y <= 0.0 {
+        return None;
+    }
+    Some(Movement {
+        moved,
+        blocked: [across.blocked[0], true, across.blocked[2]],
+    })
+}
+
+fn translated(aabb: Aabb3d, by: Vec3) -> Aabb3d {
+    Aabb3d {
+        min: aabb.min + Vec3A::from(by),
+        max: aabb.max + Vec3A::from(by),
+    }
+}
+
+/// Cells that `aabb` overlaps. Cells it only touches the faces of are left
+/// out.
+fn cells_overlapping(aabb: Aabb3d) -> impl Iterator<Item = IVec3> {
+    let min = Vec3::from(aabb.min).floor().as_ivec3();
+    let max = Vec3::from(aabb.max).ceil().as_ivec3() - IVec3::ONE;
+    iter_3d(min.x..=max.x, min.y..=max.y, min.z..=max.z).map(|(x, y, z)| IVec3::new(x, y, z))
+}
+
+/// Fraction of `delta` that `moving` travels before entering `target`, and
+/// the axis it enters across. `None` if it doesn't enter it, or already
+/// overlaps it.
+fn enter_time(moving: Aabb3d, delta: Vec3, target: Aabb3d) -> Option<(f32, usize)> {
+    let mut entry = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut entry_axis = None;
+    for axis in 0..3 {
+        let d = delta[axis];
+        let (axis_entry, axis_exit) = if d > 0.0 {
+            (
+                (target.min[axis] - moving.max[axis]) / d,
+                (target.max[axis] - moving.min[axis]) / d,
+            )
+        } else if d < 0.0 {
+            (
+                (target.max[axis] - moving.min[axis]) / d,
+                (target.min[axis] - moving.max[axis]) / d,
+            )
+        } else if target.min[axis] < moving.max[axis] - CONTACT_TOLERANCE
+            && target.max[axis] > moving.min[axis] + CONTACT_TOLERANCE
+        {
+            (f32::NEG_INFINITY, f32::INFINITY)
+        } else {
+            // Never lines up on this axis
+            return None;
+        };
+        if axis_entry > entry {
+            entry = axis_entry;
+            entry_axis = Some(axis);
+        }
+        exit = exit.min(axis_exit);
+    }
+    let axis = entry_axis?;
+    let tolerance = CONTACT_TOLERANCE / delta[axis].abs();
+    let entered = entry < exit && (-tolerance..=1.0).contains(&entry);
+    entered.then_some((entry.max(0.0), axis))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const CUBE: &[Aabb3d] = &[Aabb3d {
+        min: Vec3A::ZERO,
+        max: Vec3A::ONE,
+    }];
+    const SLAB: &[Aabb3d] = &[Aabb3d {
+        min: Vec3A::ZERO,
+        max: Vec3A::new(1.0, 0.5, 1.0),
+    }];
+
+    /// Hand-built grid of cells, empty unless filled
+    #[derive(Default)]
+    struct Layout(HashMap<IVec3, &'static [Aabb3d]>);
+
+    impl Layout {
+        fn with(
+            mut self,
+            cells: impl IntoIterator<Item = IVec3>,
+            boxes: &'static [Aabb3d],
+        ) -> Self {
+            for cell in cells {
+                self.0.insert(cell, boxes);
+            }
+            self
+        }
+
+        fn boxes_at(&self) -> impl Fn(IVec3) -> &'static [Aabb3d] + '_ {
+            |cell| self.0.get(&cell).copied().unwrap_or(&[])
+        }
+    }
+
+    /// Layer of cells at height `y`, from -4 to 4 on x and z
+    fn floor(y: i32) -> impl Iterator<Item = IVec3> {
+        iter_3d(-4..=4, y..=y, -4..=4).map(|(x, y, z)| IVec3::new(x, y, z))
+    }
+
+    /// A player sized box standing at `feet`
+    fn body(feet: Vec3) -> Aabb3d {
+        Aabb3d {
+            min: (feet - Vec3::new(0.3, 0.0, 0.3)).into(),
+            max: (feet + Vec3::new(0.3, 1.8, 0.3)).into(),
+        }
+    }
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-5),
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn moves_freely_through_empty_cells() {
+        let layout = Layout::default();
+        let delta = Vec3::new(3.0, -2.0, 1.5);
+        let movement = move_and_slide(body(Vec3::ZERO), delta, layout.boxes_at());
+        assert_close(movement.moved, delta);
+        assert_eq!(movement.blocked, [false; 3]);
+    }
+
+    #[test]
+    fn falls_onto_floor() {
+        let layout = Layout::default().with(floor(0), CUBE);
+        let movement = move_and_slide(
+            body(Vec3::new(0.5, 3.0, 0.5)),
+            Vec3::new(0.0, -5.0, 0.0),
+            layout.boxes_at(),
+        );
+        assert_close(movement.moved, Vec3::new(0.0, -2.0, 0.0));
+        assert_eq!(movement.blocked, [false, true, false]);
+    }
+
+    #[test]
+    fn doesnt_tunnel_through_thin_walls() {
+        let layout = Layout::default().with([IVec3::new(5, 0, 0), IVec3::new(5, 1, 0)], CUBE);
+        let movement = move_and_slide(
+            body(Vec3::new(0.5, 0.0, 0.5)),
+            Vec3::new(100.0, 0.0, 0.0),
+            layout.boxes_at(),
+        );
+        assert_close(movement.moved, Vec3::new(4.2, 0.0, 0.0));
+        assert_eq!(movement.blocked, [true, false, false]);
+    }
+
+    #[test]
+    fn slides_along_walls() {
+        let wall = iter_3d(1..=1, 0..=1, -4..=4).map(|(x, y, z)| IVec3::new(x, y, z));
+        let layout = Layout::default().with(wall, CUBE);
+        let movement = move_and_slide(
+            body(Vec3::new(0.5, 0.0, 0.5)),
+            Vec3::new(1.0, 0.0, 1.0),
+            layout.boxes_at(),
+        );
+        assert_close(movement.moved, Vec3::new(0.2, 0.0, 1.0));
+        assert_eq!(movement.blocked, [true, false, false]);
+    }
+
+    #[test]
+    fn walks_over_floor_without_catching_on_seams() {
+        let layout = Layout::default().with(floor(-1), CUBE);
+        let delta = Vec3::new(2.5, 0.0, -1.5);
+        let movement = move_and_slide(body(Vec3::new(0.5, 0.0, 0.5)), delta, layout.boxes_at());
+        assert_close(movement.moved, delta);
+        assert_eq!(movement.blocked, [false; 3]);
+    }
+
+    #[test]
+    fn stays_on_floor_over_many_steps() {
+        let layout = Layout::default().with(floor(-1), CUBE);
+        let mut feet = Vec3::new(-3.7, 0.3, -3.1);
+        for _ in 0..500 {
+            let delta = Vec3::new(0.013, -0.07, 0.011);
+            feet += move_and_slide(body(feet), delta, layout.boxes_at()).moved;
+        }
+        assert!(feet.y.abs() < 1e-3, "sank or floated to {}", feet.y);
+        assert!(
+            feet.x > 2.0 && feet.z > 1.0,
+            "caught on the floor at {feet}"
+        );
+    }
+
+    #[test]
+    fn lands_on_slabs() {
+        let layout = Layout::default().with(floor(0), SLAB);
+        let movement = move_and_slide(
+            body(Vec3::new(0.5, 2.0, 0.5)),
+            Vec3::new(0.0, -3.0, 0.0),
+            layout.boxes_at(),
+        );
+        assert_close(movement.moved, Vec3::new(0.0, -1.5, 0.0));
+    }
+
+    #[test]
+    fn detects_ground() {
+        let layout = Layout::default().with(floor(-1), CUBE).with(floor(4), SLAB);
+        assert!(is_grounded(body(Vec3::ZERO), layout.boxes_at()));
+        assert!(!is_grounded(
+            body(Vec3::new(0.0, 0.5, 0.0)),
+            layout.boxes_at()
+        ));
+        assert!(is_grounded(
+            body(Vec3::new(0.0, 4.5, 0.0)),
+            layout.boxes_at()
+        ));
+        // Touching a ceiling isn't standing on it
+        assert!(!is_grounded(
+            body(Vec3::new(0.0, 2.2, 0.0)),
+            layout.boxes_at()
+        ));
+    }
+
+    #[test]
+    fn sweep_finds_enter_time_and_face() {
+        let layout = Layout::default()
+            .with([IVec3::new(3, 0, 0)], CUBE)
+            .with([IVec3::new(2, 0, 0)], SLAB);
+        let hit = sweep(
+            body(Vec3::new(0.5, 0.6, 0.5)),
+            Vec3::new(4.0, 0.0, 0.0),
+            layout.boxes_at(),
+        )
+        .unwrap();
+        // Passes over the slab, and hits the cube after 2.2 of the 4 blocks
+        assert!((hit.time - 0.55).abs() < 1e-5, "time was {}", hit.time);
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert_eq!(hit.cell, IVec3::new(3, 0, 0));
+    }
+
+    #[test]
+    fn sweep_misses_boxes_out_of_reach() {
+        let layout = Layout::default().with([IVec3::new(3, 0, 0)], CUBE);
+        let feet = Vec3::new(0.5, 0.0, 0.5);
+        let short = sweep(body(feet), Vec3::new(2.0, 0.0, 0.0), layout.boxes_at());
+        let away = sweep(body(feet), Vec3::new(-4.0, 0.0, 0.0), layout.boxes_at());
+        assert_eq!(short, None);
+        assert_eq!(away, None);
+    }
+
+    #[test]
+    fn sweep_passes_through_overlapping_boxes() {
+        let layout = Layout::default().with([IVec3::ZERO], CUBE);
+        let hit = sweep(
+            body(Vec3::new(0.5, 0.0, 0.5)),
+            Vec3::new(2.0, 0.0, 0.0),
+            layout.boxes_at(),
+        );
+        assert_eq!(hit, None);
+    }
+
+    #[test]
+    fn steps_up_one_block_ledges() {
+        let layout = Layout::default()
+            .with(floor(-1), CUBE)
+            .with([IVec3::new(1, 0, 0)], CUBE);
+        let aabb = body(Vec3::new(0.5, 0.0, 0.5));
+        let delta = Vec3::new(0.5, -0.01, 0.0);
+        let movement = step_up(aabb, delta, 1.0, layout.boxes_at()).unwrap();
+        assert_close(movement.moved, Vec3::new(0.5, 1.0, 0.0));
+        assert_eq!(movement.blocked, [false, true, false]);
+    }
+
+    #[test]
+    fn doesnt_step_up_walls() {
+        let layout = Layout::default()
+            .with(floor(-1), CUBE)
+            .with([IVec3::new(1, 0, 0), IVec3::new(1, 1, 0)], CUBE);
+        let aabb = body(Vec3::new(0.5, 0.0, 0.5));
+        let delta = Vec3::new(0.5, -0.01, 0.0);
+        let movement = step_up(aabb, delta, 1.0, layout.boxes_at());
+        assert_eq!(movement, None);
+    }
+}
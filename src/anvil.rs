@@ -0,0 +1,503 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::prelude::*;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use lib_spatial::CHUNK_SIZE;
+use lib_utils::{iter_3d, square_iter};
+
+use crate::{
+    block::Block,
+    persistence::invalid_data,
+    world_gen::{GeneratingChunk, GenerationPass},
+};
+
+/// Fills chunks with the blocks of a Minecraft world, read from the `.mca`
+/// region files of its `region` folder, in place of the generated terrain.
+/// Only worlds saved by Minecraft 1.16 or later can be read. Blocks are
+/// mapped by their name with a `BlockMapping`, and anything the Minecraft
+/// world doesn't have, like chunks it never generated, is air.
+///
+/// Region files are read on the main thread as chunks are generated, so
+/// loading an imported world hitches more than generating one.
+pub struct AnvilImport {
+    region_folder: PathBuf,
+    mapping: BlockMapping,
+    /// Added to Minecraft heights to get heights in this world, so the sea
+    /// ends up just below 0
+    y_offset: i32,
+    cache: Mutex<RegionCache>,
+}
+
+impl AnvilImport {
+    /// Imports the world in `folder`, which is either the Minecraft world's
+    /// folder or its `region` folder. The blocks not covered by the mapping
+    /// file at `mapping`, if any, are mapped by `BlockMapping::default`.
+    pub fn open(folder: &Path, mapping: Option<&Path>) -> io::Result<Self> {
+        let region_folder = if folder.join("region").is_dir() {
+            folder.join("region")
+        } else {
+            folder.to_owned()
+        };
+        if !region_folder.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{folder:?} is not a folder"),
+            ));
+        }
+        let mapping = match mapping {
+            Some(path) => BlockMapping::default().with_overrides(&fs::read_to_string(path)?)?,
+            None => BlockMapping::default(),
+        };
+        info!("Importing the Minecraft world in {:?}", region_folder);
+        Ok(Self {
+            region_folder,
+            mapping,
+            y_offset: -64,
+            cache: default(),
+        })
+    }
+
+    /// Column of Minecraft blocks at `column_pos`, in Minecraft chunks, or
+    /// `None` if the world doesn't have it
+    fn column(&self, column_pos: IVec2) -> Option<Arc<Column>> {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(column) = cache.columns.get(&column_pos) {
+            return column.clone();
+        }
+        let region_pos = column_pos.div_euclid(IVec2::splat(REGION_COLUMNS));
+        let region = cache
+            .regions
+            .entry(region_pos)
+            .or_insert_with(|| {
+                let path = self
+                    .region_folder
+                    .join(format!("r.{}.{}.mca", region_pos.x, region_pos.y));
+                match fs::read(&path) {
+                    Ok(bytes) => Some(Arc::new(bytes)),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                    Err(e) => {
+                        error!("Couldn't read {:?}: {}", path, e);
+                        None
+                    }
+                }
+            })
+            .clone();
+        let column = region.and_then(|region| {
+            read_column(&region, column_pos, &self.mapping).unwrap_or_else(|e| {
+                warn!("Couldn't import Minecraft chunk {}: {}", column_pos, e);
+                None
+            })
+        });
+        let column = column.map(Arc::new);
+        if cache.columns.len() >= MAX_CACHED_COLUMNS {
+            cache.columns.clear();
+        }
+        cache.columns.insert(column_pos, column.clone());
+        column
+    }
+}
+
+impl GenerationPass for AnvilImport {
+    fn generate(&self, chunk: &mut GeneratingChunk) {
+        let origin = chunk.position * CHUNK_SIZE as i32;
+        let columns_per_chunk = CHUNK_SIZE as i32 / COLUMN_SIZE;
+        for (column_x, column_z) in square_iter(0..columns_per_chunk) {
+            let column_offset = IVec2::new(column_x, column_z);
+            let column_pos = origin.xz().div_euclid(IVec2::splat(COLUMN_SIZE)) + column_offset;
+            let column = self.column(column_pos);
+            for (x, y, z) in iter_3d(0..COLUMN_SIZE, 0..CHUNK_SIZE as i32, 0..COLUMN_SIZE) {
+                let block = column.as_ref().map_or(Block::Air, |column| {
+                    column.block(x, origin.y + y - self.y_offset, z)
+                });
+                let local_pos = IVec3::new(x, y, z) + (column_offset * COLUMN_SIZE).extend(0).xzy();
+                chunk.set_block(local_pos.as_uvec3(), block);
+            }
+        }
+    }
+}
+
+/// Blocks along each horizontal axis of a Minecraft chunk, and along each
+/// axis of its sections
+const COLUMN_SIZE: i32 = 16;
+/// Minecraft chunks along each horizontal axis of a region file
+const REGION_COLUMNS: i32 = 32;
+const SECTOR_SIZE: usize = 4096;
+/// Decoded Minecraft chunks kept around, as each is shared by a stack of
+/// chunks here
+const MAX_CACHED_COLUMNS: usize = 256;
+
+#[derive(Default)]
+struct RegionCache {
+    /// Contents of each region file read, or `None` if it doesn't exist
+    regions: HashMap<IVec2, Option<Arc<Vec<u8>>>>,
+    columns: HashMap<IVec2, Option<Arc<Column>>>,
+}
+
+/// The blocks of one Minecraft chunk, by the height of each section
+struct Column {
+    sections: HashMap<i32, Vec<Block>>,
+}
+
+impl Column {
+    /// Block at the given Minecraft height and position within the column
+    fn block(&self, x: i32, y: i32, z: i32) -> Block {
+        let Some(section) = self.sections.get(&y.div_euclid(COLUMN_SIZE)) else {
+            return Block::Air;
+        };
+        let y = y.rem_euclid(COLUMN_SIZE);
+        section[((y * COLUMN_SIZE + z) * COLUMN_SIZE + x) as usize]
+    }
+
+    fn from_nbt(root: &Tag, mapping: &BlockMapping) -> io::Result<Self> {
+        // Before 1.18, everything was inside a `Level` compound with
+        // capitalised names
+        let (level, old_names) = match root.get("Level") {
+            Some(level) => (level, true),
+            None => (root, false),
+        };
+        let sections = level
+            .get(if old_names { "Sections" } else { "sections" })
+            .and_then(Tag::as_list)
+            .ok_or_else(|| invalid_data("missing sections"))?;
+        let mut column = Self {
+            sections: HashMap::new(),
+        };
+        for section in sections {
+            let Some(Tag::Byte(y)) = section.get("Y") else {
+                return Err(invalid_data("section without a height"));
+            };
+            let (palette, data) = if old_names {
+                (section.get("Palette"), section.get("BlockStates"))
+            } else {
+                let states = section.get("block_states");
+                (
+                    states.and_then(|states| states.get("palette")),
+                    states.and_then(|states| states.get("data")),
+                )
+            };
+            // Sections of only air may have no blocks at all
+            let Some(palette) = palette.and_then(Tag::as_list) else {
+                continue;
+            };
+            let palette = palette
+                .iter()
+                .map(|state| match state.get("Name") {
+                    Some(Tag::String(name)) => Ok(mapping.block(name)),
+                    _ => Err(invalid_data("block state without a name")),
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            let data = match data {
+                Some(Tag::LongArray(data)) => data.as_slice(),
+                _ => &[],
+            };
+            column
+                .sections
+                .insert(*y as i32, unpack_section(&palette, data)?);
+        }
+        Ok(column)
+    }
+}
+
+/// Minecraft chunk at `column_pos` in the region file `region`, or `None` if
+/// it was never generated
+fn read_column(
+    region: &[u8],
+    column_pos: IVec2,
+    mapping: &BlockMapping,
+) -> io::Result<Option<Column>> {
+    let local = column_pos.rem_euclid(IVec2::splat(REGION_COLUMNS));
+    let mut header = BigEndianReader(region);
+    header.bytes(4 * (local.x + local.y * REGION_COLUMNS) as usize)?;
+    let [a, b, c, _sectors] = header.array()?;
+    let offset = u32::from_be_bytes([0, a, b, c]) as usize * SECTOR_SIZE;
+    if offset == 0 {
+        return Ok(None);
+    }
+    let mut reader = BigEndianReader(
+        region
+            .get(offset..)
+            .ok_or_else(|| invalid_data("chunk is past the end of the region file"))?,
+    );
+    let len = reader.u32()? as usize;
+    let compression = reader.u8()?;
+    let compressed = reader.bytes(len.saturating_sub(1))?;
+    let mut data = vec![];
+    match compression {
+        1 => GzDecoder::new(compressed).read_to_end(&mut data)?,
+        2 => ZlibDecoder::new(compressed).read_to_end(&mut data)?,
+        3 => {
+            data.extend_from_slice(compressed);
+            data.len()
+        }
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported compression {compression}"
+            )));
+        }
+    };
+    let root = Tag::read_root(&data)?;
+    Column::from_nbt(&root, mapping).map(Some)
+}
+
+/// Blocks of a section from its palette and the indices into it packed into
+/// `data`. Since 1.16, indices don't straddle two longs.
+fn unpack_section(palette: &[Block], data: &[i64]) -> io::Result<Vec<Block>> {
+    const SECTION_BLOCKS: usize = (COLUMN_SIZE * COLUMN_SIZE * COLUMN_SIZE) as usize;
+    match palette {
+        [] => return Err(invalid_data("empty palette")),
+        [block] => return Ok(vec![*block; SECTION_BLOCKS]),
+        _ => {}
+    }
+    let bits = (usize::BITS - (palette.len() - 1).leading_zeros()).max(4) as usize;
+    let per_long = 64 / bits;
+    if data.len() != SECTION_BLOCKS.div_ceil(per_long) {
+        return Err(invalid_data(
+            "block states are packed as before Minecraft 1.16",
+        ));
+    }
+    let mask = (1u64 << bits) - 1;
+    (0..SECTION_BLOCKS)
+        .map(|i| {
+            let long = data[i / per_long] as u64;
+            let index = (long >> (i % per_long * bits)) & mask;
+            palette
+                .get(index as usize)
+                .copied()
+                .ok_or_else(|| invalid_data("palette index out of range"))
+        })
+        .collect()
+}
+
+/// Which block each Minecraft block becomes, by its name, like
+/// `minecraft:oak_log`. Block states like which way a log faces are dropped.
+#[derive(Clone, Debug)]
+pub struct BlockMapping {
+    blocks: HashMap<String, Block>,
+    /// Block that Minecraft blocks missing from `blocks` become
+    unmapped: Block,
+}
+
+/// Maps the Minecraft blocks closest to each of this game's blocks, leaving
+/// the rest as air
+impl Default for BlockMapping {
+    fn default() -> Self {
+        let mut blocks = HashMap::new();
+        let mut map = |names: &[&str], block: Block| {
+            for name in names {
+                blocks.insert(format!("minecraft:{name}"), block);
+            }
+        };
+        map(
+            &[
+                "stone",
+                "granite",
+                "diorite",
+                "andesite",
+                "deepslate",
+                "tuff",
+                "calcite",
+                "cobblestone",
+                "cobbled_deepslate",
+            ],
+            Block::Stone,
+        );
+        map(
+            &["dirt", "coarse_dirt", "rooted_dirt", "podzol", "farmland"],
+            Block::Dirt,
+        );
+        map(&["grass_block", "mycelium"], Block::Grass);
+        map(&["bedrock"], Block::Bedrock);
+        map(&["glowstone"], Block::Glowstone);
+        map(&["torch", "wall_torch"], Block::Torch);
+        map(&["lava"], Block::Lava);
+        map(&["water"], Block::Water);
+        map(&["sand", "red_sand"], Block::Sand);
+        map(&["gravel"], Block::Gravel);
+        map(&["snow_block", "powder_snow"], Block::Snow);
+        map(&["ice", "packed_ice", "blue_ice"], Block::Ice);
+        map(&["glass"], Block::Glass);
+        map(&["coal_ore", "deepslate_coal_ore"], Block::CoalOre);
+        map(&["iron_ore", "deepslate_iron_ore"], Block::IronOre);
+        for wood in [
+            "oak", "spruce", "birch", "jungle", "acacia", "dark_oak", "mangrove", "cherry",
+        ] {
+            map(&[&format!("{wood}_log")], Block::Log);
+            map(&[&format!("{wood}_planks")], Block::Planks);
+            map(&[&format!("{wood}_leaves")], Block::Leaves);
+        }
+        map(&["azalea_leaves", "flowering_azalea_leaves"], Block::Leaves);
+        Self {
+            blocks,
+            unmapped: Block::Air,
+        }
+    }
+}
+
+impl BlockMapping {
+    pub fn block(&self, name: &str) -> Block {
+        self.blocks.get(name).copied().unwrap_or(self.unmapped)
+    }
+
+    /// Applies the lines of `text` over this mapping. Each line is a
+    /// Minecraft block name and the name of the block it becomes, like
+    /// `minecraft:terracotta stone`, with `*` in place of the Minecraft name
+    /// for every block that isn't mapped. Blank lines and lines starting with
+    /// `#` are skipped.
+    pub fn with_overrides(mut self, text: &str) -> io::Result<Self> {
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, block) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| invalid_data(format!("expected two names in {line:?}")))?;
+            let block = Block::from_name(block.trim())
+                .ok_or_else(|| invalid_data(format!("unknown block {:?}", block.trim())))?;
+            match name {
+                "*" => self.unmapped = block,
+                _ => {
+                    self.blocks.insert(name.to_owned(), block);
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// A tag of Minecraft's NBT format, keeping only what's needed to find the
+/// blocks of a chunk
+#[derive(Debug)]
+enum Tag {
+    Byte(i8),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    LongArray(Vec<i64>),
+    /// Any other number or array, which is skipped
+    Other,
+}
+
+/// Deepest nesting of lists and compounds allowed, as in Minecraft
+const MAX_NBT_DEPTH: usize = 512;
+
+impl Tag {
+    /// The root compound of an uncompressed NBT file
+    fn read_root(data: &[u8]) -> io::Result<Self> {
+        let mut reader = BigEndianReader(data);
+        let id = reader.u8()?;
+        if id != 10 {
+            return Err(invalid_data("NBT root isn't a compound"));
+        }
+        reader.string()?;
+        Self::read(&mut reader, id, 0)
+    }
+
+    fn read(reader: &mut BigEndianReader, id: u8, depth: usize) -> io::Result<Self> {
+        if depth > MAX_NBT_DEPTH {
+            return Err(invalid_data("NBT is nested too deeply"));
+        }
+        let skip_array = |reader: &mut BigEndianReader, item_size: usize| {
+            let len = reader.u32()? as usize;
+            reader.bytes(len * item_size).map(|_| Self::Other)
+        };
+        Ok(match id {
+            1 => Self::Byte(reader.u8()? as i8),
+            2 => reader.bytes(2).map(|_| Self::Other)?,
+            3 | 5 => reader.bytes(4).map(|_| Self::Other)?,
+            4 | 6 => reader.bytes(8).map(|_| Self::Other)?,
+            7 => skip_array(reader, 1)?,
+            8 => Self::String(reader.string()?),
+            9 => {
+                let item_id = reader.u8()?;
+                let len = reader.u32()?;
+                Self::List(
+                    (0..len)
+                        .map(|_| Self::read(reader, item_id, depth + 1))
+                        .collect::<io::Result<_>>()?,
+                )
+            }
+            10 => {
+                let mut tags = HashMap::new();
+                loop {
+                    let id = reader.u8()?;
+                    if id == 0 {
+                        break;
+                    }
+                    let name = reader.string()?;
+                    tags.insert(name, Self::read(reader, id, depth + 1)?);
+                }
+                Self::Compound(tags)
+            }
+            11 => skip_array(reader, 4)?,
+            12 => {
+                let len = reader.u32()? as usize;
+                Self::LongArray(
+                    reader
+                        .bytes(len * 8)?
+                        .chunks_exact(8)
+                        .map(|long| i64::from_be_bytes(long.try_into().expect("8 bytes")))
+                        .collect(),
+                )
+            }
+            _ => return Err(invalid_data(format!("unknown NBT tag {id}"))),
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Self::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Self::List(tags) => Some(tags),
+            _ => None,
+        }
+    }
+}
+
+/// Reads big-endian numbers off the front of some bytes, unlike the
+/// little-endian `persistence::Reader`
+struct BigEndianReader<'a>(&'a [u8]);
+
+impl<'a> BigEndianReader<'a> {
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid_data("ended early"));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(u8::from_be_bytes(self.array()?))
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    /// Minecraft writes strings in Java's modified UTF-8, which only differs
+    /// from UTF-8 for characters block names don't use
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
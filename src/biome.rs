@@ -0,0 +1,132 @@
+use std::num::NonZero;
+
+use bevy::prelude::*;
+use lib_chunk::ChunkPosition;
+use lib_noise::FractalNoise;
+use lib_render::TerrainTint;
+use lib_spatial::CHUNK_SIZE;
+use noise::NoiseFn;
+
+use crate::world_gen::{Chunk, WorldSeed};
+
+pub struct BiomePlugin;
+
+impl Plugin for BiomePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                // The seed can change after startup, like when a world is
+                // chosen or a server sends its own
+                init_climate_noise.run_if(resource_changed::<WorldSeed>),
+                assign_terrain_tint,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Temperature and humidity of a column of the world, each roughly in -1..1
+#[derive(Clone, Copy, Debug)]
+pub struct Climate {
+    pub temperature: f32,
+    pub humidity: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Forest,
+    Desert,
+    Tundra,
+}
+
+impl Biome {
+    pub fn from_climate(climate: Climate) -> Self {
+        if climate.temperature < -0.3 {
+            Self::Tundra
+        } else if climate.temperature > 0.35 && climate.humidity < 0.0 {
+            Self::Desert
+        } else if climate.humidity > 0.2 {
+            Self::Forest
+        } else {
+            Self::Plains
+        }
+    }
+}
+
+const DRY_FOLIAGE: Color = Color::srgb(0.75, 0.68, 0.35);
+const LUSH_FOLIAGE: Color = Color::srgb(0.36, 0.66, 0.22);
+const COLD_FOLIAGE: Color = Color::srgb(0.45, 0.62, 0.52);
+
+impl Climate {
+    /// Colour grass and leaves are tinted in this climate, browner where
+    /// it's dry and bluer where it's cold
+    pub fn foliage_tint(&self) -> Color {
+        let wetness = ((self.humidity + 1.0) / 2.0).clamp(0.0, 1.0);
+        let coldness = (-self.temperature * 2.0).clamp(0.0, 1.0);
+        DRY_FOLIAGE
+            .mix(&LUSH_FOLIAGE, wetness)
+            .mix(&COLD_FOLIAGE, coldness)
+    }
+}
+
+/// Varies much more slowly than the terrain height, so biomes span many
+/// chunks
+#[derive(Resource, Clone)]
+pub struct ClimateNoise {
+    temperature: FractalNoise,
+    humidity: FractalNoise,
+}
+
+impl ClimateNoise {
+    pub fn new(seed: u32) -> Self {
+        let num_layers = NonZero::new(4).unwrap();
+        let scale = 0.002;
+        // Different seeds from the height noise so the maps aren't correlated
+        Self {
+            temperature: FractalNoise::new(seed ^ 0x7E4F_0001, num_layers, scale),
+            humidity: FractalNoise::new(seed ^ 0x4A1D_0002, num_layers, scale),
+        }
+    }
+
+    pub fn climate_at(&self, x: i32, z: i32) -> Climate {
+        Climate {
+            temperature: self.temperature.get([x, z]) as f32,
+            humidity: self.humidity.get([x, z]) as f32,
+        }
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        Biome::from_climate(self.climate_at(x, z))
+    }
+}
+
+/// Tints each chunk by the climate at its centre. Biomes span many chunks,
+/// so the steps between chunks are small.
+fn assign_terrain_tint(
+    mut commands: Commands,
+    q_chunks: Query<(Entity, &ChunkPosition), (With<Chunk>, Without<TerrainTint>)>,
+    climate_noise: Option<Res<ClimateNoise>>,
+) {
+    let Some(climate_noise) = climate_noise else {
+        return;
+    };
+    for (entity, chunk_pos) in q_chunks.iter() {
+        let center = chunk_pos.0 * CHUNK_SIZE as i32 + CHUNK_SIZE as i32 / 2;
+        let tint = climate_noise.climate_at(center.x, center.z).foliage_tint();
+        commands.entity(entity).try_insert(TerrainTint(tint));
+    }
+}
+
+/// Chunks tinted by the noise of an old seed are tinted again
+fn init_climate_noise(
+    mut commands: Commands,
+    world_seed: Res<WorldSeed>,
+    q_tinted: Query<Entity, (With<Chunk>, With<TerrainTint>)>,
+) {
+    for entity in q_tinted.iter() {
+        commands.entity(entity).remove::<TerrainTint>();
+    }
+    commands.insert_resource(ClimateNoise::new(world_seed.0));
+}
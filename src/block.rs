@@ -1,7 +1,17 @@
-use lib_render::Normal;
-use strum_macros::EnumIter;
+use std::{str::FromStr, sync::RwLock, time::Duration};
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumIter)]
+use bevy::{
+    color::Color,
+    math::{Vec3A, bounding::Aabb3d},
+};
+use lib_render::{Normal, QuadBucket, texture::TextureIndex};
+use strum::IntoEnumIterator;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+/// Names are written to saves, so renaming a variant breaks loading worlds
+/// that contain it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, EnumIter, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
 pub enum Block {
     #[default]
     Air,
@@ -9,27 +19,590 @@ pub enum Block {
     Dirt,
     Grass,
     Bedrock,
+    Log,
+    Glowstone,
+    Torch,
+    Lava,
+    Sand,
+    Gravel,
+    Water,
+    Planks,
+    Leaves,
+    Snow,
+    Ice,
+    Glass,
+    CoalOre,
+    IronOre,
+    StoneSlab,
+    /// Added by another crate with `RegisterBlock`, by its place in the
+    /// order blocks were registered in. Isn't listed by `Block::iter`, so go
+    /// through `Block::all` instead.
+    #[strum(disabled)]
+    Custom(u16),
 }
 
+/// Blocks added with `Block::register`. Each definition is leaked, as blocks
+/// are never removed.
+static CUSTOM_BLOCKS: RwLock<Vec<&'static BlockDefinition>> = RwLock::new(Vec::new());
+
 impl Block {
+    /// Adds the block `definition` describes. Registering a name again gives
+    /// back the block already registered under it.
+    pub fn register(definition: BlockDefinition) -> Block {
+        if let Some(block) = Block::from_name(definition.name) {
+            return block;
+        }
+        let mut custom = CUSTOM_BLOCKS.write().unwrap();
+        custom.push(Box::leak(Box::new(definition)));
+        Block::Custom(custom.len() as u16 - 1)
+    }
+
+    /// The built-in blocks in the order of `Block`, then the registered ones
+    /// in the order they were registered
+    pub fn all() -> impl Iterator<Item = Block> {
+        let custom = CUSTOM_BLOCKS.read().unwrap().len() as u16;
+        Block::iter().chain((0..custom).map(Block::Custom))
+    }
+
+    /// Block with the stable name `name`, registered or not
+    pub fn from_name(name: &str) -> Option<Block> {
+        if let Ok(block) = Block::from_str(name) {
+            return Some(block);
+        }
+        let custom = CUSTOM_BLOCKS.read().unwrap();
+        let index = custom
+            .iter()
+            .position(|definition| definition.name == name)?;
+        Some(Block::Custom(index as u16))
+    }
+
+    /// How a registered block was defined, or `None` for built-in ones
+    pub fn definition(&self) -> Option<&'static BlockDefinition> {
+        match self {
+            Block::Custom(index) => CUSTOM_BLOCKS.read().unwrap().get(*index as usize).copied(),
+            _ => None,
+        }
+    }
+
+    /// Stable name of the block, e.g. `coal_ore`
+    pub fn name(&self) -> &'static str {
+        match self.definition() {
+            Some(definition) => definition.name,
+            None => self.into(),
+        }
+    }
+
+    pub fn tags(&self) -> BlockTags {
+        use BlockTag::*;
+        match self {
+            Block::Custom(_) => self.definition().map_or(BlockTags::EMPTY, |d| d.tags),
+            Block::Air => BlockTags::of(&[Replaceable]),
+            Block::Stone
+            | Block::Dirt
+            | Block::Grass
+            | Block::Bedrock
+            | Block::Log
+            | Block::Glowstone
+            | Block::Sand
+            | Block::Gravel
+            | Block::Planks
+            | Block::Snow
+            | Block::CoalOre
+            | Block::IronOre => BlockTags::of(&[Solid]),
+            Block::Torch => BlockTags::of(&[NeedsSupport]),
+            Block::Lava | Block::Water => BlockTags::of(&[Replaceable, Fluid]),
+            Block::Leaves => BlockTags::of(&[Plant]),
+            Block::Ice | Block::Glass | Block::StoneSlab => BlockTags::EMPTY,
+        }
+    }
+
+    pub fn has_tag(&self, tag: BlockTag) -> bool {
+        self.tags().contains(tag)
+    }
+
+    /// Whether the faces of neighbouring blocks show through this one
     pub fn is_transparent(&self) -> bool {
+        !self.has_tag(BlockTag::Solid)
+    }
+
+    /// Whether this block hides the face of `neighbor` touching it. The faces
+    /// between two blocks of water or glass aren't drawn, but those between
+    /// leaves are, since the leaves behind show through the gaps. Only blocks
+    /// filling their whole cell hide anything.
+    pub fn hides_face_of(&self, neighbor: Block) -> bool {
+        self.model().fills_cell()
+            && (!self.is_transparent() || (*self == neighbor && *self != Block::Leaves))
+    }
+
+    /// Shape the block is meshed as
+    pub fn model(&self) -> BlockModel {
+        match self {
+            Block::Torch => BlockModel::Cross,
+            Block::StoneSlab => BlockModel::Slab { top: false },
+            Block::Custom(_) => self
+                .definition()
+                .map_or(BlockModel::Cube, |definition| definition.model),
+            _ => BlockModel::Cube,
+        }
+    }
+
+    /// Fluids swap in the underwater fog when the camera is inside them
+    pub fn is_fluid(&self) -> bool {
+        self.has_tag(BlockTag::Fluid)
+    }
+
+    pub fn collision_shape(&self) -> CollisionShape {
+        if let Some(definition) = self.definition() {
+            return definition.collision;
+        }
+        match self.model() {
+            BlockModel::Slab { top: false } => return CollisionShape::SlabBottom,
+            BlockModel::Slab { top: true } => return CollisionShape::SlabTop,
+            _ => {}
+        }
+        match self {
+            Block::Air | Block::Torch => CollisionShape::None,
+            Block::Water | Block::Lava => CollisionShape::Fluid,
+            _ => CollisionShape::FullCube,
+        }
+    }
+
+    /// Solid boxes of the block, relative to its lowest corner
+    pub fn collision_aabbs(&self) -> &'static [Aabb3d] {
+        self.collision_shape().aabbs()
+    }
+
+    /// Brightness of the light the block gives off, from 0 (none) to 15
+    pub fn light_emission(&self) -> u8 {
+        match self {
+            Block::Glowstone | Block::Lava => 15,
+            Block::Torch => 14,
+            Block::Custom(_) => self.definition().map_or(0, |d| d.light_emission),
+            _ => 0,
+        }
+    }
+
+    /// How long the block takes to break, roughly in seconds by hand. `None`
+    /// can't be broken.
+    pub fn hardness(&self) -> Option<f32> {
+        match self {
+            Block::Air | Block::Bedrock | Block::Lava | Block::Water => None,
+            Block::Torch => Some(0.0),
+            Block::Leaves | Block::Snow => Some(0.2),
+            Block::Glowstone | Block::Glass => Some(0.3),
+            Block::Dirt | Block::Sand | Block::Ice => Some(0.5),
+            Block::Grass | Block::Gravel => Some(0.6),
+            Block::Stone | Block::StoneSlab => Some(1.5),
+            Block::Log | Block::Planks => Some(2.0),
+            Block::CoalOre | Block::IronOre => Some(3.0),
+            Block::Custom(_) => self.definition()?.hardness,
+        }
+    }
+
+    /// Weakest tool that breaks the block at full speed
+    pub fn required_tool(&self) -> ToolTier {
+        match self {
+            Block::Stone | Block::StoneSlab | Block::CoalOre => ToolTier::Wood,
+            Block::IronOre => ToolTier::Stone,
+            Block::Custom(_) => self
+                .definition()
+                .map_or(ToolTier::Hand, |d| d.required_tool),
+            _ => ToolTier::Hand,
+        }
+    }
+
+    /// Time to break the block with `tool`, or `None` if it can't be broken.
+    /// Tools below `required_tool` break it much more slowly.
+    pub fn break_duration(&self, tool: ToolTier) -> Option<Duration> {
+        let hardness = self.hardness()?;
+        let seconds = if tool >= self.required_tool() {
+            hardness * 1.5 / tool.speed()
+        } else {
+            hardness * 5.0
+        };
+        Some(Duration::from_secs_f32(seconds))
+    }
+
+    /// Sound set played when the block is broken, placed or stepped on, e.g.
+    /// `stone`
+    pub fn sound(&self) -> Option<&'static str> {
+        match self {
+            Block::Air => None,
+            Block::Stone
+            | Block::StoneSlab
+            | Block::Bedrock
+            | Block::Glowstone
+            | Block::CoalOre
+            | Block::IronOre => Some("stone"),
+            Block::Dirt | Block::Gravel => Some("gravel"),
+            Block::Grass | Block::Leaves => Some("grass"),
+            Block::Log | Block::Planks | Block::Torch => Some("wood"),
+            Block::Sand => Some("sand"),
+            Block::Snow => Some("snow"),
+            Block::Ice | Block::Glass => Some("glass"),
+            Block::Water | Block::Lava => Some("liquid"),
+            Block::Custom(_) => self.definition()?.sound,
+        }
+    }
+
+    /// Terrain textured onto the particles the block breaks into
+    pub fn particle(&self) -> Option<Terrain> {
+        Some(self.faces()?.get(Normal::PosX, Normal::PosY))
+    }
+
+    /// Terrain drawn on each face, or `None` for blocks that aren't drawn
+    pub fn faces(&self) -> Option<BlockFaces> {
+        match self {
+            Block::Air => None,
+            Block::Stone | Block::StoneSlab => Some(BlockFaces::All(Terrain::Stone)),
+            Block::Dirt => Some(BlockFaces::All(Terrain::Dirt)),
+            Block::Bedrock => Some(BlockFaces::All(Terrain::Bedrock)),
+            Block::Grass => Some(BlockFaces::TopBottomSide {
+                top: Terrain::GrassTop,
+                bottom: Terrain::Dirt,
+                side: Terrain::GrassSide,
+            }),
+            Block::Log => Some(BlockFaces::TopBottomSide {
+                top: Terrain::LogTop,
+                bottom: Terrain::LogTop,
+                side: Terrain::LogSide,
+            }),
+            Block::Glowstone => Some(BlockFaces::All(Terrain::Glowstone)),
+            Block::Torch => Some(BlockFaces::All(Terrain::Torch)),
+            Block::Lava => Some(BlockFaces::All(Terrain::Lava)),
+            Block::Sand => Some(BlockFaces::All(Terrain::Sand)),
+            Block::Gravel => Some(BlockFaces::All(Terrain::Gravel)),
+            Block::Water => Some(BlockFaces::All(Terrain::Water)),
+            Block::Planks => Some(BlockFaces::All(Terrain::Planks)),
+            Block::Leaves => Some(BlockFaces::All(Terrain::Leaves)),
+            Block::Snow => Some(BlockFaces::All(Terrain::Snow)),
+            Block::Ice => Some(BlockFaces::All(Terrain::Ice)),
+            Block::Glass => Some(BlockFaces::All(Terrain::Glass)),
+            Block::CoalOre => Some(BlockFaces::All(Terrain::CoalOre)),
+            Block::IronOre => Some(BlockFaces::All(Terrain::IronOre)),
+            Block::Custom(_) => self.definition()?.faces,
+        }
+    }
+}
+
+/// A block added by another crate, with what `Block`'s methods give for it.
+/// Its faces are textured with the game's own `Terrain`.
+#[derive(Clone)]
+pub struct BlockDefinition {
+    /// Stable name, written to saves like those of the built-in blocks
+    pub name: &'static str,
+    pub tags: BlockTags,
+    pub model: BlockModel,
+    pub collision: CollisionShape,
+    /// `None` for blocks that aren't drawn
+    pub faces: Option<BlockFaces>,
+    pub light_emission: u8,
+    /// `None` can't be broken
+    pub hardness: Option<f32>,
+    pub required_tool: ToolTier,
+    pub sound: Option<&'static str>,
+}
+
+impl BlockDefinition {
+    /// An opaque cube broken like stone, by hand. Change the rest with
+    /// struct update syntax.
+    pub fn solid(name: &'static str, faces: BlockFaces) -> Self {
+        Self {
+            name,
+            tags: BlockTags::of(&[BlockTag::Solid]),
+            model: BlockModel::Cube,
+            collision: CollisionShape::FullCube,
+            faces: Some(faces),
+            light_emission: 0,
+            hardness: Some(1.5),
+            required_tool: ToolTier::Hand,
+            sound: Some("stone"),
+        }
+    }
+}
+
+/// Categories of blocks that rules are written against, rather than lists of
+/// blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockTag {
+    /// Opaque and hides the faces of the blocks next to it
+    Solid,
+    /// Placing a block in its place replaces it
+    Replaceable,
+    Fluid,
+    Plant,
+    /// Breaks without a solid block below it
+    NeedsSupport,
+}
+
+/// Set of `BlockTag`s, one bit each
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BlockTags(u8);
+
+impl BlockTags {
+    pub const EMPTY: Self = Self(0);
+
+    pub const fn of(tags: &[BlockTag]) -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < tags.len() {
+            bits |= 1 << tags[i] as u8;
+            i += 1;
+        }
+        Self(bits)
+    }
+
+    pub fn contains(&self, tag: BlockTag) -> bool {
+        self.0 & (1 << tag as u8) != 0
+    }
+}
+
+/// Shape of a block's mesh. Each face of the model is textured as given by
+/// `Block::faces`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockModel {
+    /// Fills the block, with faces hidden by neighbouring blocks
+    Cube,
+    /// Two diagonal quads crossing in the middle of the block, like flowers.
+    /// Each side of them is textured like one of the block's ±X and ±Z
+    /// faces.
+    Cross,
+    /// Half a block, in the upper half if `top`
+    Slab { top: bool },
+}
+
+impl BlockModel {
+    /// Whether the model covers every face of its cell, so that it can hide
+    /// its neighbours' faces
+    pub fn fills_cell(&self) -> bool {
+        *self == Self::Cube
+    }
+}
+
+/// What entities collide with inside a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionShape {
+    /// Passed straight through
+    None,
+    FullCube,
+    /// The lower half of the block
+    SlabBottom,
+    /// The upper half of the block
+    SlabTop,
+    /// Not solid, but slows down and buoys up whatever is inside it
+    Fluid,
+}
+
+const FULL_CUBE: Aabb3d = Aabb3d {
+    min: Vec3A::ZERO,
+    max: Vec3A::ONE,
+};
+const SLAB_BOTTOM: Aabb3d = Aabb3d {
+    min: Vec3A::ZERO,
+    max: Vec3A::new(1.0, 0.5, 1.0),
+};
+const SLAB_TOP: Aabb3d = Aabb3d {
+    min: Vec3A::new(0.0, 0.5, 0.0),
+    max: Vec3A::ONE,
+};
+
+impl CollisionShape {
+    /// Solid boxes of the shape, relative to the block's lowest corner.
+    /// Fluids have none.
+    pub fn aabbs(&self) -> &'static [Aabb3d] {
         match self {
-            Block::Air => true,
-            _ => false,
+            Self::None | Self::Fluid => &[],
+            Self::FullCube => &[FULL_CUBE],
+            Self::SlabBottom => &[SLAB_BOTTOM],
+            Self::SlabTop => &[SLAB_TOP],
+        }
+    }
+}
+
+/// A block as stored in chunks, along with its state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlacedBlock {
+    pub block: Block,
+    pub state: BlockState,
+}
+
+impl From<Block> for PlacedBlock {
+    fn from(block: Block) -> Self {
+        Self {
+            block,
+            state: BlockState::default(),
         }
     }
 }
 
-#[derive(EnumIter, Clone)]
+/// Per-block state packed into a byte. Blocks only use the parts that apply
+/// to them, and the rest stay at their defaults.
+///
+/// - Bits 0-2: which way the block faces, e.g. the axis of a log. Defaults
+///   to up.
+/// - Bit 3: whether the block is also filled with water
+/// - Bits 4-6: number of snow layers minus one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BlockState(u8);
+
+/// Directions a block can face, in the order they're stored in
+const FACINGS: [Normal; 6] = [
+    Normal::PosY,
+    Normal::NegY,
+    Normal::PosX,
+    Normal::NegX,
+    Normal::PosZ,
+    Normal::NegZ,
+];
+const FACING_MASK: u8 = 0b0000_0111;
+const WATERLOGGED_BIT: u8 = 0b0000_1000;
+const SNOW_LAYERS_SHIFT: u8 = 4;
+const SNOW_LAYERS_MASK: u8 = 0b0111_0000;
+pub const MAX_SNOW_LAYERS: u8 = 8;
+
+impl BlockState {
+    /// The packed bits described on `BlockState`, as saved with the world
+    pub fn to_bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub fn facing(&self) -> Normal {
+        FACINGS
+            .get((self.0 & FACING_MASK) as usize)
+            .copied()
+            .unwrap_or(Normal::PosY)
+    }
+
+    pub fn with_facing(self, facing: Normal) -> Self {
+        let index = FACINGS
+            .iter()
+            .position(|f| f.as_unit_direction() == facing.as_unit_direction())
+            .unwrap_or_default() as u8;
+        Self((self.0 & !FACING_MASK) | index)
+    }
+
+    pub fn is_waterlogged(&self) -> bool {
+        self.0 & WATERLOGGED_BIT != 0
+    }
+
+    pub fn with_waterlogged(self, waterlogged: bool) -> Self {
+        if waterlogged {
+            Self(self.0 | WATERLOGGED_BIT)
+        } else {
+            Self(self.0 & !WATERLOGGED_BIT)
+        }
+    }
+
+    /// From 1 to `MAX_SNOW_LAYERS`
+    pub fn snow_layers(&self) -> u8 {
+        ((self.0 & SNOW_LAYERS_MASK) >> SNOW_LAYERS_SHIFT) + 1
+    }
+
+    /// `layers` is clamped to 1..=`MAX_SNOW_LAYERS`
+    pub fn with_snow_layers(self, layers: u8) -> Self {
+        let bits = (layers.clamp(1, MAX_SNOW_LAYERS) - 1) << SNOW_LAYERS_SHIFT;
+        Self((self.0 & !SNOW_LAYERS_MASK) | bits)
+    }
+}
+
+/// Tiers of tool a block can be broken with, from weakest to strongest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ToolTier {
+    #[default]
+    Hand,
+    Wood,
+    Stone,
+    Iron,
+}
+
+impl ToolTier {
+    /// The next stronger tier, wrapping around to `Hand`
+    pub fn next(&self) -> Self {
+        match self {
+            ToolTier::Hand => ToolTier::Wood,
+            ToolTier::Wood => ToolTier::Stone,
+            ToolTier::Stone => ToolTier::Iron,
+            ToolTier::Iron => ToolTier::Hand,
+        }
+    }
+
+    /// Breaking speed relative to breaking by hand
+    fn speed(&self) -> f32 {
+        match self {
+            ToolTier::Hand => 1.0,
+            ToolTier::Wood => 2.0,
+            ToolTier::Stone => 4.0,
+            ToolTier::Iron => 6.0,
+        }
+    }
+}
+
+/// Which terrain a block is textured with on each of its faces
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BlockFaces {
+    All(Terrain),
+    /// Like grass or logs, with the four sides alike. The top is on the face
+    /// the block's state faces, so a log on its side shows its rings at the
+    /// ends.
+    TopBottomSide {
+        top: Terrain,
+        bottom: Terrain,
+        side: Terrain,
+    },
+    /// In the order of `Normal`: +X, -X, +Y, -Y, +Z, -Z. Not rotated by the
+    /// block's facing.
+    PerFace([Terrain; 6]),
+}
+
+impl BlockFaces {
+    pub fn get(&self, normal: Normal, facing: Normal) -> Terrain {
+        match self {
+            Self::All(terrain) => *terrain,
+            Self::TopBottomSide { top, bottom, side } => {
+                let normal = normal.as_unit_direction();
+                let facing = facing.as_unit_direction();
+                if normal == facing {
+                    *top
+                } else if normal == -facing {
+                    *bottom
+                } else {
+                    *side
+                }
+            }
+            Self::PerFace(faces) => faces[normal as usize],
+        }
+    }
+}
+
+#[derive(EnumIter, Clone, Copy, PartialEq, Eq)]
 pub enum Terrain {
     Stone,
     Dirt,
     Bedrock,
     GrassTop,
     GrassSide,
+    LogTop,
+    LogSide,
+    Glowstone,
+    Torch,
+    Lava,
+    Sand,
+    Gravel,
+    Water,
+    Planks,
+    Leaves,
+    Snow,
+    Ice,
+    Glass,
+    CoalOre,
+    IronOre,
 }
 
-impl lib_render::texture::TextureIndex for Terrain {
+impl TextureIndex for Terrain {
     fn get_name(&self) -> &'static str {
         match self {
             Self::Stone => "stone",
@@ -37,21 +610,121 @@ impl lib_render::texture::TextureIndex for Terrain {
             Self::Bedrock => "bedrock",
             Self::GrassTop => "grass",
             Self::GrassSide => "grass_side",
+            Self::LogTop => "oak_log_top",
+            Self::LogSide => "oak_log",
+            Self::Glowstone => "glowstone",
+            Self::Torch => "torch",
+            Self::Lava => "lava",
+            Self::Sand => "sand",
+            Self::Gravel => "gravel",
+            Self::Water => "water",
+            Self::Planks => "oak_planks",
+            Self::Leaves => "oak_leaves",
+            Self::Snow => "snow",
+            Self::Ice => "ice",
+            Self::Glass => "glass",
+            Self::CoalOre => "coal_ore",
+            Self::IronOre => "iron_ore",
+        }
+    }
+
+    fn bucket(&self) -> QuadBucket {
+        match self {
+            Self::Leaves | Self::Glass | Self::Torch => QuadBucket::Cutout,
+            Self::Water | Self::Ice => QuadBucket::Transparent,
+            _ => QuadBucket::Opaque,
+        }
+    }
+
+    fn roughness(&self) -> f32 {
+        match self {
+            Self::Water => 0.1,
+            Self::Ice | Self::Glass => 0.2,
+            _ => 1.0,
+        }
+    }
+
+    fn ripples(&self) -> bool {
+        *self == Self::Water
+    }
+
+    fn tinted(&self) -> bool {
+        matches!(self, Self::GrassTop | Self::Leaves)
+    }
+
+    fn emission(&self) -> u8 {
+        match self {
+            Self::Glowstone => Block::Glowstone.light_emission(),
+            Self::Torch => Block::Torch.light_emission(),
+            Self::Lava => Block::Lava.light_emission(),
+            _ => 0,
+        }
+    }
+
+    fn light_color(&self) -> Color {
+        match self {
+            Self::Glowstone => Color::srgb(1.0, 0.85, 0.55),
+            Self::Lava => Color::srgb(1.0, 0.45, 0.15),
+            _ => Color::srgb(1.0, 0.75, 0.45),
         }
     }
 }
 
-impl TryFrom<(Block, Normal)> for Terrain {
+impl TryFrom<(PlacedBlock, Normal)> for Terrain {
     type Error = &'static str;
-    fn try_from(value: (Block, Normal)) -> Result<Self, Self::Error> {
-        match value {
-            (Block::Air, _) => Err("Air is not terrain"),
-            (Block::Dirt, _) => Ok(Self::Dirt),
-            (Block::Stone, _) => Ok(Self::Stone),
-            (Block::Bedrock, _) => Ok(Self::Bedrock),
-            (Block::Grass, Normal::PosY) => Ok(Self::GrassTop),
-            (Block::Grass, Normal::NegY) => Ok(Self::Dirt),
-            (Block::Grass, _) => Ok(Self::GrassSide),
+    fn try_from((placed, normal): (PlacedBlock, Normal)) -> Result<Self, Self::Error> {
+        let faces = placed.block.faces().ok_or("Block is not drawn")?;
+        Ok(faces.get(normal, placed.state.facing()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn solid_blocks_fill_their_cell() {
+        for block in Block::iter().filter(|block| block.has_tag(BlockTag::Solid)) {
+            assert_eq!(
+                block.collision_shape(),
+                CollisionShape::FullCube,
+                "{block:?}"
+            );
+            for neighbor in Block::iter() {
+                assert!(
+                    block.hides_face_of(neighbor),
+                    "{block:?} shows {neighbor:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_that_can_be_walked_through_arent_solid() {
+        for block in Block::iter() {
+            if block.collision_aabbs().is_empty() {
+                assert!(!block.has_tag(BlockTag::Solid), "{block:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn replaceable_blocks_can_be_walked_through() {
+        for block in Block::iter().filter(|block| block.has_tag(BlockTag::Replaceable)) {
+            assert!(block.collision_aabbs().is_empty(), "{block:?}");
+        }
+    }
+
+    #[test]
+    fn fluids_collide_as_fluids() {
+        for block in Block::iter() {
+            assert_eq!(
+                block.has_tag(BlockTag::Fluid),
+                block.collision_shape() == CollisionShape::Fluid,
+                "{block:?}"
+            );
         }
     }
 }
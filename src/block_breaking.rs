@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use lib_render::Normal;
+
+use crate::{
+    block::{Block, ToolTier},
+    game_state::GameState,
+    targeting::{TargetedBlock, TargetingSystems},
+    world_gen::{self, BlockChangeCause, EditBlock},
+};
+
+/// Breaks the block under the crosshair while the left mouse button is held,
+/// taking as long as the block's `break_duration` with the held tool. T
+/// cycles through the tools. Sends `BlockBroken` for each broken block, so
+/// effects can react without changes here.
+pub struct BlockBreakingPlugin;
+
+impl Plugin for BlockBreakingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HeldTool>()
+            .init_resource::<BreakingProgress>()
+            .add_event::<BlockBroken>()
+            .add_systems(
+                Update,
+                (
+                    cycle_held_tool,
+                    break_targeted_block
+                        .after(TargetingSystems)
+                        .before(world_gen::apply_block_edits),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Sent when a block is broken
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BlockBroken {
+    pub pos: IVec3,
+    pub block: Block,
+    /// Face the block was broken from, or `None` from inside the block
+    pub face: Option<Normal>,
+}
+
+/// Tool the player breaks blocks with
+#[derive(Resource, Default, Clone, Copy)]
+pub struct HeldTool(pub ToolTier);
+
+fn cycle_held_tool(keys: Res<ButtonInput<KeyCode>>, mut tool: ResMut<HeldTool>) {
+    if keys.just_pressed(KeyCode::KeyT) {
+        tool.0 = tool.0.next();
+    }
+}
+
+/// Block currently being broken, and for how long
+#[derive(Resource, Default)]
+pub struct BreakingProgress {
+    target: Option<(IVec3, Block)>,
+    face: Option<Normal>,
+    elapsed: Duration,
+}
+
+impl BreakingProgress {
+    pub fn target(&self) -> Option<Block> {
+        self.target.map(|(_, block)| block)
+    }
+
+    /// Fraction of the way to breaking the target, from 0.0 to 1.0
+    pub fn fraction(&self, tool: ToolTier) -> f32 {
+        let Some(duration) = self
+            .target
+            .and_then(|(_, block)| block.break_duration(tool))
+        else {
+            return 0.0;
+        };
+        if duration.is_zero() {
+            return 1.0;
+        }
+        (self.elapsed.as_secs_f32() / duration.as_secs_f32()).min(1.0)
+    }
+
+    fn reset(&mut self) {
+        self.target = None;
+        self.face = None;
+        self.elapsed = Duration::ZERO;
+    }
+}
+
+fn break_targeted_block(
+    mouse: Res<ButtonInput<MouseButton>>,
+    time: Res<Time>,
+    tool: Res<HeldTool>,
+    mut progress: ResMut<BreakingProgress>,
+    target: Option<Res<TargetedBlock>>,
+    mut broken: EventWriter<BlockBroken>,
+    mut edits: EventWriter<EditBlock>,
+) {
+    if !mouse.pressed(MouseButton::Left) {
+        progress.reset();
+        return;
+    }
+    let Some(&TargetedBlock {
+        pos, block, face, ..
+    }) = target.as_deref()
+    else {
+        progress.reset();
+        return;
+    };
+    if progress.target != Some((pos, block)) {
+        // Looking at a different block starts over
+        progress.reset();
+        progress.target = Some((pos, block));
+    }
+    progress.face = face;
+    let Some(duration) = block.break_duration(tool.0) else {
+        return;
+    };
+    progress.elapsed += time.delta();
+    if progress.elapsed >= duration {
+        edits.write(EditBlock {
+            pos,
+            block: Block::Air.into(),
+            cause: BlockChangeCause::Player,
+        });
+        broken.write(BlockBroken {
+            pos,
+            block,
+            face: progress.face,
+        });
+        progress.reset();
+    }
+}
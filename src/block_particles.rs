@@ -0,0 +1,226 @@
+use bevy::prelude::*;
+use lib_chunk::ChunkIndex;
+use lib_render::{
+    particles::{Particle, Particles},
+    texture::{TerrainColorTextureIndices, TextureIndex},
+};
+use lib_utils::iter_3d;
+
+use crate::{
+    biome::ClimateNoise,
+    block::BlockTag,
+    block_breaking::BlockBroken,
+    world_gen::{self, Blocks},
+};
+
+/// Bursts each broken block into debris textured with bits of its texture,
+/// which falls and settles on the blocks below before fading away
+pub struct BlockParticlesPlugin;
+
+impl Plugin for BlockParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Debris>()
+            .add_systems(Update, (spawn_debris, simulate_debris).chain());
+    }
+}
+
+/// Debris along each axis of a broken block
+const DEBRIS_PER_AXIS: u32 = 3;
+/// Width of each piece of debris, in blocks
+const DEBRIS_SIZE: f32 = 0.15;
+/// Fraction of the texture's width shown on each piece of debris
+const DEBRIS_UV_SIZE: f32 = 0.25;
+/// In blocks per second squared
+const DEBRIS_GRAVITY: f32 = 20.0;
+/// Fraction of its horizontal speed debris keeps each second while lying on
+/// a block
+const DEBRIS_FRICTION: f32 = 0.02;
+/// How long each piece of debris lasts, from the shortest to the longest, in
+/// seconds
+const DEBRIS_LIFETIME: (f32, f32) = (0.5, 1.2);
+/// Debris shrinks away over its last moments, in seconds
+const DEBRIS_SHRINK_SECONDS: f32 = 0.25;
+
+struct Piece {
+    position: Vec3,
+    /// In blocks per second
+    velocity: Vec3,
+    texture_index: u32,
+    uv_min: Vec2,
+    tint: Color,
+    /// Seconds left before it disappears
+    remaining: f32,
+}
+
+/// Debris of recently broken blocks
+#[derive(Resource, Default)]
+struct Debris {
+    pieces: Vec<Piece>,
+    /// xorshift64 state
+    rng_state: u64,
+}
+
+impl Debris {
+    /// Random number from 0.0 to 1.0
+    fn random(&mut self) -> f32 {
+        if self.rng_state == 0 {
+            self.rng_state = 0x9E3779B97F4A7C15;
+        }
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng_state = x;
+        (x >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn spawn_debris(
+    mut broken: EventReader<BlockBroken>,
+    mut debris: ResMut<Debris>,
+    indices: Option<Res<TerrainColorTextureIndices>>,
+    climate_noise: Option<Res<ClimateNoise>>,
+) {
+    let Some(indices) = indices else {
+        broken.clear();
+        return;
+    };
+    for event in broken.read() {
+        let Some(terrain) = event.block.particle() else {
+            continue;
+        };
+        // Missing textures are reported when the texture folder is scanned
+        let texture_index = indices.get_index(&terrain).copied().unwrap_or_default() as u32;
+        let tint = match (terrain.tinted(), climate_noise.as_ref()) {
+            (true, Some(climate_noise)) => climate_noise
+                .climate_at(event.pos.x, event.pos.z)
+                .foliage_tint(),
+            _ => Color::WHITE,
+        };
+        let center = event.pos.as_vec3();
+        let spacing = 1.0 / DEBRIS_PER_AXIS as f32;
+        let range = 0..DEBRIS_PER_AXIS;
+        for (x, y, z) in iter_3d(range.clone(), range.clone(), range) {
+            // Evenly spread through the block, bursting outwards from its
+            // centre
+            let offset = (Vec3::new(x as f32, y as f32, z as f32) + 0.5) * spacing - 0.5;
+            let velocity = offset * 4.0 + Vec3::Y * (1.5 + debris.random() * 2.0);
+            let uv_min = Vec2::new(debris.random(), debris.random()) * (1.0 - DEBRIS_UV_SIZE);
+            let (shortest, longest) = DEBRIS_LIFETIME;
+            let remaining = shortest + debris.random() * (longest - shortest);
+            debris.pieces.push(Piece {
+                position: center + offset,
+                velocity,
+                texture_index,
+                uv_min,
+                tint,
+                remaining,
+            });
+        }
+    }
+}
+
+fn simulate_debris(
+    time: Res<Time>,
+    mut debris: ResMut<Debris>,
+    mut particles: ResMut<Particles>,
+    chunk_index: Res<ChunkIndex>,
+    q_blocks: Query<&Blocks>,
+) {
+    let dt = time.delta_secs();
+    // Collides at the bottom of each piece, so it rests on top of blocks
+    // rather than halfway into them
+    let is_solid = |position: Vec3| {
+        let bottom = position - Vec3::Y * (DEBRIS_SIZE / 2.0);
+        let pos = world_gen::block_pos_containing(bottom);
+        world_gen::block_at(&chunk_index, &q_blocks, pos)
+            .is_some_and(|block| block.has_tag(BlockTag::Solid))
+    };
+    debris.pieces.retain_mut(|piece| {
+        piece.remaining -= dt;
+        if piece.remaining <= 0.0 {
+            return false;
+        }
+        piece.velocity.y -= DEBRIS_GRAVITY * dt;
+        // Moves one axis at a time, stopping along any axis that would move
+        // it into a solid block
+        for axis in 0..3 {
+            let mut moved = piece.position;
+            moved[axis] += piece.velocity[axis] * dt;
+            if is_solid(moved) {
+                piece.velocity[axis] = 0.0;
+            } else {
+                piece.position = moved;
+            }
+        }
+        let below = piece.position - Vec3::Y * 0.01;
+        if piece.velocity.y == 0.0 && is_solid(below) {
+            let friction = DEBRIS_FRICTION.powf(dt);
+            piece.velocity.x *= friction;
+            piece.velocity.z *= friction;
+        }
+        particles.add(Particle {
+            position: piece.position,
+            size: DEBRIS_SIZE * (piece.remaining / DEBRIS_SHRINK_SECONDS).min(1.0),
+            texture_index: piece.texture_index,
+            uv_min: piece.uv_min,
+            uv_size: Vec2::splat(DEBRIS_UV_SIZE),
+            tint: piece.tint,
+        });
+        true
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use lib_render::texture::TerrainColorTextureIndices;
+
+    use super::*;
+    use crate::{
+        block::Block,
+        test_world::TestWorld,
+        world_gen::{LoadRadius, WorldType},
+    };
+
+    const ONE_CHUNK: LoadRadius = LoadRadius {
+        horizontal: 1,
+        vertical: 1,
+    };
+
+    /// Top of the grass of the flat world, which is at y = 3
+    const GROUND: f32 = 3.5;
+
+    #[test]
+    fn debris_lands_on_the_ground_and_fades() {
+        let mut world = TestWorld::new(0, WorldType::Flat, ONE_CHUNK);
+        world
+            .app
+            .add_event::<BlockBroken>()
+            .init_resource::<Particles>()
+            .init_resource::<TerrainColorTextureIndices>();
+        // The world is already finished, so plugins can't be added the
+        // usual way
+        BlockParticlesPlugin.build(&mut world.app);
+        world.step_until_meshed(100_000);
+        world.app.world_mut().send_event(BlockBroken {
+            pos: IVec3::new(0, 4, 0),
+            block: Block::Stone,
+            face: None,
+        });
+        world.step(1);
+        let pieces = world.app.world().resource::<Debris>().pieces.len();
+        assert_eq!(pieces, DEBRIS_PER_AXIS.pow(3) as usize);
+        let mut landed = false;
+        let landed_all = world.step_until(120, |world| {
+            let debris = world.app.world().resource::<Debris>();
+            for piece in &debris.pieces {
+                let bottom = piece.position.y - DEBRIS_SIZE / 2.0;
+                assert!(bottom >= GROUND - 1e-3, "fell through to {bottom}");
+                landed |= piece.velocity.y == 0.0 && bottom < GROUND + 0.01;
+            }
+            debris.pieces.is_empty()
+        });
+        assert!(landed, "no debris landed");
+        assert!(landed_all, "debris didn't fade away");
+    }
+}
@@ -0,0 +1,139 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use lib_chunk::ChunkIndex;
+use lib_render::Normal;
+
+use crate::{
+    block::{Block, BlockState, BlockTag, PlacedBlock},
+    block_registry::BlockRegistry,
+    game_state::GameState,
+    inventory::Inventory,
+    player::{Player, PlayerBody},
+    targeting::{TargetedBlock, TargetingSystems},
+    world_gen::{self, BlockChangeCause, Blocks, EditBlock},
+};
+
+/// Places the selected block against the targeted face on right click, if
+/// the block there is replaceable and the new block wouldn't overlap the
+/// player. Each placed block is taken from the `Inventory`. The mouse wheel
+/// cycles through the blocks held. Sends `BlockPlaced` for each placed block.
+pub struct BlockPlacingPlugin;
+
+impl Plugin for BlockPlacingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedBlock>()
+            .add_event::<BlockPlaced>()
+            .add_systems(
+                Update,
+                (
+                    cycle_selected_block,
+                    place_selected_block
+                        .after(TargetingSystems)
+                        .before(world_gen::apply_block_edits),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Sent when a block is placed
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BlockPlaced {
+    pub pos: IVec3,
+    pub block: Block,
+    /// Face of the block it was placed against
+    pub face: Option<Normal>,
+}
+
+/// Block placed on right click
+#[derive(Resource, Clone, Copy)]
+pub struct SelectedBlock(pub Block);
+
+impl Default for SelectedBlock {
+    fn default() -> Self {
+        Self(Block::Stone)
+    }
+}
+
+fn cycle_selected_block(
+    mut wheel: EventReader<MouseWheel>,
+    inventory: Res<Inventory>,
+    registry: Res<BlockRegistry>,
+    mut selected: ResMut<SelectedBlock>,
+) {
+    let scrolled: f32 = wheel.read().map(|event| event.y).sum();
+    if scrolled == 0.0 {
+        return;
+    }
+    let blocks: Vec<Block> = inventory
+        .stacks()
+        .map(|(id, _)| registry.block(id))
+        .collect();
+    if blocks.is_empty() {
+        return;
+    }
+    let current = blocks.iter().position(|block| *block == selected.0);
+    let step = if scrolled > 0.0 { blocks.len() - 1 } else { 1 };
+    let next = current.map_or(0, |i| (i + step) % blocks.len());
+    selected.0 = blocks[next];
+}
+
+fn place_selected_block(
+    mouse: Res<ButtonInput<MouseButton>>,
+    selected: Res<SelectedBlock>,
+    registry: Res<BlockRegistry>,
+    mut inventory: ResMut<Inventory>,
+    target: Option<Res<TargetedBlock>>,
+    q_player: Query<(&Transform, &PlayerBody), With<Player>>,
+    chunk_index: Res<ChunkIndex>,
+    q_blocks: Query<&Blocks>,
+    mut placed: EventWriter<BlockPlaced>,
+    mut edits: EventWriter<EditBlock>,
+) {
+    if !mouse.just_pressed(MouseButton::Right) {
+        return;
+    }
+    // Nowhere to place against from inside a block
+    let Some((target_pos, Some(face))) = target.map(|target| (target.pos, target.face)) else {
+        return;
+    };
+    let pos = target_pos + face.as_unit_direction();
+    let replaceable = world_gen::block_at(&chunk_index, &q_blocks, pos)
+        .is_some_and(|block| block.has_tag(BlockTag::Replaceable));
+    if !replaceable {
+        return;
+    }
+    let block = selected.0;
+    let id = registry.id(block);
+    if inventory.count(id) == 0 {
+        return;
+    }
+    if let Ok((transform, body)) = q_player.single() {
+        let player = body.aabb(transform.translation);
+        // Touching the player is fine, like placing a block beside their feet
+        let overlaps_player = block.collision_aabbs().iter().any(|aabb| {
+            let corner = pos.as_vec3a() - 0.5;
+            let (min, max) = (aabb.min + corner, aabb.max + corner);
+            (min.cmplt(player.max) & max.cmpgt(player.min)).all()
+        });
+        if overlaps_player {
+            return;
+        }
+    }
+    inventory.take(id);
+    let state = match block {
+        // Logs lie along the axis of the face they're placed against
+        Block::Log => BlockState::default().with_facing(face),
+        _ => BlockState::default(),
+    };
+    edits.write(EditBlock {
+        pos,
+        block: PlacedBlock { block, state },
+        cause: BlockChangeCause::Player,
+    });
+    placed.write(BlockPlaced {
+        pos,
+        block,
+        face: Some(face),
+    });
+}
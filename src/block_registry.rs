@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::block::{Block, BlockDefinition};
+
+/// Numeric id of a block, used wherever blocks are written out. Ids are kept
+/// in the world's save metadata, so they don't change when blocks are added
+/// to or reordered in `Block`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u16);
+
+/// Maps blocks to their numeric ids and back
+#[derive(Resource, Clone)]
+pub struct BlockRegistry {
+    /// Block of each id. `None` for ids of blocks saved by an older version
+    /// that no longer exist.
+    blocks: Vec<Option<Block>>,
+    ids: HashMap<Block, BlockId>,
+}
+
+/// A fresh world assigns ids in the order of `Block::all`
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            blocks: vec![],
+            ids: HashMap::new(),
+        };
+        for block in Block::all() {
+            registry.register(block);
+        }
+        registry
+    }
+}
+
+impl BlockRegistry {
+    /// Restores the ids saved by `to_metadata`, remapping as the blocks have
+    /// changed since. Saved blocks keep their ids, blocks added since are
+    /// given new ones, and ids of blocks that were removed load as air.
+    pub fn from_metadata(names: &[String]) -> Self {
+        let mut registry = Self {
+            blocks: Vec::with_capacity(names.len()),
+            ids: HashMap::new(),
+        };
+        for name in names {
+            let block = Block::from_name(name);
+            if block.is_none() {
+                warn!("Saved block {name:?} no longer exists, and will load as air");
+            }
+            if let Some(block) = block {
+                let id = BlockId(registry.blocks.len() as u16);
+                registry.ids.insert(block, id);
+            }
+            registry.blocks.push(block);
+        }
+        for block in Block::all() {
+            if !registry.ids.contains_key(&block) {
+                registry.register(block);
+            }
+        }
+        registry
+    }
+
+    /// Block names by id, to be saved with the world
+    pub fn to_metadata(&self) -> Vec<String> {
+        self.blocks
+            .iter()
+            .map(|block| block.map_or(String::new(), |block| block.name().to_owned()))
+            .collect()
+    }
+
+    pub fn id(&self, block: Block) -> BlockId {
+        self.ids[&block]
+    }
+
+    /// Block with `id`, or air if the id is unknown
+    pub fn block(&self, id: BlockId) -> Block {
+        self.blocks
+            .get(id.0 as usize)
+            .copied()
+            .flatten()
+            .unwrap_or(Block::Air)
+    }
+
+    fn register(&mut self, block: Block) {
+        let id = BlockId(self.blocks.len() as u16);
+        self.blocks.push(Some(block));
+        self.ids.insert(block, id);
+    }
+}
+
+/// Blocks added by other crates, as given to `GamePlugins::register_blocks`
+pub struct CustomBlocksPlugin(pub Vec<BlockDefinition>);
+
+impl Plugin for CustomBlocksPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_blocks(self.0.iter().cloned());
+    }
+}
+
+pub trait RegisterBlock {
+    /// Adds the block `definition` describes, giving it the next free id
+    fn register_block(&mut self, definition: BlockDefinition) -> &mut Self;
+
+    fn register_blocks(
+        &mut self,
+        definitions: impl IntoIterator<Item = BlockDefinition>,
+    ) -> &mut Self {
+        for definition in definitions {
+            self.register_block(definition);
+        }
+        self
+    }
+}
+
+impl RegisterBlock for App {
+    fn register_block(&mut self, definition: BlockDefinition) -> &mut Self {
+        let block = Block::register(definition);
+        // A registry made since already has it
+        let mut registry = self.world_mut().get_resource_or_init::<BlockRegistry>();
+        if !registry.ids.contains_key(&block) {
+            registry.register(block);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BlockFaces, Terrain};
+
+    #[test]
+    fn registered_blocks_get_the_next_id_and_are_saved() {
+        let mut app = App::new();
+        app.init_resource::<BlockRegistry>();
+        let builtin = app.world().resource::<BlockRegistry>().to_metadata().len();
+        app.register_block(BlockDefinition::solid(
+            "test_marble",
+            BlockFaces::All(Terrain::Stone),
+        ));
+        let block = Block::from_name("test_marble").unwrap();
+        assert_eq!(block.name(), "test_marble");
+        let registry = app.world().resource::<BlockRegistry>();
+        assert_eq!(registry.id(block), BlockId(builtin as u16));
+        let metadata = registry.to_metadata();
+        assert_eq!(
+            BlockRegistry::from_metadata(&metadata).id(block),
+            registry.id(block)
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use lib_chunk::{ChunkIndex, ChunkPosition, NeighborhoodSystems, RefreshNeighborCopies};
+use lib_spatial::CHUNK_SIZE;
+use lib_utils::cube_iter;
+
+use crate::{
+    player::Player,
+    world_gen::{Blocks, block_pos_containing},
+};
+
+/// Keeps the blocks of chunks that haven't been used in a while compressed
+/// into runs of the same block, as most chunks are mostly air or stone.
+/// Chunks around the player and chunks that were just edited count as used.
+/// Every `COMPRESSION_INTERVAL`, if more than
+/// `ChunkCompressionSettings::max_decompressed` chunks are decompressed, the
+/// least recently used ones are compressed. Chunks are
+/// decompressed again when the player comes near or a block in them is
+/// replaced, and only for the meshing task when they're remeshed.
+pub struct ChunkCompressionPlugin;
+
+impl Plugin for ChunkCompressionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkCompressionSettings>()
+            .init_resource::<ChunkUsage>()
+            .add_systems(
+                Update,
+                (
+                    forget_removed_chunks,
+                    mark_edited_chunks_used,
+                    decompress_chunks_near_player,
+                    compress_least_recently_used_chunks,
+                )
+                    .chain()
+                    // Neighbourhoods get the new copies in the same frame
+                    .before(NeighborhoodSystems),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct ChunkCompressionSettings {
+    /// Chunks this many chunks or fewer from the player's are kept
+    /// decompressed
+    pub hot_radius: i32,
+    /// Decompressed chunks allowed before compressing the least recently
+    /// used
+    pub max_decompressed: usize,
+    /// Chunks compressed each `COMPRESSION_INTERVAL` at most, to spread out
+    /// the work
+    pub max_compressed_per_check: usize,
+}
+
+impl Default for ChunkCompressionSettings {
+    fn default() -> Self {
+        Self {
+            hot_radius: 4,
+            max_decompressed: 1024,
+            max_compressed_per_check: 64,
+        }
+    }
+}
+
+/// When each chunk was last used, in frames
+#[derive(Resource, Default)]
+struct ChunkUsage {
+    frame: u64,
+    last_used: HashMap<Entity, u64>,
+}
+
+impl ChunkUsage {
+    fn mark_used(&mut self, entity: Entity) {
+        self.last_used.insert(entity, self.frame);
+    }
+}
+
+fn forget_removed_chunks(mut usage: ResMut<ChunkUsage>, mut removed: RemovedComponents<Blocks>) {
+    usage.frame += 1;
+    for entity in removed.read() {
+        usage.last_used.remove(&entity);
+    }
+}
+
+fn mark_edited_chunks_used(
+    mut usage: ResMut<ChunkUsage>,
+    q_edited: Query<Entity, Changed<Blocks>>,
+) {
+    for entity in q_edited.iter() {
+        usage.mark_used(entity);
+    }
+}
+
+fn decompress_chunks_near_player(
+    settings: Res<ChunkCompressionSettings>,
+    mut usage: ResMut<ChunkUsage>,
+    chunk_index: Res<ChunkIndex>,
+    q_player: Query<&Transform, With<Player>>,
+    mut q_blocks: Query<&mut Blocks>,
+    mut refresh: EventWriter<RefreshNeighborCopies<Blocks>>,
+) {
+    for transform in q_player.iter() {
+        let player_chunk =
+            block_pos_containing(transform.translation).div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+        for (x, y, z) in cube_iter(-settings.hot_radius..=settings.hot_radius) {
+            let Some(&entity) = chunk_index.get_entity(&(player_chunk + IVec3::new(x, y, z)))
+            else {
+                continue;
+            };
+            let Ok(mut blocks) = q_blocks.get_mut(entity) else {
+                continue;
+            };
+            usage.mark_used(entity);
+            if blocks.is_compressed() {
+                // Stored differently, but the blocks are the same
+                blocks.bypass_change_detection().decompress();
+                refresh.write(RefreshNeighborCopies::new(entity));
+            }
+        }
+    }
+}
+
+/// Finding the least recently used chunks means going through all of them,
+/// so it's only done this often
+const COMPRESSION_INTERVAL: Duration = Duration::from_millis(250);
+
+fn compress_least_recently_used_chunks(
+    time: Res<Time>,
+    mut since_last_check: Local<Duration>,
+    settings: Res<ChunkCompressionSettings>,
+    usage: Res<ChunkUsage>,
+    mut q_blocks: Query<(Entity, &mut Blocks), With<ChunkPosition>>,
+    mut refresh: EventWriter<RefreshNeighborCopies<Blocks>>,
+) {
+    *since_last_check += time.delta();
+    if *since_last_check < COMPRESSION_INTERVAL {
+        return;
+    }
+    *since_last_check = Duration::ZERO;
+    let mut decompressed: Vec<(u64, Entity)> = q_blocks
+        .iter()
+        .filter(|(_, blocks)| !blocks.is_compressed())
+        .map(|(entity, _)| (usage.last_used.get(&entity).copied().unwrap_or(0), entity))
+        .collect();
+    let excess = decompressed.len().saturating_sub(settings.max_decompressed);
+    let count = excess.min(settings.max_compressed_per_check);
+    if count == 0 {
+        return;
+    }
+    // Moves the least recently used to the front, in no particular order
+    decompressed.select_nth_unstable(count - 1);
+    for (_, entity) in decompressed.into_iter().take(count) {
+        let Ok((_, mut blocks)) = q_blocks.get_mut(entity) else {
+            continue;
+        };
+        blocks.bypass_change_detection().compress();
+        refresh.write(RefreshNeighborCopies::new(entity));
+    }
+}
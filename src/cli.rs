@@ -0,0 +1,305 @@
+use std::{path::PathBuf, str::FromStr};
+
+use bevy::prelude::*;
+
+use crate::{
+    fly_through::FlyThroughPlugin,
+    game_state::GameState,
+    metrics_export::MetricsExportPlugin,
+    metrics_log::MetricsRecorder,
+    network::NetworkRole,
+    persistence::{self, AutosaveInterval, SaveDirectory},
+    replay::ReplayPlugin,
+    world_gen::{Blocks, Chunk, WorldSeed, WorldType},
+};
+
+pub const USAGE: &str = "\
+Usage: bevy-wgpu-demo [OPTIONS]
+
+Options:
+  --seed <SEED>               Seed of a new world, in decimal or 0x hex
+  --world <PATH>              Folder the world is saved in and loaded from
+  --world-type <TYPE>         Terrain of a new world: hills or flat
+  --import-anvil <FOLDER>     Fills the world with the blocks of the
+                              Minecraft world in FOLDER instead of
+                              generating it, saving changes to a scratch
+                              folder unless --world is given
+  --anvil-blocks <FILE>       Maps Minecraft blocks onto this game's for
+                              --import-anvil, one per line, like
+                              `minecraft:terracotta stone`
+  --render-distance <CHUNKS>  Overrides the render distance in the settings
+  --pregenerate <CHUNKS>      Generates at least this far around the centre
+                              of the world, then exits when headless
+  --autosave <SECONDS>        Overrides the time between autosaves in the
+                              settings, 0 to only save on exit
+  --host <ADDRESS>            Lets other games join this world at ADDRESS,
+                              like 0.0.0.0:7777
+  --connect <ADDRESS>         Joins the world hosted at ADDRESS
+  --server <ADDRESS>          Hosts the world at ADDRESS as a dedicated server,
+                              without a window, renderer or player
+  --record <FILE>             Records a replay of the input to FILE
+  --replay <FILE>             Plays back the replay in FILE on a new world,
+                              then exits
+  --headless                  Runs without a window
+  --benchmark                 Records metrics from startup until exit
+  --export-metrics <FILE>     Appends a line of JSON with the frame times,
+                              chunk counts, task queues and GPU buffer sizes
+                              to FILE every few seconds
+  --fly-through               Flies a fixed route through a new world with a
+                              fixed seed, writes the frame rate and chunk
+                              latencies to the metrics folder, then exits
+  --preview-map <FILE>        Writes a top-down heightmap of the terrain
+                              of --seed and --world-type to FILE, and a
+                              map of its biomes next to it, then exits
+                              without starting the game
+  --preview-size <BLOCKS>     Width of the area --preview-map covers,
+                              centred on the middle of the world [default:
+                              512]
+  --help                      Prints this message
+
+A saved world keeps the seed and world type it was created with. Running
+headless or as a server, joining, replaying or flying through a world, or
+choosing one with any of --seed, --world, --world-type or --import-anvil
+skips the main menu.";
+
+/// Options given on the command line. Everything left out keeps its usual
+/// default, or its value in the settings file.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct CommandLine {
+    pub seed: Option<u32>,
+    pub world: Option<PathBuf>,
+    pub world_type: Option<WorldType>,
+    /// Minecraft world whose blocks replace the generated terrain
+    pub import_anvil: Option<PathBuf>,
+    /// Mapping of Minecraft blocks applied over `BlockMapping::default`
+    pub anvil_blocks: Option<PathBuf>,
+    /// In chunks, like `Settings::render_distance`
+    pub render_distance: Option<u32>,
+    /// Chunks generated around the centre of the world, even past the
+    /// render distance
+    pub pregenerate_radius: Option<u32>,
+    /// Seconds between autosaves, like `Settings::autosave_interval`
+    pub autosave_interval: Option<u32>,
+    /// Address to host the world at
+    pub host: Option<String>,
+    /// Address of the server to join
+    pub connect: Option<String>,
+    /// Runs only the world and what shares it, hosted at `host`
+    pub dedicated_server: bool,
+    /// Replay file to record the input to
+    pub record: Option<PathBuf>,
+    /// Replay file to play back
+    pub replay: Option<PathBuf>,
+    pub headless: bool,
+    pub benchmark: bool,
+    /// File the metrics are periodically appended to
+    pub export_metrics: Option<PathBuf>,
+    /// Flies the benchmark route on its own world
+    pub fly_through: bool,
+    /// Heightmap to write instead of starting the game
+    pub preview_map: Option<PathBuf>,
+    /// Width of the previewed area, in blocks
+    pub preview_size: Option<u32>,
+    pub help: bool,
+}
+
+impl CommandLine {
+    /// Parses the arguments after the program's name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--seed" => cli.seed = Some(parse_seed(&value()?)?),
+                "--world" => cli.world = Some(PathBuf::from(value()?)),
+                "--world-type" => cli.world_type = Some(parse_value(&arg, &value()?)?),
+                "--import-anvil" => cli.import_anvil = Some(PathBuf::from(value()?)),
+                "--anvil-blocks" => cli.anvil_blocks = Some(PathBuf::from(value()?)),
+                "--render-distance" => cli.render_distance = Some(parse_value(&arg, &value()?)?),
+                "--pregenerate" => cli.pregenerate_radius = Some(parse_value(&arg, &value()?)?),
+                "--autosave" => cli.autosave_interval = Some(parse_value(&arg, &value()?)?),
+                "--host" => cli.host = Some(value()?),
+                "--connect" => cli.connect = Some(value()?),
+                "--server" => {
+                    cli.host = Some(value()?);
+                    cli.dedicated_server = true;
+                }
+                "--record" => cli.record = Some(PathBuf::from(value()?)),
+                "--replay" => cli.replay = Some(PathBuf::from(value()?)),
+                "--headless" => cli.headless = true,
+                "--benchmark" => cli.benchmark = true,
+                "--export-metrics" => cli.export_metrics = Some(PathBuf::from(value()?)),
+                "--fly-through" => cli.fly_through = true,
+                "--preview-map" => cli.preview_map = Some(PathBuf::from(value()?)),
+                "--preview-size" => cli.preview_size = Some(parse_value(&arg, &value()?)?),
+                "--help" | "-h" => cli.help = true,
+                _ => return Err(format!("unknown option {arg:?}")),
+            }
+        }
+        if cli.host.is_some() && cli.connect.is_some() {
+            return Err("can't both host and --connect".to_owned());
+        }
+        if cli.dedicated_server && cli.benchmark {
+            return Err("--benchmark records the metrics of a game, not a --server".to_owned());
+        }
+        if cli.dedicated_server && (cli.record.is_some() || cli.replay.is_some()) {
+            return Err("a --server has no input to --record or --replay".to_owned());
+        }
+        if cli.anvil_blocks.is_some() && cli.import_anvil.is_none() {
+            return Err("--anvil-blocks only applies with --import-anvil".to_owned());
+        }
+        if cli.import_anvil.is_some() && cli.connect.is_some() {
+            return Err("can't --import-anvil into a world hosted elsewhere".to_owned());
+        }
+        if cli.preview_size.is_some() && cli.preview_map.is_none() {
+            return Err("--preview-size only applies with --preview-map".to_owned());
+        }
+        if cli.preview_size == Some(0) {
+            return Err("--preview-size has to be at least 1".to_owned());
+        }
+        if cli.record.is_some() && cli.replay.is_some() {
+            return Err("can't both --record and --replay".to_owned());
+        }
+        if cli.fly_through
+            && (cli.seed.is_some()
+                || cli.world.is_some()
+                || cli.world_type.is_some()
+                || cli.import_anvil.is_some()
+                || cli.connect.is_some()
+                || cli.dedicated_server
+                || cli.replay.is_some())
+        {
+            return Err("--fly-through always flies through a world of its own".to_owned());
+        }
+        Ok(cli)
+    }
+
+    /// Whether the world is already chosen or hosted elsewhere, or there's no
+    /// window to show the main menu in
+    pub fn skips_main_menu(&self) -> bool {
+        self.headless
+            || self.dedicated_server
+            || self.connect.is_some()
+            || self.replay.is_some()
+            || self.fly_through
+            || self.seed.is_some()
+            || self.world.is_some()
+            || self.world_type.is_some()
+            || self.import_anvil.is_some()
+    }
+
+    /// Whether someone is playing at the terminal, rather than the game
+    /// running unattended without a window, as a server or through recorded
+    /// input
+    pub fn is_interactive(&self) -> bool {
+        !(self.headless
+            || self.dedicated_server
+            || self.benchmark
+            || self.fly_through
+            || self.replay.is_some())
+    }
+
+    /// Chunks loaded around the centre of the world along each horizontal
+    /// axis, given the render distance in the settings
+    pub fn load_radius(&self, render_distance: u32) -> u32 {
+        self.render_distance
+            .unwrap_or(render_distance)
+            .max(self.pregenerate_radius.unwrap_or(0))
+    }
+}
+
+fn parse_value<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {value:?} for {option}"))
+}
+
+pub(crate) fn parse_seed(value: &str) -> Result<u32, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+    .ok_or_else(|| format!("invalid seed {value:?}"))
+}
+
+/// Applies the `CommandLine` over the resources of the plugins added before
+/// it. The render distance, pregeneration radius and autosave interval are
+/// applied along with the settings, without being saved to the settings
+/// file.
+pub struct CommandLinePlugin(pub CommandLine);
+
+impl Plugin for CommandLinePlugin {
+    fn build(&self, app: &mut App) {
+        let cli = &self.0;
+        app.insert_resource(cli.clone());
+        if let Some(seed) = cli.seed {
+            app.insert_resource(WorldSeed(seed));
+        }
+        if let Some(world) = &cli.world {
+            app.insert_resource(SaveDirectory(world.clone()));
+        } else if cli.import_anvil.is_some() {
+            // Keeps edits to the imported world out of the default one
+            app.insert_resource(SaveDirectory(persistence::scratch_directory(
+                "anvil-import",
+            )));
+        }
+        if let Some(world_type) = cli.world_type {
+            app.insert_resource(world_type);
+        }
+        if let Some(secs) = cli.autosave_interval {
+            // Dedicated servers have no settings to apply it with
+            app.insert_resource(AutosaveInterval::from_secs(secs));
+        }
+        if let Some(address) = &cli.host {
+            app.insert_resource(NetworkRole::Server {
+                address: address.clone(),
+            });
+        }
+        if let Some(address) = &cli.connect {
+            app.insert_resource(NetworkRole::Client {
+                address: address.clone(),
+            });
+        }
+        if let Some(path) = &cli.record {
+            app.add_plugins(ReplayPlugin::Record(path.clone()));
+        }
+        if let Some(path) = &cli.replay {
+            app.add_plugins(ReplayPlugin::Play(path.clone()));
+        }
+        if cli.fly_through {
+            app.add_plugins(FlyThroughPlugin);
+        }
+        if cli.skips_main_menu() {
+            app.insert_state(GameState::Loading);
+        }
+        if cli.benchmark {
+            app.add_systems(Startup, start_benchmark_recording);
+        }
+        if let Some(path) = &cli.export_metrics {
+            app.add_plugins(MetricsExportPlugin(path.clone()));
+        }
+        if cli.headless && cli.pregenerate_radius.is_some() {
+            // Before the world is saved on exit, in `Last`
+            app.add_systems(PostUpdate, exit_once_generated);
+        }
+    }
+}
+
+fn start_benchmark_recording(time: Res<Time<Real>>, mut recorder: ResMut<MetricsRecorder>) {
+    recorder.start(time.elapsed());
+}
+
+/// Exits once every chunk has its blocks, saving the world on the way out
+fn exit_once_generated(
+    q_chunks: Query<Has<Blocks>, With<Chunk>>,
+    mut exit: EventWriter<AppExit>,
+    mut done: Local<bool>,
+) {
+    if *done || q_chunks.is_empty() || !q_chunks.iter().all(|has_blocks| has_blocks) {
+        return;
+    }
+    info!("Generated {} chunks, exiting", q_chunks.iter().count());
+    exit.write(AppExit::Success);
+    *done = true;
+}
@@ -0,0 +1,160 @@
+use std::{
+    io::BufRead,
+    sync::{
+        Mutex,
+        mpsc::{Receiver, TryRecvError, channel},
+    },
+};
+
+use bevy::prelude::*;
+
+use crate::cli::CommandLine;
+
+/// Reads commands typed into the terminal the game was started from, one per
+/// line, and sends each as a `ConsoleCommand` for the plugin it's for to run.
+/// Plugins add their commands with `register_console_command`, and `help`
+/// lists them. Only interactive runs read the terminal, so unattended ones
+/// don't keep a thread waiting on it.
+#[derive(Default)]
+pub struct ConsolePlugin {
+    commands: Vec<(&'static str, &'static str)>,
+}
+
+impl ConsolePlugin {
+    /// Accepts `name` as well, like `App::register_console_command`
+    pub fn register_console_command(mut self, name: &'static str, usage: &'static str) -> Self {
+        self.commands.push((name, usage));
+        self
+    }
+}
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ConsoleCommand>()
+            .init_resource::<ConsoleCommands>()
+            .add_systems(Startup, start_reading_console.run_if(is_interactive))
+            .add_systems(PreUpdate, read_console_commands);
+        for (name, usage) in &self.commands {
+            app.register_console_command(name, usage);
+        }
+    }
+}
+
+/// Commands the console accepts and what their arguments are, so mistyped
+/// commands can be pointed out
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(Vec<(&'static str, &'static str)>);
+
+impl ConsoleCommands {
+    fn usage(&self, name: &str) -> Option<&'static str> {
+        self.0
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, usage)| *usage)
+    }
+}
+
+pub trait RegisterConsoleCommand {
+    /// Has the console accept `name`, sending it as a `ConsoleCommand` for
+    /// the caller to run. `help` lists it with `usage`.
+    fn register_console_command(&mut self, name: &'static str, usage: &'static str) -> &mut Self;
+}
+
+impl RegisterConsoleCommand for App {
+    fn register_console_command(&mut self, name: &'static str, usage: &'static str) -> &mut Self {
+        // Plugins may register commands before the console is added
+        self.add_event::<ConsoleCommand>();
+        let mut commands = self.world_mut().get_resource_or_init::<ConsoleCommands>();
+        if commands.usage(name).is_some() {
+            warn!("Console command {name:?} is already registered");
+        } else {
+            commands.0.push((name, usage));
+        }
+        self
+    }
+}
+
+/// A line typed into the console, split into words
+#[derive(Event, Clone, Debug)]
+pub struct ConsoleCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+impl ConsoleCommand {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace().map(str::to_owned);
+        let name = words.next()?;
+        Some(Self {
+            name,
+            args: words.collect(),
+        })
+    }
+}
+
+/// Lines read from stdin on their own thread, since reading blocks
+#[derive(Resource)]
+struct ConsoleLines(Mutex<Receiver<String>>);
+
+fn is_interactive(cli: Option<Res<CommandLine>>) -> bool {
+    cli.is_none_or(|cli| cli.is_interactive())
+}
+
+fn start_reading_console(mut commands: Commands) {
+    let (sender, receiver) = channel();
+    let spawned = std::thread::Builder::new()
+        .name("console".to_owned())
+        .spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Couldn't start reading console commands: {}", e);
+        return;
+    }
+    commands.insert_resource(ConsoleLines(Mutex::new(receiver)));
+}
+
+fn read_console_commands(
+    mut commands: Commands,
+    lines: Option<Res<ConsoleLines>>,
+    registered: Res<ConsoleCommands>,
+    mut ew: EventWriter<ConsoleCommand>,
+) {
+    let Some(lines) = lines else {
+        return;
+    };
+    let receiver = lines.0.lock().unwrap();
+    loop {
+        let line = match receiver.try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Empty) => break,
+            // Nothing more can be typed, e.g. stdin isn't a terminal
+            Err(TryRecvError::Disconnected) => {
+                commands.remove_resource::<ConsoleLines>();
+                break;
+            }
+        };
+        let Some(command) = ConsoleCommand::parse(&line) else {
+            continue;
+        };
+        match command.name.as_str() {
+            "help" => {
+                info!("help Lists the commands");
+                for (name, usage) in &registered.0 {
+                    info!("{} {}", name, usage);
+                }
+            }
+            name if registered.usage(name).is_some() => {
+                ew.write(command);
+            }
+            name => warn!("Unknown command {:?}, try help", name),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+
+pub struct CrosshairPlugin;
+
+impl Plugin for CrosshairPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CrosshairStyle>()
+            .add_systems(Startup, spawn_crosshair)
+            .add_systems(
+                Update,
+                (
+                    restyle_crosshair.run_if(resource_changed::<CrosshairStyle>),
+                    update_crosshair_visibility,
+                ),
+            );
+    }
+}
+
+/// Marks the centre of the screen, which is what block interactions aim at
+#[derive(Resource, Clone, Copy)]
+pub struct CrosshairStyle {
+    pub shape: CrosshairShape,
+    /// Width and height in pixels
+    pub size: f32,
+    /// Width of the lines of `CrosshairShape::Cross`, in pixels
+    pub thickness: f32,
+    pub color: Color,
+}
+
+impl Default for CrosshairStyle {
+    fn default() -> Self {
+        Self {
+            shape: CrosshairShape::default(),
+            size: 16.0,
+            thickness: 2.0,
+            color: Color::srgba(1.0, 1.0, 1.0, 0.8),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CrosshairShape {
+    #[default]
+    Cross,
+    Dot,
+}
+
+#[derive(Component)]
+struct Crosshair;
+
+fn spawn_crosshair(mut commands: Commands) {
+    commands.spawn((
+        Crosshair,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+}
+
+fn restyle_crosshair(
+    mut commands: Commands,
+    style: Res<CrosshairStyle>,
+    q_crosshair: Query<Entity, With<Crosshair>>,
+) {
+    for entity in q_crosshair.iter() {
+        commands
+            .entity(entity)
+            .despawn_related::<Children>()
+            .with_children(|parent| match style.shape {
+                CrosshairShape::Cross => {
+                    parent.spawn(crosshair_part(&style, style.size, style.thickness));
+                    parent.spawn(crosshair_part(&style, style.thickness, style.size));
+                }
+                CrosshairShape::Dot => {
+                    parent.spawn((
+                        crosshair_part(&style, style.size, style.size),
+                        BorderRadius::MAX,
+                    ));
+                }
+            });
+    }
+}
+
+/// A rectangle centred on the screen
+fn crosshair_part(style: &CrosshairStyle, width: f32, height: f32) -> impl Bundle {
+    (
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Px(width),
+            height: Val::Px(height),
+            ..default()
+        },
+        BackgroundColor(style.color),
+    )
+}
+
+/// Only shown while the cursor is grabbed, since otherwise the mouse isn't
+/// aiming the camera
+fn update_crosshair_visibility(
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    mut q_crosshair: Query<&mut Visibility, With<Crosshair>>,
+) {
+    let Ok(window) = q_window.single() else {
+        return;
+    };
+    let visibility = if window.cursor_options.grab_mode == CursorGrabMode::None {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for mut crosshair_visibility in q_crosshair.iter_mut() {
+        crosshair_visibility.set_if_neq(visibility);
+    }
+}
@@ -1,25 +1,77 @@
+use std::{collections::VecDeque, marker::PhantomData, time::Duration};
+
 use bevy::{
     diagnostic::FrameTimeDiagnosticsPlugin,
     ecs::system::lifetimeless::{SQuery, SRes},
     prelude::*,
 };
 use iyes_perf_ui::{entry::PerfUiEntry, prelude::*};
+use lib_async_component::ComputeTaskStats;
+use lib_chunk::ChunkIndex;
+use lib_render::{
+    Normal,
+    globals::{AmbientLight, DirectionalLight},
+};
+use lib_spatial::CHUNK_SIZE;
 
-use crate::mesh::QuadCount;
+use crate::{
+    biome::{Biome, Climate, ClimateNoise},
+    block::{Block, ToolTier},
+    block_breaking::{BreakingProgress, HeldTool},
+    block_placing::SelectedBlock,
+    debug_overlay::ChunkDebugOverlay,
+    frame_graph::FrameGraphPlugin,
+    lighting_panel::LightingPanelPlugin,
+    mesh::{QuadCount, QuadsGenerated, TerrainQuads},
+    subsystem_timing::{Subsystem, SubsystemTimingPlugin, SubsystemTimings},
+    targeting::TargetedBlock,
+    world_gen::{self, Blocks, Chunk, HeightNoise},
+};
 
 pub struct DebugHudPlugin;
 
 impl Plugin for DebugHudPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((FrameTimeDiagnosticsPlugin::default(), PerfUiPlugin))
-            .add_perf_ui_simple_entry::<PerfUiEntryQuadCount>()
-            .add_perf_ui_simple_entry::<PerfUiEntryCameraPosition>()
-            .add_perf_ui_simple_entry::<PerfUiEntryCameraForward>()
-            .add_systems(Startup, spawn_perf_ui_entries);
+        app.add_plugins((
+            FrameTimeDiagnosticsPlugin::default(),
+            PerfUiPlugin,
+            FrameGraphPlugin,
+            SubsystemTimingPlugin,
+            LightingPanelPlugin,
+        ))
+        .add_perf_ui_simple_entry::<PerfUiEntryQuadCount>()
+        .add_perf_ui_simple_entry::<PerfUiEntryCameraPosition>()
+        .add_perf_ui_simple_entry::<PerfUiEntryCameraForward>()
+        .add_perf_ui_simple_entry::<PerfUiEntryCameraChunk>()
+        .add_perf_ui_simple_entry::<PerfUiEntryCameraBlock>()
+        .add_perf_ui_simple_entry::<PerfUiEntryTargetedBlock>()
+        .add_perf_ui_simple_entry::<PerfUiEntryBreaking>()
+        .add_perf_ui_simple_entry::<PerfUiEntrySelectedBlock>()
+        .add_perf_ui_simple_entry::<PerfUiEntryBiome>()
+        .add_perf_ui_simple_entry::<PerfUiEntrySurfaceHeight>()
+        .add_perf_ui_simple_entry::<PerfUiEntryLightLevel>()
+        .add_perf_ui_simple_entry::<PerfUiEntryLoadedChunks>()
+        .add_perf_ui_simple_entry::<PerfUiEntryChunksAwaitingGeneration>()
+        .add_perf_ui_simple_entry::<PerfUiEntryChunksAwaitingMeshing>()
+        .add_perf_ui_simple_entry::<PerfUiEntryCulledChunks>()
+        .add_perf_ui_simple_entry::<PerfUiEntryComputeTasks<HeightNoise>>()
+        .add_perf_ui_simple_entry::<PerfUiEntryComputeTasks<Blocks>>()
+        .add_perf_ui_simple_entry::<PerfUiEntryComputeTasks<TerrainQuads>>()
+        .add_perf_ui_simple_entry::<PerfUiEntrySubsystemTime<0>>()
+        .add_perf_ui_simple_entry::<PerfUiEntrySubsystemTime<1>>()
+        .add_perf_ui_simple_entry::<PerfUiEntrySubsystemTime<2>>()
+        .add_perf_ui_simple_entry::<PerfUiEntrySubsystemTime<3>>()
+        .add_perf_ui_simple_entry::<PerfUiEntryQuadsPerSecond>()
+        .add_perf_ui_simple_entry::<PerfUiEntryChunksMeshedPerSecond>()
+        .add_perf_ui_simple_entry::<PerfUiEntryMeshTimePerChunk>()
+        .init_resource::<MeshingThroughput>()
+        .add_systems(Startup, spawn_perf_ui_entries)
+        .add_systems(Update, sample_meshing_throughput);
     }
 }
 
 fn spawn_perf_ui_entries(mut commands: Commands) {
+    // Grouped to stay under the size limit of bundle tuples
     commands.spawn((
         PerfUiEntryFPSAverage::default(),
         PerfUiEntryFPSPctLow::default(),
@@ -27,6 +79,40 @@ fn spawn_perf_ui_entries(mut commands: Commands) {
         PerfUiEntryQuadCount::default(),
         PerfUiEntryCameraPosition::default(),
         PerfUiEntryCameraForward::default(),
+        (
+            PerfUiEntryCameraChunk::default(),
+            PerfUiEntryCameraBlock::default(),
+            PerfUiEntryTargetedBlock::default(),
+            PerfUiEntryBreaking::default(),
+            PerfUiEntrySelectedBlock::default(),
+        ),
+        (
+            PerfUiEntryBiome::default(),
+            PerfUiEntrySurfaceHeight::default(),
+            PerfUiEntryLightLevel::default(),
+        ),
+        PerfUiEntryLoadedChunks::default(),
+        PerfUiEntryChunksAwaitingGeneration::default(),
+        (
+            PerfUiEntryChunksAwaitingMeshing::default(),
+            PerfUiEntryCulledChunks::default(),
+        ),
+        (
+            PerfUiEntryComputeTasks::<HeightNoise>::new("Height Noise Tasks"),
+            PerfUiEntryComputeTasks::<Blocks>::new("Block Tasks"),
+            PerfUiEntryComputeTasks::<TerrainQuads>::new("Meshing Tasks"),
+        ),
+        (
+            PerfUiEntryQuadsPerSecond::default(),
+            PerfUiEntryChunksMeshedPerSecond::default(),
+            PerfUiEntryMeshTimePerChunk::default(),
+        ),
+        (
+            PerfUiEntrySubsystemTime::<0>::default(),
+            PerfUiEntrySubsystemTime::<1>::default(),
+            PerfUiEntrySubsystemTime::<2>::default(),
+            PerfUiEntrySubsystemTime::<3>::default(),
+        ),
     ));
 }
 
@@ -143,3 +229,798 @@ impl PerfUiEntry for PerfUiEntryQuadCount {
         format!("{}", value)
     }
 }
+
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryLoadedChunks {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryLoadedChunks {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryLoadedChunks {
+    type Value = usize;
+    type SystemParam = SQuery<(), With<Chunk>>;
+
+    fn label(&self) -> &str {
+        "Loaded Chunks"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        Some(param.iter().count())
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{}", value)
+    }
+}
+
+/// Chunks without blocks yet, including those still waiting on height noise
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryChunksAwaitingGeneration {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryChunksAwaitingGeneration {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryChunksAwaitingGeneration {
+    type Value = usize;
+    type SystemParam = SQuery<(), (With<Chunk>, Without<Blocks>)>;
+
+    fn label(&self) -> &str {
+        "Chunks Awaiting Generation"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        Some(param.iter().count())
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{}", value)
+    }
+}
+
+/// Generated chunks without quads yet, including those still waiting on
+/// their neighbours' blocks
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryChunksAwaitingMeshing {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryChunksAwaitingMeshing {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryChunksAwaitingMeshing {
+    type Value = usize;
+    type SystemParam = SQuery<(), (With<Chunk>, With<Blocks>, Without<TerrainQuads>)>;
+
+    fn label(&self) -> &str {
+        "Chunks Awaiting Meshing"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        Some(param.iter().count())
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{}", value)
+    }
+}
+
+/// Meshed chunks outside the view, which the renderer skips, and their
+/// quads. Only counted while `ChunkDebugOverlay::culling` is on, toggled
+/// with F4.
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryCulledChunks {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryCulledChunks {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryCulledChunks {
+    type Value = (usize, usize);
+    type SystemParam = (
+        SRes<ChunkDebugOverlay>,
+        SQuery<(&'static ViewVisibility, &'static TerrainQuads), With<Chunk>>,
+    );
+
+    fn label(&self) -> &str {
+        "Culled Chunks"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        let (overlay, q_chunks) = param;
+        if !overlay.culling {
+            return None;
+        }
+        let culled = q_chunks
+            .iter()
+            .filter(|(visibility, _)| !visibility.get())
+            .fold((0, 0), |(chunks, quads), (_, chunk_quads)| {
+                (chunks + 1, quads + chunk_quads.0.len())
+            });
+        Some(culled)
+    }
+
+    fn format_value(&self, (chunks, quads): &Self::Value) -> String {
+        format!("{} ({} quads)", chunks, quads)
+    }
+}
+
+/// In flight tasks computing `T`
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryComputeTasks<T: Component> {
+    pub label: &'static str,
+    pub sort_key: i32,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Component> PerfUiEntryComputeTasks<T> {
+    fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Component> PerfUiEntry for PerfUiEntryComputeTasks<T> {
+    type Value = usize;
+    type SystemParam = SRes<ComputeTaskStats<T>>;
+
+    fn label(&self) -> &str {
+        self.label
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        Some(param.in_flight)
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{}", value)
+    }
+}
+
+/// Time spent in `Subsystem::ALL[INDEX]` in the last frame. Indexed so that
+/// each subsystem's entry is a different component.
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntrySubsystemTime<const INDEX: usize> {
+    pub sort_key: i32,
+}
+
+impl<const INDEX: usize> Default for PerfUiEntrySubsystemTime<INDEX> {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl<const INDEX: usize> PerfUiEntry for PerfUiEntrySubsystemTime<INDEX> {
+    type Value = f64;
+    type SystemParam = SRes<SubsystemTimings>;
+
+    fn label(&self) -> &str {
+        Subsystem::ALL[INDEX].label()
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        Some(param.get(Subsystem::ALL[INDEX]).as_secs_f64() * 1000.0)
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{:.2} ms", value)
+    }
+}
+
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryCameraChunk {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryCameraChunk {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryCameraChunk {
+    type Value = IVec3;
+    type SystemParam = SQuery<&'static GlobalTransform, With<Camera3d>>;
+
+    fn label(&self) -> &str {
+        "Camera Chunk"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        param
+            .single()
+            .map(|t| {
+                world_gen::block_pos_containing(t.translation())
+                    .div_euclid(IVec3::splat(CHUNK_SIZE as i32))
+            })
+            .ok()
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{} / {} / {}", value.x, value.y, value.z)
+    }
+}
+
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryCameraBlock {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryCameraBlock {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryCameraBlock {
+    type Value = IVec3;
+    type SystemParam = SQuery<&'static GlobalTransform, With<Camera3d>>;
+
+    fn label(&self) -> &str {
+        "Camera Block"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        param
+            .single()
+            .map(|t| world_gen::block_pos_containing(t.translation()))
+            .ok()
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{} / {} / {}", value.x, value.y, value.z)
+    }
+}
+
+/// Block under the crosshair and the face the camera is looking at
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryTargetedBlock {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryTargetedBlock {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryTargetedBlock {
+    type Value = TargetedBlock;
+    type SystemParam = Option<SRes<TargetedBlock>>;
+
+    fn label(&self) -> &str {
+        "Targeted Block"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        param.as_deref().copied()
+    }
+
+    fn format_value(&self, target: &Self::Value) -> String {
+        let face = match target.face {
+            Some(Normal::PosX) => "+X",
+            Some(Normal::NegX) => "-X",
+            Some(Normal::PosY) => "+Y",
+            Some(Normal::NegY) => "-Y",
+            Some(Normal::PosZ) => "+Z",
+            Some(Normal::NegZ) => "-Z",
+            None => "inside",
+        };
+        let pos = target.pos;
+        format!(
+            "{:?} at {} / {} / {} ({}, {:.1} away)",
+            target.block, pos.x, pos.y, pos.z, face, target.distance
+        )
+    }
+}
+
+/// Held tool, and how far along breaking the targeted block is
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryBreaking {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryBreaking {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryBreaking {
+    type Value = (ToolTier, Option<(Block, f32)>);
+    type SystemParam = (SRes<HeldTool>, SRes<BreakingProgress>);
+
+    fn label(&self) -> &str {
+        "Breaking"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        let (tool, progress) = param;
+        let target = progress
+            .target()
+            .map(|block| (block, progress.fraction(tool.0)));
+        Some((tool.0, target))
+    }
+
+    fn format_value(&self, (tool, target): &Self::Value) -> String {
+        match target {
+            Some((block, fraction)) => {
+                format!("{:?} {:.0}% ({:?})", block, fraction * 100.0, tool)
+            }
+            None => format!("- ({:?})", tool),
+        }
+    }
+}
+
+/// Block placed on right click
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntrySelectedBlock {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntrySelectedBlock {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntrySelectedBlock {
+    type Value = Block;
+    type SystemParam = SRes<SelectedBlock>;
+
+    fn label(&self) -> &str {
+        "Selected Block"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        Some(param.0)
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{:?}", value)
+    }
+}
+
+/// Biome of the camera's column and the climate it was chosen from
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryBiome {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryBiome {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryBiome {
+    type Value = (Biome, Climate);
+    type SystemParam = (
+        SQuery<&'static GlobalTransform, With<Camera3d>>,
+        Option<SRes<ClimateNoise>>,
+    );
+
+    fn label(&self) -> &str {
+        "Biome"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        let (q_camera, climate_noise) = param;
+        let pos = world_gen::block_pos_containing(q_camera.single().ok()?.translation());
+        let climate = climate_noise.as_ref()?.climate_at(pos.x, pos.z);
+        Some((Biome::from_climate(climate), climate))
+    }
+
+    fn format_value(&self, (biome, climate): &Self::Value) -> String {
+        format!(
+            "{:?} (T {:.2}, H {:.2})",
+            biome, climate.temperature, climate.humidity
+        )
+    }
+}
+
+/// Height of the generated ground below or above the camera
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntrySurfaceHeight {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntrySurfaceHeight {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntrySurfaceHeight {
+    type Value = f32;
+    type SystemParam = (
+        SQuery<&'static GlobalTransform, With<Camera3d>>,
+        SRes<ChunkIndex>,
+        SQuery<&'static HeightNoise>,
+    );
+
+    fn label(&self) -> &str {
+        "Surface Height"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        let (q_camera, chunk_index, q_height_noise) = param;
+        let pos = world_gen::block_pos_containing(q_camera.single().ok()?.translation());
+        world_gen::surface_height_at(chunk_index, q_height_noise, pos)
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{:.2}", value)
+    }
+}
+
+/// Luminance of the light reaching an upward facing surface at the camera.
+/// Blocks don't occlude light, so this is the same everywhere at a given
+/// time of day.
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryLightLevel {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryLightLevel {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryLightLevel {
+    type Value = f32;
+    type SystemParam = (Option<SRes<AmbientLight>>, Option<SRes<DirectionalLight>>);
+
+    fn label(&self) -> &str {
+        "Light Level"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        let (ambient, directional) = param;
+        let ambient = ambient.as_ref().map_or(0.0, |a| a.0.luminance());
+        let directional = directional.as_ref().map_or(0.0, |d| {
+            d.color.luminance() * d.direction.dot(Vec3::Y).max(0.0)
+        });
+        Some(ambient + directional)
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{:.3}", value)
+    }
+}
+
+/// Window over which the meshing throughput is averaged
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+
+/// Meshing totals sampled every frame over the last `THROUGHPUT_WINDOW`
+#[derive(Resource, Default)]
+struct MeshingThroughput {
+    samples: VecDeque<MeshingSample>,
+}
+
+#[derive(Clone, Copy)]
+struct MeshingSample {
+    elapsed: Duration,
+    chunks_meshed: u64,
+    quads_generated: u64,
+    task_time: Duration,
+}
+
+impl MeshingThroughput {
+    /// Seconds covered by the window, and how much each total grew over it
+    fn change(&self) -> Option<(f64, MeshingSample)> {
+        let (first, last) = (self.samples.front()?, self.samples.back()?);
+        let seconds = (last.elapsed - first.elapsed).as_secs_f64();
+        (seconds > 0.0).then(|| {
+            (
+                seconds,
+                MeshingSample {
+                    elapsed: last.elapsed - first.elapsed,
+                    chunks_meshed: last.chunks_meshed - first.chunks_meshed,
+                    quads_generated: last.quads_generated - first.quads_generated,
+                    task_time: last.task_time - first.task_time,
+                },
+            )
+        })
+    }
+}
+
+fn sample_meshing_throughput(
+    time: Res<Time<Real>>,
+    mut throughput: ResMut<MeshingThroughput>,
+    meshing_tasks: Res<ComputeTaskStats<TerrainQuads>>,
+    quads_generated: Res<QuadsGenerated>,
+) {
+    let sample = MeshingSample {
+        elapsed: time.elapsed(),
+        chunks_meshed: meshing_tasks.completed,
+        quads_generated: quads_generated.0,
+        task_time: meshing_tasks.total_task_time,
+    };
+    while throughput
+        .samples
+        .front()
+        .is_some_and(|oldest| sample.elapsed - oldest.elapsed > THROUGHPUT_WINDOW)
+    {
+        throughput.samples.pop_front();
+    }
+    throughput.samples.push_back(sample);
+}
+
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryQuadsPerSecond {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryQuadsPerSecond {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryQuadsPerSecond {
+    type Value = f64;
+    type SystemParam = SRes<MeshingThroughput>;
+
+    fn label(&self) -> &str {
+        "Quads Generated/s"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        let (seconds, change) = param.change()?;
+        Some(change.quads_generated as f64 / seconds)
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{:.0}", value)
+    }
+}
+
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryChunksMeshedPerSecond {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryChunksMeshedPerSecond {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryChunksMeshedPerSecond {
+    type Value = f64;
+    type SystemParam = SRes<MeshingThroughput>;
+
+    fn label(&self) -> &str {
+        "Chunks Meshed/s"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        let (seconds, change) = param.change()?;
+        Some(change.chunks_meshed as f64 / seconds)
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{:.1}", value)
+    }
+}
+
+/// Average time a meshing task took, over the chunks meshed within
+/// `THROUGHPUT_WINDOW`
+#[derive(Component)]
+#[require(PerfUiRoot)]
+struct PerfUiEntryMeshTimePerChunk {
+    pub sort_key: i32,
+}
+
+impl Default for PerfUiEntryMeshTimePerChunk {
+    fn default() -> Self {
+        Self {
+            sort_key: iyes_perf_ui::utils::next_sort_key(),
+        }
+    }
+}
+
+impl PerfUiEntry for PerfUiEntryMeshTimePerChunk {
+    type Value = f64;
+    type SystemParam = SRes<MeshingThroughput>;
+
+    fn label(&self) -> &str {
+        "Mesh Time/Chunk"
+    }
+
+    fn sort_key(&self) -> i32 {
+        self.sort_key
+    }
+
+    fn update_value(
+        &self,
+        param: &mut <Self::SystemParam as bevy::ecs::system::SystemParam>::Item<'_, '_>,
+    ) -> Option<Self::Value> {
+        let (_, change) = param.change()?;
+        (change.chunks_meshed > 0)
+            .then(|| change.task_time.as_secs_f64() * 1000.0 / change.chunks_meshed as f64)
+    }
+
+    fn format_value(&self, value: &Self::Value) -> String {
+        format!("{:.2} ms", value)
+    }
+}
@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+use lib_async_component::ComputeInProgress;
+use lib_chunk::{ChunkPosition, FullNeighborhood};
+use lib_render::debug_lines::{DebugLines, DebugOverlaySettings};
+use lib_spatial::CHUNK_SIZE;
+use lib_utils::iter_3d;
+
+use crate::{
+    mesh::TerrainQuads,
+    world_gen::{Blocks, Chunk},
+};
+
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkDebugOverlay>().add_systems(
+            Update,
+            (
+                toggle_overlays,
+                draw_chunk_grid.run_if(|overlay: Res<ChunkDebugOverlay>| overlay.grid),
+                draw_chunk_bounds.run_if(|overlay: Res<ChunkDebugOverlay>| overlay.bounds),
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ChunkDebugOverlay {
+    /// Outline the chunks around the camera
+    pub grid: bool,
+    /// Outline every chunk, coloured by its `ChunkState`
+    pub bounds: bool,
+    /// Count the meshed chunks outside the view in the HUD
+    pub culling: bool,
+}
+
+const GRID_RADIUS: i32 = 1;
+const GRID_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.5);
+
+/// How far along the streaming pipeline a chunk is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkState {
+    /// Height noise or blocks are still being generated
+    Generating,
+    /// Generated, but not meshed yet and some neighbours aren't generated
+    WaitingForNeighbors,
+    /// A meshing task is in flight, or about to be spawned
+    Meshing,
+    Meshed,
+}
+
+impl ChunkState {
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Generating => Color::srgb(1.0, 0.2, 0.2),
+            Self::WaitingForNeighbors => Color::srgb(0.7, 0.3, 1.0),
+            Self::Meshing => Color::srgb(1.0, 0.8, 0.2),
+            Self::Meshed => Color::srgb(0.2, 1.0, 0.4),
+        }
+    }
+}
+
+fn toggle_overlays(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut chunk_overlay: ResMut<ChunkDebugOverlay>,
+    mut render_overlay: ResMut<DebugOverlaySettings>,
+) {
+    if keys.just_pressed(KeyCode::F4) {
+        chunk_overlay.culling = !chunk_overlay.culling;
+    }
+    if keys.just_pressed(KeyCode::F5) {
+        chunk_overlay.grid = !chunk_overlay.grid;
+    }
+    if keys.just_pressed(KeyCode::F6) {
+        chunk_overlay.bounds = !chunk_overlay.bounds;
+    }
+    if keys.just_pressed(KeyCode::F7) {
+        render_overlay.shadow_frustum = !render_overlay.shadow_frustum;
+    }
+}
+
+fn draw_chunk_grid(
+    q_camera: Query<&GlobalTransform, With<lib_render::camera::RenderCamera>>,
+    mut debug_lines: ResMut<DebugLines>,
+) {
+    let Ok(transform) = q_camera.single() else {
+        return;
+    };
+    let camera_chunk = (transform.translation() / CHUNK_SIZE as f32)
+        .floor()
+        .as_ivec3();
+    for (x, y, z) in iter_3d(
+        -GRID_RADIUS..=GRID_RADIUS,
+        -GRID_RADIUS..=GRID_RADIUS,
+        -GRID_RADIUS..=GRID_RADIUS,
+    ) {
+        debug_lines.chunk(camera_chunk + IVec3::new(x, y, z), GRID_COLOR);
+    }
+}
+
+fn draw_chunk_bounds(
+    q_chunks: Query<
+        (
+            &ChunkPosition,
+            Has<Blocks>,
+            Has<FullNeighborhood<Blocks>>,
+            Has<TerrainQuads>,
+            Has<ComputeInProgress<TerrainQuads>>,
+        ),
+        With<Chunk>,
+    >,
+    mut debug_lines: ResMut<DebugLines>,
+) {
+    for (chunk_pos, has_blocks, has_neighbors, has_quads, meshing) in q_chunks.iter() {
+        let state = if !has_blocks {
+            ChunkState::Generating
+        } else if meshing {
+            ChunkState::Meshing
+        } else if has_quads {
+            ChunkState::Meshed
+        } else if !has_neighbors {
+            ChunkState::WaitingForNeighbors
+        } else {
+            ChunkState::Meshing
+        };
+        debug_lines.chunk(chunk_pos.0, state.color());
+    }
+}
@@ -0,0 +1,122 @@
+use bevy::prelude::*;
+
+use crate::{
+    biome::{Biome, ClimateNoise},
+    world_gen,
+};
+
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EnvironmentBlend>().add_systems(
+            Update,
+            blend_environment.before(crate::time_of_day::update_lighting),
+        );
+    }
+}
+
+/// Multipliers applied to the lighting of the time of day
+#[derive(Clone, Copy, Debug)]
+pub struct EnvironmentTint {
+    pub ambient: Color,
+    pub directional: Color,
+    /// Also tints the sky, so distant terrain still fades into it
+    pub fog: Color,
+}
+
+impl Default for EnvironmentTint {
+    fn default() -> Self {
+        Self {
+            ambient: Color::WHITE,
+            directional: Color::WHITE,
+            fog: Color::WHITE,
+        }
+    }
+}
+
+impl EnvironmentTint {
+    pub fn of_biome(biome: Biome) -> Self {
+        match biome {
+            Biome::Plains => Self::default(),
+            Biome::Forest => Self {
+                ambient: Color::linear_rgb(0.85, 1.0, 0.85),
+                directional: Color::linear_rgb(0.95, 1.0, 0.9),
+                fog: Color::linear_rgb(0.8, 0.95, 0.8),
+            },
+            Biome::Desert => Self {
+                ambient: Color::linear_rgb(1.1, 1.0, 0.85),
+                directional: Color::linear_rgb(1.1, 1.0, 0.85),
+                fog: Color::linear_rgb(1.3, 1.1, 0.8),
+            },
+            Biome::Tundra => Self {
+                ambient: Color::linear_rgb(0.9, 0.95, 1.15),
+                directional: Color::linear_rgb(0.9, 0.95, 1.1),
+                fog: Color::linear_rgb(1.2, 1.25, 1.4),
+            },
+        }
+    }
+
+    fn mix(&self, other: &Self, factor: f32) -> Self {
+        Self {
+            ambient: self.ambient.mix(&other.ambient, factor),
+            directional: self.directional.mix(&other.directional, factor),
+            fog: self.fog.mix(&other.fog, factor),
+        }
+    }
+}
+
+/// Multiplies each channel of `color` by `tint`
+pub fn apply_tint(color: Color, tint: Color) -> Color {
+    let color = color.to_linear();
+    let tint = tint.to_linear();
+    Color::linear_rgba(
+        color.red * tint.red,
+        color.green * tint.green,
+        color.blue * tint.blue,
+        color.alpha,
+    )
+}
+
+/// Eases the lighting towards the tint of the biome the camera is in
+#[derive(Resource)]
+pub struct EnvironmentBlend {
+    tint: EnvironmentTint,
+    /// Time taken to get most of the way to a new biome's tint
+    pub transition_seconds: f32,
+}
+
+impl Default for EnvironmentBlend {
+    fn default() -> Self {
+        Self {
+            tint: default(),
+            transition_seconds: 3.0,
+        }
+    }
+}
+
+impl EnvironmentBlend {
+    pub fn tint(&self) -> EnvironmentTint {
+        self.tint
+    }
+}
+
+fn blend_environment(
+    mut blend: ResMut<EnvironmentBlend>,
+    climate_noise: Option<Res<ClimateNoise>>,
+    q_camera: Query<&GlobalTransform, With<lib_render::camera::RenderCamera>>,
+    time: Res<Time>,
+) {
+    let (Some(climate_noise), Ok(camera_transform)) = (climate_noise, q_camera.single()) else {
+        return;
+    };
+    let position = world_gen::block_pos_containing(camera_transform.translation());
+    let biome = climate_noise.biome_at(position.x, position.z);
+    let target = EnvironmentTint::of_biome(biome);
+    let factor = if blend.transition_seconds > 0.0 {
+        1.0 - (-time.delta_secs() / blend.transition_seconds).exp()
+    } else {
+        1.0
+    };
+    blend.tint = blend.tint.mix(&target, factor);
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    math::cubic_splines::{CubicCardinalSpline, CubicCurve, CyclicCubicGenerator},
+    prelude::*,
+    time::TimeUpdateStrategy,
+    transform::TransformSystem,
+};
+
+use crate::{
+    game_state::GameState,
+    mesh::{MeshingType, TerrainQuads},
+    metrics_log::{MetricsRecorder, json_escape},
+    persistence::{self, SaveDirectory},
+    player::{MovementMode, Player, PlayerCamera},
+    world_gen::{Blocks, Chunk, LoadRadius, WorldSeed, WorldType},
+};
+
+/// Flies the player around `ROUTE` on a new world with `FLY_THROUGH_SEED`,
+/// taking the same fixed time step each frame, so every run does the same
+/// work. Once around, writes the frame rate and how long chunks took to
+/// generate and mesh to the metrics folder, then exits.
+pub struct FlyThroughPlugin;
+
+impl Plugin for FlyThroughPlugin {
+    fn build(&self, app: &mut App) {
+        let route = CubicCardinalSpline::new_catmull_rom(ROUTE)
+            .to_curve_cyclic()
+            .expect("the route has enough points");
+        app.insert_resource(WorldSeed(FLY_THROUGH_SEED))
+            .insert_resource(WorldType::Hills)
+            .insert_resource(SaveDirectory(
+                persistence::scratch_directory("fly-through").join("world"),
+            ))
+            .insert_resource(FlyThrough {
+                route,
+                frame: None,
+                frame_times: vec![],
+            })
+            .init_resource::<ChunkLatencies>()
+            .add_systems(OnEnter(GameState::Playing), start_flying)
+            .add_systems(Update, track_chunk_latencies)
+            .add_systems(
+                PostUpdate,
+                fly_along_route
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+const FLY_THROUGH_SEED: u32 = 0xF1ED_0001;
+
+/// Points the route loops through, within the default load radius and above
+/// the hills
+const ROUTE: [Vec3; 7] = [
+    Vec3::new(0.0, 24.0, 0.0),
+    Vec3::new(120.0, 30.0, -60.0),
+    Vec3::new(200.0, 40.0, 80.0),
+    Vec3::new(60.0, 22.0, 180.0),
+    Vec3::new(-140.0, 36.0, 140.0),
+    Vec3::new(-200.0, 28.0, -40.0),
+    Vec3::new(-80.0, 24.0, -170.0),
+];
+
+/// Simulated time each frame takes, whatever it really took
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+/// Frames to fly once around the route in, a minute of simulated time
+const ROUTE_FRAMES: u32 = 3600;
+
+#[derive(Resource)]
+struct FlyThrough {
+    route: CubicCurve<Vec3>,
+    /// Frames flown so far, or `None` before the world is loaded
+    frame: Option<u32>,
+    /// Real time each frame flown took
+    frame_times: Vec<Duration>,
+}
+
+/// How long each chunk took to generate since it was spawned, and to mesh
+/// since it was generated
+#[derive(Resource, Default)]
+struct ChunkLatencies {
+    spawned: HashMap<Entity, Instant>,
+    generated: HashMap<Entity, Instant>,
+    generation: Vec<Duration>,
+    meshing: Vec<Duration>,
+}
+
+fn start_flying(
+    mut commands: Commands,
+    mut fly_through: ResMut<FlyThrough>,
+    mut q_player: Query<&mut MovementMode, With<Player>>,
+) {
+    // Unpausing enters playing again
+    if fly_through.frame.is_some() {
+        return;
+    }
+    info!("Flying through the world for {} frames", ROUTE_FRAMES);
+    fly_through.frame = Some(0);
+    commands.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_TIME));
+    for mut mode in q_player.iter_mut() {
+        *mode = MovementMode::Flying;
+    }
+}
+
+/// Places the player on the route, looking along it, over whatever they
+/// were moved to by the input
+fn fly_along_route(
+    time: Res<Time<Real>>,
+    mut fly_through: ResMut<FlyThrough>,
+    mut q_player: Query<&mut Transform, (With<Player>, Without<PlayerCamera>)>,
+    mut q_camera: Query<(&mut Transform, &PlayerCamera), Without<Player>>,
+    results: FlyThroughResults,
+) {
+    let Some(frame) = fly_through.frame else {
+        return;
+    };
+    if frame > 0 {
+        fly_through.frame_times.push(time.delta());
+    }
+    if frame == ROUTE_FRAMES {
+        results.write(&fly_through);
+        fly_through.frame = Some(frame + 1);
+        return;
+    }
+    if frame > ROUTE_FRAMES {
+        return;
+    }
+    fly_through.frame = Some(frame + 1);
+
+    let t = frame as f32 / ROUTE_FRAMES as f32 * fly_through.route.segments().len() as f32;
+    let eyes = fly_through.route.position(t);
+    let direction = fly_through.route.velocity(t).normalize_or(Vec3::X);
+    for (mut camera_transform, camera) in q_camera.iter_mut() {
+        camera_transform.rotation = Transform::IDENTITY.looking_to(direction, Vec3::Y).rotation;
+        for mut transform in q_player.iter_mut() {
+            transform.translation = eyes - Vec3::Y * camera.eye_height;
+        }
+    }
+}
+
+fn track_chunk_latencies(
+    mut latencies: ResMut<ChunkLatencies>,
+    q_spawned: Query<Entity, Added<Chunk>>,
+    q_generated: Query<Entity, (With<Chunk>, Added<Blocks>)>,
+    q_meshed: Query<Entity, (With<Chunk>, Added<TerrainQuads>)>,
+) {
+    let now = Instant::now();
+    let latencies = &mut *latencies;
+    for entity in q_spawned.iter() {
+        latencies.spawned.insert(entity, now);
+    }
+    for entity in q_generated.iter() {
+        // Only the first time the chunk got its blocks
+        let Some(spawned) = latencies.spawned.remove(&entity) else {
+            continue;
+        };
+        latencies.generation.push(now - spawned);
+        latencies.generated.insert(entity, now);
+    }
+    for entity in q_meshed.iter() {
+        if let Some(generated) = latencies.generated.remove(&entity) {
+            latencies.meshing.push(now - generated);
+        }
+    }
+}
+
+#[derive(bevy::ecs::system::SystemParam)]
+struct FlyThroughResults<'w> {
+    seed: Res<'w, WorldSeed>,
+    meshing: Res<'w, MeshingType>,
+    load_radius: Res<'w, LoadRadius>,
+    latencies: Res<'w, ChunkLatencies>,
+    recorder: Res<'w, MetricsRecorder>,
+    exit: EventWriter<'w, AppExit>,
+}
+
+impl FlyThroughResults<'_> {
+    /// Writes the results as JSON next to the metrics recordings, and exits
+    fn write(mut self, fly_through: &FlyThrough) {
+        let mut json = String::new();
+        let _ = writeln!(json, "{{");
+        let _ = writeln!(json, "  \"seed\": \"{:08x}\",", self.seed.0);
+        let meshing = format!("{:?}", *self.meshing);
+        let _ = writeln!(json, "  \"meshing\": \"{}\",", json_escape(&meshing));
+        let _ = writeln!(
+            json,
+            "  \"load_radius\": [{}, {}],",
+            self.load_radius.horizontal, self.load_radius.vertical
+        );
+        let _ = writeln!(json, "  \"frames\": {},", fly_through.frame_times.len());
+        let (average_fps, low_fps) = frame_rates(&fly_through.frame_times);
+        let _ = writeln!(json, "  \"average_fps\": {:.3},", average_fps);
+        let _ = writeln!(json, "  \"one_percent_low_fps\": {:.3},", low_fps);
+        let _ = writeln!(
+            json,
+            "  \"chunk_generation_ms\": {},",
+            latency_summary(&self.latencies.generation)
+        );
+        let _ = writeln!(
+            json,
+            "  \"chunk_meshing_ms\": {}",
+            latency_summary(&self.latencies.meshing)
+        );
+        let _ = writeln!(json, "}}");
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let directory = &self.recorder.directory;
+        let path = directory.join(format!(
+            "fly-through-{:08x}-{}.json",
+            self.seed.0, timestamp
+        ));
+        let result = std::fs::create_dir_all(directory).and_then(|_| std::fs::write(&path, json));
+        match result {
+            Ok(()) => {
+                info!(
+                    "Flew through at {:.1} FPS, 1% low {:.1}. Wrote the results to {:?}",
+                    average_fps, low_fps, path
+                );
+                self.exit.write(AppExit::Success);
+            }
+            Err(e) => {
+                error!(
+                    "Couldn't write the fly-through results to {:?}: {}",
+                    path, e
+                );
+                self.exit.write(AppExit::error());
+            }
+        }
+    }
+}
+
+/// The average frame rate, and the average of the slowest 1% of frames
+fn frame_rates(frame_times: &[Duration]) -> (f64, f64) {
+    let fps = |frames: &[Duration]| {
+        let total: Duration = frames.iter().sum();
+        if total.is_zero() {
+            0.0
+        } else {
+            frames.len() as f64 / total.as_secs_f64()
+        }
+    };
+    let mut slowest = frame_times.to_vec();
+    slowest.sort_unstable_by(|a, b| b.cmp(a));
+    slowest.truncate(frame_times.len().div_ceil(100));
+    (fps(frame_times), fps(&slowest))
+}
+
+/// An object of the count, mean, median, 99th percentile and maximum, in
+/// milliseconds
+fn latency_summary(latencies: &[Duration]) -> String {
+    let mut millis: Vec<f64> = latencies.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+    millis.sort_unstable_by(f64::total_cmp);
+    let percentile = |p: f64| {
+        let index = ((millis.len() as f64 - 1.0) * p).round() as usize;
+        millis.get(index).copied().unwrap_or(0.0)
+    };
+    let mean = if millis.is_empty() {
+        0.0
+    } else {
+        millis.iter().sum::<f64>() / millis.len() as f64
+    };
+    format!(
+        "{{\"count\":{},\"mean\":{:.3},\"median\":{:.3},\"p99\":{:.3},\"max\":{:.3}}}",
+        millis.len(),
+        mean,
+        percentile(0.5),
+        percentile(0.99),
+        percentile(1.0)
+    )
+}
@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+/// Number of frames shown, one bar each
+const HISTORY_LENGTH: usize = 300;
+const BAR_WIDTH: f32 = 1.0;
+const GRAPH_HEIGHT: f32 = 80.0;
+/// Frame time at the top of the graph. Longer frames are clipped.
+const MAX_FRAME_MILLIS: f32 = 50.0;
+const TARGET_FRAME_MILLIS: f32 = 1000.0 / 60.0;
+
+/// Rolling graph of recent frame times under the HUD, so that hitches stand
+/// out even once the averages have recovered
+pub struct FrameGraphPlugin;
+
+impl Plugin for FrameGraphPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameTimeHistory>()
+            .add_systems(Startup, spawn_frame_graph)
+            .add_systems(Update, (record_frame_time, update_frame_graph).chain());
+    }
+}
+
+/// Durations of the last `HISTORY_LENGTH` frames in milliseconds, oldest
+/// first
+#[derive(Resource)]
+pub struct FrameTimeHistory(pub VecDeque<f32>);
+
+impl Default for FrameTimeHistory {
+    fn default() -> Self {
+        Self(VecDeque::with_capacity(HISTORY_LENGTH))
+    }
+}
+
+#[derive(Component)]
+struct FrameGraphBar(usize);
+
+fn spawn_frame_graph(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.0),
+                bottom: Val::Px(8.0),
+                width: Val::Px(HISTORY_LENGTH as f32 * BAR_WIDTH),
+                height: Val::Px(GRAPH_HEIGHT),
+                align_items: AlignItems::FlexEnd,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        ))
+        .with_children(|parent| {
+            for i in 0..HISTORY_LENGTH {
+                parent.spawn((
+                    FrameGraphBar(i),
+                    Node {
+                        width: Val::Px(BAR_WIDTH),
+                        height: Val::Px(0.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::NONE),
+                ));
+            }
+            // Frames taller than this line missed 60 FPS
+            parent.spawn((
+                Node {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(0.0),
+                    bottom: Val::Px(bar_height(TARGET_FRAME_MILLIS)),
+                    width: Val::Percent(100.0),
+                    height: Val::Px(1.0),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.4)),
+            ));
+        });
+}
+
+fn record_frame_time(time: Res<Time<Real>>, mut history: ResMut<FrameTimeHistory>) {
+    if history.0.len() == HISTORY_LENGTH {
+        history.0.pop_front();
+    }
+    history.0.push_back(time.delta_secs() * 1000.0);
+}
+
+fn update_frame_graph(
+    history: Res<FrameTimeHistory>,
+    mut q_bars: Query<(&FrameGraphBar, &mut Node, &mut BackgroundColor)>,
+) {
+    // The newest frame is always drawn at the right edge
+    let first_bar = HISTORY_LENGTH - history.0.len();
+    for (FrameGraphBar(i), mut node, mut color) in q_bars.iter_mut() {
+        let Some(millis) = i.checked_sub(first_bar).map(|j| history.0[j]) else {
+            continue;
+        };
+        node.height = Val::Px(bar_height(millis));
+        color.0 = bar_color(millis);
+    }
+}
+
+fn bar_height(millis: f32) -> f32 {
+    (millis / MAX_FRAME_MILLIS).min(1.0) * GRAPH_HEIGHT
+}
+
+fn bar_color(millis: f32) -> Color {
+    if millis <= TARGET_FRAME_MILLIS {
+        Color::srgb(0.3, 0.9, 0.3)
+    } else if millis <= 2.0 * TARGET_FRAME_MILLIS {
+        Color::srgb(0.9, 0.8, 0.2)
+    } else {
+        Color::srgb(0.9, 0.2, 0.2)
+    }
+}
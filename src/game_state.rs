@@ -0,0 +1,183 @@
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use lib_chunk::ChunkIndex;
+use lib_first_person_camera::FirstPersonCameraSystems;
+use lib_spatial::CHUNK_SIZE;
+use lib_utils::iter_3d;
+
+use crate::{
+    player::Player,
+    world_gen::{Blocks, Chunk, WorldGenerationSystems, block_pos_containing},
+};
+
+/// Tracks whether the game is in the main menu, loading, playing or paused,
+/// gating the gameplay, world streaming and camera systems on it. Escape
+/// pauses and resumes.
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameState>()
+            .configure_sets(
+                PreUpdate,
+                FirstPersonCameraSystems.run_if(in_state(GameState::Playing)),
+            )
+            .configure_sets(
+                Update,
+                WorldGenerationSystems
+                    .run_if(in_state(GameState::Loading).or(in_state(GameState::Playing))),
+            )
+            .add_systems(
+                Update,
+                (
+                    finish_loading.run_if(in_state(GameState::Loading)),
+                    toggle_pause
+                        .run_if(in_state(GameState::Playing).or(in_state(GameState::Paused))),
+                ),
+            )
+            .add_systems(OnEnter(GameState::Playing), capture_mouse)
+            .add_systems(
+                OnEnter(GameState::Paused),
+                (release_mouse, pause_time, spawn_pause_overlay),
+            )
+            .add_systems(
+                OnExit(GameState::Paused),
+                (unpause_time, despawn_pause_overlay),
+            );
+    }
+}
+
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GameState {
+    /// Choosing a world to create or load
+    #[default]
+    MainMenu,
+    /// Generating the chunks around the player, who can't move yet
+    Loading,
+    Playing,
+    /// Behind the pause overlay, with the cursor released and the virtual
+    /// clock stopped
+    Paused,
+}
+
+/// Chunks around the player's along each axis that have to be generated
+/// before they can move
+const SPAWN_AREA_RADIUS: i32 = 1;
+
+/// Starts playing once the spawn area has its blocks. Chunks outside the
+/// load radius are never spawned, so they aren't waited for. Dedicated
+/// servers have no player, and wait for the centre of the world instead.
+fn finish_loading(
+    chunk_index: Res<ChunkIndex>,
+    q_player: Query<&Transform, With<Player>>,
+    q_chunks: Query<Has<Blocks>, With<Chunk>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if q_chunks.is_empty() {
+        return;
+    }
+    let center = q_player
+        .single()
+        .map_or(Vec3::ZERO, |transform| transform.translation);
+    let player_chunk = block_pos_containing(center).div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+    let r = SPAWN_AREA_RADIUS;
+    let loaded = iter_3d(-r..=r, -r..=r, -r..=r).all(|(x, y, z)| {
+        chunk_index
+            .get_entity(&(player_chunk + IVec3::new(x, y, z)))
+            .is_none_or(|&entity| q_chunks.get(entity).unwrap_or(false))
+    });
+    if loaded {
+        info!("Spawn area generated, starting to play");
+        next_state.set(GameState::Playing);
+    }
+}
+
+fn toggle_pause(
+    keys: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keys.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    next_state.set(match state.get() {
+        GameState::Paused => GameState::Playing,
+        _ => GameState::Paused,
+    });
+}
+
+fn capture_mouse(mut q_windows: Query<&mut Window, With<PrimaryWindow>>) {
+    // No window to capture it in while headless
+    let Ok(mut primary_window) = q_windows.single_mut() else {
+        return;
+    };
+
+    // for a game that doesn't use the cursor (like a shooter):
+    // use `Locked` mode to keep the cursor in one place
+    primary_window.cursor_options.grab_mode = CursorGrabMode::Locked;
+
+    // also hide the cursor
+    primary_window.cursor_options.visible = false;
+}
+
+fn release_mouse(mut q_windows: Query<&mut Window, With<PrimaryWindow>>) {
+    let Ok(mut primary_window) = q_windows.single_mut() else {
+        return;
+    };
+    primary_window.cursor_options.grab_mode = CursorGrabMode::None;
+    primary_window.cursor_options.visible = true;
+}
+
+/// Stops everything driven by `Time`, like the day/night cycle, physics and
+/// particles
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn unpause_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+#[derive(Component)]
+struct PauseOverlay;
+
+fn spawn_pause_overlay(mut commands: Commands) {
+    commands.spawn((
+        PauseOverlay,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(12.0),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+        children![
+            (
+                Text::new("Paused"),
+                TextFont {
+                    font_size: 48.0,
+                    ..default()
+                },
+            ),
+            (
+                Text::new("Press Escape to resume"),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+            ),
+        ],
+    ));
+}
+
+fn despawn_pause_overlay(mut commands: Commands, q_overlay: Query<Entity, With<PauseOverlay>>) {
+    for entity in q_overlay.iter() {
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use lib_render::texture::TextureIndex;
+
+use crate::{
+    biome::Climate, block_placing::SelectedBlock, block_registry::BlockRegistry,
+    inventory::Inventory,
+};
+
+/// Shows the blocks in the `Inventory` along the bottom of the screen, each
+/// with its count, outlining the selected block
+pub struct HotbarPlugin;
+
+impl Plugin for HotbarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_hotbar).add_systems(
+            Update,
+            fill_hotbar.run_if(resource_changed::<Inventory>.or(resource_changed::<SelectedBlock>)),
+        );
+    }
+}
+
+/// Where lib_render loads the terrain textures from
+const TERRAIN_TEXTURE_FOLDER: &str = "textures/terrain";
+
+/// Width and height of each slot, in pixels
+const SLOT_SIZE: f32 = 48.0;
+const SLOT_BORDER: f32 = 3.0;
+const SLOT_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.4);
+const SLOT_BORDER_COLOR: Color = Color::srgba(0.3, 0.3, 0.3, 0.8);
+const SELECTED_SLOT_BORDER_COLOR: Color = Color::WHITE;
+
+#[derive(Component)]
+struct Hotbar;
+
+fn spawn_hotbar(mut commands: Commands) {
+    commands.spawn((
+        Hotbar,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            bottom: Val::Px(12.0),
+            justify_content: JustifyContent::Center,
+            column_gap: Val::Px(4.0),
+            ..default()
+        },
+    ));
+}
+
+fn fill_hotbar(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    inventory: Res<Inventory>,
+    selected: Res<SelectedBlock>,
+    registry: Res<BlockRegistry>,
+    q_hotbar: Query<Entity, With<Hotbar>>,
+) {
+    // Tinted terrain, like leaves, is shown as it would be in a temperate
+    // climate
+    let tint = Climate {
+        temperature: 0.0,
+        humidity: 0.0,
+    }
+    .foliage_tint();
+    for entity in q_hotbar.iter() {
+        commands
+            .entity(entity)
+            .despawn_related::<Children>()
+            .with_children(|parent| {
+                for (id, count) in inventory.stacks() {
+                    let block = registry.block(id);
+                    let border_color = if block == selected.0 {
+                        SELECTED_SLOT_BORDER_COLOR
+                    } else {
+                        SLOT_BORDER_COLOR
+                    };
+                    let icon = block.particle().map(|terrain| {
+                        let path = format!("{TERRAIN_TEXTURE_FOLDER}/{}.png", terrain.get_name());
+                        let color = if terrain.tinted() { tint } else { Color::WHITE };
+                        ImageNode::new(asset_server.load(path)).with_color(color)
+                    });
+                    parent
+                        .spawn((
+                            Node {
+                                width: Val::Px(SLOT_SIZE),
+                                height: Val::Px(SLOT_SIZE),
+                                border: UiRect::all(Val::Px(SLOT_BORDER)),
+                                justify_content: JustifyContent::End,
+                                align_items: AlignItems::End,
+                                ..default()
+                            },
+                            BorderColor(border_color),
+                            BackgroundColor(SLOT_COLOR),
+                        ))
+                        .with_children(|slot| {
+                            if let Some(icon) = icon {
+                                slot.spawn((
+                                    Node {
+                                        position_type: PositionType::Absolute,
+                                        width: Val::Percent(100.0),
+                                        height: Val::Percent(100.0),
+                                        ..default()
+                                    },
+                                    icon,
+                                ));
+                            }
+                            slot.spawn((
+                                Text::new(count.to_string()),
+                                TextFont {
+                                    font_size: 14.0,
+                                    ..default()
+                                },
+                            ));
+                        });
+                }
+            });
+    }
+}
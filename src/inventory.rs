@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    block_breaking::BlockBroken,
+    block_placing::SelectedBlock,
+    block_registry::{BlockId, BlockRegistry},
+};
+
+/// Keeps the blocks the player has broken, which are used up by placing
+/// them
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inventory>()
+            .add_systems(Update, pick_up_broken_blocks);
+    }
+}
+
+/// Number of each block the player holds, by block id
+#[derive(Resource, Clone, Default, Debug)]
+pub struct Inventory {
+    counts: BTreeMap<BlockId, u32>,
+}
+
+impl Inventory {
+    pub fn count(&self, id: BlockId) -> u32 {
+        self.counts.get(&id).copied().unwrap_or(0)
+    }
+
+    pub fn add(&mut self, id: BlockId, count: u32) {
+        if count > 0 {
+            *self.counts.entry(id).or_default() += count;
+        }
+    }
+
+    /// Removes one of `id`, returning whether there was one to remove
+    pub fn take(&mut self, id: BlockId) -> bool {
+        let Some(count) = self.counts.get_mut(&id) else {
+            return false;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.counts.remove(&id);
+        }
+        true
+    }
+
+    /// Every block held, with how many, in order of id
+    pub fn stacks(&self) -> impl Iterator<Item = (BlockId, u32)> + '_ {
+        self.counts.iter().map(|(id, count)| (*id, *count))
+    }
+}
+
+fn pick_up_broken_blocks(
+    mut broken: EventReader<BlockBroken>,
+    registry: Res<BlockRegistry>,
+    mut inventory: ResMut<Inventory>,
+    mut selected: ResMut<SelectedBlock>,
+) {
+    for event in broken.read() {
+        let id = registry.id(event.block);
+        // Picking up something new while holding nothing of the selected
+        // block selects it, so it can be placed straight away
+        if inventory.count(registry.id(selected.0)) == 0 {
+            selected.0 = event.block;
+        }
+        inventory.add(id, 1);
+    }
+}
@@ -0,0 +1,318 @@
+//! The game, as a library so that other crates can extend it. Start it with
+//! `GamePlugins::from_args().run()`, adding blocks, generation passes, a
+//! mesher or console commands with the `register_*` methods in between.
+
+use std::time::Duration;
+
+use bevy::{
+    app::{PluginGroupBuilder, ScheduleRunnerPlugin},
+    prelude::*,
+    window::{ExitCondition, PresentMode},
+    winit::WinitPlugin,
+};
+use lib_chunk::{ChunkIndex, ChunkIndexPlugin, ChunkPosition};
+use lib_first_person_camera::FirstPersonCameraPlugin;
+
+use crate::{
+    block::BlockDefinition,
+    block_registry::CustomBlocksPlugin,
+    cli::{CommandLine, CommandLinePlugin},
+    console::ConsolePlugin,
+    crosshair::CrosshairPlugin,
+    debug_hud::DebugHudPlugin,
+    debug_overlay::DebugOverlayPlugin,
+    mesh::{Mesher, WorldMeshPlugin},
+    metrics_log::MetricsLogPlugin,
+    world_gen::{Blocks, Chunk, GenerationPass, LoadRadius, WorldGenerationPlugin},
+};
+
+mod anvil;
+mod biome;
+pub mod block;
+pub mod block_breaking;
+mod block_particles;
+pub mod block_placing;
+pub mod block_registry;
+mod chunk_compression;
+pub mod cli;
+pub mod console;
+pub mod crosshair;
+mod debug_hud;
+mod debug_overlay;
+mod environment;
+mod fly_through;
+mod frame_graph;
+mod game_state;
+mod hotbar;
+mod inventory;
+mod lighting_panel;
+mod main_menu;
+mod map_preview;
+pub mod mesh;
+mod metrics_export;
+pub mod metrics_log;
+mod network;
+mod persistence;
+mod player;
+mod raycast;
+mod replay;
+mod settings;
+mod sound;
+mod subsystem_timing;
+mod targeting;
+#[cfg(test)]
+mod test_world;
+pub mod time_of_day;
+pub mod world_gen;
+
+/// The game's own plugins, chosen by the command line: everything but
+/// Bevy's, or only what the world needs with `--dedicated-server`. The
+/// dedicated server has no console, so commands registered here go unused
+/// there.
+pub struct GamePlugins {
+    cli: CommandLine,
+    blocks: Vec<BlockDefinition>,
+    world_generation: WorldGenerationPlugin,
+    mesh: WorldMeshPlugin,
+    console: ConsolePlugin,
+}
+
+impl GamePlugins {
+    /// Reads the command line the game was started with. Exits after
+    /// printing the usage if it's wrong or `--help` is given, and after
+    /// writing the map preview for `--preview-map`.
+    pub fn from_args() -> Self {
+        let cli = match CommandLine::parse(std::env::args().skip(1)) {
+            Ok(cli) => cli,
+            Err(e) => {
+                eprintln!("{e}\n\n{}", cli::USAGE);
+                std::process::exit(2);
+            }
+        };
+        if cli.help {
+            println!("{}", cli::USAGE);
+            std::process::exit(0);
+        }
+        if let Some(path) = &cli.preview_map {
+            write_map_preview(&cli, path);
+            std::process::exit(0);
+        }
+        Self::new(cli)
+    }
+
+    pub fn new(cli: CommandLine) -> Self {
+        Self {
+            world_generation: world_generation_plugin(&cli),
+            cli,
+            blocks: vec![],
+            mesh: WorldMeshPlugin::default(),
+            console: ConsolePlugin::default(),
+        }
+    }
+
+    /// Adds a block after the built-in ones, which keeps its id in saves
+    /// like theirs
+    pub fn register_block(mut self, definition: BlockDefinition) -> Self {
+        self.blocks.push(definition);
+        self
+    }
+
+    pub fn register_blocks(
+        mut self,
+        definitions: impl IntoIterator<Item = BlockDefinition>,
+    ) -> Self {
+        self.blocks.extend(definitions);
+        self
+    }
+
+    /// See `WorldGenerationPlugin::register_generation_pass`
+    pub fn register_generation_pass(mut self, pass: impl GenerationPass) -> Self {
+        self.world_generation = self.world_generation.register_generation_pass(pass);
+        self
+    }
+
+    /// See `WorldMeshPlugin::register_mesher`
+    pub fn register_mesher(mut self, name: &'static str, mesher: impl Mesher) -> Self {
+        self.mesh = self.mesh.register_mesher(name, mesher);
+        self
+    }
+
+    /// See `ConsolePlugin::register_console_command`
+    pub fn register_console_command(mut self, name: &'static str, usage: &'static str) -> Self {
+        self.console = self.console.register_console_command(name, usage);
+        self
+    }
+
+    /// Runs the game along with the Bevy plugins it needs, until it exits
+    pub fn run(self) -> AppExit {
+        let mut app = App::new();
+        if self.cli.dedicated_server {
+            let load_radius = LoadRadius {
+                horizontal: self
+                    .cli
+                    .load_radius(LoadRadius::default().horizontal as u32)
+                    as i32,
+                ..default()
+            };
+            app.add_plugins((
+                MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(HEADLESS_FRAME_TIME)),
+                bevy::log::LogPlugin::default(),
+                // Exits through `AppExit` on Ctrl+C, saving the world first
+                bevy::app::TerminalCtrlCHandlerPlugin,
+                bevy::state::app::StatesPlugin,
+                bevy::input::InputPlugin,
+            ))
+            // Only saved, as there's no player to pick anything up
+            .init_resource::<inventory::Inventory>()
+            .insert_resource(load_radius);
+            return app.add_plugins(self).run();
+        }
+        let mut default_plugins = DefaultPlugins.set(if self.cli.headless {
+            WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                ..Default::default()
+            }
+        } else {
+            WindowPlugin {
+                primary_window: Some(Window {
+                    present_mode: PresentMode::AutoNoVsync,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }
+        });
+        if self.cli.headless {
+            // Without winit, something else has to keep updating the app
+            default_plugins = default_plugins.disable::<WinitPlugin>();
+            app.add_plugins(ScheduleRunnerPlugin::run_loop(HEADLESS_FRAME_TIME));
+        }
+        app.add_plugins((default_plugins, self)).run()
+    }
+}
+
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let group = PluginGroupBuilder::start::<Self>()
+            // Before anything makes a `BlockRegistry` without them
+            .add(CustomBlocksPlugin(self.blocks))
+            .add(ChunkIndexPlugin)
+            .add(self.world_generation)
+            .add(persistence::PersistencePlugin)
+            .add(game_state::GameStatePlugin)
+            .add(network::NetworkPlugin)
+            .add(chunk_compression::ChunkCompressionPlugin);
+        if self.cli.dedicated_server {
+            return group.add(CommandLinePlugin(self.cli));
+        }
+        group
+            .add(DebugHudPlugin)
+            .add(DebugOverlayPlugin)
+            .add(MetricsLogPlugin)
+            .add(CrosshairPlugin)
+            .add(targeting::TargetingPlugin)
+            .add(block_breaking::BlockBreakingPlugin)
+            .add(block_placing::BlockPlacingPlugin)
+            .add(block_particles::BlockParticlesPlugin)
+            .add(inventory::InventoryPlugin)
+            .add(hotbar::HotbarPlugin)
+            .add(lib_render::TerrainRenderPlugin::<block::Terrain>::new())
+            .add(FirstPersonCameraPlugin::<lib_render::camera::RenderCamera>::new())
+            .add(player::PlayerPlugin)
+            .add(settings::SettingsPlugin)
+            .add(main_menu::MainMenuPlugin)
+            .add(self.mesh)
+            .add(time_of_day::TimeOfDayPlugin)
+            .add(self.console)
+            .add(biome::BiomePlugin)
+            .add(environment::EnvironmentPlugin)
+            .add(sound::SoundPlugin)
+            .add(TerrainDisplayPlugin)
+            .add(CommandLinePlugin(self.cli))
+    }
+}
+
+/// Time between updates without a window, which would otherwise pace them
+const HEADLESS_FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+/// Hands the chunks and the fluid around the camera to the renderer
+struct TerrainDisplayPlugin;
+
+impl Plugin for TerrainDisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(lib_render::globals::FogSettings {
+            // Coloured by the time of day
+            color: Color::BLACK,
+            b: 0.002,
+            height_falloff: 0.05,
+            base_height: 0.0,
+            underwater: default(),
+        })
+        .insert_resource(lib_render::globals::DepthPrepass(true))
+        .add_systems(Update, (assign_terrain_position, update_camera_in_fluid));
+    }
+}
+
+/// Writes the heightmap and biome map of `--preview-map` without starting
+/// the app
+fn write_map_preview(cli: &CommandLine, path: &std::path::Path) {
+    let preview = map_preview::MapPreview {
+        seed: cli.seed.unwrap_or(world_gen::DEFAULT_SEED),
+        world_type: cli.world_type.unwrap_or_default(),
+        size: cli.preview_size.unwrap_or(512),
+    };
+    match preview.write(path) {
+        Ok((lowest, highest)) => println!(
+            "Wrote {:?} and {:?}, with the ground from {:.1} to {:.1}",
+            path,
+            map_preview::biome_map_path(path),
+            lowest,
+            highest
+        ),
+        Err(e) => {
+            eprintln!("Couldn't write the map preview: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Generates the world, or imports it with `--import-anvil`. Exits if the
+/// Minecraft world or its block mapping can't be opened.
+fn world_generation_plugin(cli: &CommandLine) -> WorldGenerationPlugin {
+    let plugin = WorldGenerationPlugin::default();
+    let Some(folder) = &cli.import_anvil else {
+        return plugin;
+    };
+    match anvil::AnvilImport::open(folder, cli.anvil_blocks.as_deref()) {
+        Ok(import) => plugin.register_generation_pass(import),
+        Err(e) => {
+            eprintln!("Couldn't import the Minecraft world in {folder:?}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn assign_terrain_position(
+    mut commands: Commands,
+    q_chunk: Query<(Entity, &ChunkPosition), (With<Chunk>, Without<lib_render::TerrainPosition>)>,
+) {
+    for (entity, chunk_pos) in q_chunk.iter() {
+        let terrain_position = lib_render::TerrainPosition(chunk_pos.0);
+        commands.entity(entity).try_insert(terrain_position);
+    }
+}
+
+fn update_camera_in_fluid(
+    q_camera: Query<&GlobalTransform, With<lib_render::camera::RenderCamera>>,
+    q_blocks: Query<&Blocks>,
+    chunk_index: Res<ChunkIndex>,
+    mut camera_in_fluid: ResMut<lib_render::globals::CameraInFluid>,
+) {
+    let Ok(transform) = q_camera.single() else {
+        return;
+    };
+    let block_pos = world_gen::block_pos_containing(transform.translation());
+    let in_fluid = world_gen::block_at(&chunk_index, &q_blocks, block_pos)
+        .is_some_and(|block| block.is_fluid());
+    camera_in_fluid.set_if_neq(lib_render::globals::CameraInFluid(in_fluid));
+}
@@ -0,0 +1,296 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use lib_render::globals::{AmbientLight, DirectionalLight, FogSettings};
+
+use crate::time_of_day::LightingOverrides;
+
+/// Panel for tuning lighting and fog at runtime. F9 opens it, up and down
+/// select a setting, left and right adjust it, and backspace goes back to
+/// following the time of day.
+pub struct LightingPanelPlugin;
+
+impl Plugin for LightingPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LightingPanel>()
+            .add_systems(Startup, spawn_lighting_panel)
+            .add_systems(
+                Update,
+                (
+                    toggle_lighting_panel,
+                    (adjust_setting, update_lighting_panel_text)
+                        .chain()
+                        .run_if(|panel: Res<LightingPanel>| panel.open),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                update_lighting_panel_visibility.run_if(resource_changed::<LightingPanel>),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+struct LightingPanel {
+    open: bool,
+    selected: usize,
+    /// Fog density and height falloff before the first adjustment, restored
+    /// on reset
+    initial_fog: Option<(f32, f32)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Setting {
+    LightElevation,
+    LightAzimuth,
+    LightRed,
+    LightGreen,
+    LightBlue,
+    AmbientBrightness,
+    FogDensity,
+    FogHeightFalloff,
+}
+
+impl Setting {
+    const ALL: [Self; 8] = [
+        Self::LightElevation,
+        Self::LightAzimuth,
+        Self::LightRed,
+        Self::LightGreen,
+        Self::LightBlue,
+        Self::AmbientBrightness,
+        Self::FogDensity,
+        Self::FogHeightFalloff,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::LightElevation => "Light Elevation",
+            Self::LightAzimuth => "Light Azimuth",
+            Self::LightRed => "Light Red",
+            Self::LightGreen => "Light Green",
+            Self::LightBlue => "Light Blue",
+            Self::AmbientBrightness => "Ambient Brightness",
+            Self::FogDensity => "Fog Density",
+            Self::FogHeightFalloff => "Fog Height Falloff",
+        }
+    }
+
+    /// Change of one key press, and the range the value is kept in
+    fn step_and_range(&self) -> (f32, f32, f32) {
+        match self {
+            Self::LightElevation => (5.0, -90.0, 90.0),
+            Self::LightAzimuth => (5.0, -180.0, 180.0),
+            Self::LightRed | Self::LightGreen | Self::LightBlue => (0.05, 0.0, 2.0),
+            Self::AmbientBrightness => (0.01, 0.0, 1.0),
+            Self::FogDensity => (0.0005, 0.0, 0.05),
+            Self::FogHeightFalloff => (0.005, 0.0, 1.0),
+        }
+    }
+}
+
+/// The resources written back to, which the renderer extracts into its
+/// globals
+#[derive(SystemParam)]
+struct LightingSettings<'w> {
+    ambient: Option<Res<'w, AmbientLight>>,
+    directional: Option<Res<'w, DirectionalLight>>,
+    overrides: ResMut<'w, LightingOverrides>,
+    fog: Option<ResMut<'w, FogSettings>>,
+}
+
+impl LightingSettings<'_> {
+    /// Direction towards the light, as elevation and azimuth in degrees
+    fn light_angles(&self) -> Option<(f32, f32)> {
+        let towards_light = -*self.directional.as_ref()?.direction;
+        Some((
+            towards_light.y.clamp(-1.0, 1.0).asin().to_degrees(),
+            towards_light.z.atan2(towards_light.x).to_degrees(),
+        ))
+    }
+
+    fn light_color(&self) -> Option<LinearRgba> {
+        Some(self.directional.as_ref()?.color.to_linear())
+    }
+
+    fn get(&self, setting: Setting) -> Option<f32> {
+        match setting {
+            Setting::LightElevation => self.light_angles().map(|(elevation, _)| elevation),
+            Setting::LightAzimuth => self.light_angles().map(|(_, azimuth)| azimuth),
+            Setting::LightRed => self.light_color().map(|c| c.red),
+            Setting::LightGreen => self.light_color().map(|c| c.green),
+            Setting::LightBlue => self.light_color().map(|c| c.blue),
+            Setting::AmbientBrightness => {
+                let ambient = self.ambient.as_ref()?.0.to_linear();
+                Some((ambient.red + ambient.green + ambient.blue) / 3.0)
+            }
+            Setting::FogDensity => Some(self.fog.as_ref()?.b),
+            Setting::FogHeightFalloff => Some(self.fog.as_ref()?.height_falloff),
+        }
+    }
+
+    fn set(&mut self, setting: Setting, value: f32) {
+        match setting {
+            Setting::LightElevation | Setting::LightAzimuth => {
+                let Some((mut elevation, mut azimuth)) = self.light_angles() else {
+                    return;
+                };
+                if setting == Setting::LightElevation {
+                    elevation = value;
+                } else {
+                    azimuth = value;
+                }
+                let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+                let towards_light = Vec3::new(
+                    elevation.cos() * azimuth.cos(),
+                    elevation.sin(),
+                    elevation.cos() * azimuth.sin(),
+                );
+                self.overrides.directional_direction = Dir3::new(-towards_light).ok();
+            }
+            Setting::LightRed | Setting::LightGreen | Setting::LightBlue => {
+                let Some(mut color) = self.light_color() else {
+                    return;
+                };
+                match setting {
+                    Setting::LightRed => color.red = value,
+                    Setting::LightGreen => color.green = value,
+                    _ => color.blue = value,
+                }
+                self.overrides.directional_color = Some(color.into());
+            }
+            Setting::AmbientBrightness => {
+                let current = self.get(setting).unwrap_or_default();
+                let ambient = match self.ambient.as_ref() {
+                    // Keeps the tint of the current ambient light
+                    Some(ambient) if current > 0.0 => {
+                        (ambient.0.to_linear() * (value / current)).into()
+                    }
+                    _ => Color::linear_rgb(value, value, value),
+                };
+                self.overrides.ambient = Some(ambient);
+            }
+            Setting::FogDensity => {
+                if let Some(fog) = self.fog.as_mut() {
+                    fog.b = value;
+                }
+            }
+            Setting::FogHeightFalloff => {
+                if let Some(fog) = self.fog.as_mut() {
+                    fog.height_falloff = value;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+struct LightingPanelText;
+
+fn spawn_lighting_panel(mut commands: Commands) {
+    commands.spawn((
+        LightingPanelText,
+        Text::default(),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(8.0),
+            top: Val::Px(8.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        Visibility::Hidden,
+    ));
+}
+
+fn toggle_lighting_panel(keys: Res<ButtonInput<KeyCode>>, mut panel: ResMut<LightingPanel>) {
+    if keys.just_pressed(KeyCode::F9) {
+        panel.open = !panel.open;
+    }
+}
+
+fn update_lighting_panel_visibility(
+    panel: Res<LightingPanel>,
+    mut q_text: Query<&mut Visibility, With<LightingPanelText>>,
+) {
+    for mut visibility in q_text.iter_mut() {
+        *visibility = if panel.open {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn adjust_setting(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut panel: ResMut<LightingPanel>,
+    mut settings: LightingSettings,
+) {
+    let num_settings = Setting::ALL.len();
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        panel.selected = (panel.selected + 1) % num_settings;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        panel.selected = (panel.selected + num_settings - 1) % num_settings;
+    }
+    if keys.just_pressed(KeyCode::Backspace) {
+        *settings.overrides = LightingOverrides::default();
+        if let (Some((b, height_falloff)), Some(fog)) =
+            (panel.initial_fog.take(), settings.fog.as_mut())
+        {
+            fog.b = b;
+            fog.height_falloff = height_falloff;
+        }
+        return;
+    }
+
+    let direction = match (
+        keys.just_pressed(KeyCode::ArrowLeft),
+        keys.just_pressed(KeyCode::ArrowRight),
+    ) {
+        (true, false) => -1.0,
+        (false, true) => 1.0,
+        _ => return,
+    };
+    let setting = Setting::ALL[panel.selected];
+    let Some(value) = settings.get(setting) else {
+        return;
+    };
+    if panel.initial_fog.is_none() {
+        panel.initial_fog = settings.fog.as_ref().map(|fog| (fog.b, fog.height_falloff));
+    }
+    let (step, min, max) = setting.step_and_range();
+    settings.set(setting, (value + direction * step).clamp(min, max));
+}
+
+fn update_lighting_panel_text(
+    panel: Res<LightingPanel>,
+    settings: LightingSettings,
+    mut q_text: Query<&mut Text, With<LightingPanelText>>,
+) {
+    let mut lines: Vec<String> = Setting::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, setting)| {
+            let cursor = if i == panel.selected { ">" } else { " " };
+            let value = settings
+                .get(*setting)
+                .map_or("-".to_string(), |value| format!("{:.4}", value));
+            format!("{} {}: {}", cursor, setting.label(), value)
+        })
+        .collect();
+    let overrides = &settings.overrides;
+    if overrides.directional_color.is_some()
+        || overrides.directional_direction.is_some()
+        || overrides.ambient.is_some()
+    {
+        lines.push("Overriding the time of day (backspace to reset)".into());
+    }
+    for mut text in q_text.iter_mut() {
+        text.0 = lines.join("\n");
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    prelude::*,
+};
+
+use crate::{
+    cli::parse_seed,
+    game_state::GameState,
+    persistence::{self, SAVES_FOLDER, SaveDirectory},
+    world_gen::{WorldSeed, WorldType},
+};
+
+/// Shown on startup to create a new world or load one from `SAVES_FOLDER`.
+/// Either way the game moves on to loading it. The seed of a new world is
+/// typed in, and left empty for a random one.
+pub struct MainMenuPlugin;
+
+impl Plugin for MainMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NewWorldOptions>()
+            .add_systems(OnEnter(GameState::MainMenu), spawn_main_menu)
+            .add_systems(
+                Update,
+                (
+                    type_seed,
+                    highlight_buttons,
+                    press_buttons,
+                    update_new_world_labels.run_if(resource_changed::<NewWorldOptions>),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::MainMenu)),
+            )
+            .add_systems(OnExit(GameState::MainMenu), despawn_main_menu);
+    }
+}
+
+#[derive(Resource, Default)]
+struct NewWorldOptions {
+    /// As typed, in decimal or 0x hex. Empty for a random seed.
+    seed: String,
+    world_type: WorldType,
+}
+
+/// Longest seed that can be typed, which fits any `u32` in hex
+const MAX_SEED_LENGTH: usize = 10;
+
+const BACKGROUND_COLOR: Color = Color::srgb(0.1, 0.1, 0.12);
+const BUTTON_COLOR: Color = Color::srgb(0.2, 0.2, 0.25);
+const HOVERED_BUTTON_COLOR: Color = Color::srgb(0.3, 0.3, 0.35);
+const PRESSED_BUTTON_COLOR: Color = Color::srgb(0.4, 0.4, 0.5);
+
+#[derive(Component)]
+struct MainMenu;
+
+#[derive(Component)]
+enum MenuButton {
+    CycleWorldType,
+    CreateWorld,
+    LoadWorld(PathBuf),
+}
+
+#[derive(Component)]
+struct SeedLabel;
+
+#[derive(Component)]
+struct WorldTypeLabel;
+
+fn spawn_main_menu(mut commands: Commands) {
+    let saved_worlds = persistence::saved_worlds();
+    commands
+        .spawn((
+            MainMenu,
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            BackgroundColor(BACKGROUND_COLOR),
+            // Above the rest of the UI, like the hotbar
+            GlobalZIndex(1),
+        ))
+        .with_children(|parent| {
+            parent.spawn(heading("New world"));
+            parent.spawn((SeedLabel, Text::default()));
+            parent.spawn(button(
+                MenuButton::CycleWorldType,
+                (WorldTypeLabel, Text::default()),
+            ));
+            parent.spawn(button(MenuButton::CreateWorld, Text::new("Create")));
+            parent.spawn(heading("Saved worlds"));
+            if saved_worlds.is_empty() {
+                parent.spawn(Text::new("None yet"));
+            }
+            for path in saved_worlds {
+                let name = path
+                    .file_name()
+                    .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+                parent.spawn(button(MenuButton::LoadWorld(path), Text::new(name)));
+            }
+        });
+}
+
+fn heading(text: &str) -> impl Bundle {
+    (
+        Text::new(text),
+        TextFont {
+            font_size: 32.0,
+            ..default()
+        },
+        Node {
+            margin: UiRect::top(Val::Px(16.0)),
+            ..default()
+        },
+    )
+}
+
+fn button(action: MenuButton, label: impl Bundle) -> impl Bundle {
+    (
+        action,
+        Button,
+        Node {
+            width: Val::Px(240.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(BUTTON_COLOR),
+        children![label],
+    )
+}
+
+fn despawn_main_menu(mut commands: Commands, q_menu: Query<Entity, With<MainMenu>>) {
+    for entity in q_menu.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Takes the characters a seed can have, ignoring anything else
+fn type_seed(mut keys: EventReader<KeyboardInput>, mut options: ResMut<NewWorldOptions>) {
+    for event in keys.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Backspace => {
+                options.seed.pop();
+            }
+            Key::Character(text) => {
+                for c in text.chars() {
+                    if (c.is_ascii_hexdigit() || c == 'x') && options.seed.len() < MAX_SEED_LENGTH {
+                        options.seed.push(c.to_ascii_lowercase());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn highlight_buttons(
+    mut q_buttons: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<Button>),
+    >,
+) {
+    for (interaction, mut color) in q_buttons.iter_mut() {
+        color.0 = match interaction {
+            Interaction::Pressed => PRESSED_BUTTON_COLOR,
+            Interaction::Hovered => HOVERED_BUTTON_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        };
+    }
+}
+
+fn press_buttons(
+    mut commands: Commands,
+    mut options: ResMut<NewWorldOptions>,
+    mut next_state: ResMut<NextState<GameState>>,
+    q_buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+) {
+    for (interaction, action) in q_buttons.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            MenuButton::CycleWorldType => {
+                options.world_type = match options.world_type {
+                    WorldType::Hills => WorldType::Flat,
+                    WorldType::Flat => WorldType::Hills,
+                };
+            }
+            MenuButton::CreateWorld => {
+                let seed = if options.seed.is_empty() {
+                    random_seed()
+                } else {
+                    match parse_seed(&options.seed) {
+                        Ok(seed) => seed,
+                        Err(e) => {
+                            warn!("Not creating a world: {}", e);
+                            continue;
+                        }
+                    }
+                };
+                let directory = new_world_directory();
+                info!("Creating a world in {:?} with seed {}", directory, seed);
+                commands.insert_resource(WorldSeed(seed));
+                commands.insert_resource(options.world_type);
+                commands.insert_resource(SaveDirectory(directory));
+                next_state.set(GameState::Loading);
+            }
+            MenuButton::LoadWorld(path) => {
+                commands.insert_resource(SaveDirectory(path.clone()));
+                next_state.set(GameState::Loading);
+            }
+        }
+    }
+}
+
+fn update_new_world_labels(
+    options: Res<NewWorldOptions>,
+    mut q_seed: Query<&mut Text, (With<SeedLabel>, Without<WorldTypeLabel>)>,
+    mut q_world_type: Query<&mut Text, (With<WorldTypeLabel>, Without<SeedLabel>)>,
+) {
+    let seed = if options.seed.is_empty() {
+        "random, or type one"
+    } else {
+        &options.seed
+    };
+    for mut text in q_seed.iter_mut() {
+        text.0 = format!("Seed: {seed}");
+    }
+    let world_type: &str = options.world_type.into();
+    for mut text in q_world_type.iter_mut() {
+        text.0 = format!("Terrain: {world_type}");
+    }
+}
+
+fn random_seed() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.subsec_nanos() ^ since.as_secs() as u32)
+}
+
+/// A folder in `SAVES_FOLDER` that nothing is saved in yet
+fn new_world_directory() -> PathBuf {
+    let mut number = 1;
+    loop {
+        let name = match number {
+            1 => "world".to_owned(),
+            _ => format!("world-{number}"),
+        };
+        let path = Path::new(SAVES_FOLDER).join(name);
+        if !path.exists() {
+            return path;
+        }
+        number += 1;
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use lib_chunk::ChunkPosition;
+use lib_spatial::CHUNK_SIZE;
+use lib_utils::square_iter;
+
+use crate::{
+    biome::{Biome, ClimateNoise},
+    world_gen::{HeightNoise, HeightNoiseGenerator, WorldType},
+};
+
+/// Top-down map of the ground the world generator shapes, without starting
+/// the game, to tune the noise quickly. Each pixel is a column of blocks.
+pub struct MapPreview {
+    pub seed: u32,
+    pub world_type: WorldType,
+    /// Blocks along each side, centred on the middle of the world
+    pub size: u32,
+}
+
+impl MapPreview {
+    /// Writes a greyscale heightmap to `path`, and a map of the biomes
+    /// shaded by height to `biome_map_path(path)`. Returns the lowest and
+    /// highest ground on the map, which the heightmap is stretched between.
+    pub fn write(&self, path: &Path) -> Result<(f32, f32), String> {
+        let heights = self.heights();
+        let (lowest, highest) = heights
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), h| (lo.min(*h), hi.max(*h)));
+        // Flat worlds have nothing to stretch
+        let range = (highest - lowest).max(f32::EPSILON);
+        let brightness = |height: f32| (height - lowest) / range;
+
+        let climate = ClimateNoise::new(self.seed);
+        let half = self.size as i32 / 2;
+        let mut height_pixels = Vec::with_capacity(heights.len() * 4);
+        let mut biome_pixels = Vec::with_capacity(heights.len() * 4);
+        for (i, height) in heights.iter().enumerate() {
+            let x = (i as u32 % self.size) as i32 - half;
+            let z = (i as u32 / self.size) as i32 - half;
+            let brightness = brightness(*height);
+            let grey = (brightness * 255.0).round() as u8;
+            height_pixels.extend([grey, grey, grey, 255]);
+            let shade = 0.5 + 0.5 * brightness;
+            let [r, g, b, _] = biome_color(climate.biome_at(x, z)).to_srgba().to_u8_array();
+            biome_pixels.extend([r, g, b].map(|c| (c as f32 * shade).round() as u8));
+            biome_pixels.push(255);
+        }
+        save_png(&height_pixels, self.size, path)?;
+        save_png(&biome_pixels, self.size, &biome_map_path(path))?;
+        Ok((lowest, highest))
+    }
+
+    /// Height of the ground in each column, row by row along +z, generated
+    /// a chunk at a time like the game does
+    fn heights(&self) -> Vec<f32> {
+        let generator = HeightNoiseGenerator::new(self.seed);
+        let size = self.size as i32;
+        let min = -size / 2;
+        let chunk_size = CHUNK_SIZE as i32;
+        let mut heights = vec![0.0; (size * size) as usize];
+        let chunks = min.div_euclid(chunk_size)..=(min + size - 1).div_euclid(chunk_size);
+        for chunk_x in chunks.clone() {
+            for chunk_z in chunks.clone() {
+                let chunk_position = ChunkPosition(IVec3::new(chunk_x, 0, chunk_z));
+                let noise =
+                    HeightNoise::generate(chunk_position, self.world_type, generator.clone());
+                for (x, z) in square_iter(0..chunk_size) {
+                    let pixel_x = chunk_x * chunk_size + x - min;
+                    let pixel_z = chunk_z * chunk_size + z - min;
+                    if !(0..size).contains(&pixel_x) || !(0..size).contains(&pixel_z) {
+                        continue;
+                    }
+                    heights[(pixel_z * size + pixel_x) as usize] =
+                        noise.surface_height(x as usize, z as usize);
+                }
+            }
+        }
+        heights
+    }
+}
+
+/// Where the biome map of the heightmap at `path` is written, e.g.
+/// `map-biomes.png` next to `map.png`
+pub fn biome_map_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}-biomes.png"))
+}
+
+fn biome_color(biome: Biome) -> Color {
+    match biome {
+        Biome::Plains => Color::srgb(0.45, 0.7, 0.3),
+        Biome::Forest => Color::srgb(0.15, 0.45, 0.15),
+        Biome::Desert => Color::srgb(0.85, 0.78, 0.5),
+        Biome::Tundra => Color::srgb(0.85, 0.9, 0.95),
+    }
+}
+
+fn save_png(pixels: &[u8], size: u32, path: &Path) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("{parent:?}: {e}"))?;
+    }
+    let image = Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        pixels.to_vec(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image
+        .try_into_dynamic()
+        .map_err(|e| format!("{path:?}: {e}"))?
+        .to_rgba8()
+        .save(path)
+        .map_err(|e| format!("{path:?}: {e}"))
+}
@@ -1,22 +1,44 @@
-use std::num::NonZero;
+use std::{fmt, num::NonZero, sync::Arc};
 
 use bevy::prelude::*;
 use lib_async_component::{AsyncComponentPlugin, ComputeTasks};
-use lib_chunk::Neighborhood;
+use lib_chunk::{ChunkPosition, Neighborhood};
 use lib_utils::cube_iter;
 
 use crate::{
-    block::Terrain,
+    block::{BlockModel, BlockTag, Terrain},
     world_gen::{Blocks, Chunk},
 };
 
-use lib_render::Normal;
+use lib_render::{Normal, QuadShape};
 
-pub struct WorldMeshPlugin;
+/// Meshes each chunk into quads with its neighbourhood's blocks, greedily
+/// unless told otherwise
+pub struct WorldMeshPlugin {
+    meshing: MeshingType,
+}
+
+impl Default for WorldMeshPlugin {
+    fn default() -> Self {
+        Self {
+            meshing: MeshingType::Greedy,
+        }
+    }
+}
+
+impl WorldMeshPlugin {
+    /// Meshes chunks with `mesher` instead, going by `name` in the metrics
+    pub fn register_mesher(mut self, name: &'static str, mesher: impl Mesher) -> Self {
+        self.meshing = MeshingType::Custom(name, Arc::new(mesher));
+        self
+    }
+}
 
 impl Plugin for WorldMeshPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<QuadCount>()
+        app.insert_resource(self.meshing.clone())
+            .init_resource::<QuadCount>()
+            .init_resource::<QuadsGenerated>()
             .add_systems(Update, assign_quads)
             .add_observer(update_quad_count_for_despawn)
             .add_observer(update_quad_count_for_replace)
@@ -25,12 +47,17 @@ impl Plugin for WorldMeshPlugin {
     }
 }
 
-type TerrainQuads = lib_render::Quads<Terrain>;
-type TerrainQuad = lib_render::Quad<Terrain>;
+pub(crate) type TerrainQuads = lib_render::Quads<Terrain>;
+pub type TerrainQuad = lib_render::Quad<Terrain>;
 
 #[derive(Resource, Default)]
 pub struct QuadCount(pub u32);
 
+/// Quads produced by meshing since the app started, including those since
+/// replaced
+#[derive(Resource, Default)]
+pub struct QuadsGenerated(pub u64);
+
 fn update_quad_count_for_despawn(
     trigger: Trigger<OnRemove, TerrainQuads>,
     mut count: ResMut<QuadCount>,
@@ -58,6 +85,7 @@ fn update_quad_count_for_replace(
 fn update_quad_count_for_insert(
     trigger: Trigger<OnInsert, TerrainQuads>,
     mut count: ResMut<QuadCount>,
+    mut generated: ResMut<QuadsGenerated>,
     q_quads: Query<&TerrainQuads>,
 ) {
     let entity = trigger.target();
@@ -65,31 +93,73 @@ fn update_quad_count_for_insert(
         return;
     };
     count.0 += quads.0.len() as u32;
+    generated.0 += quads.0.len() as u64;
 }
 
 #[derive(Resource, Clone)]
 pub enum MeshingType {
     Naive,
+    Greedy,
+    /// Registered with `WorldMeshPlugin::register_mesher`
+    Custom(&'static str, Arc<dyn Mesher>),
+}
+
+impl fmt::Debug for MeshingType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Naive => f.write_str("Naive"),
+            Self::Greedy => f.write_str("Greedy"),
+            Self::Custom(name, _) => f.write_str(name),
+        }
+    }
+}
+
+/// Turns the blocks of the chunk in the middle of a neighbourhood into
+/// quads. Neighbouring chunks that aren't generated yet are `None`, and the
+/// chunk is meshed again once they are.
+pub trait Mesher: Send + Sync + 'static {
+    fn mesh(&self, blocks: &Neighborhood<Blocks>) -> Vec<TerrainQuad>;
+}
+
+impl<F: Fn(&Neighborhood<Blocks>) -> Vec<TerrainQuad> + Send + Sync + 'static> Mesher for F {
+    fn mesh(&self, blocks: &Neighborhood<Blocks>) -> Vec<TerrainQuad> {
+        self(blocks)
+    }
 }
 
 fn assign_quads(
     meshing_type: Res<MeshingType>,
     q_unmeshed_chunks: Query<
-        (Entity, &Neighborhood<Blocks>),
+        (Entity, &ChunkPosition, &Neighborhood<Blocks>),
         (With<Chunk>, Changed<Neighborhood<Blocks>>),
     >,
     mut compute_tasks: ResMut<ComputeTasks<TerrainQuads>>,
 ) {
-    for (entity, blocks) in q_unmeshed_chunks.iter() {
+    for (entity, _chunk_position, blocks) in q_unmeshed_chunks.iter() {
         let blocks = blocks.clone();
         let meshing_type = meshing_type.clone();
-        compute_tasks.spawn_task(entity, async move { get_quads(blocks, meshing_type) });
+        #[cfg(feature = "trace")]
+        let span = info_span!("mesh_chunk", chunk = %_chunk_position.0);
+        compute_tasks.spawn_task(entity, async move {
+            #[cfg(feature = "trace")]
+            let _span = span.entered();
+            get_quads(blocks, meshing_type)
+        });
     }
 }
 
-fn get_quads(blocks: Neighborhood<Blocks>, meshing_type: MeshingType) -> TerrainQuads {
+fn get_quads(mut blocks: Neighborhood<Blocks>, meshing_type: MeshingType) -> TerrainQuads {
+    // Meshing reads every block, so searching compressed chunks for each one
+    // would be far slower than decompressing them for the task
+    for chunk in blocks.chunks.iter_mut().flatten() {
+        if chunk.is_compressed() {
+            Arc::make_mut(chunk).decompress();
+        }
+    }
     let quads = match meshing_type {
         MeshingType::Naive => get_quads_naive(&blocks),
+        MeshingType::Greedy => get_quads_greedy(&blocks),
+        MeshingType::Custom(_, mesher) => mesher.mesh(&blocks),
     };
     lib_render::Quads(quads)
 }
@@ -97,24 +167,96 @@ fn get_quads(blocks: Neighborhood<Blocks>, meshing_type: MeshingType) -> Terrain
 fn get_quads_naive(blocks: &Neighborhood<Blocks>) -> Vec<TerrainQuad> {
     cube_iter(0..32)
         .map(|(x, y, z)| [x, y, z])
-        .flat_map(|pos| get_quads_around_block(blocks, pos))
+        .flat_map(|pos| get_quads_around_block(blocks, pos).chain(get_shaped_quads(blocks, pos)))
+        .collect()
+}
+
+const NORMALS: [Normal; 6] = [
+    Normal::PosX,
+    Normal::NegX,
+    Normal::PosY,
+    Normal::NegY,
+    Normal::PosZ,
+    Normal::NegZ,
+];
+
+fn get_quads_greedy(blocks: &Neighborhood<Blocks>) -> Vec<TerrainQuad> {
+    NORMALS
+        .iter()
+        .flat_map(|normal| (0..32).flat_map(move |layer| get_quads_in_layer(blocks, normal, layer)))
+        .chain(
+            cube_iter(0..32)
+                .map(|(x, y, z)| [x, y, z])
+                .flat_map(|pos| get_shaped_quads(blocks, pos)),
+        )
         .collect()
 }
 
+/// Merges the visible faces of one 32x32 layer of blocks into as few quads as possible.
+///
+/// Faces are only merged when they have the same texture and identical ambient occlusion
+/// corners. The shader repeats the corners across every block of a merged quad, so it looks
+/// the same as the unmerged faces.
+fn get_quads_in_layer(
+    blocks: &Neighborhood<Blocks>,
+    normal: &Normal,
+    layer: i32,
+) -> Vec<TerrainQuad> {
+    let (a0, a1) = get_perpendicular_axes(normal);
+    let u = a0.as_unit_direction();
+    let v = a1.as_unit_direction();
+    // Block with the lowest coordinates along +u and +v within the layer
+    let origin =
+        (u.min(IVec3::ZERO) + v.min(IVec3::ZERO)) * -31 + normal.as_unit_direction().abs() * layer;
+    let pos_at = |i: i32, j: i32| origin + u * i + v * j;
+
+    let mut faces: [[Option<TerrainQuad>; 32]; 32] = std::array::from_fn(|i| {
+        std::array::from_fn(|j| {
+            get_quad_on_face(blocks, pos_at(i as i32, j as i32).to_array(), normal)
+        })
+    });
+
+    let mut quads = vec![];
+    for j in 0..32 {
+        for i in 0..32 {
+            let Some(face) = faces[i][j].take() else {
+                continue;
+            };
+            let can_merge = |other: &Option<TerrainQuad>| {
+                other.as_ref().is_some_and(|other| {
+                    other.ty == face.ty && other.ambient_occlusion == face.ambient_occlusion
+                })
+            };
+            let mut width = 1;
+            while i + width < 32 && can_merge(&faces[i + width][j]) {
+                width += 1;
+            }
+            let mut height = 1;
+            while j + height < 32 && (i..i + width).all(|x| can_merge(&faces[x][j + height])) {
+                height += 1;
+            }
+            for column in &mut faces[i..i + width] {
+                for merged in &mut column[j..j + height] {
+                    *merged = None;
+                }
+            }
+            quads.push(lib_render::Quad {
+                width: NonZero::new(width as u32).unwrap(),
+                height: NonZero::new(height as u32).unwrap(),
+                ..face
+            });
+        }
+    }
+    quads
+}
+
 fn get_quads_around_block(
     blocks: &Neighborhood<Blocks>,
     pos: [i32; 3],
 ) -> impl Iterator<Item = TerrainQuad> {
-    [
-        Normal::PosX,
-        Normal::NegX,
-        Normal::PosY,
-        Normal::NegY,
-        Normal::PosZ,
-        Normal::NegZ,
-    ]
-    .iter()
-    .filter_map(move |normal| get_quad_on_face(blocks, pos, normal))
+    NORMALS
+        .iter()
+        .filter_map(move |normal| get_quad_on_face(blocks, pos, normal))
 }
 
 fn get_quad_on_face(
@@ -122,17 +264,19 @@ fn get_quad_on_face(
     pos: [i32; 3],
     normal: &Normal,
 ) -> Option<TerrainQuad> {
-    let ty = blocks
-        .at_pos(&pos)
-        .map(|block| Terrain::try_from((*block, *normal)).ok())
-        .flatten()?;
+    let placed = blocks.at_pos(&pos).copied()?;
+    if placed.block.model() != BlockModel::Cube {
+        // Meshed by `get_shaped_quads` instead
+        return None;
+    }
+    let ty = Terrain::try_from((placed, *normal)).ok()?;
     let pos = IVec3::from(pos);
     let other_pos = pos + normal.as_unit_direction();
     let other_block = blocks
         .at_pos(&other_pos.into())
         .cloned()
         .unwrap_or_default();
-    if !other_block.is_transparent() {
+    if other_block.block.hides_face_of(placed.block) {
         return None;
     }
     let quad = lib_render::Quad {
@@ -143,8 +287,56 @@ fn get_quad_on_face(
         pos,
         ambient_occlusion: [0, 1, 2, 3]
             .map(|idx| get_ambient_occlusion_factor(blocks, pos, normal, idx)),
+        shape: QuadShape::Full,
+    };
+    Some(quad)
+}
+
+const CROSS_NORMALS: [Normal; 4] = [Normal::PosX, Normal::NegX, Normal::PosZ, Normal::NegZ];
+
+/// Quads of a block that isn't a cube, which are never merged. Faces on the
+/// edge of the block's cell are hidden by neighbours like those of cubes,
+/// but the rest always show. None of them are ambient occluded.
+fn get_shaped_quads(blocks: &Neighborhood<Blocks>, pos: [i32; 3]) -> Vec<TerrainQuad> {
+    let Some(placed) = blocks.at_pos(&pos).copied() else {
+        return vec![];
     };
-    return Some(quad);
+    let (normals, shape, inner_normal) = match placed.block.model() {
+        BlockModel::Cube => return vec![],
+        // Both sides of each diagonal, as quads are only drawn from the front
+        BlockModel::Cross => (CROSS_NORMALS.as_slice(), QuadShape::Diagonal, None),
+        BlockModel::Slab { top: false } => (
+            NORMALS.as_slice(),
+            QuadShape::BottomHalf,
+            Some(Normal::PosY),
+        ),
+        BlockModel::Slab { top: true } => {
+            (NORMALS.as_slice(), QuadShape::TopHalf, Some(Normal::NegY))
+        }
+    };
+    let pos = IVec3::from(pos);
+    normals
+        .iter()
+        .filter(|normal| {
+            let on_edge = shape != QuadShape::Diagonal && Some(**normal) != inner_normal;
+            let neighbor = blocks
+                .at_pos(&(pos + normal.as_unit_direction()).into())
+                .copied()
+                .unwrap_or_default();
+            !(on_edge && neighbor.block.hides_face_of(placed.block))
+        })
+        .filter_map(|normal| {
+            Some(lib_render::Quad {
+                ty: Terrain::try_from((placed, *normal)).ok()?,
+                normal: *normal,
+                width: NonZero::new(1).unwrap(),
+                height: NonZero::new(1).unwrap(),
+                pos,
+                ambient_occlusion: [0; 4],
+                shape,
+            })
+        })
+        .collect()
 }
 
 fn get_ambient_occlusion_factor(
@@ -168,7 +360,7 @@ fn get_ambient_occlusion_factor(
     let is_solid = |p: IVec3| {
         blocks
             .at_pos(&p.to_array())
-            .map(|block| !block.is_transparent())
+            .map(|placed| placed.block.has_tag(BlockTag::Solid))
             .unwrap_or(false)
     };
     let left = is_solid(one_layer_up + offset_0);
@@ -187,7 +379,7 @@ fn get_ambient_occlusion_factor(
     if corner {
         return 1;
     }
-    return 0;
+    0
 }
 
 fn get_perpendicular_axes(normal: &Normal) -> (Normal, Normal) {
@@ -200,3 +392,112 @@ fn get_perpendicular_axes(normal: &Normal) -> (Normal, Normal) {
         Normal::NegZ => (Normal::NegX, Normal::NegY),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lib_spatial::CHUNK_SIZE;
+
+    use super::*;
+    use crate::block::{Block, PlacedBlock};
+
+    /// Chunk with `block(y)` at every block of each layer
+    fn layered_chunk(block: impl Fn(usize) -> Block) -> Option<Arc<Blocks>> {
+        let blocks = cube_iter(0..CHUNK_SIZE)
+            .map(|(_, y, _)| PlacedBlock::from(block(y)))
+            .collect();
+        Blocks::from_blocks(blocks).map(Arc::new)
+    }
+
+    /// Flat ground with its top in the middle chunk at `height`, and the
+    /// chunks around it filled in the same way
+    fn flat_ground(height: usize) -> Neighborhood<Blocks> {
+        let ground = layered_chunk(|y| match y {
+            y if y < height => Block::Dirt,
+            y if y == height => Block::Grass,
+            _ => Block::Air,
+        });
+        let below = layered_chunk(|_| Block::Stone);
+        let above = layered_chunk(|_| Block::Air);
+        let mut blocks = Neighborhood {
+            chunks: std::array::from_fn(|_| None),
+        };
+        for (x, y, z) in cube_iter(-1..=1) {
+            let chunk = match y {
+                -1 => below.clone(),
+                0 => ground.clone(),
+                _ => above.clone(),
+            };
+            blocks.put_chunk(&[x, y, z], chunk);
+        }
+        blocks
+    }
+
+    #[test]
+    fn naive_meshing_draws_each_face_of_flat_ground() {
+        let quads = get_quads_naive(&flat_ground(3));
+        assert_eq!(quads.len(), CHUNK_SIZE * CHUNK_SIZE);
+        assert!(quads.iter().all(|quad| matches!(quad.normal, Normal::PosY)));
+    }
+
+    /// `flat_ground` with a stone slab and a torch on the middle chunk's
+    /// ground
+    fn ground_with_slab_and_torch(height: usize) -> Neighborhood<Blocks> {
+        let mut blocks = flat_ground(height);
+        // The middle of the 3x3x3 chunks
+        let mut chunk = blocks.chunks[13].clone().unwrap();
+        let above = height as i32 + 1;
+        let blocks_in_chunk = Arc::make_mut(&mut chunk);
+        blocks_in_chunk.replace(IVec3::new(1, above, 1), Block::StoneSlab.into());
+        blocks_in_chunk.replace(IVec3::new(5, above, 5), Block::Torch.into());
+        blocks.put_chunk(&[0, 0, 0], Some(chunk));
+        blocks
+    }
+
+    #[test]
+    fn slabs_and_crosses_are_meshed_unmerged_in_their_shapes() {
+        for quads in [
+            get_quads_naive(&ground_with_slab_and_torch(3)),
+            get_quads_greedy(&ground_with_slab_and_torch(3)),
+        ] {
+            let shaped: Vec<_> = quads
+                .iter()
+                .filter(|quad| quad.shape != QuadShape::Full)
+                .collect();
+            assert!(
+                shaped
+                    .iter()
+                    .all(|quad| quad.width.get() == 1 && quad.height.get() == 1)
+            );
+            // The ground hides the bottom of the slab, but not its top in the
+            // middle of its cell
+            let slab: Vec<_> = shaped
+                .iter()
+                .filter(|quad| quad.shape == QuadShape::BottomHalf)
+                .collect();
+            assert_eq!(slab.len(), 5);
+            assert!(slab.iter().all(|quad| quad.pos == IVec3::new(1, 4, 1)));
+            assert!(!slab.iter().any(|quad| quad.normal == Normal::NegY));
+            let cross: Vec<_> = shaped
+                .iter()
+                .filter(|quad| quad.shape == QuadShape::Diagonal)
+                .collect();
+            assert_eq!(cross.len(), 4);
+            assert!(cross.iter().all(|quad| quad.pos == IVec3::new(5, 4, 5)));
+        }
+        // Neither hides the ground under it, so it's still one quad
+        assert_eq!(
+            get_quads_greedy(&ground_with_slab_and_torch(3)).len(),
+            1 + 5 + 4
+        );
+    }
+
+    #[test]
+    fn greedy_meshing_merges_flat_ground_into_one_quad() {
+        let quads = get_quads_greedy(&flat_ground(3));
+        assert_eq!(quads.len(), 1);
+        let quad = &quads[0];
+        assert!(matches!(quad.normal, Normal::PosY));
+        assert_eq!(quad.width.get(), CHUNK_SIZE as u32);
+        assert_eq!(quad.height.get(), CHUNK_SIZE as u32);
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use lib_async_component::ComputeTaskStats;
+use lib_render::stats::RenderStats;
+
+use crate::{
+    mesh::{QuadCount, TerrainQuads},
+    world_gen::{Blocks, Chunk, HeightNoise},
+};
+
+/// Appends a line of JSON with the runtime metrics to a file every
+/// `interval`, so long runs and dedicated servers can be watched without the
+/// HUD. Unlike `MetricsLogPlugin`, nothing has to be toggled and each line is
+/// written as soon as it's sampled. Metrics of plugins the app doesn't have,
+/// like the renderer's on a server, are `null`.
+pub struct MetricsExportPlugin(pub PathBuf);
+
+impl Plugin for MetricsExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MetricsExport {
+            path: self.0.clone(),
+            interval: Duration::from_secs(5),
+        })
+        .add_systems(Last, export_metrics);
+    }
+}
+
+#[derive(Resource)]
+pub struct MetricsExport {
+    pub path: PathBuf,
+    /// Time between lines
+    pub interval: Duration,
+}
+
+/// Frame times since the last line was written
+#[derive(Default)]
+struct FrameTimes {
+    since_export: Duration,
+    frames: u32,
+    longest: Duration,
+}
+
+#[derive(bevy::ecs::system::SystemParam)]
+struct ExportedStats<'w> {
+    height_noise_tasks: Res<'w, ComputeTaskStats<HeightNoise>>,
+    block_tasks: Res<'w, ComputeTaskStats<Blocks>>,
+    meshing_tasks: Option<Res<'w, ComputeTaskStats<TerrainQuads>>>,
+    quad_count: Option<Res<'w, QuadCount>>,
+    render_stats: Option<Res<'w, RenderStats>>,
+}
+
+fn export_metrics(
+    time: Res<Time<Real>>,
+    export: Res<MetricsExport>,
+    stats: ExportedStats,
+    q_chunks: Query<(Has<Blocks>, Has<TerrainQuads>), With<Chunk>>,
+    mut frame_times: Local<FrameTimes>,
+) {
+    frame_times.since_export += time.delta();
+    frame_times.frames += 1;
+    frame_times.longest = frame_times.longest.max(time.delta());
+    if frame_times.since_export < export.interval {
+        return;
+    }
+    let FrameTimes {
+        since_export,
+        frames,
+        longest,
+    } = std::mem::take(&mut *frame_times);
+
+    let (mut loaded_chunks, mut generated_chunks, mut meshed_chunks) = (0, 0, 0);
+    for (has_blocks, has_quads) in q_chunks.iter() {
+        loaded_chunks += 1;
+        generated_chunks += has_blocks as usize;
+        meshed_chunks += has_quads as usize;
+    }
+    let optional = |value: Option<u64>| value.map_or("null".to_owned(), |v| v.to_string());
+    let meshing = stats.meshing_tasks.as_deref();
+    let render_stats = stats.render_stats.as_deref();
+    let fields = [
+        ("time", format!("{:.3}", time.elapsed_secs_f64())),
+        ("frames", frames.to_string()),
+        (
+            "frame_time_avg_ms",
+            format!("{:.3}", since_export.as_secs_f64() * 1000.0 / frames as f64),
+        ),
+        (
+            "frame_time_max_ms",
+            format!("{:.3}", longest.as_secs_f64() * 1000.0),
+        ),
+        ("loaded_chunks", loaded_chunks.to_string()),
+        ("generated_chunks", generated_chunks.to_string()),
+        (
+            "meshed_chunks",
+            optional(meshing.map(|_| meshed_chunks as u64)),
+        ),
+        (
+            "height_noise_tasks",
+            stats.height_noise_tasks.in_flight.to_string(),
+        ),
+        ("block_tasks", stats.block_tasks.in_flight.to_string()),
+        (
+            "meshing_tasks",
+            optional(meshing.map(|tasks| tasks.in_flight as u64)),
+        ),
+        (
+            "quad_count",
+            optional(stats.quad_count.as_deref().map(|count| count.0 as u64)),
+        ),
+        (
+            "instance_buffer_bytes",
+            optional(render_stats.map(|stats| stats.instance_buffer_bytes)),
+        ),
+        (
+            "bytes_uploaded_last_frame",
+            optional(render_stats.map(|stats| stats.bytes_uploaded)),
+        ),
+    ];
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("\"{}\":{}", name, value))
+        .collect();
+    let line = format!("{{{}}}\n", fields.join(","));
+    if let Err(e) = append(&export.path, &line) {
+        error!("Couldn't export metrics to {:?}: {}", export.path, e);
+    }
+}
+
+fn append(path: &Path, line: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
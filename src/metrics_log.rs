@@ -0,0 +1,327 @@
+use std::{
+    fmt::Write as _,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    diagnostic::{DiagnosticPath, DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+use lib_async_component::ComputeTaskStats;
+use lib_render::globals::RenderFeatures;
+
+use crate::{
+    mesh::{MeshingType, QuadCount, QuadsGenerated, TerrainQuads},
+    subsystem_timing::{Subsystem, SubsystemTimings},
+    world_gen::{Blocks, Chunk, WorldSeed},
+};
+
+/// Records the HUD metrics to disk while toggled on with F8, so benchmark
+/// fly-throughs can be compared across commits. The file is written when
+/// recording stops or the app exits.
+pub struct MetricsLogPlugin;
+
+impl Plugin for MetricsLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MetricsRecorder>().add_systems(
+            Last,
+            (
+                toggle_recording,
+                record_metrics,
+                finish_recording_on_exit.run_if(on_event::<AppExit>),
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricsFormat {
+    Csv,
+    Json,
+}
+
+impl MetricsFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct MetricsRecorder {
+    pub format: MetricsFormat,
+    /// Time between samples
+    pub interval: Duration,
+    /// Where recordings are written, one file each
+    pub directory: PathBuf,
+    recording: Option<Recording>,
+}
+
+impl Default for MetricsRecorder {
+    fn default() -> Self {
+        Self {
+            format: MetricsFormat::Csv,
+            interval: Duration::from_millis(250),
+            directory: PathBuf::from("metrics"),
+            recording: None,
+        }
+    }
+}
+
+impl MetricsRecorder {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Starts a new recording, `now` being the real time elapsed since
+    /// startup
+    pub fn start(&mut self, now: Duration) {
+        info!("Recording metrics every {:?}", self.interval);
+        self.recording = Some(Recording {
+            started: now,
+            // Fires on the first frame, so every recording starts with a
+            // sample
+            timer: Timer::new(Duration::ZERO, TimerMode::Once),
+            samples: vec![],
+        });
+    }
+}
+
+struct Recording {
+    started: Duration,
+    timer: Timer,
+    samples: Vec<MetricsSample>,
+}
+
+struct MetricsSample {
+    /// Seconds since recording started
+    time: f64,
+    fps: Option<f64>,
+    frame_time_millis: Option<f64>,
+    quad_count: u32,
+    loaded_chunks: usize,
+    chunks_awaiting_generation: usize,
+    chunks_awaiting_meshing: usize,
+    /// Running totals, so throughput over any span is their difference
+    chunks_meshed: u64,
+    quads_generated: u64,
+    subsystem_millis: [f64; Subsystem::ALL.len()],
+}
+
+/// What a recording was made with, written alongside its samples
+struct RecordingTags {
+    seed: u32,
+    meshing: String,
+    render_features: String,
+}
+
+fn toggle_recording(
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time<Real>>,
+    mut recorder: ResMut<MetricsRecorder>,
+    tags: RecordingTagsParam,
+) {
+    if !keys.just_pressed(KeyCode::F8) {
+        return;
+    }
+    if recorder.is_recording() {
+        finish_recording(&mut recorder, tags.get());
+        return;
+    }
+    recorder.start(time.elapsed());
+}
+
+#[derive(bevy::ecs::system::SystemParam)]
+struct RecordingTagsParam<'w> {
+    seed: Res<'w, WorldSeed>,
+    meshing: Res<'w, MeshingType>,
+    render_features: Res<'w, RenderFeatures>,
+}
+
+impl RecordingTagsParam<'_> {
+    fn get(&self) -> RecordingTags {
+        RecordingTags {
+            seed: self.seed.0,
+            meshing: format!("{:?}", *self.meshing),
+            render_features: format!("{:?}", *self.render_features),
+        }
+    }
+}
+
+fn record_metrics(
+    time: Res<Time<Real>>,
+    mut recorder: ResMut<MetricsRecorder>,
+    diagnostics: Res<DiagnosticsStore>,
+    quad_count: Res<QuadCount>,
+    quads_generated: Res<QuadsGenerated>,
+    meshing_tasks: Res<ComputeTaskStats<TerrainQuads>>,
+    timings: Res<SubsystemTimings>,
+    q_chunks: Query<(Has<Blocks>, Has<TerrainQuads>), With<Chunk>>,
+) {
+    let interval = recorder.interval;
+    let Some(recording) = recorder.recording.as_mut() else {
+        return;
+    };
+    recording.timer.tick(time.delta());
+    if !recording.timer.finished() {
+        return;
+    }
+    recording.timer = Timer::new(interval, TimerMode::Once);
+
+    let smoothed = |path: &DiagnosticPath| diagnostics.get(path).and_then(|d| d.smoothed());
+    let (mut loaded_chunks, mut awaiting_generation, mut awaiting_meshing) = (0, 0, 0);
+    for (has_blocks, has_quads) in q_chunks.iter() {
+        loaded_chunks += 1;
+        if !has_blocks {
+            awaiting_generation += 1;
+        } else if !has_quads {
+            awaiting_meshing += 1;
+        }
+    }
+    recording.samples.push(MetricsSample {
+        time: (time.elapsed() - recording.started).as_secs_f64(),
+        fps: smoothed(&FrameTimeDiagnosticsPlugin::FPS),
+        frame_time_millis: smoothed(&FrameTimeDiagnosticsPlugin::FRAME_TIME),
+        quad_count: quad_count.0,
+        loaded_chunks,
+        chunks_awaiting_generation: awaiting_generation,
+        chunks_awaiting_meshing: awaiting_meshing,
+        chunks_meshed: meshing_tasks.completed,
+        quads_generated: quads_generated.0,
+        subsystem_millis: Subsystem::ALL.map(|s| timings.get(s).as_secs_f64() * 1000.0),
+    });
+}
+
+fn finish_recording_on_exit(mut recorder: ResMut<MetricsRecorder>, tags: RecordingTagsParam) {
+    if recorder.is_recording() {
+        finish_recording(&mut recorder, tags.get());
+    }
+}
+
+fn finish_recording(recorder: &mut MetricsRecorder, tags: RecordingTags) {
+    let Some(recording) = recorder.recording.take() else {
+        return;
+    };
+    let contents = match recorder.format {
+        MetricsFormat::Csv => to_csv(&tags, &recording.samples),
+        MetricsFormat::Json => to_json(&tags, &recording.samples),
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = recorder.directory.join(format!(
+        "metrics-{:08x}-{}.{}",
+        tags.seed,
+        timestamp,
+        recorder.format.extension()
+    ));
+    let result =
+        std::fs::create_dir_all(&recorder.directory).and_then(|_| std::fs::write(&path, contents));
+    match result {
+        Ok(()) => info!(
+            "Wrote {} metrics samples to {:?}",
+            recording.samples.len(),
+            path
+        ),
+        Err(e) => error!("Couldn't write metrics to {:?}: {}", path, e),
+    }
+}
+
+fn column_names() -> Vec<String> {
+    let mut names: Vec<String> = [
+        "time",
+        "fps",
+        "frame_time_ms",
+        "quad_count",
+        "loaded_chunks",
+        "chunks_awaiting_generation",
+        "chunks_awaiting_meshing",
+        "chunks_meshed",
+        "quads_generated",
+    ]
+    .map(String::from)
+    .into();
+    names.extend(Subsystem::ALL.map(|s| {
+        let label = s.label().to_lowercase().replace([' ', '/'], "_");
+        format!("{}_ms", label)
+    }));
+    names
+}
+
+fn column_values(sample: &MetricsSample) -> Vec<String> {
+    let optional = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:.3}", v));
+    let mut values = vec![
+        format!("{:.3}", sample.time),
+        optional(sample.fps),
+        optional(sample.frame_time_millis),
+        sample.quad_count.to_string(),
+        sample.loaded_chunks.to_string(),
+        sample.chunks_awaiting_generation.to_string(),
+        sample.chunks_awaiting_meshing.to_string(),
+        sample.chunks_meshed.to_string(),
+        sample.quads_generated.to_string(),
+    ];
+    values.extend(sample.subsystem_millis.map(|ms| format!("{:.3}", ms)));
+    values
+}
+
+/// Tags as comment lines, then one row per sample. Missing values are left
+/// empty.
+fn to_csv(tags: &RecordingTags, samples: &[MetricsSample]) -> String {
+    let mut csv = String::new();
+    let _ = writeln!(csv, "# seed: {:08x}", tags.seed);
+    let _ = writeln!(csv, "# meshing: {}", tags.meshing);
+    let _ = writeln!(csv, "# render_features: {}", tags.render_features);
+    csv.push_str(&column_names().join(","));
+    csv.push('\n');
+    for sample in samples {
+        csv.push_str(&column_values(sample).join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// An object of the tags, with the samples as an array of objects. Missing
+/// values are `null`.
+fn to_json(tags: &RecordingTags, samples: &[MetricsSample]) -> String {
+    let names = column_names();
+    let rows: Vec<String> = samples
+        .iter()
+        .map(|sample| {
+            let fields: Vec<String> = names
+                .iter()
+                .zip(column_values(sample))
+                .map(|(name, value)| {
+                    let value = if value.is_empty() {
+                        "null".into()
+                    } else {
+                        value
+                    };
+                    format!("\"{}\":{}", name, value)
+                })
+                .collect();
+            format!("    {{{}}}", fields.join(","))
+        })
+        .collect();
+    let mut json = String::new();
+    let _ = writeln!(json, "{{");
+    let _ = writeln!(json, "  \"seed\": \"{:08x}\",", tags.seed);
+    let _ = writeln!(json, "  \"meshing\": \"{}\",", json_escape(&tags.meshing));
+    let _ = writeln!(
+        json,
+        "  \"render_features\": \"{}\",",
+        json_escape(&tags.render_features)
+    );
+    let _ = writeln!(json, "  \"samples\": [\n{}\n  ]", rows.join(",\n"));
+    let _ = writeln!(json, "}}");
+    json
+}
+
+pub(crate) fn json_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,651 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use lib_chunk::{ChunkIndex, ChunkPosition};
+use lib_spatial::CHUNK_SIZE;
+
+use crate::{
+    block::{BlockState, PlacedBlock},
+    block_registry::{BlockId, BlockRegistry},
+    game_state::GameState,
+    persistence::{Reader, decode_chunk, encode_chunk, invalid_data},
+    player::Player,
+    world_gen::{
+        self, BlockChangeCause, BlockChanged, Blocks, Chunk, EditBlock, WorldSeed, WorldType,
+    },
+};
+
+/// Shares a world between games over TCP, as given by `NetworkRole`. The
+/// server owns the world: it generates, edits and saves the blocks, and
+/// streams each client the chunks nearest its player, then every
+/// `BlockChanged` in them. Clients only send where their player is and the
+/// `EditBlock`s they'd like applied. Chunks are sent in the palette and run
+/// format they're saved in.
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkRole>()
+            .add_systems(Startup, start_network)
+            .add_systems(
+                Update,
+                (
+                    (
+                        accept_clients,
+                        receive_from_clients.before(world_gen::apply_block_edits),
+                        stream_chunks,
+                    )
+                        .chain()
+                        // The world isn't chosen until the main menu is left
+                        .run_if(resource_exists::<Server>.and(not(in_state(GameState::MainMenu)))),
+                    (
+                        receive_from_server,
+                        send_to_server.after(world_gen::apply_block_edits),
+                    )
+                        .chain()
+                        .run_if(resource_exists::<ServerConnection>),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    (send_block_changes, flush_clients)
+                        .chain()
+                        .run_if(resource_exists::<Server>),
+                    flush_to_server.run_if(resource_exists::<ServerConnection>),
+                ),
+            );
+    }
+}
+
+/// Whether this game owns its world, shares it, or plays on another's
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NetworkRole {
+    #[default]
+    Offline,
+    /// Owns the world, letting clients connect to it at `address`
+    Server { address: String },
+    /// Plays on the world of the server at `address`
+    Client { address: String },
+}
+
+/// Run condition for systems that generate, change or save the world, which
+/// clients leave to the server
+pub(crate) fn owns_world(role: Res<NetworkRole>) -> bool {
+    !matches!(*role, NetworkRole::Client { .. })
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest message either side accepts, which fits any encoded chunk
+const MAX_MESSAGE_LEN: usize = 1 << 20;
+/// Chunks sent to each client per frame, nearest its player first
+const CHUNKS_PER_FRAME: usize = 8;
+/// Shortest time between clients telling the server where their player is
+const MOVE_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Resource)]
+struct Server {
+    listener: TcpListener,
+    clients: Vec<RemoteClient>,
+}
+
+struct RemoteClient {
+    connection: Connection,
+    address: SocketAddr,
+    /// Where its player last said it was
+    position: Vec3,
+    /// Chunks it has the blocks of, and is sent the changes to
+    sent_chunks: HashSet<IVec3>,
+}
+
+#[derive(Resource)]
+struct ServerConnection(Connection);
+
+/// A stream of messages, each after its length as a little-endian `u32`.
+/// Neither sending nor receiving blocks.
+struct Connection {
+    stream: TcpStream,
+    received: Vec<u8>,
+    unsent: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            received: vec![],
+            unsent: vec![],
+        })
+    }
+
+    /// Queues `message` to be written by `flush`
+    fn send(&mut self, message: &[u8]) {
+        self.unsent.extend((message.len() as u32).to_le_bytes());
+        self.unsent.extend(message);
+    }
+
+    /// Writes as much of what was sent as the stream takes
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.unsent.is_empty() {
+            match self.stream.write(&self.unsent) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.unsent.drain(..written);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Every whole message that has arrived, or an error once the other side
+    /// is gone
+    fn receive(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.received.extend(&buffer[..read]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut messages = vec![];
+        while let Some(len) = self.received.get(..4) {
+            let len = u32::from_le_bytes(len.try_into().expect("took 4 bytes")) as usize;
+            if len > MAX_MESSAGE_LEN {
+                return Err(invalid_data(format!("message of {len} bytes is too long")));
+            }
+            if self.received.len() < 4 + len {
+                break;
+            }
+            messages.push(self.received.drain(..4 + len).skip(4).collect());
+        }
+        Ok(messages)
+    }
+}
+
+enum ServerMessage {
+    /// Sent first, with what the client needs to read the rest
+    Welcome {
+        seed: u32,
+        world_type: WorldType,
+        /// From `BlockRegistry::to_metadata`
+        block_names: Vec<String>,
+    },
+    /// All the blocks of a chunk, as written by `encode_chunk`
+    Chunk { pos: IVec3, data: Vec<u8> },
+    BlockChanged {
+        pos: IVec3,
+        block: BlockId,
+        state: BlockState,
+        cause: BlockChangeCause,
+    },
+}
+
+impl ServerMessage {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        match self {
+            Self::Welcome {
+                seed,
+                world_type,
+                block_names,
+            } => {
+                bytes.push(0);
+                bytes.extend(seed.to_le_bytes());
+                write_string(&mut bytes, (*world_type).into());
+                bytes.extend((block_names.len() as u16).to_le_bytes());
+                for name in block_names {
+                    write_string(&mut bytes, name);
+                }
+            }
+            Self::Chunk { pos, data } => {
+                bytes.push(1);
+                write_ivec3(&mut bytes, *pos);
+                bytes.extend(data);
+            }
+            Self::BlockChanged {
+                pos,
+                block,
+                state,
+                cause,
+            } => {
+                bytes.push(2);
+                write_ivec3(&mut bytes, *pos);
+                bytes.extend(block.0.to_le_bytes());
+                bytes.push(state.to_bits());
+                bytes.push(match cause {
+                    BlockChangeCause::Player => 0,
+                    BlockChangeCause::WorldGen => 1,
+                });
+            }
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader(bytes);
+        Ok(match reader.u8()? {
+            0 => Self::Welcome {
+                seed: reader.u32()?,
+                world_type: read_string(&mut reader)?
+                    .parse()
+                    .map_err(|_| invalid_data("unknown world type"))?,
+                block_names: (0..reader.u16()?)
+                    .map(|_| read_string(&mut reader))
+                    .collect::<io::Result<_>>()?,
+            },
+            1 => Self::Chunk {
+                pos: read_ivec3(&mut reader)?,
+                data: reader.0.to_vec(),
+            },
+            2 => Self::BlockChanged {
+                pos: read_ivec3(&mut reader)?,
+                block: BlockId(reader.u16()?),
+                state: BlockState::from_bits(reader.u8()?),
+                cause: match reader.u8()? {
+                    0 => BlockChangeCause::Player,
+                    1 => BlockChangeCause::WorldGen,
+                    cause => return Err(invalid_data(format!("unknown cause {cause}"))),
+                },
+            },
+            tag => return Err(invalid_data(format!("unknown message {tag}"))),
+        })
+    }
+}
+
+enum ClientMessage {
+    /// Where the client's player is
+    Move { position: Vec3 },
+    /// Asks for an `EditBlock`
+    EditBlock {
+        pos: IVec3,
+        block: BlockId,
+        state: BlockState,
+    },
+}
+
+impl ClientMessage {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        match self {
+            Self::Move { position } => {
+                bytes.push(0);
+                for x in position.to_array() {
+                    bytes.extend(x.to_le_bytes());
+                }
+            }
+            Self::EditBlock { pos, block, state } => {
+                bytes.push(1);
+                write_ivec3(&mut bytes, *pos);
+                bytes.extend(block.0.to_le_bytes());
+                bytes.push(state.to_bits());
+            }
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader(bytes);
+        Ok(match reader.u8()? {
+            0 => Self::Move {
+                position: Vec3::new(reader.f32()?, reader.f32()?, reader.f32()?),
+            },
+            1 => Self::EditBlock {
+                pos: read_ivec3(&mut reader)?,
+                block: BlockId(reader.u16()?),
+                state: BlockState::from_bits(reader.u8()?),
+            },
+            tag => return Err(invalid_data(format!("unknown message {tag}"))),
+        })
+    }
+}
+
+fn write_ivec3(bytes: &mut Vec<u8>, v: IVec3) {
+    for x in v.to_array() {
+        bytes.extend(x.to_le_bytes());
+    }
+}
+
+fn read_ivec3(reader: &mut Reader) -> io::Result<IVec3> {
+    Ok(IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?))
+}
+
+/// Written after its length in bytes, as a `u16`
+fn write_string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend((string.len() as u16).to_le_bytes());
+    bytes.extend(string.as_bytes());
+}
+
+fn read_string(reader: &mut Reader) -> io::Result<String> {
+    let len = reader.u16()? as usize;
+    String::from_utf8(reader.bytes(len)?.to_vec()).map_err(|_| invalid_data("invalid string"))
+}
+
+fn start_network(mut commands: Commands, role: Res<NetworkRole>, mut exit: EventWriter<AppExit>) {
+    match &*role {
+        NetworkRole::Offline => {}
+        NetworkRole::Server { address } => {
+            let listener = TcpListener::bind(address).and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            });
+            match listener {
+                Ok(listener) => {
+                    info!("Hosting the world at {}", address);
+                    commands.insert_resource(Server {
+                        listener,
+                        clients: vec![],
+                    });
+                }
+                Err(e) => {
+                    error!("Couldn't host the world at {}: {}", address, e);
+                    exit.write(AppExit::error());
+                }
+            }
+        }
+        NetworkRole::Client { address } => match connect(address) {
+            Ok(connection) => {
+                info!("Connected to the server at {}", address);
+                commands.insert_resource(ServerConnection(connection));
+            }
+            Err(e) => {
+                error!("Couldn't connect to the server at {}: {}", address, e);
+                exit.write(AppExit::error());
+            }
+        },
+    }
+}
+
+/// Tries each address `address` resolves to in turn
+fn connect(address: &str) -> io::Result<Connection> {
+    let mut last_error = invalid_data(format!("{address:?} isn't an address"));
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Connection::new(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn accept_clients(
+    mut server: ResMut<Server>,
+    seed: Res<WorldSeed>,
+    world_type: Res<WorldType>,
+    registry: Res<BlockRegistry>,
+) {
+    loop {
+        let (stream, address) = match server.listener.accept() {
+            Ok(accepted) => accepted,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => {
+                error!("Couldn't accept a client: {}", e);
+                break;
+            }
+        };
+        let mut connection = match Connection::new(stream) {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Couldn't set up the connection to {}: {}", address, e);
+                continue;
+            }
+        };
+        let welcome = ServerMessage::Welcome {
+            seed: seed.0,
+            world_type: *world_type,
+            block_names: registry.to_metadata(),
+        };
+        connection.send(&welcome.encode());
+        info!("{} connected", address);
+        server.clients.push(RemoteClient {
+            connection,
+            address,
+            position: Vec3::ZERO,
+            sent_chunks: HashSet::new(),
+        });
+    }
+}
+
+fn receive_from_clients(
+    mut server: ResMut<Server>,
+    registry: Res<BlockRegistry>,
+    mut edits: EventWriter<EditBlock>,
+) {
+    server.clients.retain_mut(|client| {
+        let messages = match client.connection.receive() {
+            Ok(messages) => messages,
+            Err(e) => {
+                info!("{} disconnected: {}", client.address, e);
+                return false;
+            }
+        };
+        for message in messages {
+            match ClientMessage::decode(&message) {
+                Ok(ClientMessage::Move { position }) => client.position = position,
+                Ok(ClientMessage::EditBlock { pos, block, state }) => {
+                    edits.write(EditBlock {
+                        pos,
+                        block: PlacedBlock {
+                            block: registry.block(block),
+                            state,
+                        },
+                        cause: BlockChangeCause::Player,
+                    });
+                }
+                Err(e) => {
+                    warn!(
+                        "Disconnecting {}, which sent an invalid message: {}",
+                        client.address, e
+                    );
+                    return false;
+                }
+            }
+        }
+        true
+    });
+}
+
+fn stream_chunks(
+    mut server: ResMut<Server>,
+    registry: Res<BlockRegistry>,
+    q_chunks: Query<(&ChunkPosition, &Blocks), With<Chunk>>,
+) {
+    for client in server.clients.iter_mut() {
+        let player_chunk = world_gen::block_pos_containing(client.position)
+            .div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+        let mut unsent: Vec<_> = q_chunks
+            .iter()
+            .filter(|(chunk_pos, _)| !client.sent_chunks.contains(&chunk_pos.0))
+            .collect();
+        unsent.sort_by_key(|(chunk_pos, _)| (chunk_pos.0 - player_chunk).length_squared());
+        for (chunk_pos, blocks) in unsent.into_iter().take(CHUNKS_PER_FRAME) {
+            let message = ServerMessage::Chunk {
+                pos: chunk_pos.0,
+                data: encode_chunk(blocks, &registry),
+            };
+            client.connection.send(&message.encode());
+            client.sent_chunks.insert(chunk_pos.0);
+        }
+    }
+}
+
+/// Sends each change to the clients that have its chunk. Clients are sent
+/// chunks as they are when sent, so later changes are all they're missing.
+fn send_block_changes(
+    mut server: ResMut<Server>,
+    registry: Res<BlockRegistry>,
+    mut changed: EventReader<BlockChanged>,
+) {
+    for event in changed.read() {
+        let chunk_pos = event.pos.div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+        let message = ServerMessage::BlockChanged {
+            pos: event.pos,
+            block: registry.id(event.new.block),
+            state: event.new.state,
+            cause: event.cause,
+        }
+        .encode();
+        for client in server.clients.iter_mut() {
+            if client.sent_chunks.contains(&chunk_pos) {
+                client.connection.send(&message);
+            }
+        }
+    }
+}
+
+fn flush_clients(mut server: ResMut<Server>) {
+    server
+        .clients
+        .retain_mut(|client| match client.connection.flush() {
+            Ok(()) => true,
+            Err(e) => {
+                info!("{} disconnected: {}", client.address, e);
+                false
+            }
+        });
+}
+
+fn receive_from_server(
+    mut commands: Commands,
+    mut connection: ResMut<ServerConnection>,
+    mut seed: ResMut<WorldSeed>,
+    mut world_type: ResMut<WorldType>,
+    mut registry: ResMut<BlockRegistry>,
+    chunk_index: Res<ChunkIndex>,
+    mut q_blocks: Query<&mut Blocks>,
+    mut changed: EventWriter<BlockChanged>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let messages = match connection.0.receive() {
+        Ok(messages) => messages,
+        Err(e) => {
+            lose_server(&mut commands, &mut exit, e);
+            return;
+        }
+    };
+    // Chunks are only inserted once these commands are applied, so changes
+    // to them until then are made here
+    let mut arrived: HashMap<IVec3, Blocks> = HashMap::new();
+    for message in messages {
+        let message = match ServerMessage::decode(&message) {
+            Ok(message) => message,
+            Err(e) => {
+                lose_server(&mut commands, &mut exit, e);
+                return;
+            }
+        };
+        match message {
+            ServerMessage::Welcome {
+                seed: world_seed,
+                world_type: server_world_type,
+                block_names,
+            } => {
+                *seed = WorldSeed(world_seed);
+                *world_type = server_world_type;
+                *registry = BlockRegistry::from_metadata(&block_names);
+            }
+            ServerMessage::Chunk { pos, data } => match decode_chunk(&data, &registry) {
+                Ok(blocks) => {
+                    arrived.insert(pos, blocks);
+                }
+                Err(e) => error!("Couldn't read chunk {} from the server: {}", pos, e),
+            },
+            ServerMessage::BlockChanged {
+                pos,
+                block,
+                state,
+                cause,
+            } => {
+                let block = PlacedBlock {
+                    block: registry.block(block),
+                    state,
+                };
+                let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+                match arrived.get_mut(&chunk_pos) {
+                    Some(blocks) => {
+                        blocks.replace(pos, block);
+                    }
+                    None => {
+                        world_gen::set_block(
+                            &chunk_index,
+                            &mut q_blocks,
+                            &mut changed,
+                            pos,
+                            block,
+                            cause,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    for (pos, blocks) in arrived {
+        match chunk_index.get_entity(&pos) {
+            Some(&entity) => {
+                commands.entity(entity).try_insert(blocks);
+            }
+            // Past this game's own load radius
+            None => {
+                commands.spawn((Chunk, ChunkPosition(pos), blocks));
+            }
+        }
+    }
+}
+
+fn send_to_server(
+    time: Res<Time<Real>>,
+    mut since_move: Local<Duration>,
+    mut connection: ResMut<ServerConnection>,
+    registry: Res<BlockRegistry>,
+    mut edits: EventReader<EditBlock>,
+    q_player: Query<&Transform, With<Player>>,
+) {
+    for edit in edits.read() {
+        let message = ClientMessage::EditBlock {
+            pos: edit.pos,
+            block: registry.id(edit.block.block),
+            state: edit.block.state,
+        };
+        connection.0.send(&message.encode());
+    }
+    *since_move += time.delta();
+    if *since_move < MOVE_INTERVAL {
+        return;
+    }
+    if let Ok(transform) = q_player.single() {
+        let message = ClientMessage::Move {
+            position: transform.translation,
+        };
+        connection.0.send(&message.encode());
+        *since_move = Duration::ZERO;
+    }
+}
+
+fn flush_to_server(
+    mut commands: Commands,
+    mut connection: ResMut<ServerConnection>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if let Err(e) = connection.0.flush() {
+        lose_server(&mut commands, &mut exit, e);
+    }
+}
+
+/// Without the server there's no world to play on
+fn lose_server(commands: &mut Commands, exit: &mut EventWriter<AppExit>, error: io::Error) {
+    error!("Lost the connection to the server: {}", error);
+    commands.remove_resource::<ServerConnection>();
+    exit.write(AppExit::error());
+}
@@ -0,0 +1,883 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use lib_async_component::{AsyncComponentPlugin, ComputeInProgress, ComputeTasks};
+use lib_chunk::{ChunkIndex, ChunkPosition};
+use lib_spatial::CHUNK_SIZE;
+
+use crate::{
+    block::{BlockState, PlacedBlock},
+    block_registry::{BlockId, BlockRegistry},
+    game_state::GameState,
+    inventory::Inventory,
+    network::owns_world,
+    player::{MovementMode, Player, PlayerCamera, Velocity},
+    world_gen::{self, BlockChanged, Blocks, Chunk, WorldGenerationSystems, WorldSeed, WorldType},
+};
+
+/// Saves the world to `SaveDirectory` whenever `SaveWorld` is sent, every
+/// `AutosaveInterval` and when the app exits, and loads it back once it
+/// starts loading. Besides the seed, block ids, player and inventory, only
+/// chunks changed since they were generated are saved, grouped into region
+/// files of `REGION_SIZE` chunks along each axis. Every file is replaced
+/// atomically, so a crash part way through a save leaves the last one intact.
+pub struct PersistencePlugin;
+
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveDirectory>()
+            .init_resource::<DirtyChunks>()
+            .init_resource::<RegionCache>()
+            .init_resource::<UnsavedChunks>()
+            .init_resource::<AutosaveInterval>()
+            .add_event::<SaveWorld>()
+            .add_plugins((
+                AsyncComponentPlugin::<SavedBlocks>::new(),
+                AsyncComponentPlugin::<WorldSaved>::new(),
+            ))
+            .add_observer(keep_unloaded_changes)
+            .add_systems(
+                OnEnter(GameState::Loading),
+                load_metadata.run_if(owns_world),
+            )
+            .add_systems(
+                Update,
+                (
+                    restore_player.run_if(resource_exists::<SavedPlayer>),
+                    load_saved_blocks
+                        .before(WorldGenerationSystems)
+                        .run_if(owns_world),
+                ),
+            )
+            .add_systems(
+                Last,
+                (
+                    mark_dirty_chunks,
+                    // Nothing's loaded to save from the main menu
+                    (autosave, save_on_exit.run_if(on_event::<AppExit>))
+                        .run_if(not(in_state(GameState::MainMenu))),
+                    save_world,
+                    finish_saves,
+                )
+                    .chain()
+                    // Clients of a server leave saving to it
+                    .run_if(owns_world),
+            );
+    }
+}
+
+/// Folder the world is saved in
+#[derive(Resource, Clone, Debug)]
+pub struct SaveDirectory(pub PathBuf);
+
+impl Default for SaveDirectory {
+    fn default() -> Self {
+        Self(Path::new(SAVES_FOLDER).join("world"))
+    }
+}
+
+/// Folder the main menu lists worlds from, each in its own folder
+pub const SAVES_FOLDER: &str = "saves";
+
+/// Folders in `SAVES_FOLDER` with a world saved in them, by name
+pub(crate) fn saved_worlds() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(SAVES_FOLDER) else {
+        return vec![];
+    };
+    let mut worlds: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join(METADATA_FILE).is_file())
+        .collect();
+    worlds.sort();
+    worlds
+}
+
+/// A folder of this process's own in the temporary directory, for worlds
+/// that are thrown away afterwards
+pub(crate) fn scratch_directory(purpose: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bevy-wgpu-demo-{purpose}-{}", std::process::id()))
+}
+
+/// Send to save the world. It's written in the background once the last
+/// save is, and saves still being written when the app exits are finished
+/// first.
+#[derive(Event, Default, Debug, Clone, Copy)]
+pub struct SaveWorld;
+
+/// Time between saves while a world is loaded, or `None` to only save on
+/// exit and when `SaveWorld` is sent
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct AutosaveInterval(pub Option<Duration>);
+
+pub(crate) const DEFAULT_AUTOSAVE_SECS: u32 = 300;
+
+impl Default for AutosaveInterval {
+    fn default() -> Self {
+        Self::from_secs(DEFAULT_AUTOSAVE_SECS)
+    }
+}
+
+impl AutosaveInterval {
+    /// Never autosaves for 0
+    pub fn from_secs(secs: u32) -> Self {
+        Self((secs > 0).then(|| Duration::from_secs(secs.into())))
+    }
+}
+
+/// Blocks a chunk was saved with, or `None` if it was never changed and is
+/// generated instead. Chunks are generated once this is loaded, which takes
+/// the saved blocks.
+#[derive(Component)]
+pub(crate) struct SavedBlocks(pub Option<Blocks>);
+
+/// Chunks read from each region file so far, so a file is read once however
+/// many of its chunks are loaded. Saves update the regions they write.
+#[derive(Resource, Clone, Default)]
+struct RegionCache(Arc<Mutex<HashMap<PathBuf, Arc<Mutex<Option<RegionChunks>>>>>>);
+
+type RegionChunks = HashMap<IVec3, Arc<[u8]>>;
+
+impl RegionCache {
+    /// Saved data of the chunk at `chunk_pos`, reading its region file at
+    /// `path` if no chunk was loaded from it yet. Loads of other chunks in the
+    /// same region wait for the file to be read rather than reading it again.
+    fn chunk(&self, path: &Path, chunk_pos: IVec3) -> io::Result<Option<Arc<[u8]>>> {
+        let region = self
+            .0
+            .lock()
+            .unwrap()
+            .entry(path.to_owned())
+            .or_default()
+            .clone();
+        let mut region = region.lock().unwrap();
+        if region.is_none() {
+            *region = Some(read_region_file(path)?);
+        }
+        Ok(region
+            .as_ref()
+            .and_then(|chunks| chunks.get(&chunk_pos).cloned()))
+    }
+
+    /// Replaces the chunks a save writes to the region file at `path`, if it
+    /// was already read
+    fn update(&self, path: &Path, chunks: &[(IVec3, Vec<u8>)]) {
+        let Some(region) = self.0.lock().unwrap().get(path).cloned() else {
+            return;
+        };
+        if let Some(read) = region.lock().unwrap().as_mut() {
+            read.extend(
+                chunks
+                    .iter()
+                    .map(|(pos, data)| (*pos, Arc::from(data.as_slice()))),
+            );
+        }
+    }
+}
+
+/// Chunks changed since the world was last saved
+#[derive(Resource, Default)]
+struct DirtyChunks(HashSet<IVec3>);
+
+/// Changed chunks that unloaded before they were saved, or that a save
+/// couldn't write, as written by `encode_chunk`. They're kept until a save
+/// writes them, and loaded from here if the chunk loads again first.
+#[derive(Resource, Default)]
+struct UnsavedChunks(HashMap<IVec3, Vec<u8>>);
+
+/// A save being written in the background, until it's reported
+#[derive(Component)]
+struct WorldSave;
+
+/// The chunks a save wrote, once it's written
+#[derive(Component)]
+struct WorldSaved(Result<Vec<IVec3>, FailedSave>);
+
+/// The changed chunks of a save that couldn't be written, which are saved
+/// again next time
+struct FailedSave {
+    error: io::Error,
+    chunks: Vec<(IVec3, Vec<u8>)>,
+}
+
+const METADATA_FILE: &str = "world.txt";
+const REGION_FOLDER: &str = "regions";
+/// Bump whenever the way worlds are saved changes
+const FORMAT_VERSION: u32 = 1;
+/// Chunks along each axis of a region file
+const REGION_SIZE: i32 = 8;
+const REGION_MAGIC: &[u8; 4] = b"VXRG";
+
+/// The player as saved, kept until it's restored onto the spawned player
+#[derive(Resource, Clone, Copy)]
+struct SavedPlayer {
+    translation: Vec3,
+    velocity: Vec3,
+    mode: MovementMode,
+    camera_rotation: Quat,
+}
+
+/// Everything saved besides the chunks, as lines of a key and its value
+struct Metadata {
+    seed: u32,
+    world_type: WorldType,
+    generator_version: u32,
+    /// Names of the blocks by id, from `BlockRegistry::to_metadata`
+    block_names: Vec<String>,
+    player: Option<SavedPlayer>,
+    inventory: Vec<(BlockId, u32)>,
+}
+
+impl Metadata {
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "format {FORMAT_VERSION}");
+        let _ = writeln!(text, "seed {}", self.seed);
+        let world_type: &str = self.world_type.into();
+        let _ = writeln!(text, "world_type {world_type}");
+        let _ = writeln!(text, "generator {}", self.generator_version);
+        for name in &self.block_names {
+            let _ = writeln!(text, "block {name}");
+        }
+        if let Some(player) = self.player {
+            let [x, y, z] = player.translation.to_array();
+            let _ = writeln!(text, "player {x} {y} {z}");
+            let [x, y, z] = player.velocity.to_array();
+            let _ = writeln!(text, "velocity {x} {y} {z}");
+            let _ = writeln!(text, "flying {}", player.mode == MovementMode::Flying);
+            let [x, y, z, w] = player.camera_rotation.to_array();
+            let _ = writeln!(text, "camera {x} {y} {z} {w}");
+        }
+        for (id, count) in &self.inventory {
+            let _ = writeln!(text, "item {} {count}", id.0);
+        }
+        text
+    }
+
+    fn from_text(text: &str) -> io::Result<Self> {
+        let mut seed = None;
+        let mut world_type = WorldType::Hills;
+        let mut generator_version = None;
+        let mut block_names = vec![];
+        let mut translation = None;
+        let mut velocity = Vec3::ZERO;
+        let mut mode = MovementMode::Walking;
+        let mut camera_rotation = Quat::IDENTITY;
+        let mut inventory = vec![];
+        for line in text.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "format" => {
+                    let format: u32 = parse(value)?;
+                    if format != FORMAT_VERSION {
+                        return Err(invalid_data(format!("unsupported format {format}")));
+                    }
+                }
+                "seed" => seed = Some(parse(value)?),
+                "world_type" => world_type = parse(value)?,
+                "generator" => generator_version = Some(parse(value)?),
+                "block" => block_names.push(value.to_owned()),
+                "player" => translation = Some(Vec3::from_array(parse_floats(value)?)),
+                "velocity" => velocity = Vec3::from_array(parse_floats(value)?),
+                "flying" => {
+                    mode = if parse(value)? {
+                        MovementMode::Flying
+                    } else {
+                        MovementMode::Walking
+                    }
+                }
+                "camera" => camera_rotation = Quat::from_array(parse_floats(value)?),
+                "item" => {
+                    let (id, count) = value.split_once(' ').ok_or_else(|| {
+                        invalid_data(format!("expected an id and count in {line:?}"))
+                    })?;
+                    inventory.push((BlockId(parse(id)?), parse(count)?));
+                }
+                "" => {}
+                _ => warn!("Ignoring unknown line in world metadata: {line:?}"),
+            }
+        }
+        Ok(Self {
+            seed: seed.ok_or_else(|| invalid_data("missing seed"))?,
+            world_type,
+            generator_version: generator_version
+                .ok_or_else(|| invalid_data("missing generator version"))?,
+            block_names,
+            player: translation.map(|translation| SavedPlayer {
+                translation,
+                velocity,
+                mode,
+                camera_rotation,
+            }),
+            inventory,
+        })
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> io::Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| invalid_data(format!("couldn't parse {value:?}")))
+}
+
+fn parse_floats<const N: usize>(value: &str) -> io::Result<[f32; N]> {
+    let floats = value
+        .split_whitespace()
+        .map(parse)
+        .collect::<io::Result<Vec<f32>>>()?;
+    floats
+        .try_into()
+        .map_err(|_| invalid_data(format!("expected {N} numbers in {value:?}")))
+}
+
+pub(crate) fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Restores the seed, block ids and inventory before the world starts
+/// generating, keeping the player to restore once it's spawned
+fn load_metadata(mut commands: Commands, directory: Res<SaveDirectory>) {
+    let path = directory.0.join(METADATA_FILE);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("No world saved in {:?}, starting a new one", directory.0);
+            return;
+        }
+        Err(e) => {
+            error!("Couldn't read {:?}: {}", path, e);
+            return;
+        }
+    };
+    let metadata = match Metadata::from_text(&text) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            error!("Couldn't load {:?}: {}", path, e);
+            return;
+        }
+    };
+    if metadata.generator_version != world_gen::GENERATOR_VERSION {
+        warn!(
+            "World was saved with generator version {}, but this is version {}. Chunks that \
+             weren't changed will generate differently.",
+            metadata.generator_version,
+            world_gen::GENERATOR_VERSION
+        );
+    }
+    let mut inventory = Inventory::default();
+    for (id, count) in metadata.inventory {
+        inventory.add(id, count);
+    }
+    commands.insert_resource(WorldSeed(metadata.seed));
+    commands.insert_resource(metadata.world_type);
+    commands.insert_resource(BlockRegistry::from_metadata(&metadata.block_names));
+    commands.insert_resource(inventory);
+    if let Some(player) = metadata.player {
+        commands.insert_resource(player);
+    }
+    info!("Loaded world from {:?}", directory.0);
+}
+
+fn restore_player(
+    mut commands: Commands,
+    saved: Option<Res<SavedPlayer>>,
+    mut q_player: Query<(&mut Transform, &mut Velocity, &mut MovementMode), With<Player>>,
+    mut q_camera: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>,
+) {
+    let Some(saved) = saved else {
+        return;
+    };
+    // Skipping the main menu loads the world before the player is spawned
+    if q_player.is_empty() {
+        return;
+    }
+    for (mut transform, mut velocity, mut mode) in q_player.iter_mut() {
+        transform.translation = saved.translation;
+        velocity.0 = saved.velocity;
+        *mode = saved.mode;
+    }
+    // The camera's pitch and yaw are read from its rotation once it starts
+    // looking around
+    for mut transform in q_camera.iter_mut() {
+        transform.rotation = saved.camera_rotation.normalize();
+    }
+    commands.remove_resource::<SavedPlayer>();
+}
+
+fn load_saved_blocks(
+    q_chunks: Query<
+        (Entity, &ChunkPosition),
+        (
+            With<Chunk>,
+            Without<SavedBlocks>,
+            Without<ComputeInProgress<SavedBlocks>>,
+        ),
+    >,
+    directory: Res<SaveDirectory>,
+    registry: Res<BlockRegistry>,
+    cache: Res<RegionCache>,
+    unsaved: Res<UnsavedChunks>,
+    mut tasks: ResMut<ComputeTasks<SavedBlocks>>,
+) {
+    for (entity, chunk_position) in q_chunks.iter() {
+        let chunk_pos = chunk_position.0;
+        let path = region_path(&directory.0, chunk_pos);
+        let registry = registry.clone();
+        let cache = cache.clone();
+        // Newer than what's in the region file
+        let unsaved = unsaved.0.get(&chunk_pos).cloned();
+        tasks.spawn_task(entity, async move {
+            let blocks = match unsaved {
+                Some(data) => decode_chunk(&data, &registry).map(Some),
+                None => read_saved_chunk(&cache, &path, chunk_pos, &registry),
+            }
+            .unwrap_or_else(|e| {
+                error!("Couldn't load chunk {} from {:?}: {}", chunk_pos, path, e);
+                None
+            });
+            SavedBlocks(blocks)
+        });
+    }
+}
+
+/// Keeps the blocks of a chunk changed since the last save when it unloads,
+/// for the next save to write
+fn keep_unloaded_changes(
+    trigger: Trigger<OnRemove, Blocks>,
+    q_chunks: Query<(&ChunkPosition, &Blocks)>,
+    dirty: Res<DirtyChunks>,
+    registry: Option<Res<BlockRegistry>>,
+    mut unsaved: ResMut<UnsavedChunks>,
+) {
+    let Ok((chunk_position, blocks)) = q_chunks.get(trigger.target()) else {
+        return;
+    };
+    let Some(registry) = registry else {
+        return;
+    };
+    if dirty.0.contains(&chunk_position.0) {
+        unsaved
+            .0
+            .insert(chunk_position.0, encode_chunk(blocks, &registry));
+    }
+}
+
+fn mark_dirty_chunks(mut changed: EventReader<BlockChanged>, mut dirty: ResMut<DirtyChunks>) {
+    for event in changed.read() {
+        dirty
+            .0
+            .insert(event.pos.div_euclid(IVec3::splat(CHUNK_SIZE as i32)));
+    }
+}
+
+/// Real time is used, so the world is still saved while paused
+fn autosave(
+    time: Res<Time<Real>>,
+    interval: Res<AutosaveInterval>,
+    mut since_last_save: Local<Duration>,
+    mut save: EventWriter<SaveWorld>,
+) {
+    let Some(interval) = interval.0 else {
+        return;
+    };
+    *since_last_save += time.delta();
+    if *since_last_save >= interval {
+        *since_last_save = Duration::ZERO;
+        save.write(SaveWorld);
+    }
+}
+
+fn save_on_exit(mut save: EventWriter<SaveWorld>) {
+    save.write(SaveWorld);
+}
+
+#[derive(SystemParam)]
+struct MetadataParam<'w, 's> {
+    seed: Res<'w, WorldSeed>,
+    world_type: Res<'w, WorldType>,
+    registry: Res<'w, BlockRegistry>,
+    inventory: Res<'w, Inventory>,
+    q_player:
+        Query<'w, 's, (&'static Transform, &'static Velocity, &'static MovementMode), With<Player>>,
+    q_camera: Query<'w, 's, &'static Transform, (With<PlayerCamera>, Without<Player>)>,
+    /// Kept by dedicated servers, which have no player to restore it onto
+    saved_player: Option<Res<'w, SavedPlayer>>,
+}
+
+impl MetadataParam<'_, '_> {
+    fn get(&self) -> Metadata {
+        let player = self
+            .q_player
+            .single()
+            .ok()
+            .map(|(transform, velocity, mode)| SavedPlayer {
+                translation: transform.translation,
+                velocity: velocity.0,
+                mode: *mode,
+                camera_rotation: self
+                    .q_camera
+                    .single()
+                    .map_or(Quat::IDENTITY, |transform| transform.rotation),
+            })
+            .or(self.saved_player.as_deref().copied());
+        Metadata {
+            seed: self.seed.0,
+            world_type: *self.world_type,
+            generator_version: world_gen::GENERATOR_VERSION,
+            block_names: self.registry.to_metadata(),
+            player,
+            inventory: self.inventory.stacks().collect(),
+        }
+    }
+}
+
+fn save_world(
+    mut commands: Commands,
+    mut requests: EventReader<SaveWorld>,
+    mut exit: EventReader<AppExit>,
+    mut requested: Local<bool>,
+    q_writing: Query<Entity, (With<WorldSave>, Without<WorldSaved>)>,
+    mut tasks: ResMut<ComputeTasks<WorldSaved>>,
+    directory: Res<SaveDirectory>,
+    cache: Res<RegionCache>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut unsaved: ResMut<UnsavedChunks>,
+    chunk_index: Res<ChunkIndex>,
+    q_blocks: Query<&Blocks>,
+    metadata: MetadataParam,
+) {
+    if !requests.is_empty() {
+        requests.clear();
+        *requested = true;
+    }
+    if !*requested {
+        return;
+    }
+    // Saves rewrite whole region files, so the last one has to be written
+    // before this one reads them. Until then the request waits, unless the
+    // app is exiting and there's no later frame to save in.
+    if !q_writing.is_empty() {
+        if exit.read().count() == 0 {
+            return;
+        }
+        for entity in q_writing.iter() {
+            finish_save(entity, &mut commands, &mut tasks, &mut dirty, &mut unsaved);
+        }
+    }
+    *requested = false;
+    let mut regions: HashMap<PathBuf, Vec<(IVec3, Vec<u8>)>> = HashMap::new();
+    for chunk_pos in dirty.0.drain() {
+        let blocks = chunk_index
+            .get_entity(&chunk_pos)
+            .and_then(|entity| q_blocks.get(*entity).ok());
+        let data = match (blocks, unsaved.0.get(&chunk_pos)) {
+            (Some(blocks), _) => encode_chunk(blocks, &metadata.registry),
+            // Unloaded since it changed
+            (None, Some(data)) => data.clone(),
+            (None, None) => continue,
+        };
+        regions
+            .entry(region_path(&directory.0, chunk_pos))
+            .or_default()
+            .push((chunk_pos, data));
+    }
+    let metadata = metadata.get().to_text();
+    let directory = directory.0.clone();
+    let cache = cache.clone();
+    let entity = commands.spawn(WorldSave).id();
+    tasks.spawn_task(entity, async move {
+        let result = write_save(&directory, &regions, &metadata, &cache);
+        let chunks = regions.into_values().flatten();
+        WorldSaved(match result {
+            Ok(()) => {
+                let chunks: Vec<IVec3> = chunks.map(|(pos, _)| pos).collect();
+                info!(
+                    "Saved the world to {:?}, with {} changed chunks",
+                    directory,
+                    chunks.len()
+                );
+                Ok(chunks)
+            }
+            Err(error) => Err(FailedSave {
+                error,
+                chunks: chunks.collect(),
+            }),
+        })
+    });
+}
+
+fn write_save(
+    directory: &Path,
+    regions: &HashMap<PathBuf, Vec<(IVec3, Vec<u8>)>>,
+    metadata: &str,
+    cache: &RegionCache,
+) -> io::Result<()> {
+    for (path, chunks) in regions {
+        cache.update(path, chunks);
+        write_region(path, chunks)?;
+    }
+    // Written last, so the chunks of a world are all there once it's listed
+    // as saved
+    write_atomically(&directory.join(METADATA_FILE), metadata.as_bytes())
+}
+
+/// Reports saves once they're written, waiting for them all while the app
+/// exits
+fn finish_saves(
+    mut commands: Commands,
+    mut exit: EventReader<AppExit>,
+    q_saved: Query<(Entity, &WorldSaved)>,
+    q_writing: Query<Entity, (With<WorldSave>, Without<WorldSaved>)>,
+    mut tasks: ResMut<ComputeTasks<WorldSaved>>,
+    mut dirty: ResMut<DirtyChunks>,
+    mut unsaved: ResMut<UnsavedChunks>,
+) {
+    for (entity, saved) in q_saved.iter() {
+        report_save(&saved.0, &mut dirty, &mut unsaved);
+        commands.entity(entity).despawn();
+    }
+    if exit.read().count() > 0 {
+        for entity in q_writing.iter() {
+            finish_save(entity, &mut commands, &mut tasks, &mut dirty, &mut unsaved);
+        }
+    }
+}
+
+/// Waits for a save still being written and reports it
+fn finish_save(
+    entity: Entity,
+    commands: &mut Commands,
+    tasks: &mut ComputeTasks<WorldSaved>,
+    dirty: &mut DirtyChunks,
+    unsaved: &mut UnsavedChunks,
+) {
+    if let Some(saved) = tasks.finish(entity) {
+        report_save(&saved.0, dirty, unsaved);
+    }
+    commands.entity(entity).despawn();
+}
+
+/// Forgets the unsaved blocks of chunks a save wrote, unless they changed
+/// again since. Marks the chunks of a failed save as changed again instead,
+/// so the next save retries them.
+fn report_save(
+    result: &Result<Vec<IVec3>, FailedSave>,
+    dirty: &mut DirtyChunks,
+    unsaved: &mut UnsavedChunks,
+) {
+    match result {
+        Ok(chunks) => {
+            for chunk_pos in chunks {
+                if !dirty.0.contains(chunk_pos) {
+                    unsaved.0.remove(chunk_pos);
+                }
+            }
+        }
+        Err(failed) => {
+            error!("Couldn't save the world: {}", failed.error);
+            for (chunk_pos, data) in &failed.chunks {
+                dirty.0.insert(*chunk_pos);
+                // Unless it unloaded with newer blocks since
+                unsaved.0.entry(*chunk_pos).or_insert_with(|| data.clone());
+            }
+        }
+    }
+}
+
+/// Writes to a temporary file that then replaces `path`, so failing part
+/// way through leaves the old file as it was. The temporary file is synced
+/// to disk first, so a crash can't replace `path` with a partly written
+/// file.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("tmp");
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(temp, path)
+}
+
+fn region_path(directory: &Path, chunk_pos: IVec3) -> PathBuf {
+    let region = chunk_pos.div_euclid(IVec3::splat(REGION_SIZE));
+    directory
+        .join(REGION_FOLDER)
+        .join(format!("{}.{}.{}.region", region.x, region.y, region.z))
+}
+
+/// Region files start with `REGION_MAGIC` and the number of chunks, then
+/// each chunk's position, its length in bytes, and the chunk as written by
+/// `encode_chunk`. All numbers are little-endian.
+fn read_region(bytes: &[u8]) -> io::Result<Vec<(IVec3, Vec<u8>)>> {
+    let mut reader = Reader(bytes);
+    if reader.bytes(REGION_MAGIC.len())? != REGION_MAGIC {
+        return Err(invalid_data("not a region file"));
+    }
+    let count = reader.u32()?;
+    (0..count)
+        .map(|_| {
+            let pos = IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+            let len = reader.u32()? as usize;
+            Ok((pos, reader.bytes(len)?.to_vec()))
+        })
+        .collect()
+}
+
+/// Replaces the given chunks in the region file at `path`, keeping the rest
+fn write_region(path: &Path, chunks: &[(IVec3, Vec<u8>)]) -> io::Result<()> {
+    let mut entries = match fs::read(path) {
+        Ok(bytes) => read_region(&bytes)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e),
+    };
+    for (pos, data) in chunks {
+        match entries.iter_mut().find(|(saved_pos, _)| saved_pos == pos) {
+            Some(entry) => entry.1 = data.clone(),
+            None => entries.push((*pos, data.clone())),
+        }
+    }
+    let mut bytes = REGION_MAGIC.to_vec();
+    bytes.extend((entries.len() as u32).to_le_bytes());
+    for (pos, data) in &entries {
+        for x in pos.to_array() {
+            bytes.extend(x.to_le_bytes());
+        }
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+    }
+    write_atomically(path, &bytes)
+}
+
+/// Chunks of the region file at `path`, or none if it doesn't exist
+fn read_region_file(path: &Path) -> io::Result<RegionChunks> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(read_region(&bytes)?
+            .into_iter()
+            .map(|(pos, data)| (pos, Arc::from(data)))
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Saved blocks of the chunk at `chunk_pos`, or `None` if it was never saved
+fn read_saved_chunk(
+    cache: &RegionCache,
+    path: &Path,
+    chunk_pos: IVec3,
+    registry: &BlockRegistry,
+) -> io::Result<Option<Blocks>> {
+    match cache.chunk(path, chunk_pos)? {
+        Some(data) => decode_chunk(&data, registry).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// A palette of the different blocks in the chunk, each as its id and state,
+/// then runs of the same block in the order of `Blocks::iter`, each as its
+/// length and index into the palette
+pub(crate) fn encode_chunk(blocks: &Blocks, registry: &BlockRegistry) -> Vec<u8> {
+    let mut palette: Vec<PlacedBlock> = vec![];
+    let mut runs: Vec<(u16, u16)> = vec![];
+    for placed in blocks.iter() {
+        let index = match palette.iter().position(|p| p == placed) {
+            Some(index) => index,
+            None => {
+                palette.push(*placed);
+                palette.len() - 1
+            }
+        } as u16;
+        match runs.last_mut() {
+            Some((len, last)) if *last == index && *len < u16::MAX => *len += 1,
+            _ => runs.push((1, index)),
+        }
+    }
+    let mut bytes = vec![];
+    bytes.extend((palette.len() as u16).to_le_bytes());
+    for placed in &palette {
+        bytes.extend(registry.id(placed.block).0.to_le_bytes());
+        bytes.push(placed.state.to_bits());
+    }
+    bytes.extend((runs.len() as u32).to_le_bytes());
+    for (len, index) in runs {
+        bytes.extend(len.to_le_bytes());
+        bytes.extend(index.to_le_bytes());
+    }
+    bytes
+}
+
+pub(crate) fn decode_chunk(data: &[u8], registry: &BlockRegistry) -> io::Result<Blocks> {
+    let mut reader = Reader(data);
+    let palette_len = reader.u16()?;
+    let palette = (0..palette_len)
+        .map(|_| {
+            Ok(PlacedBlock {
+                block: registry.block(BlockId(reader.u16()?)),
+                state: BlockState::from_bits(reader.u8()?),
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let run_count = reader.u32()?;
+    let block_count = CHUNK_SIZE.pow(3);
+    let mut blocks = Vec::with_capacity(block_count);
+    for _ in 0..run_count {
+        let len = reader.u16()? as usize;
+        let index = reader.u16()? as usize;
+        let placed = palette
+            .get(index)
+            .ok_or_else(|| invalid_data("palette index out of range"))?;
+        if blocks.len() + len > block_count {
+            return Err(invalid_data("more blocks than fit in a chunk"));
+        }
+        blocks.extend(std::iter::repeat_n(*placed, len));
+    }
+    Blocks::from_blocks(blocks).ok_or_else(|| invalid_data("fewer blocks than fill a chunk"))
+}
+
+/// Reads little-endian numbers off the front of some bytes
+pub(crate) struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid_data("ended early"));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().expect("took N bytes"))
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(u8::from_le_bytes(self.array()?))
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+}
@@ -0,0 +1,550 @@
+use bevy::{
+    math::{
+        Vec3A,
+        bounding::{Aabb3d, BoundingVolume},
+    },
+    prelude::*,
+};
+use lib_chunk::ChunkIndex;
+use lib_render::camera::{ProjectionSettings, RenderCamera};
+use lib_utils::iter_3d;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::{BlockTag, CollisionShape},
+    game_state::GameState,
+    world_gen::{self, Blocks},
+};
+
+/// Walks the player through the world with gravity and jumping, colliding
+/// with blocks. `PlayerControls::toggle_flying` switches between walking and
+/// flying through everything, as does double tapping jump while flying or in
+/// the air after a jump. Walking into a ledge up to
+/// `PlayerPhysics::max_step_height` high steps up onto it. In fluids, the
+/// player is buoyed up, slowed down and swims up while holding jump. Holding
+/// sprint while walking forwards speeds the player up and widens the camera's
+/// view, and holding crouch slows them down, lowers their eyes and keeps them
+/// from walking off edges.
+///
+/// The camera is a child of the player, raised to its eyes by
+/// `PlayerCamera`. The camera only turns itself.
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerPhysics>()
+            .init_resource::<PlayerControls>()
+            .add_systems(Startup, spawn_player)
+            .add_systems(
+                Update,
+                (
+                    toggle_flying,
+                    update_stance,
+                    move_player,
+                    raise_camera_to_eyes,
+                    kick_fov,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+#[derive(Component)]
+#[require(
+    Transform,
+    Visibility,
+    PlayerBody,
+    Velocity,
+    Grounded,
+    MovementMode,
+    Stance,
+    StepOffset,
+    CrouchOffset
+)]
+pub struct Player;
+
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MovementMode {
+    /// Falls, and collides with blocks
+    #[default]
+    Walking,
+    /// Moves freely in every direction, passing through blocks
+    Flying,
+}
+
+/// How the player is moving on foot. Always `Standing` while flying.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Stance {
+    #[default]
+    Standing,
+    /// Faster, while walking forwards
+    Sprinting,
+    /// Slower, with lowered eyes, and without walking off edges
+    Crouching,
+}
+
+/// Size of the player's collision box, which stands on the player's
+/// translation
+#[derive(Component, Clone, Copy)]
+pub struct PlayerBody {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for PlayerBody {
+    fn default() -> Self {
+        Self {
+            width: 0.6,
+            height: 1.8,
+        }
+    }
+}
+
+/// Camera attached to the player it's a child of. The player's movement is
+/// relative to where it looks.
+#[derive(Component, Clone, Copy)]
+#[require(FovKick)]
+pub struct PlayerCamera {
+    /// Height above the player's feet
+    pub eye_height: f32,
+    /// How fast the camera catches up after the player steps up, in blocks
+    /// per second, or `None` to move up with the player at once
+    pub smoothing: Option<f32>,
+    /// How far crouching lowers the eyes, in blocks
+    pub crouch_drop: f32,
+    /// Added to the camera's field of view while sprinting, in radians
+    pub sprint_fov_kick: f32,
+}
+
+impl Default for PlayerCamera {
+    fn default() -> Self {
+        Self {
+            eye_height: 1.62,
+            smoothing: Some(6.0),
+            crouch_drop: 0.35,
+            sprint_fov_kick: 0.1,
+        }
+    }
+}
+
+/// How much wider the camera's field of view currently is than its
+/// `ProjectionSettings` would otherwise be, in radians. Eases towards the
+/// kick for the player's `Stance`.
+#[derive(Component, Default, Clone, Copy)]
+pub struct FovKick(pub f32);
+
+impl PlayerBody {
+    pub fn aabb(&self, feet: Vec3) -> Aabb3d {
+        let half_width = self.width / 2.0;
+        Aabb3d {
+            min: (feet - Vec3::new(half_width, 0.0, half_width)).into(),
+            max: (feet + Vec3::new(half_width, self.height, half_width)).into(),
+        }
+    }
+}
+
+/// In blocks per second
+#[derive(Component, Default, Clone, Copy)]
+pub struct Velocity(pub Vec3);
+
+/// How far the player has stepped up that their camera hasn't caught up with
+#[derive(Component, Default, Clone, Copy)]
+pub struct StepOffset(pub f32);
+
+/// How far crouching has lowered the player's eyes so far
+#[derive(Component, Default, Clone, Copy)]
+pub struct CrouchOffset(pub f32);
+
+/// Whether the player is standing on something
+#[derive(Component, Default, Clone, Copy)]
+pub struct Grounded(pub bool);
+
+/// Keys are saved in `Settings` as their variant of `KeyCode`, like `"KeyW"`
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerControls {
+    pub forward: KeyCode,
+    pub backward: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    /// Also flies up
+    pub jump: KeyCode,
+    /// Flies down
+    pub descend: KeyCode,
+    /// Flies faster
+    pub speed_up: KeyCode,
+    pub toggle_flying: KeyCode,
+    pub sprint: KeyCode,
+    pub crouch: KeyCode,
+}
+
+impl Default for PlayerControls {
+    fn default() -> Self {
+        Self {
+            forward: KeyCode::KeyW,
+            backward: KeyCode::KeyS,
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            jump: KeyCode::Space,
+            descend: KeyCode::KeyQ,
+            speed_up: KeyCode::KeyR,
+            toggle_flying: KeyCode::KeyF,
+            sprint: KeyCode::ControlLeft,
+            crouch: KeyCode::ShiftLeft,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct PlayerPhysics {
+    /// In blocks per second
+    pub walk_speed: f32,
+    /// Upwards speed at the start of a jump, in blocks per second
+    pub jump_speed: f32,
+    /// In blocks per second squared
+    pub gravity: f32,
+    /// In blocks per second
+    pub max_fall_speed: f32,
+    /// In blocks per second
+    pub fly_speed: f32,
+    /// Multiplies `fly_speed` while the speed up key is held
+    pub fly_boost: f32,
+    /// Highest ledge the player steps up onto by walking into it, or `None`
+    /// to not step up
+    pub max_step_height: Option<f32>,
+    /// Upwards acceleration while fully in fluid, in blocks per second
+    /// squared. Just under `gravity`, so the player slowly sinks.
+    pub buoyancy: f32,
+    /// How quickly fluid slows the player's fall or rise, per second
+    pub fluid_drag: f32,
+    /// Upwards speed while holding jump in fluid, in blocks per second
+    pub swim_speed: f32,
+    /// Multiplies `walk_speed` while fully in fluid
+    pub fluid_walk_factor: f32,
+    /// Multiplies `walk_speed` while sprinting
+    pub sprint_factor: f32,
+    /// Multiplies `walk_speed` while crouching
+    pub crouch_factor: f32,
+}
+
+impl Default for PlayerPhysics {
+    fn default() -> Self {
+        Self {
+            walk_speed: 4.3,
+            jump_speed: 8.5,
+            gravity: 28.0,
+            max_fall_speed: 60.0,
+            fly_speed: 7.5,
+            fly_boost: 10.0,
+            max_step_height: Some(1.0),
+            buoyancy: 25.0,
+            fluid_drag: 3.0,
+            swim_speed: 3.0,
+            fluid_walk_factor: 0.5,
+            sprint_factor: 1.3,
+            crouch_factor: 0.3,
+        }
+    }
+}
+
+/// Longest frame simulated in one step, so a hitch doesn't launch the player
+const MAX_TIME_STEP: f32 = 0.05;
+
+fn spawn_player(mut commands: Commands) {
+    let camera = PlayerCamera::default();
+    commands.spawn((
+        Player,
+        // Above the highest generated terrain, falling onto it once it's
+        // loaded
+        Transform::from_xyz(0.0, 16.0, 0.0),
+        children![(
+            Camera3d::default(),
+            camera,
+            Transform::from_xyz(0.0, camera.eye_height, 0.0),
+            RenderCamera,
+        )],
+    ));
+}
+
+/// Longest time between two presses of jump that toggles flying
+const DOUBLE_TAP_WINDOW: f32 = 0.3;
+
+fn toggle_flying(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    controls: Res<PlayerControls>,
+    physics: Res<PlayerPhysics>,
+    mut last_jump_press: Local<Option<f32>>,
+    mut q_player: Query<(&mut MovementMode, &mut Velocity, &mut Grounded), With<Player>>,
+) {
+    let now = time.elapsed_secs();
+    let pressed_toggle = keys.just_pressed(controls.toggle_flying);
+    let mut double_tapped = false;
+    if keys.just_pressed(controls.jump) {
+        double_tapped = last_jump_press.is_some_and(|last| now - last <= DOUBLE_TAP_WINDOW);
+        // A third tap starts a new double tap rather than toggling back
+        *last_jump_press = if double_tapped { None } else { Some(now) };
+    }
+    if !pressed_toggle && !double_tapped {
+        return;
+    }
+    for (mut mode, mut velocity, mut grounded) in q_player.iter_mut() {
+        // Jumping again right after landing, like when hopping up stairs,
+        // keeps walking
+        if !pressed_toggle && *mode == MovementMode::Walking && grounded.0 {
+            continue;
+        }
+        *mode = match *mode {
+            MovementMode::Walking => {
+                // Hover where flying started, rather than carrying on
+                // jumping or falling
+                velocity.0.y = 0.0;
+                grounded.0 = false;
+                MovementMode::Flying
+            }
+            MovementMode::Flying => {
+                // Carries on the way the player was heading, but without
+                // launching higher than a jump
+                velocity.0.y = velocity.0.y.min(physics.jump_speed);
+                MovementMode::Walking
+            }
+        };
+    }
+}
+
+fn update_stance(
+    keys: Res<ButtonInput<KeyCode>>,
+    controls: Res<PlayerControls>,
+    mut q_player: Query<(&mut Stance, &MovementMode), With<Player>>,
+) {
+    for (mut stance, mode) in q_player.iter_mut() {
+        let new_stance = if *mode == MovementMode::Flying {
+            Stance::Standing
+        } else if keys.pressed(controls.crouch) {
+            Stance::Crouching
+        } else if keys.pressed(controls.sprint) && keys.pressed(controls.forward) {
+            Stance::Sprinting
+        } else {
+            Stance::Standing
+        };
+        stance.set_if_neq(new_stance);
+    }
+}
+
+fn move_player(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    controls: Res<PlayerControls>,
+    physics: Res<PlayerPhysics>,
+    q_camera: Query<&Transform, (With<PlayerCamera>, Without<Player>)>,
+    mut q_player: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut Grounded,
+            &mut StepOffset,
+            &PlayerBody,
+            &MovementMode,
+            &Stance,
+        ),
+        With<Player>,
+    >,
+    chunk_index: Res<ChunkIndex>,
+    q_blocks: Query<&Blocks>,
+) {
+    let Ok(camera) = q_camera.single() else {
+        return;
+    };
+    let dt = time.delta_secs().min(MAX_TIME_STEP);
+    let forward = camera.forward().with_y(0.0).normalize_or_zero();
+    let right = camera.right().with_y(0.0).normalize_or_zero();
+    let mut wish = Vec3::ZERO;
+    for (key, direction) in [
+        (controls.forward, forward),
+        (controls.backward, -forward),
+        (controls.right, right),
+        (controls.left, -right),
+    ] {
+        if keys.pressed(key) {
+            wish += direction;
+        }
+    }
+    let wish = wish.normalize_or_zero();
+    let mut fly_vertical = 0.0;
+    if keys.pressed(controls.jump) {
+        fly_vertical += 1.0;
+    }
+    if keys.pressed(controls.descend) {
+        fly_vertical -= 1.0;
+    }
+    let fly_speed = if keys.pressed(controls.speed_up) {
+        physics.fly_speed * physics.fly_boost
+    } else {
+        physics.fly_speed
+    };
+
+    // Chunks that aren't generated yet are solid, so the player waits for
+    // them instead of falling through
+    let boxes_at = |pos: IVec3| match world_gen::block_at(&chunk_index, &q_blocks, pos) {
+        Some(block) => block.collision_aabbs(),
+        None => CollisionShape::FullCube.aabbs(),
+    };
+    let is_fluid = |pos: IVec3| {
+        world_gen::block_at(&chunk_index, &q_blocks, pos)
+            .is_some_and(|block| block.has_tag(BlockTag::Fluid))
+    };
+    for (mut transform, mut velocity, mut grounded, mut step_offset, body, mode, stance) in
+        q_player.iter_mut()
+    {
+        if *mode == MovementMode::Flying {
+            velocity.0 = (wish + Vec3::Y * fly_vertical).normalize_or_zero() * fly_speed;
+            transform.translation += velocity.0 * dt;
+            continue;
+        }
+        let submerged = submerged_fraction(body.aabb(transform.translation), is_fluid);
+        let stance_factor = match stance {
+            Stance::Standing => 1.0,
+            Stance::Sprinting => physics.sprint_factor,
+            Stance::Crouching => physics.crouch_factor,
+        };
+        let fluid_factor = 1.0 - submerged * (1.0 - physics.fluid_walk_factor);
+        let walk_speed = physics.walk_speed * stance_factor * fluid_factor;
+        velocity.0.x = wish.x * walk_speed;
+        velocity.0.z = wish.z * walk_speed;
+        if grounded.0 && keys.pressed(controls.jump) {
+            velocity.0.y = physics.jump_speed;
+        }
+        let buoyancy = physics.buoyancy * submerged;
+        velocity.0.y += (buoyancy - physics.gravity) * dt;
+        if submerged > 0.0 {
+            velocity.0.y *= (-physics.fluid_drag * submerged * dt).exp();
+            if keys.pressed(controls.jump) {
+                velocity.0.y = velocity.0.y.max(physics.swim_speed);
+            }
+        }
+        velocity.0.y = velocity.0.y.max(-physics.max_fall_speed);
+
+        // Collision boxes are relative to the corner of their block, half a
+        // block below its position
+        let aabb = body.aabb(transform.translation + Vec3::splat(0.5));
+        let delta = velocity.0 * dt;
+        let mut movement = lib_voxel_physics::move_and_slide(aabb, delta, boxes_at);
+        let can_step = grounded.0 && (movement.blocked[0] || movement.blocked[2]);
+        if let Some(max_step_height) = physics.max_step_height.filter(|_| can_step) {
+            let horizontal = |v: Vec3| v.with_y(0.0).length_squared();
+            let stepped = lib_voxel_physics::step_up(aabb, delta, max_step_height, boxes_at)
+                .filter(|stepped| horizontal(stepped.moved) > horizontal(movement.moved));
+            if let Some(stepped) = stepped {
+                step_offset.0 += stepped.moved.y - movement.moved.y;
+                movement = stepped;
+            }
+        }
+        if *stance == Stance::Crouching && grounded.0 {
+            // Keeps each horizontal axis of the move only if the player
+            // still stands on something after it
+            let mut kept = Vec3::Y * movement.moved.y;
+            for axis in [0, 2] {
+                let mut tried = kept;
+                tried[axis] = movement.moved[axis];
+                if lib_voxel_physics::is_grounded(aabb.translated_by(tried), boxes_at) {
+                    kept = tried;
+                } else {
+                    movement.blocked[axis] = true;
+                }
+            }
+            movement.moved = kept;
+        }
+        transform.translation += movement.moved;
+        grounded.0 = movement.blocked[1] && velocity.0.y < 0.0;
+        for (axis, blocked) in movement.blocked.into_iter().enumerate() {
+            if blocked {
+                velocity.0[axis] = 0.0;
+            }
+        }
+    }
+}
+
+/// Fraction of `aabb`'s volume inside blocks where `is_fluid` is true
+fn submerged_fraction(aabb: Aabb3d, is_fluid: impl Fn(IVec3) -> bool) -> f32 {
+    let volume = (aabb.max - aabb.min).element_product();
+    if volume <= 0.0 {
+        return 0.0;
+    }
+    let min = world_gen::block_pos_containing(aabb.min.into());
+    let max = world_gen::block_pos_containing(aabb.max.into());
+    let mut submerged = 0.0;
+    for (x, y, z) in iter_3d(min.x..=max.x, min.y..=max.y, min.z..=max.z) {
+        let pos = IVec3::new(x, y, z);
+        if !is_fluid(pos) {
+            continue;
+        }
+        // Blocks are centred on their position
+        let block_min = pos.as_vec3a() - Vec3A::splat(0.5);
+        let block_max = block_min + Vec3A::ONE;
+        let overlap = (aabb.max.min(block_max) - aabb.min.max(block_min)).max(Vec3A::ZERO);
+        submerged += overlap.element_product();
+    }
+    (submerged / volume).min(1.0)
+}
+
+/// How fast the eyes lower or rise when crouching starts or stops, in blocks
+/// per second
+const CROUCH_SPEED: f32 = 2.0;
+
+fn raise_camera_to_eyes(
+    time: Res<Time>,
+    mut q_player: Query<(&mut StepOffset, &mut CrouchOffset, &Stance), With<Player>>,
+    mut q_camera: Query<(&mut Transform, &PlayerCamera, &ChildOf)>,
+) {
+    let dt = time.delta_secs();
+    for (mut transform, camera, child_of) in q_camera.iter_mut() {
+        let Ok((mut step_offset, mut crouch_offset, stance)) = q_player.get_mut(child_of.parent())
+        else {
+            continue;
+        };
+        step_offset.0 = match camera.smoothing {
+            Some(speed) => (step_offset.0 - speed * dt).max(0.0),
+            None => 0.0,
+        };
+        let crouch_target = if *stance == Stance::Crouching {
+            camera.crouch_drop
+        } else {
+            0.0
+        };
+        let max_change = CROUCH_SPEED * dt;
+        crouch_offset.0 += (crouch_target - crouch_offset.0).clamp(-max_change, max_change);
+        transform.translation = Vec3::Y * (camera.eye_height - step_offset.0 - crouch_offset.0);
+    }
+}
+
+/// How quickly the field of view eases towards its kick, per second
+const FOV_KICK_RATE: f32 = 10.0;
+
+fn kick_fov(
+    time: Res<Time>,
+    q_player: Query<&Stance, With<Player>>,
+    mut q_camera: Query<(
+        &mut ProjectionSettings,
+        &mut FovKick,
+        &PlayerCamera,
+        &ChildOf,
+    )>,
+) {
+    let ease = 1.0 - (-FOV_KICK_RATE * time.delta_secs()).exp();
+    for (mut projection, mut kick, camera, child_of) in q_camera.iter_mut() {
+        let Ok(stance) = q_player.get(child_of.parent()) else {
+            continue;
+        };
+        let target = if *stance == Stance::Sprinting {
+            camera.sprint_fov_kick
+        } else {
+            0.0
+        };
+        let new_kick = kick.0.lerp(target, ease);
+        if new_kick != kick.0 {
+            projection.fov += new_kick - kick.0;
+            kick.0 = new_kick;
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use lib_chunk::ChunkIndex;
+use lib_render::Normal;
+
+use crate::{
+    block::Block,
+    world_gen::{self, Blocks},
+};
+
+/// Casts rays through the blocks of the loaded chunks, e.g. to find the block
+/// under the crosshair. Chunks that aren't generated yet are passed through.
+#[derive(SystemParam)]
+pub struct Raycaster<'w, 's> {
+    chunk_index: Res<'w, ChunkIndex>,
+    q_blocks: Query<'w, 's, &'static Blocks>,
+}
+
+/// Block a ray stopped at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    pub pos: IVec3,
+    /// Face the ray entered through, or `None` when it started inside the
+    /// block
+    pub normal: Option<Normal>,
+    pub block: Block,
+    /// Distance from the origin to where the ray entered the block, in blocks
+    pub distance: f32,
+}
+
+impl Raycaster<'_, '_> {
+    /// Block at `pos` in world space, or `None` while its chunk isn't
+    /// generated
+    pub fn block_at(&self, pos: IVec3) -> Option<Block> {
+        world_gen::block_at(&self.chunk_index, &self.q_blocks, pos)
+    }
+
+    /// First block within `max_distance` of `origin` along `direction` that
+    /// `is_hit` is true for. Blocks are centred on their position, like
+    /// everything else in world space.
+    pub fn cast(
+        &self,
+        origin: Vec3,
+        direction: Dir3,
+        max_distance: f32,
+        mut is_hit: impl FnMut(Block) -> bool,
+    ) -> Option<RaycastHit> {
+        // The raycast's voxels start at their position, half a block along
+        // from the blocks centred on theirs
+        let hit = lib_utils::voxel_raycast(
+            (origin + Vec3::splat(0.5)).to_array(),
+            direction.to_array(),
+            max_distance,
+            |pos| self.block_at(IVec3::from(pos)).is_some_and(&mut is_hit),
+        )?;
+        let pos = IVec3::from(hit.pos);
+        Some(RaycastHit {
+            pos,
+            normal: Normal::from_unit_direction(IVec3::from(hit.normal)),
+            block: self.block_at(pos)?,
+            distance: hit.distance,
+        })
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use bevy::{
+    input::{
+        ButtonState, InputSystem,
+        keyboard::{Key, KeyboardInput, NativeKey},
+        mouse::{MouseButtonInput, MouseMotion, MouseScrollUnit, MouseWheel},
+    },
+    prelude::*,
+    time::TimeUpdateStrategy,
+};
+use lib_first_person_camera::FirstPersonCameraSystems;
+
+use crate::{
+    game_state::GameState,
+    persistence::{self, SaveDirectory},
+    settings::{self, Settings, SettingsFile},
+    world_gen::{WorldSeed, WorldType},
+};
+
+/// Records the input of each frame played to a replay file, or plays one
+/// back in place of the real input. Replays start on a new world with the
+/// recorded seed and settings, from the first frame of playing, and each
+/// frame takes as long as it was recorded taking. Only record on new worlds
+/// for a replay to play out the same, as the world and player aren't saved
+/// with it.
+pub enum ReplayPlugin {
+    Record(PathBuf),
+    Play(PathBuf),
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        match self {
+            Self::Record(path) => {
+                app.insert_resource(ReplayRecorder {
+                    path: path.clone(),
+                    writer: None,
+                })
+                .add_systems(
+                    OnEnter(GameState::Playing),
+                    start_recording.run_if(not(recording)),
+                )
+                .add_systems(
+                    PreUpdate,
+                    record_input.before(InputSystem).run_if(recording),
+                )
+                .add_systems(
+                    Last,
+                    finish_recording.run_if(on_event::<AppExit>.and(recording)),
+                );
+            }
+            Self::Play(path) => match Replay::read(path) {
+                Ok(replay) => replay.prepare(app),
+                Err(e) => {
+                    error!("Couldn't read the replay {:?}: {}", path, e);
+                    app.add_systems(Startup, |mut exit: EventWriter<AppExit>| {
+                        exit.write(AppExit::error());
+                    });
+                }
+            },
+        }
+    }
+}
+
+/// Bump whenever the replay format changes
+const REPLAY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+enum ReplayEvent {
+    Key(KeyCode, ButtonState),
+    MouseButton(MouseButton, ButtonState),
+    MouseMotion(Vec2),
+    MouseWheel(MouseScrollUnit, Vec2),
+}
+
+struct ReplayFrame {
+    /// Real time the frame took
+    delta: Duration,
+    events: Vec<ReplayEvent>,
+}
+
+/// A replay file, as lines of a key and its value. The seed, world type and
+/// each line of the settings come first, then each frame's duration in
+/// seconds followed by its input.
+struct Replay {
+    seed: u32,
+    world_type: WorldType,
+    /// As written to the settings file
+    settings: String,
+    frames: VecDeque<ReplayFrame>,
+}
+
+impl Replay {
+    fn read(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut seed = None;
+        let mut world_type = WorldType::default();
+        let mut settings = String::new();
+        let mut frames: VecDeque<ReplayFrame> = VecDeque::new();
+        for line in text.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let mut values = value.split_whitespace();
+            let mut next = || {
+                values
+                    .next()
+                    .ok_or_else(|| invalid_data(format!("missing value in {line:?}")))
+            };
+            let event = match key {
+                "replay" => {
+                    let version: u32 = parse(next()?)?;
+                    if version != REPLAY_VERSION {
+                        return Err(invalid_data(format!("unsupported version {version}")));
+                    }
+                    continue;
+                }
+                "seed" => {
+                    seed = Some(parse(next()?)?);
+                    continue;
+                }
+                "world_type" => {
+                    world_type = parse(next()?)?;
+                    continue;
+                }
+                "settings" => {
+                    let _ = writeln!(settings, "{value}");
+                    continue;
+                }
+                "frame" => {
+                    let delta = Duration::try_from_secs_f64(parse(next()?)?)
+                        .map_err(|_| invalid_data(format!("invalid frame in {line:?}")))?;
+                    frames.push_back(ReplayFrame {
+                        delta,
+                        events: vec![],
+                    });
+                    continue;
+                }
+                "key" => ReplayEvent::Key(
+                    settings::parse_key(next()?)
+                        .ok_or_else(|| invalid_data(format!("unknown key in {line:?}")))?,
+                    parse_button_state(next()?)?,
+                ),
+                "mouse_button" => ReplayEvent::MouseButton(
+                    parse_mouse_button(next()?)?,
+                    parse_button_state(next()?)?,
+                ),
+                "mouse_motion" => {
+                    ReplayEvent::MouseMotion(Vec2::new(parse(next()?)?, parse(next()?)?))
+                }
+                "mouse_wheel" => {
+                    let unit = match next()? {
+                        "line" => MouseScrollUnit::Line,
+                        "pixel" => MouseScrollUnit::Pixel,
+                        _ => return Err(invalid_data(format!("unknown unit in {line:?}"))),
+                    };
+                    ReplayEvent::MouseWheel(unit, Vec2::new(parse(next()?)?, parse(next()?)?))
+                }
+                "" => continue,
+                _ => {
+                    warn!("Ignoring unknown line in replay: {line:?}");
+                    continue;
+                }
+            };
+            frames
+                .back_mut()
+                .ok_or_else(|| invalid_data("input before the first frame"))?
+                .events
+                .push(event);
+        }
+        Ok(Self {
+            seed: seed.ok_or_else(|| invalid_data("missing seed"))?,
+            world_type,
+            settings,
+            frames,
+        })
+    }
+
+    /// Starts a new world in a scratch directory with the replay's seed and
+    /// settings, to play the frames back on once it's loaded, so nothing of
+    /// the player's is loaded or overwritten
+    fn prepare(self, app: &mut App) {
+        let scratch = persistence::scratch_directory("replay");
+        let settings_file = scratch.join("settings.toml");
+        if let Err(e) =
+            fs::create_dir_all(&scratch).and_then(|()| fs::write(&settings_file, &self.settings))
+        {
+            error!(
+                "Couldn't write the replay's settings to {:?}: {}",
+                settings_file, e
+            );
+        }
+        info!("Replaying {} frames", self.frames.len());
+        app.insert_resource(WorldSeed(self.seed))
+            .insert_resource(self.world_type)
+            .insert_resource(SaveDirectory(scratch.join("world")))
+            .insert_resource(SettingsFile(settings_file))
+            .insert_resource(ReplayPlayback {
+                frames: self.frames,
+                started: false,
+            })
+            .add_systems(OnEnter(GameState::Playing), start_playback)
+            .add_systems(
+                PreUpdate,
+                play_back_input
+                    .before(InputSystem)
+                    .before(FirstPersonCameraSystems),
+            );
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid_data(format!("couldn't parse {value:?}")))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn button_state_name(state: ButtonState) -> &'static str {
+    match state {
+        ButtonState::Pressed => "pressed",
+        ButtonState::Released => "released",
+    }
+}
+
+fn parse_button_state(name: &str) -> io::Result<ButtonState> {
+    match name {
+        "pressed" => Ok(ButtonState::Pressed),
+        "released" => Ok(ButtonState::Released),
+        _ => Err(invalid_data(format!("unknown button state {name:?}"))),
+    }
+}
+
+/// Mouse buttons are written like their `Debug`, such as `Left` or
+/// `Other(8)`
+fn parse_mouse_button(name: &str) -> io::Result<MouseButton> {
+    Ok(match name {
+        "Left" => MouseButton::Left,
+        "Right" => MouseButton::Right,
+        "Middle" => MouseButton::Middle,
+        "Back" => MouseButton::Back,
+        "Forward" => MouseButton::Forward,
+        _ => MouseButton::Other(parse(
+            name.strip_prefix("Other(")
+                .and_then(|n| n.strip_suffix(')'))
+                .unwrap_or(name),
+        )?),
+    })
+}
+
+#[derive(Resource)]
+struct ReplayRecorder {
+    path: PathBuf,
+    /// Opened once the game starts playing
+    writer: Option<BufWriter<File>>,
+}
+
+fn recording(recorder: Res<ReplayRecorder>) -> bool {
+    recorder.writer.is_some()
+}
+
+/// Opens the replay and writes what the world starts from, which is all
+/// known by the time the game starts playing
+fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    seed: Res<WorldSeed>,
+    world_type: Res<WorldType>,
+    settings: Res<Settings>,
+) {
+    let mut header = String::new();
+    let _ = writeln!(header, "replay {REPLAY_VERSION}");
+    let _ = writeln!(header, "seed {}", seed.0);
+    let world_type: &str = (*world_type).into();
+    let _ = writeln!(header, "world_type {world_type}");
+    for line in settings.to_toml().lines() {
+        let _ = writeln!(header, "settings {line}");
+    }
+    let writer = File::create(&recorder.path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        writer.write_all(header.as_bytes())?;
+        Ok(writer)
+    });
+    match writer {
+        Ok(writer) => {
+            info!("Recording a replay to {:?}", recorder.path);
+            recorder.writer = Some(writer);
+        }
+        Err(e) => error!("Couldn't record a replay to {:?}: {}", recorder.path, e),
+    }
+}
+
+fn record_input(
+    time: Res<Time<Real>>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut keys: EventReader<KeyboardInput>,
+    mut mouse_buttons: EventReader<MouseButtonInput>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+) {
+    let mut frame = String::new();
+    let _ = writeln!(frame, "frame {}", time.delta_secs_f64());
+    // Held keys repeating don't change what's pressed
+    for event in keys.read().filter(|event| !event.repeat) {
+        let state = button_state_name(event.state);
+        let _ = writeln!(frame, "key {:?} {state}", event.key_code);
+    }
+    for event in mouse_buttons.read() {
+        let state = button_state_name(event.state);
+        let _ = writeln!(frame, "mouse_button {:?} {state}", event.button);
+    }
+    for event in mouse_motion.read() {
+        let _ = writeln!(frame, "mouse_motion {} {}", event.delta.x, event.delta.y);
+    }
+    for event in mouse_wheel.read() {
+        let unit = match event.unit {
+            MouseScrollUnit::Line => "line",
+            MouseScrollUnit::Pixel => "pixel",
+        };
+        let _ = writeln!(frame, "mouse_wheel {unit} {} {}", event.x, event.y);
+    }
+    let Some(writer) = recorder.writer.as_mut() else {
+        return;
+    };
+    if let Err(e) = writer.write_all(frame.as_bytes()) {
+        error!("Stopped recording the replay: {}", e);
+        recorder.writer = None;
+    }
+}
+
+fn finish_recording(mut recorder: ResMut<ReplayRecorder>) {
+    let Some(mut writer) = recorder.writer.take() else {
+        return;
+    };
+    match writer.flush() {
+        Ok(()) => info!("Saved the replay to {:?}", recorder.path),
+        Err(e) => error!("Couldn't save the replay to {:?}: {}", recorder.path, e),
+    }
+}
+
+#[derive(Resource)]
+struct ReplayPlayback {
+    frames: VecDeque<ReplayFrame>,
+    started: bool,
+}
+
+/// Plays the frames from the next one, which is the first that was recorded
+fn start_playback(mut playback: ResMut<ReplayPlayback>, mut commands: Commands) {
+    if playback.started {
+        return;
+    }
+    playback.started = true;
+    if let Some(frame) = playback.frames.front() {
+        commands.insert_resource(TimeUpdateStrategy::ManualDuration(frame.delta));
+    }
+}
+
+/// Replaces the frame's real input with the recorded input, and times the
+/// next frame as it was recorded. Exits once every frame is played.
+fn play_back_input(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut keys: ResMut<Events<KeyboardInput>>,
+    mut mouse_buttons: ResMut<Events<MouseButtonInput>>,
+    mut mouse_motion: ResMut<Events<MouseMotion>>,
+    mut mouse_wheel: ResMut<Events<MouseWheel>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !playback.started {
+        return;
+    }
+    keys.clear();
+    mouse_buttons.clear();
+    mouse_motion.clear();
+    mouse_wheel.clear();
+    let Some(frame) = playback.frames.pop_front() else {
+        info!("Finished replaying");
+        playback.started = false;
+        commands.insert_resource(TimeUpdateStrategy::Automatic);
+        exit.write(AppExit::Success);
+        return;
+    };
+    // The events aren't from any window
+    let window = Entity::PLACEHOLDER;
+    for event in frame.events {
+        match event {
+            ReplayEvent::Key(key_code, state) => {
+                keys.send(KeyboardInput {
+                    key_code,
+                    logical_key: Key::Unidentified(NativeKey::Unidentified),
+                    state,
+                    text: None,
+                    repeat: false,
+                    window,
+                });
+            }
+            ReplayEvent::MouseButton(button, state) => {
+                mouse_buttons.send(MouseButtonInput {
+                    button,
+                    state,
+                    window,
+                });
+            }
+            ReplayEvent::MouseMotion(delta) => {
+                mouse_motion.send(MouseMotion { delta });
+            }
+            ReplayEvent::MouseWheel(unit, Vec2 { x, y }) => {
+                mouse_wheel.send(MouseWheel { unit, x, y, window });
+            }
+        }
+    }
+    if let Some(next) = playback.frames.front() {
+        commands.insert_resource(TimeUpdateStrategy::ManualDuration(next.delta));
+    }
+}
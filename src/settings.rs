@@ -0,0 +1,215 @@
+use std::{fs, io, path::PathBuf};
+
+use bevy::{
+    prelude::*,
+    reflect::{DynamicEnum, DynamicVariant},
+    window::{PresentMode, PrimaryWindow},
+};
+use lib_first_person_camera::{CameraControls, CameraMouseSensitivity};
+use lib_render::{
+    camera::{ProjectionSettings, RenderCamera},
+    globals::{RenderFeatures, ShadowSettings},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::CommandLine,
+    persistence::{AutosaveInterval, DEFAULT_AUTOSAVE_SECS},
+    player::{FovKick, PlayerControls},
+    world_gen::LoadRadius,
+};
+
+/// Loads `Settings` from `SettingsFile` on startup, applies them to the
+/// plugins they configure, and saves them whenever they change. Settings
+/// missing from the file keep their defaults.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SettingsFile>()
+            .init_resource::<Settings>()
+            .add_systems(PreStartup, load_settings)
+            // After the player's camera and everything else configured is
+            // spawned in `Startup`
+            .add_systems(PostStartup, apply_settings)
+            .add_systems(Update, apply_settings.run_if(resource_changed::<Settings>))
+            .add_systems(Last, save_settings.run_if(resource_changed::<Settings>));
+    }
+}
+
+/// Where `Settings` are loaded from and saved to
+#[derive(Resource, Clone, Debug)]
+pub struct SettingsFile(pub PathBuf);
+
+impl Default for SettingsFile {
+    fn default() -> Self {
+        Self(PathBuf::from("settings.toml"))
+    }
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Radians turned per pixel the mouse moves, horizontally and vertically
+    pub mouse_sensitivity: Vec2,
+    pub invert_mouse_x: bool,
+    pub invert_mouse_y: bool,
+    /// Chunks loaded around the centre of the world, along each horizontal
+    /// axis
+    pub render_distance: u32,
+    pub vsync: bool,
+    /// Vertical field of view, in degrees
+    pub fov: f32,
+    pub shadow_quality: ShadowQuality,
+    /// Seconds between autosaves, or 0 to only save on exit
+    pub autosave_interval: u32,
+    /// Last, as TOML tables come after the plain values
+    pub controls: PlayerControls,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let sensitivity = CameraMouseSensitivity::default();
+        let camera_controls = CameraControls::default();
+        Self {
+            mouse_sensitivity: Vec2::new(sensitivity.x, sensitivity.y),
+            invert_mouse_x: camera_controls.mouse_x_inverted,
+            invert_mouse_y: camera_controls.mouse_y_inverted,
+            render_distance: LoadRadius::default().horizontal as u32,
+            vsync: false,
+            fov: ProjectionSettings::default().fov.to_degrees(),
+            shadow_quality: ShadowQuality::Medium,
+            autosave_interval: DEFAULT_AUTOSAVE_SECS,
+            controls: PlayerControls::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShadowQuality {
+    Off,
+    /// Hard edged
+    Low,
+    Medium,
+    /// Softest edges
+    High,
+}
+
+impl ShadowQuality {
+    /// `ShadowSettings::pcf_radius`, or `None` without shadows
+    fn pcf_radius(&self) -> Option<u32> {
+        match self {
+            Self::Off => None,
+            Self::Low => Some(0),
+            Self::Medium => Some(1),
+            Self::High => Some(2),
+        }
+    }
+}
+
+/// Keys are written as their variant of `KeyCode`, like `"KeyW"`
+pub(crate) fn parse_key(name: &str) -> Option<KeyCode> {
+    KeyCode::from_reflect(&DynamicEnum::new(name.to_owned(), DynamicVariant::Unit))
+}
+
+impl Settings {
+    /// The settings as TOML, with the controls in their own table
+    pub(crate) fn to_toml(&self) -> String {
+        toml::to_string(self).expect("settings are plain values and tables")
+    }
+
+    /// Reads the settings written by `to_toml`. Settings that are missing
+    /// keep their defaults.
+    fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+}
+
+fn load_settings(mut commands: Commands, file: Res<SettingsFile>) {
+    match fs::read_to_string(&file.0).map(|text| Settings::from_toml(&text)) {
+        Ok(Ok(settings)) => {
+            commands.insert_resource(settings);
+            info!("Loaded settings from {:?}", file.0);
+        }
+        Ok(Err(e)) => error!(
+            "Couldn't read settings from {:?}, using the defaults: {}",
+            file.0, e
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!("No settings in {:?}, using the defaults", file.0);
+        }
+        Err(e) => error!("Couldn't read settings from {:?}: {}", file.0, e),
+    }
+}
+
+/// Only writes settings that differ from the last ones loaded or saved, so
+/// starting the app doesn't rewrite the file, and neither does anything that
+/// marks `Settings` changed without changing them
+fn save_settings(
+    settings: Res<Settings>,
+    file: Res<SettingsFile>,
+    mut last_saved: Local<Option<Settings>>,
+) {
+    let Some(last_saved) = last_saved.as_mut() else {
+        // The first run sees the settings as loaded
+        *last_saved = Some(settings.clone());
+        return;
+    };
+    if *last_saved == *settings {
+        return;
+    }
+    match fs::write(&file.0, settings.to_toml()) {
+        Ok(()) => *last_saved = settings.clone(),
+        Err(e) => error!("Couldn't save settings to {:?}: {}", file.0, e),
+    }
+}
+
+fn apply_settings(
+    settings: Res<Settings>,
+    cli: Option<Res<CommandLine>>,
+    mut commands: Commands,
+    mut sensitivity: ResMut<CameraMouseSensitivity>,
+    mut camera_controls: ResMut<CameraControls>,
+    mut load_radius: ResMut<LoadRadius>,
+    mut autosave_interval: ResMut<AutosaveInterval>,
+    mut render_features: ResMut<RenderFeatures>,
+    mut shadow_settings: ResMut<ShadowSettings>,
+    mut q_windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut q_camera: Query<(&mut ProjectionSettings, Option<&FovKick>), With<RenderCamera>>,
+) {
+    commands.insert_resource(settings.controls.clone());
+    sensitivity.x = settings.mouse_sensitivity.x;
+    sensitivity.y = settings.mouse_sensitivity.y;
+    camera_controls.mouse_x_inverted = settings.invert_mouse_x;
+    camera_controls.mouse_y_inverted = settings.invert_mouse_y;
+    let horizontal = match &cli {
+        Some(cli) => cli.load_radius(settings.render_distance),
+        None => settings.render_distance,
+    } as i32;
+    if load_radius.horizontal != horizontal {
+        load_radius.horizontal = horizontal;
+    }
+    let autosave_secs = cli
+        .and_then(|cli| cli.autosave_interval)
+        .unwrap_or(settings.autosave_interval);
+    autosave_interval.set_if_neq(AutosaveInterval::from_secs(autosave_secs));
+    let pcf_radius = settings.shadow_quality.pcf_radius();
+    if render_features.shadows != pcf_radius.is_some() {
+        render_features.shadows = pcf_radius.is_some();
+    }
+    if let Some(pcf_radius) = pcf_radius {
+        shadow_settings.pcf_radius = pcf_radius;
+    }
+    let present_mode = if settings.vsync {
+        PresentMode::AutoVsync
+    } else {
+        PresentMode::AutoNoVsync
+    };
+    for mut window in q_windows.iter_mut() {
+        window.present_mode = present_mode;
+    }
+    for (mut projection, kick) in q_camera.iter_mut() {
+        projection.fov = settings.fov.to_radians() + kick.map_or(0.0, |kick| kick.0);
+    }
+}
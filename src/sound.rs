@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use bevy::{audio::Volume, prelude::*};
+use lib_chunk::ChunkIndex;
+use lib_render::camera::RenderCamera;
+
+use crate::{
+    biome::{Biome, ClimateNoise},
+    block::{Block, BlockTag},
+    block_breaking::BlockBroken,
+    block_placing::BlockPlaced,
+    player::{Grounded, MovementMode, Player, PlayerBody},
+    world_gen::{self, Blocks},
+};
+
+/// Plays footsteps on the block under the player, the sound of each block
+/// broken or placed from where it is, and wind or cave ambience depending
+/// on the biome and how much sky is overhead.
+///
+/// Sounds are loaded from `assets/sounds`, named after `Block::sound`.
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SoundSettings>()
+            .add_systems(Startup, (load_sounds, spawn_ambient_loops).chain())
+            .add_systems(
+                Update,
+                (
+                    add_listener_to_camera,
+                    play_footsteps,
+                    play_block_sounds,
+                    mix_ambient_loops,
+                ),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct SoundSettings {
+    /// Distance walked between footsteps, in blocks
+    pub stride: f32,
+    /// Block sounds further from the camera than this aren't played, in
+    /// blocks
+    pub hearing_distance: f32,
+    /// Time taken to get most of the way to a new ambient mix
+    pub ambient_transition_seconds: f32,
+    pub footstep_volume: f32,
+    pub block_volume: f32,
+    pub ambient_volume: f32,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            stride: 1.6,
+            hearing_distance: 32.0,
+            ambient_transition_seconds: 2.0,
+            footstep_volume: 0.4,
+            block_volume: 1.0,
+            ambient_volume: 0.5,
+        }
+    }
+}
+
+const STEP_SOUND_FOLDER: &str = "sounds/step";
+const BLOCK_SOUND_FOLDER: &str = "sounds/block";
+const AMBIENT_SOUND_FOLDER: &str = "sounds/ambient";
+
+#[derive(Resource)]
+struct Sounds {
+    /// By `Block::sound`
+    steps: HashMap<&'static str, Handle<AudioSource>>,
+    /// Played when a block is broken or placed, by `Block::sound`
+    blocks: HashMap<&'static str, Handle<AudioSource>>,
+    wind: Handle<AudioSource>,
+    cave: Handle<AudioSource>,
+}
+
+fn load_sounds(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let mut names: Vec<&'static str> = Block::all().filter_map(|block| block.sound()).collect();
+    names.sort();
+    names.dedup();
+    let load_each = |folder: &str| {
+        names
+            .iter()
+            .map(|name| (*name, asset_server.load(format!("{folder}/{name}.ogg"))))
+            .collect()
+    };
+    commands.insert_resource(Sounds {
+        steps: load_each(STEP_SOUND_FOLDER),
+        blocks: load_each(BLOCK_SOUND_FOLDER),
+        wind: asset_server.load(format!("{AMBIENT_SOUND_FOLDER}/wind.ogg")),
+        cave: asset_server.load(format!("{AMBIENT_SOUND_FOLDER}/cave.ogg")),
+    });
+}
+
+/// Block sounds are heard from the camera, panned between its ears
+fn add_listener_to_camera(
+    mut commands: Commands,
+    q_camera: Query<Entity, (With<RenderCamera>, Without<SpatialListener>)>,
+) {
+    for entity in q_camera.iter() {
+        commands.entity(entity).insert(SpatialListener::new(0.3));
+    }
+}
+
+fn play_footsteps(
+    mut commands: Commands,
+    settings: Res<SoundSettings>,
+    sounds: Res<Sounds>,
+    q_player: Query<(&Transform, &PlayerBody, &Grounded, &MovementMode), With<Player>>,
+    chunk_index: Res<ChunkIndex>,
+    q_blocks: Query<&Blocks>,
+    mut last_position: Local<Option<Vec3>>,
+    mut walked: Local<f32>,
+    mut left_foot: Local<bool>,
+) {
+    let Ok((transform, body, grounded, mode)) = q_player.single() else {
+        return;
+    };
+    let position = transform.translation;
+    let moved = last_position.replace(position).unwrap_or(position) - position;
+    if !grounded.0 || *mode == MovementMode::Flying {
+        // The next footstep lands a full stride after touching down
+        *walked = 0.0;
+        return;
+    }
+    *walked += moved.with_y(0.0).length();
+    if *walked < settings.stride {
+        return;
+    }
+    *walked = 0.0;
+    // Standing over an edge, the block under the middle of the player is
+    // air, so look under the corners of their feet too
+    let half_width = body.width / 2.0;
+    let under_feet = [
+        Vec3::new(0.0, -0.01, 0.0),
+        Vec3::new(-half_width, -0.01, -half_width),
+        Vec3::new(-half_width, -0.01, half_width),
+        Vec3::new(half_width, -0.01, -half_width),
+        Vec3::new(half_width, -0.01, half_width),
+    ]
+    .into_iter()
+    .map(|offset| world_gen::block_pos_containing(position + offset))
+    .filter_map(|pos| world_gen::block_at(&chunk_index, &q_blocks, pos))
+    .find(|block| block.has_tag(BlockTag::Solid));
+    let Some(handle) = under_feet
+        .and_then(|block| block.sound())
+        .and_then(|name| sounds.steps.get(name))
+    else {
+        return;
+    };
+    // Alternating feet sound slightly different, so footsteps don't drone
+    *left_foot = !*left_foot;
+    let speed = if *left_foot { 1.0 } else { 0.94 };
+    commands.spawn((
+        AudioPlayer::new(handle.clone()),
+        PlaybackSettings::DESPAWN
+            .with_volume(Volume::Linear(settings.footstep_volume))
+            .with_speed(speed),
+    ));
+}
+
+fn play_block_sounds(
+    mut commands: Commands,
+    settings: Res<SoundSettings>,
+    sounds: Res<Sounds>,
+    q_camera: Query<&GlobalTransform, With<RenderCamera>>,
+    mut broken: EventReader<BlockBroken>,
+    mut placed: EventReader<BlockPlaced>,
+) {
+    let listener = q_camera
+        .single()
+        .ok()
+        .map(|transform| transform.translation());
+    let events = broken
+        .read()
+        .map(|event| (event.pos, event.block))
+        .chain(placed.read().map(|event| (event.pos, event.block)));
+    for (pos, block) in events {
+        let Some(handle) = block.sound().and_then(|name| sounds.blocks.get(name)) else {
+            continue;
+        };
+        let center = pos.as_vec3();
+        let out_of_earshot =
+            listener.is_some_and(|listener| listener.distance(center) > settings.hearing_distance);
+        if out_of_earshot {
+            continue;
+        }
+        // Fades with distance from the listener
+        commands.spawn((
+            AudioPlayer::new(handle.clone()),
+            PlaybackSettings::DESPAWN
+                .with_spatial(true)
+                .with_volume(Volume::Linear(settings.block_volume)),
+            Transform::from_translation(center),
+        ));
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+enum AmbientLoop {
+    /// Heard under the open sky, louder in exposed biomes
+    Wind,
+    /// Heard with the sky blocked out
+    Cave,
+}
+
+fn spawn_ambient_loops(mut commands: Commands, sounds: Res<Sounds>) {
+    for (ambient, handle) in [
+        (AmbientLoop::Wind, &sounds.wind),
+        (AmbientLoop::Cave, &sounds.cave),
+    ] {
+        commands.spawn((
+            ambient,
+            AudioPlayer::new(handle.clone()),
+            PlaybackSettings::LOOP.with_volume(Volume::SILENT),
+        ));
+    }
+}
+
+/// How far above the camera to look for blocks shutting out the sky
+const SKY_SEARCH_HEIGHT: i32 = 32;
+
+/// How strong the wind is in each biome, from 0.0 to 1.0
+fn wind_strength(biome: Biome) -> f32 {
+    match biome {
+        Biome::Plains => 0.6,
+        // Sheltered by the trees
+        Biome::Forest => 0.3,
+        Biome::Desert => 0.8,
+        Biome::Tundra => 1.0,
+    }
+}
+
+fn mix_ambient_loops(
+    time: Res<Time>,
+    settings: Res<SoundSettings>,
+    climate_noise: Option<Res<ClimateNoise>>,
+    q_camera: Query<&GlobalTransform, With<RenderCamera>>,
+    chunk_index: Res<ChunkIndex>,
+    q_blocks: Query<&Blocks>,
+    mut q_loops: Query<(&AmbientLoop, &mut AudioSink)>,
+) {
+    let (Some(climate_noise), Ok(camera_transform)) = (climate_noise, q_camera.single()) else {
+        return;
+    };
+    let position = world_gen::block_pos_containing(camera_transform.translation());
+    // There's no light propagated through the blocks, so skylight reaches
+    // the camera if nothing solid is straight above it. Chunks that aren't
+    // generated yet let it through.
+    let under_sky = (1..=SKY_SEARCH_HEIGHT).all(|dy| {
+        world_gen::block_at(&chunk_index, &q_blocks, position + IVec3::Y * dy)
+            .is_none_or(|block| !block.has_tag(BlockTag::Solid))
+    });
+    let sky_light = if under_sky { 1.0 } else { 0.0 };
+    let wind = wind_strength(climate_noise.biome_at(position.x, position.z));
+    let factor = if settings.ambient_transition_seconds > 0.0 {
+        1.0 - (-time.delta_secs() / settings.ambient_transition_seconds).exp()
+    } else {
+        1.0
+    };
+    for (ambient, mut sink) in q_loops.iter_mut() {
+        let target = match ambient {
+            AmbientLoop::Wind => sky_light * wind,
+            AmbientLoop::Cave => 1.0 - sky_light,
+        } * settings.ambient_volume;
+        let current = sink.volume().to_linear();
+        sink.set_volume(Volume::Linear(current + (target - current) * factor));
+    }
+}
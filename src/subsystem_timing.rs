@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use lib_async_component::ComputeTaskStats;
+use lib_chunk::NeighborhoodSystems;
+use lib_render::stats::RenderStats;
+
+use crate::{
+    mesh::TerrainQuads,
+    world_gen::{Blocks, HeightNoise, WorldGenerationSystems},
+};
+
+/// Records how long each part of chunk streaming took in the last frame
+pub struct SubsystemTimingPlugin;
+
+impl Plugin for SubsystemTimingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SubsystemTimings>()
+            .add_systems(
+                Update,
+                (
+                    start_timing(Subsystem::WorldGeneration).before(WorldGenerationSystems),
+                    finish_timing(Subsystem::WorldGeneration).after(WorldGenerationSystems),
+                    start_timing(Subsystem::Neighborhoods).before(NeighborhoodSystems),
+                    finish_timing(Subsystem::Neighborhoods).after(NeighborhoodSystems),
+                ),
+            )
+            .add_systems(Last, collect_timings);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Generating blocks, and receiving generated height noise and blocks
+    WorldGeneration,
+    /// Receiving meshed quads
+    Meshing,
+    /// Keeping chunk neighbourhoods up to date
+    Neighborhoods,
+    /// Extracting and uploading changed chunks in the render world, as of the
+    /// last rendered frame
+    ExtractPrepare,
+}
+
+impl Subsystem {
+    pub const ALL: [Self; 4] = [
+        Self::WorldGeneration,
+        Self::Meshing,
+        Self::Neighborhoods,
+        Self::ExtractPrepare,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::WorldGeneration => "World Generation",
+            Self::Meshing => "Meshing Results",
+            Self::Neighborhoods => "Neighborhoods",
+            Self::ExtractPrepare => "Extract/Prepare",
+        }
+    }
+}
+
+/// Duration of each `Subsystem` in the last frame.
+///
+/// Systems timed as a set are timed from before the set's first system to
+/// after its last, so other systems running in parallel are included.
+#[derive(Resource, Default)]
+pub struct SubsystemTimings {
+    durations: [Duration; Subsystem::ALL.len()],
+    started: [Option<Instant>; Subsystem::ALL.len()],
+}
+
+impl SubsystemTimings {
+    pub fn get(&self, subsystem: Subsystem) -> Duration {
+        self.durations[subsystem as usize]
+    }
+}
+
+fn start_timing(subsystem: Subsystem) -> impl FnMut(ResMut<SubsystemTimings>) {
+    move |mut timings| {
+        timings.started[subsystem as usize] = Some(Instant::now());
+    }
+}
+
+fn finish_timing(subsystem: Subsystem) -> impl FnMut(ResMut<SubsystemTimings>) {
+    move |mut timings| {
+        if let Some(started) = timings.started[subsystem as usize].take() {
+            timings.durations[subsystem as usize] = started.elapsed();
+        }
+    }
+}
+
+/// Adds the parts of each subsystem timed outside of `Update`
+fn collect_timings(
+    mut timings: ResMut<SubsystemTimings>,
+    height_noise_tasks: Res<ComputeTaskStats<HeightNoise>>,
+    block_tasks: Res<ComputeTaskStats<Blocks>>,
+    meshing_tasks: Res<ComputeTaskStats<TerrainQuads>>,
+    render_stats: Res<RenderStats>,
+) {
+    timings.durations[Subsystem::WorldGeneration as usize] +=
+        height_noise_tasks.receive_time + block_tasks.receive_time;
+    timings.durations[Subsystem::Meshing as usize] = meshing_tasks.receive_time;
+    timings.durations[Subsystem::ExtractPrepare as usize] = render_stats.extract_prepare_time;
+}
@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+use lib_render::{Normal, camera::RenderCamera, outline::BlockOutline};
+
+use crate::{block::Block, game_state::GameState, raycast::Raycaster};
+
+/// Finds the block under the crosshair each frame, publishing it as the
+/// `TargetedBlock` resource and outlining it. Fluids are looked through, but
+/// the nearest one is published as `TargetedFluid`. Which blocks are
+/// targeted, and from how far, is up to `TargetingRules`.
+pub struct TargetingPlugin;
+
+impl Plugin for TargetingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TargetingRules>().add_systems(
+            Update,
+            (update_targeted_block, outline_targeted_block)
+                .chain()
+                .in_set(TargetingSystems)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+/// Systems updating `TargetedBlock`. Systems reading it should run after.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct TargetingSystems;
+
+/// How the targeting raycast treats a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Targetability {
+    /// Looked through, like air
+    Ignored,
+    /// Stops the ray, becoming the `TargetedBlock` to break or place against
+    Solid,
+    /// Looked through for the `TargetedBlock`, but can be the
+    /// `TargetedFluid`, for scooping it up or the like
+    Fluid,
+}
+
+#[derive(Resource, Clone, Copy)]
+pub struct TargetingRules {
+    /// How far away blocks can be reached, in blocks
+    pub reach: f32,
+    /// Decides how each block is targeted
+    pub targetability: fn(Block) -> Targetability,
+}
+
+impl Default for TargetingRules {
+    fn default() -> Self {
+        Self {
+            reach: 8.0,
+            targetability: default_targetability,
+        }
+    }
+}
+
+/// Looks through air, targets fluids as fluids and everything else as solid
+pub fn default_targetability(block: Block) -> Targetability {
+    if block == Block::Air {
+        Targetability::Ignored
+    } else if block.is_fluid() {
+        Targetability::Fluid
+    } else {
+        Targetability::Solid
+    }
+}
+
+/// Block under the crosshair within `TargetingRules::reach`. Removed while
+/// no block is targeted.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct TargetedBlock {
+    pub pos: IVec3,
+    /// Face the camera is looking at, or `None` from inside the block
+    pub face: Option<Normal>,
+    pub block: Block,
+    /// Distance from the camera to the targeted face, in blocks
+    pub distance: f32,
+}
+
+/// Nearest fluid under the crosshair within `TargetingRules::reach`, in
+/// front of the `TargetedBlock`. Removed while no fluid is targeted.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct TargetedFluid(pub TargetedBlock);
+
+fn update_targeted_block(
+    mut commands: Commands,
+    rules: Res<TargetingRules>,
+    q_camera: Query<&GlobalTransform, With<RenderCamera>>,
+    raycaster: Raycaster,
+    current_block: Option<Res<TargetedBlock>>,
+    current_fluid: Option<Res<TargetedFluid>>,
+) {
+    // First block along the crosshair that's one of `stops_at`
+    let raycast = |stops_at: &[Targetability]| {
+        let transform = q_camera.single().ok()?;
+        let hit = raycaster.cast(
+            transform.translation(),
+            transform.forward(),
+            rules.reach,
+            |block| stops_at.contains(&(rules.targetability)(block)),
+        )?;
+        Some(TargetedBlock {
+            pos: hit.pos,
+            face: hit.normal,
+            block: hit.block,
+            distance: hit.distance,
+        })
+    };
+    let block = raycast(&[Targetability::Solid]);
+    // Solid blocks stop the ray, so fluids behind them aren't targeted
+    let fluid = raycast(&[Targetability::Solid, Targetability::Fluid])
+        .filter(|target| (rules.targetability)(target.block) == Targetability::Fluid)
+        .map(TargetedFluid);
+    update_resource(&mut commands, block, current_block.as_deref());
+    update_resource(&mut commands, fluid, current_fluid.as_deref());
+}
+
+/// Inserts `new`, or removes the resource for `None`, unless it's unchanged
+fn update_resource<T: Resource + PartialEq>(
+    commands: &mut Commands,
+    new: Option<T>,
+    current: Option<&T>,
+) {
+    match (new, current) {
+        (Some(new), Some(current)) if new == *current => {}
+        (Some(new), _) => commands.insert_resource(new),
+        (None, Some(_)) => commands.remove_resource::<T>(),
+        (None, None) => {}
+    }
+}
+
+fn outline_targeted_block(target: Option<Res<TargetedBlock>>, mut outline: ResMut<BlockOutline>) {
+    let block = target.map(|target| target.pos);
+    if outline.block != block {
+        outline.block = block;
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    any::type_name,
+    ops::RangeInclusive,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use bevy::{prelude::*, state::app::StatesPlugin, time::TimeUpdateStrategy};
+use lib_chunk::{ChunkIndex, ChunkIndexPlugin, FullNeighborhood};
+
+use crate::{
+    game_state::{GameState, GameStatePlugin},
+    inventory::Inventory,
+    mesh::{QuadCount, TerrainQuads, WorldMeshPlugin},
+    network::NetworkRole,
+    persistence::{self, PersistencePlugin, SaveDirectory},
+    world_gen::{Blocks, Chunk, LoadRadius, WorldGenerationPlugin, WorldSeed, WorldType},
+};
+
+/// A world for tests, without a window, renderer or player. Chunks are
+/// indexed, generated and meshed like in the game, starting as soon as it's
+/// stepped, and saved to a scratch folder of its own. Each frame advances
+/// time by `FRAME_TIME`, but generation and meshing still run on background
+/// tasks, so wait for them with `step_until` rather than a number of frames.
+pub(crate) struct TestWorld {
+    pub app: App,
+}
+
+/// Simulated time each frame takes
+pub(crate) const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+/// Keeps the save folders of worlds in tests running at once apart
+static NEXT_WORLD: AtomicUsize = AtomicUsize::new(0);
+
+impl TestWorld {
+    /// Loads the chunks within `load_radius` of the centre of a new world
+    pub fn new(seed: u32, world_type: WorldType, load_radius: LoadRadius) -> Self {
+        let number = NEXT_WORLD.fetch_add(1, Ordering::Relaxed);
+        let directory = persistence::scratch_directory(&format!("test-{number}"));
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            bevy::input::InputPlugin,
+            ChunkIndexPlugin,
+            WorldGenerationPlugin::default(),
+            PersistencePlugin,
+            GameStatePlugin,
+            WorldMeshPlugin::default(),
+        ))
+        .init_resource::<NetworkRole>()
+        .init_resource::<Inventory>()
+        .insert_resource(WorldSeed(seed))
+        .insert_resource(world_type)
+        .insert_resource(load_radius)
+        .insert_resource(SaveDirectory(directory.join("world")))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_TIME))
+        .insert_state(GameState::Loading);
+        // Done by `App::run`, which isn't used so the frames can be stepped
+        app.finish();
+        app.cleanup();
+        Self { app }
+    }
+
+    pub fn step(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.app.update();
+        }
+    }
+
+    /// Steps until `condition` holds, for at most `max_frames`. Returns
+    /// whether it held.
+    pub fn step_until(
+        &mut self,
+        max_frames: u32,
+        mut condition: impl FnMut(&Self) -> bool,
+    ) -> bool {
+        for _ in 0..max_frames {
+            if condition(self) {
+                return true;
+            }
+            self.app.update();
+        }
+        condition(self)
+    }
+
+    /// Steps until every chunk has its blocks and quads, panicking after
+    /// `max_frames`
+    pub fn step_until_meshed(&mut self, max_frames: u32) {
+        let meshed = self.step_until(max_frames, |world| {
+            // None are spawned until the first frame
+            world.chunks().next().is_some()
+                && world.chunks().all(|entity| {
+                    world.entity_has::<Blocks>(entity) && world.entity_has::<TerrainQuads>(entity)
+                })
+        });
+        assert!(
+            meshed,
+            "chunks weren't all meshed within {max_frames} frames"
+        );
+    }
+
+    pub fn chunk(&self, pos: IVec3) -> Option<Entity> {
+        self.app
+            .world()
+            .resource::<ChunkIndex>()
+            .get_entity(&pos)
+            .copied()
+    }
+
+    /// Every chunk spawned so far
+    pub fn chunks(&self) -> impl Iterator<Item = Entity> + '_ {
+        let world = self.app.world();
+        world
+            .iter_entities()
+            .filter(|entity| entity.contains::<Chunk>())
+            .map(|entity| entity.id())
+    }
+
+    fn entity_has<T: Component>(&self, entity: Entity) -> bool {
+        self.app.world().entity(entity).contains::<T>()
+    }
+
+    /// Whether the chunk at `pos` is spawned and has a `T`
+    pub fn chunk_has<T: Component>(&self, pos: IVec3) -> bool {
+        self.chunk(pos)
+            .is_some_and(|entity| self.entity_has::<T>(entity))
+    }
+
+    pub fn assert_chunk_has<T: Component>(&self, pos: IVec3) {
+        assert!(self.chunk(pos).is_some(), "no chunk at {pos}");
+        assert!(
+            self.chunk_has::<T>(pos),
+            "chunk at {pos} has no {}",
+            type_name::<T>()
+        );
+    }
+
+    pub fn assert_has_blocks(&self, pos: IVec3) {
+        self.assert_chunk_has::<Blocks>(pos);
+    }
+
+    pub fn assert_full_neighborhood(&self, pos: IVec3) {
+        self.assert_chunk_has::<FullNeighborhood<Blocks>>(pos);
+    }
+
+    /// Quads in every meshed chunk
+    pub fn quad_count(&self) -> u32 {
+        self.app.world().resource::<QuadCount>().0
+    }
+
+    pub fn assert_quad_count_within(&self, range: RangeInclusive<u32>) {
+        let count = self.quad_count();
+        assert!(
+            range.contains(&count),
+            "{count} quads, expected {}..={}",
+            range.start(),
+            range.end()
+        );
+    }
+}
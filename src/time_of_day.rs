@@ -0,0 +1,343 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use lib_render::globals::{AmbientLight, DirectionalLight, FogSettings, NightSky, SkyColor};
+
+use crate::{
+    console::{ConsoleCommand, RegisterConsoleCommand},
+    environment::{EnvironmentBlend, apply_tint},
+};
+
+/// Advances the time of day and lights the world by it. The clock is
+/// controlled through `TimeCommand`s, which the `time` console command sends,
+/// and `DayPhaseChanged` is sent whenever the sun rises or sets.
+pub struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .init_resource::<LightingOverrides>()
+            .add_event::<TimeCommand>()
+            .add_event::<DayPhaseChanged>()
+            .register_console_command("time", TIME_USAGE)
+            .add_systems(
+                Update,
+                (
+                    read_time_console_commands,
+                    apply_time_commands,
+                    advance_time_of_day,
+                    detect_day_phase_change,
+                    update_lighting,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Time of day as a fraction of a full day, starting at midnight. Sunrise is
+/// at 0.25, noon at 0.5 and sunset at 0.75.
+#[derive(Resource)]
+pub struct TimeOfDay {
+    time: f32,
+    /// Real time length of a full day
+    pub day_length_seconds: f32,
+    pub paused: bool,
+    /// How many times faster than `day_length_seconds` the day passes
+    pub speed: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            time: 0.35,
+            day_length_seconds: 600.0,
+            paused: false,
+            speed: 1.0,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Jumps to `time`, wrapping it into a single day
+    pub fn set(&mut self, time: f32) {
+        self.time = time.rem_euclid(1.0);
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Direction from the world towards the sun, which crosses the sky from
+    /// east (+x) to west, tilted slightly south so shadows aren't axis-aligned
+    pub fn sun_position(&self) -> Dir3 {
+        let angle = (self.time - 0.25) * TAU;
+        Dir3::new(Vec3::new(angle.cos(), angle.sin(), -0.4)).expect("Non-zero sun position")
+    }
+
+    /// 1.0 during the day and 0.0 at night, blending across the horizon
+    pub fn daylight(&self) -> f32 {
+        let sun_height = self.sun_position().y;
+        (sun_height / HORIZON_BLEND + 0.5).clamp(0.0, 1.0)
+    }
+
+    /// Day while the sun is above the horizon, when it's the shadow casting
+    /// light
+    pub fn phase(&self) -> DayPhase {
+        if self.sun_position().y >= 0.0 {
+            DayPhase::Day
+        } else {
+            DayPhase::Night
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DayPhase {
+    Day,
+    Night,
+}
+
+/// Sent when the sun rises or sets, including when the time is set across
+/// the horizon
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DayPhaseChanged(pub DayPhase);
+
+/// Changes to the clock of `TimeOfDay`, applied before it advances
+#[derive(Event, Clone, Copy, Debug, PartialEq)]
+pub enum TimeCommand {
+    /// Jumps to a time, as a fraction of a day from midnight
+    Set(f32),
+    Pause,
+    Resume,
+    /// Sets `TimeOfDay::speed`
+    Speed(f32),
+}
+
+impl TimeCommand {
+    /// Parses the arguments of the `time` console command
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            ["set", time] => {
+                let time = match *time {
+                    "midnight" => 0.0,
+                    "sunrise" => 0.25,
+                    "noon" => 0.5,
+                    "sunset" => 0.75,
+                    time => time
+                        .parse()
+                        .ok()
+                        .filter(|time: &f32| time.is_finite())
+                        .ok_or_else(|| format!("Invalid time {:?}", time))?,
+                };
+                Ok(Self::Set(time))
+            }
+            ["pause"] => Ok(Self::Pause),
+            ["resume"] => Ok(Self::Resume),
+            ["speed", factor] => factor
+                .parse()
+                .ok()
+                .filter(|factor: &f32| factor.is_finite() && *factor >= 0.0)
+                .map(Self::Speed)
+                .ok_or_else(|| format!("Invalid speed {:?}", factor)),
+            _ => Err("Expected set <TIME>, pause, resume or speed <FACTOR>".to_owned()),
+        }
+    }
+}
+
+/// Lighting set by hand, e.g. from the lighting panel, replacing what the
+/// time of day would otherwise give. `None` follows the time of day.
+#[derive(Resource, Default)]
+pub struct LightingOverrides {
+    pub directional_color: Option<Color>,
+    pub directional_direction: Option<Dir3>,
+    pub ambient: Option<Color>,
+}
+
+/// Height of the sun, as the y of its direction, over which day fades into
+/// night
+const HORIZON_BLEND: f32 = 0.2;
+
+const DAY_SKY: Color = Color::linear_rgb(0.1, 0.2, 0.4);
+const NIGHT_SKY: Color = Color::linear_rgb(0.002, 0.003, 0.01);
+const SUNSET_SKY: Color = Color::linear_rgb(0.4, 0.15, 0.05);
+const DAY_AMBIENT: Color = Color::linear_rgb(0.1, 0.1, 0.1);
+const NIGHT_AMBIENT: Color = Color::linear_rgb(0.01, 0.01, 0.02);
+const SUNLIGHT: Color = Color::linear_rgb(0.75, 0.75, 0.75);
+const MOONLIGHT: Color = Color::linear_rgb(0.04, 0.05, 0.08);
+
+/// Arguments of the `time` console command, as `help` lists them
+const TIME_USAGE: &str = "set <TIME> | pause | resume | speed <FACTOR>\n  \
+    TIME is a fraction of a day from midnight, or one of midnight,\n  \
+    sunrise, noon or sunset";
+
+fn read_time_console_commands(
+    mut er: EventReader<ConsoleCommand>,
+    mut ew: EventWriter<TimeCommand>,
+) {
+    for command in er.read().filter(|command| command.name == "time") {
+        match TimeCommand::parse(&command.args) {
+            Ok(time_command) => {
+                ew.write(time_command);
+            }
+            Err(e) => warn!("time: {}", e),
+        }
+    }
+}
+
+fn apply_time_commands(mut er: EventReader<TimeCommand>, mut time_of_day: ResMut<TimeOfDay>) {
+    for command in er.read() {
+        match *command {
+            TimeCommand::Set(time) => time_of_day.set(time),
+            TimeCommand::Pause => time_of_day.pause(),
+            TimeCommand::Resume => time_of_day.resume(),
+            TimeCommand::Speed(speed) => time_of_day.speed = speed,
+        }
+        let state = if time_of_day.paused {
+            "paused"
+        } else {
+            "running"
+        };
+        info!(
+            "Time of day {:.3}, {}, at {}x speed",
+            time_of_day.time(),
+            state,
+            time_of_day.speed
+        );
+    }
+}
+
+fn advance_time_of_day(mut time_of_day: ResMut<TimeOfDay>, time: Res<Time>) {
+    if time_of_day.paused || time_of_day.day_length_seconds <= 0.0 {
+        return;
+    }
+    let time =
+        time_of_day.time + time.delta_secs() * time_of_day.speed / time_of_day.day_length_seconds;
+    time_of_day.set(time);
+}
+
+fn detect_day_phase_change(
+    time_of_day: Res<TimeOfDay>,
+    mut last_phase: Local<Option<DayPhase>>,
+    mut ew: EventWriter<DayPhaseChanged>,
+) {
+    let phase = time_of_day.phase();
+    // Nothing changed when the game starts
+    if last_phase.is_some_and(|last_phase| last_phase != phase) {
+        ew.write(DayPhaseChanged(phase));
+    }
+    *last_phase = Some(phase);
+}
+
+pub(crate) fn update_lighting(
+    mut commands: Commands,
+    time_of_day: Res<TimeOfDay>,
+    environment_blend: Res<EnvironmentBlend>,
+    overrides: Res<LightingOverrides>,
+    fog_settings: Option<ResMut<FogSettings>>,
+) {
+    let tint = environment_blend.tint();
+    let daylight = time_of_day.daylight();
+    // Strongest when the sun is right on the horizon
+    let sunset = 1.0 - (2.0 * daylight - 1.0).abs();
+
+    let sky = NIGHT_SKY
+        .mix(&DAY_SKY, daylight)
+        .mix(&SUNSET_SKY, sunset * 0.5);
+    let sky = apply_tint(sky, tint.fog);
+    commands.insert_resource(SkyColor(sky));
+    if let Some(mut fog_settings) = fog_settings {
+        // Distant terrain fades into the sky
+        fog_settings.color = sky;
+    }
+    let ambient = NIGHT_AMBIENT.mix(&DAY_AMBIENT, daylight);
+    let ambient = overrides
+        .ambient
+        .unwrap_or_else(|| apply_tint(ambient, tint.ambient));
+    commands.insert_resource(AmbientLight(ambient));
+    // The moon is opposite the sun and takes over as the shadow casting light
+    // at night. Both fade out at the horizon so the switch isn't visible.
+    let sun_position = time_of_day.sun_position();
+    let moon_position = -sun_position;
+    let (light, position) = match time_of_day.phase() {
+        DayPhase::Day => (SUNLIGHT, sun_position),
+        DayPhase::Night => (MOONLIGHT, moon_position),
+    };
+    let light_strength = (position.y / HORIZON_BLEND).clamp(0.0, 1.0);
+    let color = apply_tint(Color::BLACK.mix(&light, light_strength), tint.directional);
+    commands.insert_resource(DirectionalLight {
+        color: overrides.directional_color.unwrap_or(color),
+        direction: overrides.directional_direction.unwrap_or(-position),
+    });
+    commands.insert_resource(NightSky {
+        moon_direction: moon_position,
+        star_visibility: 1.0 - daylight,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<TimeCommand, String> {
+        let args: Vec<String> = line.split_whitespace().map(str::to_owned).collect();
+        TimeCommand::parse(&args)
+    }
+
+    #[test]
+    fn parses_set_with_a_fraction_of_a_day() {
+        assert_eq!(parse("set 0.6"), Ok(TimeCommand::Set(0.6)));
+        assert_eq!(parse("set 0"), Ok(TimeCommand::Set(0.0)));
+        // Wrapped into a day once applied
+        assert_eq!(parse("set 1.5"), Ok(TimeCommand::Set(1.5)));
+    }
+
+    #[test]
+    fn parses_set_with_a_named_time() {
+        assert_eq!(parse("set midnight"), Ok(TimeCommand::Set(0.0)));
+        assert_eq!(parse("set sunrise"), Ok(TimeCommand::Set(0.25)));
+        assert_eq!(parse("set noon"), Ok(TimeCommand::Set(0.5)));
+        assert_eq!(parse("set sunset"), Ok(TimeCommand::Set(0.75)));
+    }
+
+    #[test]
+    fn parses_pause_and_resume() {
+        assert_eq!(parse("pause"), Ok(TimeCommand::Pause));
+        assert_eq!(parse("resume"), Ok(TimeCommand::Resume));
+    }
+
+    #[test]
+    fn parses_speed() {
+        assert_eq!(parse("speed 2"), Ok(TimeCommand::Speed(2.0)));
+        assert_eq!(parse("speed 0"), Ok(TimeCommand::Speed(0.0)));
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        for line in ["set dusk", "set", "set NaN", "set inf", "set 0.5 0.6"] {
+            assert!(parse(line).is_err(), "{line:?} parsed");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_speeds() {
+        for line in ["speed fast", "speed", "speed -1", "speed inf", "speed NaN"] {
+            assert!(parse(line).is_err(), "{line:?} parsed");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_subcommands() {
+        for line in ["", "stop", "pause now", "resume 1", "Pause"] {
+            assert!(parse(line).is_err(), "{line:?} parsed");
+        }
+    }
+}
@@ -1,71 +1,260 @@
-use std::num::NonZero;
+use std::{num::NonZero, sync::Arc, time::Duration};
 
 use bevy::{ecs::query::QueryData, prelude::*};
 use lib_async_component::{AsyncComponentPlugin, ComputeInProgress, ComputeTasks};
-use lib_chunk::{ChunkPosition, NeighborhoodPlugin};
+use lib_chunk::{ChunkIndex, ChunkPosition, NeighborhoodPlugin};
 use lib_noise::FractalNoise;
-use lib_spatial::{CHUNK_SIZE, SpatiallyMapped};
-use lib_spatial_macro::{SpatiallyMapped2d, SpatiallyMapped3d};
+use lib_spatial::{CHUNK_SIZE, SpatiallyMapped, pos_to_index_3d};
+use lib_spatial_macro::SpatiallyMapped2d;
 use lib_utils::iter_3d;
 use ndarray::{Array2, Array3};
 use noise::NoiseFn;
+use strum_macros::{EnumString, IntoStaticStr};
 
-use crate::block::Block;
+use crate::{
+    block::{Block, BlockTag, PlacedBlock},
+    block_registry::BlockRegistry,
+    game_state::GameState,
+    network::owns_world,
+    persistence::SavedBlocks,
+};
 
-pub struct WorldGenerationPlugin;
+/// Generates the chunks within `LoadRadius`, shaping the terrain and then
+/// running each registered `GenerationPass` over it
+#[derive(Default)]
+pub struct WorldGenerationPlugin {
+    passes: Vec<Arc<dyn GenerationPass>>,
+}
+
+impl WorldGenerationPlugin {
+    /// Adds a pass run on every chunk after the passes added before it
+    pub fn register_generation_pass(mut self, pass: impl GenerationPass) -> Self {
+        self.passes.push(Arc::new(pass));
+        self
+    }
+}
 
 impl Plugin for WorldGenerationPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(WorldSeed(0xDEADBEEF))
+        app.insert_resource(WorldSeed(DEFAULT_SEED))
+            .insert_resource(GenerationPasses(self.passes.clone()))
+            .init_resource::<WorldType>()
+            .init_resource::<BlockRegistry>()
+            .init_resource::<LoadRadius>()
+            .add_event::<BlockChanged>()
+            .add_event::<EditBlock>()
             .add_plugins((
                 NeighborhoodPlugin::<HeightNoise>::new(),
                 NeighborhoodPlugin::<Blocks>::new(),
                 AsyncComponentPlugin::<HeightNoise>::new(),
                 AsyncComponentPlugin::<Blocks>::new(),
             ))
+            .add_systems(OnEnter(GameState::Loading), spawn_chunks_in_load_radius)
             .add_systems(
-                Startup,
-                (init_height_noise_generator, spawn_chunk_at_center_of_world),
+                Update,
+                spawn_chunks_in_load_radius
+                    .run_if(resource_changed::<LoadRadius>.and(not(in_state(GameState::MainMenu)))),
             )
-            .add_systems(Update, (assign_height_noise, assign_blocks));
+            .add_systems(
+                Update,
+                (
+                    // The seed is only known once a world is chosen
+                    init_height_noise_generator.run_if(resource_changed::<WorldSeed>),
+                    (assign_height_noise, assign_blocks).in_set(WorldGenerationSystems),
+                )
+                    .chain(),
+            )
+            .add_systems(Update, (apply_block_edits, spread_grass).run_if(owns_world))
+            .configure_sets(Update, WorldGenerationSystems.run_if(owns_world));
+    }
+}
+
+/// Systems starting height noise tasks and generating blocks from their results
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct WorldGenerationSystems;
+
+/// Chunks spawned around the centre of the world, in chunks. Growing it
+/// spawns the chunks newly in range, but shrinking it doesn't despawn any.
+#[derive(Resource, Clone, Copy, Debug)]
+pub(crate) struct LoadRadius {
+    pub horizontal: i32,
+    pub vertical: i32,
+}
+
+impl Default for LoadRadius {
+    fn default() -> Self {
+        Self {
+            horizontal: 10,
+            vertical: 1,
+        }
     }
 }
 
-fn spawn_chunk_at_center_of_world(mut commands: Commands) {
-    const RADIUS_HORIZONTAL: i32 = 10;
-    const RADIUS_VERTICAL: i32 = 1;
+fn spawn_chunks_in_load_radius(
+    mut commands: Commands,
+    radius: Res<LoadRadius>,
+    chunk_index: Res<ChunkIndex>,
+) {
+    let LoadRadius {
+        horizontal,
+        vertical,
+    } = *radius;
     for (x, y, z) in iter_3d(
-        -RADIUS_HORIZONTAL..=RADIUS_HORIZONTAL,
-        -RADIUS_VERTICAL..=RADIUS_VERTICAL,
-        -RADIUS_HORIZONTAL..=RADIUS_HORIZONTAL,
+        -horizontal..=horizontal,
+        -vertical..=vertical,
+        -horizontal..=horizontal,
     ) {
         let pos = IVec3::new(x, y, z);
-        commands.spawn((Chunk, ChunkPosition(pos)));
+        if chunk_index.get_entity(&pos).is_none() {
+            commands.spawn((Chunk, ChunkPosition(pos)));
+        }
     }
 }
 
 #[derive(Resource)]
-struct WorldSeed(u32);
+pub(crate) struct WorldSeed(pub u32);
 
-#[derive(Resource)]
-struct HeightNoiseGenerator(FractalNoise);
+/// Seed of a new world when none is chosen
+pub(crate) const DEFAULT_SEED: u32 = 0xDEADBEEF;
+
+/// How the terrain is shaped. Saved with the world, as it changes what's
+/// generated.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum WorldType {
+    /// Rolling hills from the height noise
+    #[default]
+    Hills,
+    /// Level ground at `FLAT_GROUND_HEIGHT`
+    Flat,
+}
+
+const FLAT_GROUND_HEIGHT: f32 = 4.0;
+
+/// Saved with the world. Bump it whenever the same seed would generate
+/// different blocks, as unmodified chunks of older saves are generated
+/// afresh.
+pub(crate) const GENERATOR_VERSION: u32 = 1;
+
+/// Sent whenever a block in a generated chunk changes, whatever changed it.
+/// Generating a chunk doesn't count.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BlockChanged {
+    pub pos: IVec3,
+    pub old: PlacedBlock,
+    pub new: PlacedBlock,
+    pub cause: BlockChangeCause,
+}
+
+/// Asks for a block to be changed. Whatever owns the world applies it,
+/// sending `BlockChanged` if the block changes, so that clients of a server
+/// never change their blocks themselves.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EditBlock {
+    pub pos: IVec3,
+    pub block: PlacedBlock,
+    pub cause: BlockChangeCause,
+}
+
+/// What changed a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockChangeCause {
+    /// Breaking or placing it
+    Player,
+    /// The world changing by itself after being generated, like grass
+    /// spreading
+    WorldGen,
+}
+
+/// Changes the blocks of each newly generated chunk after the terrain is
+/// shaped, like scattering ores or trees. A pass only sees its own chunk, so
+/// anything crossing into a neighbour has to be cut off at the border.
+/// Chunks loaded from a save aren't generated again. Register passes with
+/// `WorldGenerationPlugin::register_generation_pass`, and bump
+/// `GENERATOR_VERSION` when changing what they generate.
+pub trait GenerationPass: Send + Sync + 'static {
+    fn generate(&self, chunk: &mut GeneratingChunk);
+}
+
+impl<F: Fn(&mut GeneratingChunk) + Send + Sync + 'static> GenerationPass for F {
+    fn generate(&self, chunk: &mut GeneratingChunk) {
+        self(chunk)
+    }
+}
+
+#[derive(Resource, Default)]
+struct GenerationPasses(Vec<Arc<dyn GenerationPass>>);
+
+/// A chunk being generated, as passed to each `GenerationPass`. Positions
+/// within it are local, from 0 to `CHUNK_SIZE` along each axis.
+pub struct GeneratingChunk<'a> {
+    /// Position of the chunk, in chunks
+    pub position: IVec3,
+    pub seed: u32,
+    height_noise: &'a HeightNoise,
+    blocks: &'a mut Array3<PlacedBlock>,
+}
+
+impl GeneratingChunk<'_> {
+    pub fn block(&self, local_pos: UVec3) -> PlacedBlock {
+        self.blocks[local_pos.to_array().map(|x| x as usize)]
+    }
+
+    pub fn set_block(&mut self, local_pos: UVec3, block: impl Into<PlacedBlock>) {
+        self.blocks[local_pos.to_array().map(|x| x as usize)] = block.into();
+    }
+
+    /// Height of the generated ground in the column at `x` and `z`, in world
+    /// space
+    pub fn surface_height(&self, x: u32, z: u32) -> f32 {
+        self.height_noise.surface_height(x as usize, z as usize)
+    }
+}
+
+#[derive(Resource, Clone)]
+pub(crate) struct HeightNoiseGenerator(FractalNoise);
+
+impl HeightNoiseGenerator {
+    pub fn new(seed: u32) -> Self {
+        let num_layers = 6;
+        let scale = 0.02;
+        Self(FractalNoise::new(
+            seed,
+            NonZero::new(num_layers).unwrap(),
+            scale,
+        ))
+    }
+}
 
 fn init_height_noise_generator(mut commands: Commands, world_seed: Res<WorldSeed>) {
-    let seed = world_seed.0;
-    let num_layers = 6;
-    let scale = 0.02;
-    let noise = FractalNoise::new(seed, NonZero::new(num_layers).unwrap(), scale);
-    let generator = HeightNoiseGenerator(noise);
-    commands.insert_resource(generator);
+    commands.insert_resource(HeightNoiseGenerator::new(world_seed.0));
 }
 
 #[derive(Component)]
 pub struct Chunk;
 
 #[derive(Component, Clone, SpatiallyMapped2d)]
-struct HeightNoise(Array2<f32>);
+pub(crate) struct HeightNoise(Array2<f32>);
 
 impl HeightNoise {
+    /// Height noise of the chunks in the column at `chunk_position`
+    pub fn generate(
+        chunk_position: ChunkPosition,
+        world_type: WorldType,
+        generator: HeightNoiseGenerator,
+    ) -> Self {
+        match world_type {
+            WorldType::Hills => Self::from_noise(chunk_position, generator.0),
+            WorldType::Flat => Self::flat(FLAT_GROUND_HEIGHT),
+        }
+    }
+
+    /// Height of the generated ground at `x` and `z` within the chunk, in
+    /// world space
+    pub fn surface_height(&self, x: usize, z: usize) -> f32 {
+        *self.at_pos([x, z]) * WORLD_AMPLITUDE
+    }
+
     fn from_noise(chunk_position: ChunkPosition, noise: FractalNoise) -> Self {
         let offset = chunk_position.0 * CHUNK_SIZE as i32;
         let values = Array2::from_shape_fn((CHUNK_SIZE, CHUNK_SIZE), |(x, z)| {
@@ -73,6 +262,14 @@ impl HeightNoise {
         });
         Self(values)
     }
+
+    /// Level everywhere, so the ground is at `height`
+    fn flat(height: f32) -> Self {
+        Self(Array2::from_elem(
+            (CHUNK_SIZE, CHUNK_SIZE),
+            height / WORLD_AMPLITUDE,
+        ))
+    }
 }
 
 fn assign_height_noise(
@@ -85,26 +282,251 @@ fn assign_height_noise(
         ),
     >,
     generator: Res<HeightNoiseGenerator>,
+    world_type: Res<WorldType>,
     mut height_noise_tasks: ResMut<ComputeTasks<HeightNoise>>,
 ) {
     for (entity, chunk_position) in q_chunks.iter() {
         let chunk_position = *chunk_position;
-        let generator = generator.0.clone();
+        let generator = generator.clone();
+        let world_type = *world_type;
+        #[cfg(feature = "trace")]
+        let span = info_span!("generate_height_noise", chunk = %chunk_position.0);
         height_noise_tasks.spawn_task(entity, async move {
-            HeightNoise::from_noise(chunk_position, generator)
+            #[cfg(feature = "trace")]
+            let _span = span.entered();
+            HeightNoise::generate(chunk_position, world_type, generator)
         });
     }
 }
 
 #[derive(QueryData)]
+#[query_data(mutable)]
 struct BlockGenerationData {
     entity: Entity,
     chunk_position: &'static ChunkPosition,
     height_noise: &'static HeightNoise,
+    saved_blocks: &'static mut SavedBlocks,
+}
+
+/// Blocks of a chunk, either as they are or compressed. Compressed chunks
+/// can be read as they are, but each read searches the runs, so anything
+/// reading many blocks should decompress them first.
+#[derive(Component, Clone)]
+pub struct Blocks(BlockStorage);
+
+#[derive(Clone)]
+enum BlockStorage {
+    Raw(Array3<PlacedBlock>),
+    /// Runs of the same block in the order of `Blocks::iter`, like chunks
+    /// are saved
+    Compressed {
+        palette: Vec<PlacedBlock>,
+        /// Index just past the last block of each run, which is at most the
+        /// number of blocks in a chunk
+        run_ends: Vec<u16>,
+        /// Index into `palette` of each run's block
+        run_blocks: Vec<u16>,
+    },
+}
+
+// Otherwise the ends of the last runs in a chunk wouldn't fit in a `u16`
+const _: () = assert!(CHUNK_SIZE.pow(3) <= u16::MAX as usize);
+
+impl SpatiallyMapped<3> for Blocks {
+    type Index = usize;
+    type Item = PlacedBlock;
+
+    fn at_pos(&self, pos: [usize; 3]) -> &PlacedBlock {
+        match &self.0 {
+            BlockStorage::Raw(blocks) => &blocks[pos],
+            BlockStorage::Compressed {
+                palette,
+                run_ends,
+                run_blocks,
+            } => {
+                let index = pos_to_index_3d(pos);
+                let run = run_ends.partition_point(|end| *end as usize <= index);
+                &palette[run_blocks[run] as usize]
+            }
+        }
+    }
+}
+
+impl Blocks {
+    /// Every block in the chunk, in the order `from_blocks` takes them
+    pub fn iter(&self) -> impl Iterator<Item = &PlacedBlock> {
+        let blocks: Box<dyn Iterator<Item = &PlacedBlock>> = match &self.0 {
+            BlockStorage::Raw(blocks) => Box::new(blocks.iter()),
+            BlockStorage::Compressed {
+                palette,
+                run_ends,
+                run_blocks,
+            } => {
+                let run_starts = std::iter::once(0).chain(run_ends.iter().copied());
+                let runs = run_starts.zip(run_ends).zip(run_blocks);
+                Box::new(runs.flat_map(|((start, end), block)| {
+                    std::iter::repeat_n(&palette[*block as usize], (end - start) as usize)
+                }))
+            }
+        };
+        blocks
+    }
+
+    /// Chunk of `blocks` in the order `iter` gives them, or `None` unless
+    /// there are exactly enough to fill it
+    pub(crate) fn from_blocks(blocks: Vec<PlacedBlock>) -> Option<Self> {
+        Array3::from_shape_vec((CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE), blocks)
+            .ok()
+            .map(|blocks| Self(BlockStorage::Raw(blocks)))
+    }
+
+    /// Block at `local_pos`, or `None` if it's outside the chunk
+    pub fn get(&self, local_pos: [usize; 3]) -> Option<&PlacedBlock> {
+        local_pos
+            .iter()
+            .all(|x| *x < CHUNK_SIZE)
+            .then(|| self.at_pos(local_pos))
+    }
+
+    /// Replaces the block at `pos` in world space, which has to be in this
+    /// chunk, returning the old block. Decompresses the chunk first.
+    pub(crate) fn replace(&mut self, pos: IVec3, block: PlacedBlock) -> PlacedBlock {
+        let local_pos = pos.rem_euclid(IVec3::splat(CHUNK_SIZE as i32)).as_uvec3();
+        let index = local_pos.to_array().map(|x| x as usize);
+        self.decompress();
+        let BlockStorage::Raw(blocks) = &mut self.0 else {
+            unreachable!("blocks were just decompressed");
+        };
+        std::mem::replace(&mut blocks[index], block)
+    }
+
+    pub(crate) fn is_compressed(&self) -> bool {
+        matches!(self.0, BlockStorage::Compressed { .. })
+    }
+
+    /// Stores the blocks as runs of the same block, which usually takes a
+    /// small fraction of the memory
+    pub(crate) fn compress(&mut self) {
+        let BlockStorage::Raw(blocks) = &self.0 else {
+            return;
+        };
+        let mut palette: Vec<PlacedBlock> = vec![];
+        let mut run_ends: Vec<u16> = vec![];
+        let mut run_blocks: Vec<u16> = vec![];
+        for (i, placed) in blocks.iter().enumerate() {
+            let index = match palette.iter().position(|p| p == placed) {
+                Some(index) => index,
+                None => {
+                    palette.push(*placed);
+                    palette.len() - 1
+                }
+            } as u16;
+            match (run_ends.last_mut(), run_blocks.last()) {
+                (Some(end), Some(last)) if *last == index => *end = i as u16 + 1,
+                _ => {
+                    run_ends.push(i as u16 + 1);
+                    run_blocks.push(index);
+                }
+            }
+        }
+        run_ends.shrink_to_fit();
+        run_blocks.shrink_to_fit();
+        self.0 = BlockStorage::Compressed {
+            palette,
+            run_ends,
+            run_blocks,
+        };
+    }
+
+    pub(crate) fn decompress(&mut self) {
+        if self.is_compressed() {
+            let blocks = self.iter().copied().collect();
+            *self = Self::from_blocks(blocks).expect("runs cover the whole chunk");
+        }
+    }
 }
 
-#[derive(Component, Clone, SpatiallyMapped3d)]
-pub struct Blocks(Array3<Block>);
+/// Position of the block containing `point`. Blocks are drawn centred on
+/// their position, so each spans half a block either side of it.
+pub(crate) fn block_pos_containing(point: Vec3) -> IVec3 {
+    (point + Vec3::splat(0.5)).floor().as_ivec3()
+}
+
+/// Block at `pos` in world space, or `None` while its chunk isn't generated
+pub(crate) fn block_at(
+    chunk_index: &ChunkIndex,
+    q_blocks: &Query<&Blocks>,
+    pos: IVec3,
+) -> Option<Block> {
+    let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+    let local_pos = pos.rem_euclid(IVec3::splat(CHUNK_SIZE as i32)).as_uvec3();
+    let blocks = chunk_index
+        .get_entity(&chunk_pos)
+        .and_then(|entity| q_blocks.get(*entity).ok())?;
+    let placed = blocks.at_pos([local_pos.x as _, local_pos.y as _, local_pos.z as _]);
+    Some(placed.block)
+}
+
+/// Height of the generated ground in the column containing `pos`, or `None`
+/// while the height noise of its chunk isn't generated
+pub(crate) fn surface_height_at(
+    chunk_index: &ChunkIndex,
+    q_height_noise: &Query<&HeightNoise>,
+    pos: IVec3,
+) -> Option<f32> {
+    let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+    let local_pos = pos.rem_euclid(IVec3::splat(CHUNK_SIZE as i32)).as_uvec3();
+    let height_noise = chunk_index
+        .get_entity(&chunk_pos)
+        .and_then(|entity| q_height_noise.get(*entity).ok())?;
+    Some(height_noise.surface_height(local_pos.x as _, local_pos.z as _))
+}
+
+/// Replaces the block at `pos` in world space, sending `BlockChanged` if it
+/// differs. Returns the block it replaced, or `None` while its chunk isn't
+/// generated.
+pub(crate) fn set_block(
+    chunk_index: &ChunkIndex,
+    q_blocks: &mut Query<&mut Blocks>,
+    changed: &mut EventWriter<BlockChanged>,
+    pos: IVec3,
+    block: impl Into<PlacedBlock>,
+    cause: BlockChangeCause,
+) -> Option<PlacedBlock> {
+    let chunk_pos = pos.div_euclid(IVec3::splat(CHUNK_SIZE as i32));
+    let mut blocks = chunk_index
+        .get_entity(&chunk_pos)
+        .and_then(|entity| q_blocks.get_mut(*entity).ok())?;
+    let new = block.into();
+    let old = blocks.replace(pos, new);
+    if old != new {
+        changed.write(BlockChanged {
+            pos,
+            old,
+            new,
+            cause,
+        });
+    }
+    Some(old)
+}
+
+pub(crate) fn apply_block_edits(
+    chunk_index: Res<ChunkIndex>,
+    mut edits: EventReader<EditBlock>,
+    mut q_blocks: Query<&mut Blocks>,
+    mut changed: EventWriter<BlockChanged>,
+) {
+    for edit in edits.read() {
+        set_block(
+            &chunk_index,
+            &mut q_blocks,
+            &mut changed,
+            edit.pos,
+            edit.block,
+            edit.cause,
+        );
+    }
+}
 
 const BEDROCK_DEPTH: i32 = -128;
 const DIRT_LAYER_THICKNESS: u32 = 3;
@@ -112,28 +534,246 @@ const WORLD_AMPLITUDE: f32 = 10.;
 
 fn assign_blocks(
     mut commands: Commands,
-    q_chunks: Query<BlockGenerationData, (With<Chunk>, Without<Blocks>)>,
+    world_seed: Res<WorldSeed>,
+    passes: Res<GenerationPasses>,
+    mut q_chunks: Query<BlockGenerationData, (With<Chunk>, Without<Blocks>)>,
 ) {
-    for item in q_chunks.iter() {
+    for mut item in q_chunks.iter_mut() {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("generate_blocks", chunk = %item.chunk_position.0).entered();
+        // Chunks that were changed are loaded as they were saved
+        if let Some(blocks) = item.saved_blocks.0.take() {
+            commands.entity(item.entity).try_insert(blocks);
+            continue;
+        }
         let chunk_y = item.chunk_position.0.y * CHUNK_SIZE as i32;
-        let blocks = Array3::from_shape_fn((CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE), |(x, y, z)| {
-            let height_sample = *item.height_noise.at_pos([x, z]);
-            let true_y = (y as i32 + chunk_y) as f32;
-            let ground_height = height_sample * WORLD_AMPLITUDE;
-            if true_y + 1. < BEDROCK_DEPTH as _ {
-                Block::Air
-            } else if true_y < BEDROCK_DEPTH as _ {
-                Block::Bedrock
-            } else if (true_y + (DIRT_LAYER_THICKNESS + 1) as f32) < ground_height {
-                Block::Stone
-            } else if true_y + 1. < ground_height {
-                Block::Dirt
-            } else if true_y < ground_height {
-                Block::Grass
-            } else {
-                Block::Air
+        let mut blocks =
+            Array3::from_shape_fn((CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE), |(x, y, z)| {
+                let height_sample = *item.height_noise.at_pos([x, z]);
+                let true_y = (y as i32 + chunk_y) as f32;
+                let ground_height = height_sample * WORLD_AMPLITUDE;
+                let block = if true_y + 1. < BEDROCK_DEPTH as _ {
+                    Block::Air
+                } else if true_y < BEDROCK_DEPTH as _ {
+                    Block::Bedrock
+                } else if (true_y + (DIRT_LAYER_THICKNESS + 1) as f32) < ground_height {
+                    Block::Stone
+                } else if true_y + 1. < ground_height {
+                    Block::Dirt
+                } else if true_y < ground_height {
+                    Block::Grass
+                } else {
+                    Block::Air
+                };
+                PlacedBlock::from(block)
+            });
+        let mut chunk = GeneratingChunk {
+            position: item.chunk_position.0,
+            seed: world_seed.0,
+            height_noise: item.height_noise,
+            blocks: &mut blocks,
+        };
+        for pass in &passes.0 {
+            pass.generate(&mut chunk);
+        }
+        commands
+            .entity(item.entity)
+            .try_insert(Blocks(BlockStorage::Raw(blocks)));
+    }
+}
+
+const GRASS_SPREAD_INTERVAL: Duration = Duration::from_secs(1);
+/// Blocks picked in each chunk every `GRASS_SPREAD_INTERVAL`
+const GRASS_SPREAD_ATTEMPTS: usize = 8;
+
+/// Grass spreads onto dirt next to it, as long as nothing solid covers the
+/// dirt. Blocks are picked at random, and only grass in the same chunk
+/// spreads.
+fn spread_grass(
+    time: Res<Time>,
+    world_seed: Res<WorldSeed>,
+    mut since_last_spread: Local<Duration>,
+    mut rng_state: Local<u64>,
+    mut q_blocks: Query<(&mut Blocks, &ChunkPosition)>,
+    mut changed: EventWriter<BlockChanged>,
+) {
+    *since_last_spread += time.delta();
+    if *since_last_spread < GRASS_SPREAD_INTERVAL {
+        return;
+    }
+    *since_last_spread = Duration::ZERO;
+    if *rng_state == 0 {
+        *rng_state = world_seed.0 as u64 | 1;
+    }
+    let mut next_random = || {
+        // xorshift64
+        let mut x = *rng_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        *rng_state = x;
+        x as usize
+    };
+    let offset = |index: [usize; 3], (x, y, z): (isize, isize, isize)| {
+        Some([
+            index[0].checked_add_signed(x)?,
+            index[1].checked_add_signed(y)?,
+            index[2].checked_add_signed(z)?,
+        ])
+    };
+    for (mut blocks, chunk_position) in q_blocks.iter_mut() {
+        for _ in 0..GRASS_SPREAD_ATTEMPTS {
+            let r = next_random();
+            let index = [0, 8, 16].map(|shift| (r >> shift) % CHUNK_SIZE);
+            if blocks.at_pos(index).block != Block::Dirt {
+                continue;
             }
+            let covered = offset(index, (0, 1, 0))
+                .and_then(|above| blocks.get(above))
+                // Whatever is above the top of the chunk is unknown
+                .is_none_or(|above| above.block.has_tag(BlockTag::Solid));
+            if covered {
+                continue;
+            }
+            let next_to_grass = iter_3d(-1..=1, -1..=1, -1..=1).any(|neighbor| {
+                offset(index, neighbor)
+                    .and_then(|pos| blocks.get(pos))
+                    .is_some_and(|placed| placed.block == Block::Grass)
+            });
+            if next_to_grass {
+                let new = PlacedBlock::from(Block::Grass);
+                let local_pos = IVec3::from_array(index.map(|x| x as i32));
+                let pos = chunk_position.0 * CHUNK_SIZE as i32 + local_pos;
+                let old = blocks.replace(pos, new);
+                changed.write(BlockChanged {
+                    pos,
+                    old,
+                    new,
+                    cause: BlockChangeCause::WorldGen,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lib_chunk::FullNeighborhood;
+
+    use super::*;
+    use crate::test_world::TestWorld;
+
+    /// Frames to wait for at most. Generation and meshing run on
+    /// background tasks, so this is generous as frames take next to no time.
+    const MAX_FRAMES: u32 = 100_000;
+
+    /// Load radius of one chunk around the centre, so 3x3x3 chunks
+    const ONE_CHUNK: LoadRadius = LoadRadius {
+        horizontal: 1,
+        vertical: 1,
+    };
+
+    const BLOCKS_IN_CHUNK: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+    /// Chunk with `block(index)` at each index in the order of `Blocks::iter`
+    fn chunk_of(block: impl Fn(usize) -> Block) -> Blocks {
+        let blocks = (0..BLOCKS_IN_CHUNK)
+            .map(|index| PlacedBlock::from(block(index)))
+            .collect();
+        Blocks::from_blocks(blocks).unwrap()
+    }
+
+    fn runs(blocks: &Blocks) -> &[u16] {
+        match &blocks.0 {
+            BlockStorage::Raw(_) => panic!("blocks aren't compressed"),
+            BlockStorage::Compressed { run_ends, .. } => run_ends,
+        }
+    }
+
+    /// Compresses and decompresses `blocks`, checking they read the same
+    /// all along. Returns them compressed.
+    fn assert_round_trip(blocks: &Blocks) -> Blocks {
+        let mut compressed = blocks.clone();
+        compressed.compress();
+        assert!(compressed.is_compressed());
+        assert!(compressed.iter().eq(blocks.iter()));
+        for (x, y, z) in iter_3d(0..CHUNK_SIZE, 0..CHUNK_SIZE, 0..CHUNK_SIZE) {
+            assert_eq!(compressed.at_pos([x, y, z]), blocks.at_pos([x, y, z]));
+        }
+        let mut decompressed = compressed.clone();
+        decompressed.decompress();
+        assert!(!decompressed.is_compressed());
+        assert!(decompressed.iter().eq(blocks.iter()));
+        compressed
+    }
+
+    #[test]
+    fn uniform_chunk_compresses_into_one_run() {
+        let compressed = assert_round_trip(&chunk_of(|_| Block::Stone));
+        assert_eq!(runs(&compressed), [BLOCKS_IN_CHUNK as u16]);
+    }
+
+    #[test]
+    fn chunk_with_every_block_differing_from_the_last_round_trips() {
+        const BLOCKS: [Block; 4] = [Block::Stone, Block::Dirt, Block::Grass, Block::Bedrock];
+        let blocks = chunk_of(|index| BLOCKS[index % BLOCKS.len()]);
+        let compressed = assert_round_trip(&blocks);
+        // One run per block, the last ending at the end of the chunk
+        let runs = runs(&compressed);
+        assert_eq!(runs.len(), BLOCKS_IN_CHUNK);
+        assert_eq!(runs.last(), Some(&(BLOCKS_IN_CHUNK as u16)));
+    }
+
+    #[test]
+    fn generated_chunk_round_trips() {
+        let blocks = chunk_of(|index| match index % CHUNK_SIZE {
+            0..4 => Block::Stone,
+            4..7 => Block::Dirt,
+            7 => Block::Grass,
+            _ => Block::Air,
         });
-        commands.entity(item.entity).try_insert(Blocks(blocks));
+        assert_round_trip(&blocks);
+    }
+
+    #[test]
+    fn replacing_a_block_decompresses_the_chunk() {
+        let mut blocks = chunk_of(|_| Block::Air);
+        blocks.compress();
+        let pos = IVec3::new(1, 2, 3);
+        let old = blocks.replace(pos, PlacedBlock::from(Block::Stone));
+        assert_eq!(old.block, Block::Air);
+        assert!(!blocks.is_compressed());
+        assert_eq!(blocks.at_pos([1, 2, 3]).block, Block::Stone);
+    }
+
+    #[test]
+    fn flat_world_generates_and_meshes_every_chunk() {
+        let mut world = TestWorld::new(0, WorldType::Flat, ONE_CHUNK);
+        world.step_until_meshed(MAX_FRAMES);
+        for (x, y, z) in iter_3d(-1..=1, -1..=1, -1..=1) {
+            world.assert_has_blocks(IVec3::new(x, y, z));
+        }
+        // Only the centre chunk has all its neighbours loaded
+        world.assert_full_neighborhood(IVec3::ZERO);
+        assert!(!world.chunk_has::<FullNeighborhood<Blocks>>(IVec3::ONE));
+    }
+
+    #[test]
+    fn flat_world_meshes_into_one_quad_per_layer_and_side() {
+        // The ground is grass on three layers of dirt at the top of the
+        // chunks at y = 0, with stone filling the chunks below. The top of
+        // each column of chunks is one quad of grass and the bottom one of
+        // stone. Each of the 12 sides of the loaded area is one quad of
+        // stone below, and one of grass over one of dirt above.
+        const QUADS: u32 = 9 + 9 + 12 + 12 * 2;
+        let mut world = TestWorld::new(0, WorldType::Flat, ONE_CHUNK);
+        world.step_until_meshed(MAX_FRAMES);
+        // Chunks meshed before their neighbours loaded have more faces
+        // showing until they're meshed again
+        world.step_until(MAX_FRAMES, |world| world.quad_count() <= QUADS);
+        world.assert_quad_count_within(QUADS..=QUADS);
+        // Nothing changes, so nothing is meshed again
+        world.step(60);
+        world.assert_quad_count_within(QUADS..=QUADS);
     }
 }